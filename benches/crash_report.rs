@@ -0,0 +1,46 @@
+//! Measures the cost of buffering the records for the crash report: `cargo bench --bench crash_report`
+//!
+//! The only destination is a layer doing nothing, so the time per event is the dispatch overhead plus the formatting
+//! of the record into the buffer.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use tracing_init::TracingInit;
+
+const EVENTS: u32 = 1_000_000;
+
+fn time_per_event(configure: impl FnOnce(&mut TracingInit)) -> Duration {
+    let mut builder = TracingInit::builder("Bench");
+
+    builder
+        .log_to_console(false)
+        .with_layer(tracing_subscriber::layer::Identity::new());
+    configure(&mut builder);
+
+    let (dispatch, _guard) = builder.into_dispatch().unwrap();
+
+    tracing::dispatcher::with_default(&dispatch, || {
+        let start = Instant::now();
+
+        for n in 0..EVENTS {
+            tracing::info!(target: "bench::events", n = black_box(n), "event");
+        }
+
+        start.elapsed() / EVENTS
+    })
+}
+
+fn main() {
+    let crash_dir = std::env::temp_dir().join("tracing-init-bench");
+    let baseline = time_per_event(|_| ());
+    let crash_report = time_per_event(|builder| {
+        builder.crash_report(crash_dir.to_str().unwrap(), 200);
+    });
+
+    println!("no crash report:  {baseline:?} per event");
+    println!(
+        "crash report:     {crash_report:?} per event (+{:?})",
+        crash_report.saturating_sub(baseline)
+    );
+}
@@ -0,0 +1,69 @@
+//! Compares the concrete destination layers built by `into_dispatch` with the boxed ones built by `build_layers`:
+//! `cargo bench --bench destinations`
+//!
+//! The only destination is the log file, so the time per event is the dispatch overhead plus the formatting and
+//! writing of the record. The throughput is reported in events per second.
+
+use std::hint::black_box;
+
+use criterion::measurement::WallTime;
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, Criterion, Throughput};
+use tracing_init::{Rotation, TracingInit, TracingLayers};
+use tracing_subscriber::layer::SubscriberExt;
+
+fn builder(name: &str) -> TracingInit {
+    let log_dir = std::env::temp_dir().join("tracing-init-bench").join(name);
+    let _ = std::fs::remove_dir_all(&log_dir);
+    let mut builder = TracingInit::builder("Bench");
+
+    builder
+        .log_to_console(false)
+        .log_to_file(true)
+        .log_file_path(log_dir.to_str().unwrap())
+        .log_file_rotation(Rotation::Never);
+    builder
+}
+
+// One event per iteration, logged within a span
+fn bench_events(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    name: &str,
+    dispatch: &tracing::Dispatch,
+) {
+    tracing::dispatcher::with_default(dispatch, || {
+        let span = tracing::info_span!("request", id = 7);
+        let _entered = span.enter();
+        let mut n = 0u64;
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                n += 1;
+                tracing::info!(target: "bench::events", n = black_box(n), "event");
+            })
+        });
+    });
+}
+
+fn destinations(c: &mut Criterion) {
+    let mut group = c.benchmark_group("destinations");
+
+    group.throughput(Throughput::Elements(1));
+
+    let TracingLayers {
+        layers,
+        filter,
+        guard: _boxed_guard,
+        ..
+    } = builder("boxed").build_layers().unwrap();
+    let boxed = tracing::Dispatch::new(tracing_subscriber::registry().with(layers).with(filter));
+
+    bench_events(&mut group, "boxed", &boxed);
+
+    let (concrete, _guard) = builder("concrete").into_dispatch().unwrap();
+
+    bench_events(&mut group, "concrete", &concrete);
+    group.finish();
+}
+
+criterion_group!(benches, destinations);
+criterion_main!(benches);
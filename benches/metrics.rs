@@ -0,0 +1,51 @@
+//! Measures the cost of counting events: `cargo bench --bench metrics`
+//!
+//! The only destination is a layer doing nothing, so the time per event is the dispatch overhead plus the counting.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use tracing_init::TracingInit;
+
+const EVENTS: u32 = 2_000_000;
+
+fn time_per_event(configure: impl FnOnce(&mut TracingInit)) -> Duration {
+    let mut builder = TracingInit::builder("Bench");
+
+    builder
+        .log_to_console(false)
+        .with_layer(tracing_subscriber::layer::Identity::new());
+    configure(&mut builder);
+
+    let (dispatch, _guard) = builder.into_dispatch().unwrap();
+
+    tracing::dispatcher::with_default(&dispatch, || {
+        let start = Instant::now();
+
+        for n in 0..EVENTS {
+            tracing::info!(target: "bench::events", n = black_box(n), "event");
+        }
+
+        start.elapsed() / EVENTS
+    })
+}
+
+fn main() {
+    let baseline = time_per_event(|_| ());
+    let metrics = time_per_event(|builder| {
+        builder.with_metrics(true);
+    });
+    let per_target = time_per_event(|builder| {
+        builder.with_metrics(true).metrics_per_target(true);
+    });
+
+    println!("no metrics:          {baseline:?} per event");
+    println!(
+        "metrics:             {metrics:?} per event (+{:?})",
+        metrics.saturating_sub(baseline)
+    );
+    println!(
+        "metrics per target:  {per_target:?} per event (+{:?})",
+        per_target.saturating_sub(baseline)
+    );
+}
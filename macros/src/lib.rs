@@ -0,0 +1,205 @@
+//! The `#[tracing_init::main]` attribute, enabled by the `macros` feature of tracing-init
+//!
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{Expr, ExprLit, Ident, ItemFn, Lit, LitStr, MetaNameValue, Token};
+
+const LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error"];
+
+/// Initialize logging when the function is entered and keep the guard until it returns
+///
+/// The arguments `app`, `destinations` (like LOG_DESTINATION, e.g. `"cf"` or `"c:info,f:debug"`) and `level` (like
+/// LOG_LEVEL) are optional, the settings which are not given are taken from the environment variables.
+#[proc_macro_attribute]
+pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
+    let item = TokenStream2::from(item);
+
+    match expand(args.into(), item.clone()) {
+        Ok(expanded) => expanded.into(),
+        // The function is kept, the errors of the attribute are not hidden by those of a missing main
+        Err(error) => {
+            let error = error.to_compile_error();
+            quote!(#error #item).into()
+        }
+    }
+}
+
+// The arguments of the attribute
+#[derive(Default)]
+struct Args {
+    app: Option<LitStr>,
+    destinations: Option<LitStr>,
+    level: Option<LitStr>,
+}
+
+fn expand(args: TokenStream2, item: TokenStream2) -> syn::Result<TokenStream2> {
+    let args = parse_args(args)?;
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = syn::parse2::<ItemFn>(item)?;
+
+    let builder = match args.app {
+        Some(ref app) => quote!(::tracing_init::TracingInit::builder(#app)),
+        None => quote!(::tracing_init::TracingInit::default()),
+    };
+    let mut calls = Vec::new();
+
+    // Checked here, set by the library: the builder methods of the file and server destinations depend on its features
+    let destinations = match args.destinations {
+        Some(ref destinations) => {
+            parse_destinations(&destinations.value())
+                .map_err(|message| syn::Error::new(destinations.span(), message))?;
+
+            quote!(::core::option::Option::Some(#destinations))
+        }
+        None => quote!(::core::option::Option::None),
+    };
+
+    if let Some(ref level) = args.level {
+        if !is_level(&level.value()) {
+            return Err(syn::Error::new(
+                level.span(),
+                format!(
+                    "invalid level '{}', expected one of {}",
+                    level.value(),
+                    LEVELS.join(", ")
+                ),
+            ));
+        }
+
+        let level = format_ident!("{}", level.value().to_ascii_uppercase());
+
+        calls.push(quote!(.level(::tracing_init::macro_support::Level::#level)));
+    }
+
+    // Not visible to the body, declared first to be dropped last: after the body returned, with an error or not, or
+    // panicked
+    let guard = Ident::new("__tracing_init_guard", Span::mixed_site());
+    let stmts = &block.stmts;
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            let #guard = ::tracing_init::macro_support::init(#builder #(#calls)*, #destinations);
+
+            #(#stmts)*
+        }
+    })
+}
+
+fn parse_args(args: TokenStream2) -> syn::Result<Args> {
+    let mut parsed = Args::default();
+
+    for arg in Punctuated::<MetaNameValue, Token![,]>::parse_terminated.parse2(args)? {
+        let name = arg
+            .path
+            .get_ident()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        let value = match name.as_str() {
+            "app" => &mut parsed.app,
+            "destinations" => &mut parsed.destinations,
+            "level" => &mut parsed.level,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &arg.path,
+                    "unknown argument, expected `app`, `destinations` or `level`",
+                ))
+            }
+        };
+
+        if value.is_some() {
+            return Err(syn::Error::new_spanned(
+                &arg.path,
+                format!("duplicate argument `{name}`"),
+            ));
+        }
+
+        match arg.value {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(literal),
+                ..
+            }) => *value = Some(literal),
+            other => {
+                return Err(syn::Error::new_spanned(
+                    other,
+                    format!("expected a string literal for `{name}`"),
+                ))
+            }
+        }
+    }
+
+    if let Some(ref app) = parsed.app {
+        if app.value().is_empty() {
+            return Err(syn::Error::new(app.span(), "the app name cannot be empty"));
+        }
+    }
+
+    Ok(parsed)
+}
+
+// The entries of a LOG_DESTINATION like value: the destination characters and their level (if given)
+fn parse_destinations(value: &str) -> Result<Vec<(String, Option<String>)>, String> {
+    value
+        .split(',')
+        .map(|entry| {
+            let (destinations, level) = match entry.split_once(':') {
+                Some((destinations, level)) => (destinations, Some(level)),
+                None => (entry, None),
+            };
+
+            if destinations.is_empty() || !destinations.chars().all(|c| "cfsd".contains(c)) {
+                return Err(format!(
+                    "invalid destinations '{entry}', expected one or more of c (console), f (file), s (server) and d \
+                     (debug output), optionally followed by a level (e.g. c:info,f:debug)"
+                ));
+            }
+
+            match level {
+                Some(level) if !is_level(level) => Err(format!(
+                    "invalid level '{level}' in '{entry}', expected one of {}",
+                    LEVELS.join(", ")
+                )),
+                _ => Ok((destinations.to_string(), level.map(String::from))),
+            }
+        })
+        .collect()
+}
+
+fn is_level(level: &str) -> bool {
+    LEVELS.contains(&level.to_ascii_lowercase().as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_destinations() {
+        let entry = |destinations: &str, level: Option<&str>| {
+            (destinations.to_string(), level.map(String::from))
+        };
+
+        assert_eq!(parse_destinations("cf"), Ok(vec![entry("cf", None)]));
+        assert_eq!(
+            parse_destinations("c:info,fs:DEBUG"),
+            Ok(vec![entry("c", Some("info")), entry("fs", Some("DEBUG"))])
+        );
+        assert_eq!(
+            parse_destinations("cd:trace"),
+            Ok(vec![entry("cd", Some("trace"))])
+        );
+
+        assert!(parse_destinations("").is_err());
+        assert!(parse_destinations("cx").is_err());
+        assert!(parse_destinations("c,").is_err());
+        assert!(parse_destinations("c:loud").is_err());
+        assert!(parse_destinations(":info").is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::{FilterHandle, ResolvedConfig, TracingInitError};
+
+#[cfg(unix)]
+type Listener = std::os::unix::net::UnixListener;
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+#[cfg(not(unix))]
+type Listener = std::net::TcpListener;
+#[cfg(not(unix))]
+type Stream = std::net::TcpStream;
+
+// A request which takes longer than this to arrive is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Largest accepted request body (a filter directive string)
+const MAX_BODY_LENGTH: usize = 64 * 1024;
+
+// Serves the admin commands (GET /level, PUT /level, GET /config) on a Unix domain socket (a localhost TCP address
+// on other platforms). Stopped, and the socket file removed, when dropped
+pub(crate) struct AdminSocket {
+    address: String,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AdminSocket {
+    pub(crate) fn start(
+        address: &str,
+        config: Arc<ResolvedConfig>,
+        filter_handle: FilterHandle,
+    ) -> Result<AdminSocket, TracingInitError> {
+        let admin_socket_error = |source| TracingInitError::AdminSocket {
+            address: address.to_string(),
+            source,
+        };
+
+        let listener = bind(address).map_err(admin_socket_error)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = std::thread::Builder::new()
+            .name(String::from("tracing-init-admin"))
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if thread_stop.load(Ordering::Acquire) {
+                        break;
+                    }
+
+                    if let Ok(stream) = stream {
+                        let _ = serve(stream, &config, &filter_handle);
+                    }
+                }
+            })
+            .map_err(admin_socket_error)?;
+
+        Ok(AdminSocket {
+            address: address.to_string(),
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for AdminSocket {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+
+        // Wake up the listener thread blocked in accept
+        if Stream::connect(&self.address).is_ok() {
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.address);
+    }
+}
+
+#[cfg(unix)]
+fn bind(path: &str) -> std::io::Result<Listener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // A socket file left behind by a previous run which was killed
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() && Stream::connect(path).is_err() {
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    Listener::bind(path)
+}
+
+#[cfg(not(unix))]
+fn bind(address: &str) -> std::io::Result<Listener> {
+    let listener = Listener::bind(address)?;
+
+    if !listener.local_addr()?.ip().is_loopback() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "the admin socket must be bound to a localhost address",
+        ));
+    }
+
+    Ok(listener)
+}
+
+// Handle a single HTTP/1.x request
+fn serve(
+    stream: Stream,
+    config: &ResolvedConfig,
+    filter_handle: &FilterHandle,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+
+    reader.read_line(&mut request_line)?;
+
+    let mut content_length = 0;
+
+    loop {
+        let mut header = String::new();
+
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length.min(MAX_BODY_LENGTH)];
+
+    reader.read_exact(&mut body)?;
+
+    let mut parts = request_line.split_whitespace();
+    let (status, response) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/level")) => ("200 OK", filter_handle.current()),
+        (Some("PUT"), Some("/level")) => {
+            let directives = String::from_utf8_lossy(&body);
+
+            match filter_handle.set_filter(directives.trim()) {
+                Ok(()) => {
+                    tracing::info!(target: "tracing_init", filter = %directives.trim(), "filter changed by admin socket");
+                    ("200 OK", filter_handle.current())
+                }
+                Err(e) => ("400 Bad Request", e.to_string()),
+            }
+        }
+        (Some("GET"), Some("/config")) => ("200 OK", config.to_string()),
+        (Some(_), Some("/level" | "/config")) => {
+            ("405 Method Not Allowed", String::from("method not allowed"))
+        }
+        _ => ("404 Not Found", String::from("not found")),
+    };
+
+    write!(
+        &stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {length}\r\nConnection: close\r\n\r\n{response}\n",
+        length = response.len() + 1
+    )?;
+
+    (&stream).flush()
+}
@@ -0,0 +1,276 @@
+use std::fmt::{Debug, Write as _};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::clock::ClockSource;
+
+// The width of the level column, the longest level names have 5 characters
+const LEVEL_WIDTH: usize = 5;
+const ELLIPSIS: char = '…';
+
+const DIMMED: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// The layout of the console records in the [`LogFormat::Full`](crate::LogFormat::Full) format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ConsoleStyle {
+    /// The layout of tracing-subscriber's full format
+    #[default]
+    Default,
+    /// The level and the target in fixed width columns so that the messages start at the same column. Longer targets
+    /// are truncated from the left, keeping their last module segments
+    Aligned {
+        /// The width of the target column
+        target_width: usize,
+    },
+}
+
+impl std::fmt::Display for ConsoleStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConsoleStyle::Default => write!(f, "default"),
+            ConsoleStyle::Aligned { target_width } => {
+                write!(f, "aligned, target width {target_width}")
+            }
+        }
+    }
+}
+
+// Formats the events of the aligned console style: time, level, target and message in columns, then the fields, the
+// spans and the source location
+pub(crate) struct AlignedFormat {
+    target_width: usize,
+    source_location: bool,
+    timer: ClockSource,
+}
+
+impl AlignedFormat {
+    pub(crate) fn new(
+        target_width: usize,
+        source_location: bool,
+        timer: ClockSource,
+    ) -> AlignedFormat {
+        AlignedFormat {
+            target_width,
+            source_location,
+            timer,
+        }
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for AlignedFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let ansi = writer.has_ansi_escapes();
+        let metadata = event.metadata();
+        let (dimmed, reset) = if ansi { (DIMMED, RESET) } else { ("", "") };
+
+        let mut time = String::new();
+        self.timer.format_time(&mut Writer::new(&mut time))?;
+
+        let level = metadata.level();
+        let target = fit_target(metadata.target(), self.target_width);
+        let target_padding = self.target_width.saturating_sub(target.chars().count());
+
+        write!(writer, "{dimmed}{time}{reset} ")?;
+
+        if ansi {
+            write!(
+                writer,
+                "{}{level:>LEVEL_WIDTH$}{RESET} ",
+                level_color(level)
+            )?;
+        } else {
+            write!(writer, "{level:>LEVEL_WIDTH$} ")?;
+        }
+
+        write!(writer, "{dimmed}{target}{reset}{:target_padding$} ", "")?;
+
+        let mut fields = AlignedFields::default();
+        event.record(&mut fields);
+
+        // The continuation lines of the message start at the message column
+        let column = time.chars().count() + 1 + LEVEL_WIDTH + 1 + self.target_width + 1;
+        let indent = format!("\n{:column$}", "");
+
+        write!(writer, "{}", fields.message.replace('\n', &indent))?;
+
+        for (name, value) in fields.fields {
+            write!(writer, " {name}={value}")?;
+        }
+
+        if let Some(scope) = ctx.event_scope() {
+            let spans = scope
+                .from_root()
+                .map(|span| span.name())
+                .collect::<Vec<_>>();
+
+            write!(writer, " {dimmed}[{}]{reset}", spans.join(" > "))?;
+        }
+
+        if let (true, Some(file), Some(line)) =
+            (self.source_location, metadata.file(), metadata.line())
+        {
+            write!(writer, " {dimmed}({file}:{line}){reset}")?;
+        }
+
+        writeln!(writer)
+    }
+}
+
+// The colors of tracing-subscriber's full format
+fn level_color(level: &Level) -> &'static str {
+    match *level {
+        Level::TRACE => "\x1b[35m",
+        Level::DEBUG => "\x1b[34m",
+        Level::INFO => "\x1b[32m",
+        Level::WARN => "\x1b[33m",
+        _ => "\x1b[31m",
+    }
+}
+
+// The target shortened to the width by dropping its first module segments, replaced by an ellipsis. A last segment
+// longer than the width keeps its end
+fn fit_target(target: &str, width: usize) -> String {
+    if target.chars().count() <= width {
+        return target.to_string();
+    }
+
+    let kept = width.saturating_sub(1);
+    let tail = target
+        .match_indices("::")
+        .map(|(index, separator)| &target[index + separator.len()..])
+        .find(|tail| tail.chars().count() <= kept)
+        .unwrap_or_else(|| {
+            let skip = target.chars().count() - kept;
+            &target[target
+                .char_indices()
+                .nth(skip)
+                .map_or(target.len(), |(index, _)| index)..]
+        });
+
+    if width == 0 {
+        String::new()
+    } else {
+        format!("{ELLIPSIS}{tail}")
+    }
+}
+
+// The message and the other fields of an event, formatted as by the full format
+#[derive(Default)]
+struct AlignedFields {
+    message: String,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Visit for AlignedFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields.push((field.name(), format!("{value:?}")));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            self.fields.push((field.name(), format!("{value:?}")));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::layers::{self, ConsoleOptions};
+    use crate::tests::MemoryWriter;
+    use crate::ManualClock;
+
+    fn format(ansi: bool, source_location: bool) -> String {
+        let console = MemoryWriter::default();
+        let options = ConsoleOptions {
+            style: ConsoleStyle::Aligned { target_width: 16 },
+            ansi,
+            source_location,
+            clock: Some(Arc::new(ManualClock::new(
+                UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+            ))),
+            ..ConsoleOptions::default()
+        };
+        let subscriber = tracing_subscriber::registry()
+            .with(layers::console_with_writer(&options, console.clone()))
+            .with(tracing_subscriber::filter::LevelFilter::TRACE);
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "app", "started");
+            tracing::warn!(target: "app::db", user = "bob", retries = 3, "slow query");
+            tracing::error!(target: "app::http::server::handlers", "request failed");
+            tracing::debug!(target: "an_extremely_long_module_name", "long segment");
+            tracing::info_span!("request", id = 7).in_scope(|| {
+                tracing::trace!(target: "app::db::pool", "line 1\nline 2");
+            });
+        });
+
+        console.contents()
+    }
+
+    #[test]
+    fn test_aligned_style() {
+        assert_eq!(
+            format(false, false),
+            "\
+2023-11-14T22:13:20.123Z  INFO app              started
+2023-11-14T22:13:20.123Z  WARN app::db          slow query user=\"bob\" retries=3
+2023-11-14T22:13:20.123Z ERROR …handlers        request failed
+2023-11-14T22:13:20.123Z DEBUG …ong_module_name long segment
+2023-11-14T22:13:20.123Z TRACE app::db::pool    line 1
+                                                line 2 [request]
+"
+        );
+    }
+
+    #[test]
+    fn test_aligned_style_ansi() {
+        let lines = format(true, true);
+        let line = lines.lines().next().unwrap();
+
+        assert!(
+            line.starts_with(
+                "\x1b[2m2023-11-14T22:13:20.123Z\x1b[0m \x1b[32m INFO\x1b[0m \x1b[2mapp\x1b[0m              started"
+            ),
+            "{line:?}"
+        );
+        assert!(line.contains(" \x1b[2m(src/aligned.rs:"), "{line:?}");
+        assert!(line.ends_with(")\x1b[0m"), "{line:?}");
+    }
+
+    #[test]
+    fn test_fit_target() {
+        assert_eq!(fit_target("app", 8), "app");
+        assert_eq!(fit_target("app::http::server", 12), "…server");
+        assert_eq!(fit_target("app::http::server", 14), "…http::server");
+        assert_eq!(fit_target("app::handlers", 6), "…dlers");
+        assert_eq!(fit_target("app", 0), "");
+    }
+}
@@ -0,0 +1,685 @@
+//! Reading the log files written in the [`LogFormat::Binary`](crate::LogFormat::Binary) format
+//!
+//! Each record is self-delimiting: a marker byte and the length of the record precede its content, so a record cut
+//! short when the process crashed while writing it is detected (see [`Reader::truncated`]) and the records around it
+//! are read as usual.
+//!
+//! # Example
+//! ```no_run
+//! use tracing_init::binlog;
+//!
+//! let reader = binlog::Reader::open("logs/App.log").unwrap();
+//! binlog::decode_to_text(reader, std::io::stdout()).unwrap();
+//! ```
+//!
+
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+use crate::clock::ClockSource;
+use crate::preinit::format_time;
+use crate::read_ahead::ReadAhead;
+use crate::FieldValue;
+
+// The first byte of every record, anything else is not a record written by this format
+const MARKER: u8 = 0xb7;
+// The marker and the length of the record content (u32, little endian)
+const HEADER_LEN: usize = 5;
+// The longest record looked for when skipping invalid data, where the lengths read are mostly garbage
+const MAX_RESYNC_LEN: usize = 16 * 1024 * 1024;
+// The bytes searched for the next marker at once when skipping invalid data
+const SCAN_LEN: usize = 4096;
+
+const STR: u8 = 0;
+const I64: u8 = 1;
+const U64: u8 = 2;
+const BOOL: u8 = 3;
+
+/// A log record read from a binary log file
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Record {
+    /// The time the event was logged
+    pub time: SystemTime,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    /// The source file of the event (only when the source location is logged)
+    pub file: Option<String>,
+    /// The line number of the event (only when the source location is logged)
+    pub line: Option<u32>,
+    /// The fields of the event other than the message, floating point and Debug values are strings
+    pub fields: Vec<(String, FieldValue)>,
+}
+
+// The same layout as the full text format, without the spans
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // On a single line, the line breaks of the message are escaped as those of the string fields
+        write!(
+            f,
+            "{} {:>5} {}: {}",
+            format_time(self.time),
+            self.level,
+            self.target,
+            self.message.replace('\r', "\\r").replace('\n', "\\n")
+        )?;
+
+        for (name, value) in &self.fields {
+            match value {
+                FieldValue::Str(value) => write!(f, " {name}={value:?}")?,
+                _ => write!(f, " {name}={value}")?,
+            }
+        }
+
+        if let (Some(file), Some(line)) = (&self.file, self.line) {
+            write!(f, " ({file}:{line})")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Iterate over the records of a binary log file
+///
+/// Invalid data is skipped up to the next record which can be decoded. A record cut short (see [`Reader::truncated`])
+/// is skipped silently, e.g. when the process crashed while writing it and the next run appended its records after
+/// it. Any other invalid data (a record which cannot be decoded, or data which is not a record) is returned as an
+/// [`ErrorKind::InvalidData`] error and the following records are still read.
+///
+pub struct Reader<R> {
+    input: ReadAhead<R>,
+    truncated: bool,
+    done: bool,
+}
+
+impl Reader<BufReader<File>> {
+    /// Open a binary log file
+    ///
+    /// # Errors
+    /// The error opening the file
+    ///
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Reader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> Reader<R> {
+    /// Read the records from a reader, e.g. a file opened by the caller
+    pub fn new(reader: R) -> Self {
+        Reader {
+            input: ReadAhead::new(reader),
+            truncated: false,
+            done: false,
+        }
+    }
+
+    /// true if a record was cut short (e.g. by a crash while it was written), it was skipped
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    // The next record or the invalid data before it, None at the end of the file. The errors are those reading it
+    fn next_record(&mut self) -> std::io::Result<Option<std::io::Result<Record>>> {
+        let (len, cut, error) = match self.ahead(usize::MAX)? {
+            Ahead::End => return Ok(None),
+            Ahead::Record(record, len) => {
+                self.input.consume(len);
+                return Ok(Some(Ok(record)));
+            }
+            Ahead::Cut(len) => (len, true, invalid_record()),
+            Ahead::Invalid(len) => (Some(len), false, invalid_record()),
+            Ahead::NotRecord => (
+                None,
+                false,
+                std::io::Error::new(ErrorKind::InvalidData, "not a binary log record"),
+            ),
+        };
+
+        match self.resync()? {
+            // The records written after a crash start within the record it cut short
+            Some(skipped) if len.is_some_and(|len| skipped < len) => {
+                self.truncated = true;
+                self.next_record()
+            }
+            Some(_) => Ok(Some(Err(error))),
+            None if cut => {
+                self.truncated = true;
+                Ok(None)
+            }
+            None => {
+                self.done = true;
+                Ok(Some(Err(error)))
+            }
+        }
+    }
+
+    // The record at the start of the data ahead, without consuming it
+    fn ahead(&mut self, max_len: usize) -> std::io::Result<Ahead> {
+        let header = self.input.peek(HEADER_LEN)?;
+
+        match header.first() {
+            None => return Ok(Ahead::End),
+            Some(&byte) if byte != MARKER => return Ok(Ahead::NotRecord),
+            _ if header.len() < HEADER_LEN => return Ok(Ahead::Cut(None)),
+            _ => {}
+        }
+
+        let len = HEADER_LEN.saturating_add(u32::from_le_bytes([
+            header[1], header[2], header[3], header[4],
+        ]) as usize);
+
+        if len > max_len {
+            return Ok(Ahead::NotRecord);
+        }
+
+        let record = self.input.peek(len)?;
+
+        if record.len() < len {
+            return Ok(Ahead::Cut(Some(len)));
+        }
+
+        Ok(match decode(&record[HEADER_LEN..]) {
+            Ok(record) => Ahead::Record(record, len),
+            Err(_) => Ahead::Invalid(len),
+        })
+    }
+
+    // Skip the invalid data at the start up to the next whole record which can be decoded, returning the number of
+    // bytes skipped (None if there is no record after it)
+    fn resync(&mut self) -> std::io::Result<Option<usize>> {
+        self.input.consume(1);
+        let mut skipped = 1;
+
+        loop {
+            let ahead = self.input.peek(SCAN_LEN)?;
+
+            if ahead.is_empty() {
+                return Ok(None);
+            }
+
+            match ahead.iter().position(|&byte| byte == MARKER) {
+                Some(pos) => {
+                    self.input.consume(pos);
+                    skipped += pos;
+
+                    if let Ahead::Record(..) = self.ahead(MAX_RESYNC_LEN)? {
+                        return Ok(Some(skipped));
+                    }
+
+                    self.input.consume(1);
+                    skipped += 1;
+                }
+                None => {
+                    let len = ahead.len();
+
+                    self.input.consume(len);
+                    skipped += len;
+                }
+            }
+        }
+    }
+}
+
+// What starts the data ahead of the reader
+enum Ahead {
+    End,
+    // A whole record and its length (with the header)
+    Record(Record, usize),
+    // A record ending after the end of the file, with its length when its header is whole
+    Cut(Option<usize>),
+    // A whole record which cannot be decoded, with its length
+    Invalid(usize),
+    NotRecord,
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = std::io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.next_record() {
+            Ok(Some(record)) => Some(record),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Write the records of a binary log file as text, one line per record
+///
+/// The line breaks of the messages are written as `\n` (and `\r`), as in the string fields. A record cut short is
+/// skipped.
+///
+/// # Errors
+/// A record which cannot be decoded, or the error reading the file or writing the text
+///
+pub fn decode_to_text<R: Read, W: Write>(reader: Reader<R>, mut writer: W) -> std::io::Result<()> {
+    for record in reader {
+        writeln!(writer, "{}", record?)?;
+    }
+
+    writer.flush()
+}
+
+// The layer writing each event as a binary record
+pub(crate) struct BinlogLayer<W> {
+    make_writer: W,
+    source_location: bool,
+    clock: ClockSource,
+}
+
+impl<W> BinlogLayer<W> {
+    pub(crate) fn new(make_writer: W, source_location: bool, clock: ClockSource) -> Self {
+        BinlogLayer {
+            make_writer,
+            source_location,
+            clock,
+        }
+    }
+}
+
+impl<S, W> Layer<S> for BinlogLayer<W>
+where
+    S: Subscriber,
+    W: for<'w> MakeWriter<'w> + 'static,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut fields = BinaryFields::default();
+
+        event.record(&mut fields);
+
+        let mut record = Encoder(vec![MARKER, 0, 0, 0, 0]);
+        let since_epoch = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        record.u64(since_epoch.as_nanos() as u64);
+        record.0.push(level_code(metadata.level()));
+        record.str(metadata.target());
+        record.str(&fields.message);
+
+        match (self.source_location, metadata.file(), metadata.line()) {
+            (true, Some(file), Some(line)) => {
+                record.0.push(1);
+                record.str(file);
+                record.u64(line.into());
+            }
+            _ => record.0.push(0),
+        }
+
+        record.u64(fields.fields.len() as u64);
+
+        for (name, value) in &fields.fields {
+            record.str(name);
+            record.value(value);
+        }
+
+        let len = (record.0.len() - HEADER_LEN) as u32;
+        record.0[1..HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+
+        // Written at once, so a non-blocking writer queues the whole record
+        let _ = self
+            .make_writer
+            .make_writer_for(metadata)
+            .write_all(&record.0);
+    }
+}
+
+fn level_code(level: &Level) -> u8 {
+    match *level {
+        Level::TRACE => 0,
+        Level::DEBUG => 1,
+        Level::INFO => 2,
+        Level::WARN => 3,
+        _ => 4,
+    }
+}
+
+// The content of a record: the integers are LEB128 varints (zigzag encoded when signed), the strings are preceded by
+// their length
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u64(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+
+        self.0.push(value as u8);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u64(value.len() as u64);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn value(&mut self, value: &FieldValue) {
+        match value {
+            FieldValue::Str(value) => {
+                self.0.push(STR);
+                self.str(value);
+            }
+            FieldValue::I64(value) => {
+                self.0.push(I64);
+                self.u64(((value << 1) ^ (value >> 63)) as u64);
+            }
+            FieldValue::U64(value) => {
+                self.0.push(U64);
+                self.u64(*value);
+            }
+            FieldValue::Bool(value) => {
+                self.0.push(BOOL);
+                self.0.push(u8::from(*value));
+            }
+        }
+    }
+}
+
+fn decode(content: &[u8]) -> std::io::Result<Record> {
+    let mut decoder = Decoder(content);
+    let since_epoch = Duration::from_nanos(decoder.u64()?);
+    let level = match decoder.byte()? {
+        0 => Level::TRACE,
+        1 => Level::DEBUG,
+        2 => Level::INFO,
+        3 => Level::WARN,
+        4 => Level::ERROR,
+        _ => return Err(invalid_record()),
+    };
+    let target = decoder.str()?;
+    let message = decoder.str()?;
+    let (file, line) = match decoder.byte()? {
+        0 => (None, None),
+        1 => (
+            Some(decoder.str()?),
+            Some(u32::try_from(decoder.u64()?).map_err(|_| invalid_record())?),
+        ),
+        _ => return Err(invalid_record()),
+    };
+    let count = decoder.u64()?;
+    let mut fields = Vec::new();
+
+    for _ in 0..count {
+        let name = decoder.str()?;
+        let value = match decoder.byte()? {
+            STR => FieldValue::Str(decoder.str()?),
+            I64 => {
+                let value = decoder.u64()?;
+                FieldValue::I64((value >> 1) as i64 ^ -((value & 1) as i64))
+            }
+            U64 => FieldValue::U64(decoder.u64()?),
+            BOOL => FieldValue::Bool(decoder.byte()? != 0),
+            _ => return Err(invalid_record()),
+        };
+
+        fields.push((name, value));
+    }
+
+    if !decoder.0.is_empty() {
+        return Err(invalid_record());
+    }
+
+    Ok(Record {
+        time: UNIX_EPOCH + since_epoch,
+        level,
+        target,
+        message,
+        file,
+        line,
+        fields,
+    })
+}
+
+fn invalid_record() -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, "invalid binary log record")
+}
+
+struct Decoder<'a>(&'a [u8]);
+
+impl Decoder<'_> {
+    fn byte(&mut self) -> std::io::Result<u8> {
+        let (&byte, rest) = self.0.split_first().ok_or_else(invalid_record)?;
+
+        self.0 = rest;
+        Ok(byte)
+    }
+
+    fn u64(&mut self) -> std::io::Result<u64> {
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+
+            value |= u64::from(byte & 0x7f) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(invalid_record())
+    }
+
+    fn str(&mut self) -> std::io::Result<String> {
+        let len = usize::try_from(self.u64()?).map_err(|_| invalid_record())?;
+
+        if len > self.0.len() {
+            return Err(invalid_record());
+        }
+
+        let (value, rest) = self.0.split_at(len);
+
+        self.0 = rest;
+        String::from_utf8(value.to_vec()).map_err(|_| invalid_record())
+    }
+}
+
+// The message and the fields of an event, the values which are not integers or booleans as strings
+#[derive(Default)]
+struct BinaryFields {
+    message: String,
+    fields: Vec<(&'static str, FieldValue)>,
+}
+
+impl Visit for BinaryFields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.push((field.name(), FieldValue::I64(value)));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.push((field.name(), FieldValue::U64(value)));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.push((field.name(), FieldValue::Bool(value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields
+                .push((field.name(), FieldValue::Str(value.to_string())));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        let value = format!("{value:?}");
+
+        if field.name() == "message" {
+            self.message = value;
+        } else {
+            self.fields.push((field.name(), FieldValue::Str(value)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_log_dir, with_env};
+    use crate::{LogFormat, ManualClock, Rotation, TracingInit};
+
+    // The binary log file of a few events, written through the builder
+    fn write_log(name: &str) -> std::path::PathBuf {
+        let log_dir = test_log_dir(name);
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .log_file_format(LogFormat::Binary)
+                .level(Level::DEBUG)
+                .with_clock(ManualClock::new(
+                    UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+                ))
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::info!(target: "app::db", "connected");
+            tracing::warn!(
+                user = "bob",
+                attempts = 3,
+                delta = -2,
+                ok = false,
+                ratio = 0.5,
+                "login failed"
+            );
+            tracing::debug!(target: "app", path = "a\nb", "multi\nline");
+        });
+        drop(guard);
+
+        log_dir.join("App.log")
+    }
+
+    fn record_len(record: &[u8]) -> usize {
+        u32::from_le_bytes(record[1..HEADER_LEN].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn test_binlog_round_trip() {
+        let path = write_log("binlog-round-trip");
+        let mut reader = Reader::open(&path).unwrap();
+        let records = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert!(!reader.truncated());
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[1],
+            Record {
+                time: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+                level: Level::WARN,
+                target: String::from("tracing_init::binlog::tests"),
+                message: String::from("login failed"),
+                file: None,
+                line: None,
+                fields: vec![
+                    (String::from("user"), FieldValue::from("bob")),
+                    (String::from("attempts"), FieldValue::I64(3)),
+                    (String::from("delta"), FieldValue::I64(-2)),
+                    (String::from("ok"), FieldValue::Bool(false)),
+                    (String::from("ratio"), FieldValue::from("0.5")),
+                ],
+            }
+        );
+
+        let mut text = Vec::new();
+        decode_to_text(Reader::open(&path).unwrap(), &mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "\
+2023-11-14T22:13:20.123Z  INFO app::db: connected
+2023-11-14T22:13:20.123Z  WARN tracing_init::binlog::tests: login failed user=\"bob\" attempts=3 delta=-2 ok=false ratio=\"0.5\"
+2023-11-14T22:13:20.123Z DEBUG app: multi\\nline path=\"a\\nb\"
+"
+        );
+    }
+
+    #[test]
+    fn test_binlog_truncated() {
+        let content = std::fs::read(write_log("binlog-truncated")).unwrap();
+
+        let mut last = 0;
+        while last + HEADER_LEN + record_len(&content[last..]) < content.len() {
+            last += HEADER_LEN + record_len(&content[last..]);
+        }
+
+        // Cut in the content of the last record, then in its header
+        for len in [content.len() - 3, last + 3] {
+            let mut reader = Reader::new(&content[..len]);
+            let records = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[1].message, "login failed");
+            assert!(reader.truncated());
+        }
+
+        // A corrupt record is reported, the following ones are read
+        let mut corrupt = content.clone();
+        // The level, after the 9 bytes of the timestamp
+        corrupt[HEADER_LEN + 9] = 9;
+        let records = Reader::new(corrupt.as_slice()).collect::<Vec<_>>();
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[0].as_ref().unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        assert_eq!(records[2].as_ref().unwrap().message, "multi\nline");
+
+        // The second record cut short by a crash, anywhere in it, the next run appended its records after it
+        let second = HEADER_LEN + record_len(&content);
+        for cut in second + 1..second + HEADER_LEN + record_len(&content[second..]) {
+            let mut appended = content[..cut].to_vec();
+            appended.extend_from_slice(&content);
+            let mut reader = Reader::new(appended.as_slice());
+            let messages = reader
+                .by_ref()
+                .map(|record| record.unwrap().message)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                messages,
+                ["connected", "connected", "login failed", "multi\nline"]
+            );
+            assert!(reader.truncated());
+        }
+
+        // Garbage between the records is reported once
+        let mut garbage = content[..second].to_vec();
+        garbage.extend_from_slice(b"garbage");
+        garbage.extend_from_slice(&content[second..]);
+        let records = Reader::new(garbage.as_slice()).collect::<Vec<_>>();
+        assert_eq!(records.len(), 4);
+        assert!(records[1].is_err());
+        assert_eq!(records[2].as_ref().unwrap().message, "login failed");
+
+        // Not a binary log file
+        let mut reader = Reader::new("text log\n".as_bytes());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,164 @@
+use std::io::{ErrorKind, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use tracing::dispatcher::WeakDispatch;
+use tracing_subscriber::fmt::MakeWriter;
+
+// The console writer, which stops writing once the reader of the output is gone (e.g. piped into `head` which
+// exited): the broken pipe error is swallowed rather than reported by the fmt layer for every record, and logged once
+// as a WARN event for the other destinations. When strict, the errors are passed on as they are
+pub(crate) struct ConsoleWriter<W> {
+    make_writer: W,
+    strict: bool,
+    closed: AtomicBool,
+    // The dispatch the closing is logged to, the global default one if not set
+    dispatch: Arc<OnceLock<WeakDispatch>>,
+}
+
+impl<W> ConsoleWriter<W> {
+    pub(crate) fn new(
+        make_writer: W,
+        strict: bool,
+        dispatch: Arc<OnceLock<WeakDispatch>>,
+    ) -> ConsoleWriter<W> {
+        ConsoleWriter {
+            make_writer,
+            strict,
+            closed: AtomicBool::new(false),
+            dispatch,
+        }
+    }
+
+    // Stop writing, the first time the event is logged. The console discards it
+    fn close(&self) {
+        if self.closed.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        // Logged by a thread: the console is closed while an event is dispatched
+        let dispatch = self.dispatch.clone();
+        let _ = std::thread::Builder::new()
+            .name(String::from("tracing-init-console"))
+            .spawn(move || {
+                let report = || {
+                    tracing::warn!(
+                        target: "tracing_init",
+                        "the console output was closed (broken pipe), the records are no longer written to the console"
+                    )
+                };
+
+                match dispatch.get().map(WeakDispatch::upgrade) {
+                    Some(Some(dispatch)) => tracing::dispatcher::with_default(&dispatch, report),
+                    // The subscriber was dropped
+                    Some(None) => {}
+                    None => report(),
+                }
+            });
+    }
+}
+
+impl<'a, W> MakeWriter<'a> for ConsoleWriter<W>
+where
+    W: MakeWriter<'a> + 'a,
+{
+    type Writer = PipeWriter<'a, W>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        PipeWriter {
+            writer: (!self.closed.load(Ordering::Relaxed)).then(|| self.make_writer.make_writer()),
+            console: self,
+        }
+    }
+}
+
+// Writes a record to the console, nothing once it was closed
+pub(crate) struct PipeWriter<'a, W: MakeWriter<'a>> {
+    writer: Option<W::Writer>,
+    console: &'a ConsoleWriter<W>,
+}
+
+impl<'a, W> PipeWriter<'a, W>
+where
+    W: MakeWriter<'a>,
+{
+    fn handle<T>(
+        &mut self,
+        f: impl FnOnce(&mut W::Writer) -> std::io::Result<T>,
+        discarded: T,
+    ) -> std::io::Result<T> {
+        let Some(ref mut writer) = self.writer else {
+            return Ok(discarded);
+        };
+
+        match f(writer) {
+            Err(error) if error.kind() == ErrorKind::BrokenPipe && !self.console.strict => {
+                self.writer = None;
+                self.console.close();
+                Ok(discarded)
+            }
+            result => result,
+        }
+    }
+}
+
+impl<'a, W> Write for PipeWriter<'a, W>
+where
+    W: MakeWriter<'a>,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.handle(|writer| writer.write(buf), buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.handle(|writer| writer.flush(), ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    // Fails with a broken pipe, counting the writes
+    #[derive(Default)]
+    struct ClosedPipe(AtomicUsize);
+
+    impl Write for &ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Err(ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_broken_pipe_discarded() {
+        let pipe = ClosedPipe::default();
+        let console = ConsoleWriter::new(|| &pipe, false, Default::default());
+
+        assert_eq!(console.make_writer().write(b"first\n").unwrap(), 6);
+        console.make_writer().write_all(b"second\n").unwrap();
+        console.make_writer().flush().unwrap();
+
+        // Not written to once closed
+        assert_eq!(pipe.0.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_broken_pipe_strict() {
+        let pipe = ClosedPipe::default();
+        let console = ConsoleWriter::new(|| &pipe, true, Default::default());
+
+        for _ in 0..2 {
+            let error = console.make_writer().write_all(b"record\n").unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+        }
+
+        assert_eq!(pipe.0.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// A log record captured by [`TracingInit::capture_for_test`](crate::TracingInit::capture_for_test)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CapturedRecord {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    /// The fields of the event other than the message, in the order they were recorded
+    pub fields: Vec<(String, String)>,
+    /// The names of the spans the event was emitted in, from the root span to the innermost one
+    pub spans: Vec<String>,
+}
+
+impl CapturedRecord {
+    /// The value of a field of the event
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The log records captured by [`TracingInit::capture_for_test`](crate::TracingInit::capture_for_test)
+///
+/// Cloning the handle shares the records.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedLogs {
+    records: Arc<Mutex<Vec<CapturedRecord>>>,
+}
+
+impl CapturedLogs {
+    /// The records captured so far, in the order they were logged
+    pub fn records(&self) -> Vec<CapturedRecord> {
+        self.lock().clone()
+    }
+
+    /// The first record for which the predicate returns true
+    pub fn find(&self, predicate: impl Fn(&CapturedRecord) -> bool) -> Option<CapturedRecord> {
+        self.lock().iter().find(|record| predicate(record)).cloned()
+    }
+
+    /// Panic unless a record of this level with a message containing the substring was captured
+    ///
+    /// # Panics
+    /// When no such record was captured, the message lists the captured records
+    #[track_caller]
+    pub fn assert_logged(&self, level: Level, substring: &str) {
+        let records = self.lock();
+
+        if !records
+            .iter()
+            .any(|record| record.level == level && record.message.contains(substring))
+        {
+            let captured = records
+                .iter()
+                .map(|record| format!("\n  {} {}: {}", record.level, record.target, record.message))
+                .collect::<String>();
+
+            panic!("no {level} record containing {substring:?} was logged, captured records:{captured}");
+        }
+    }
+
+    /// Forget the records captured so far
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<CapturedRecord>> {
+        self.records.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Records the events passing the filter into the shared buffer of the handle
+pub(crate) struct CaptureLayer {
+    logs: CapturedLogs,
+}
+
+impl CaptureLayer {
+    pub(crate) fn new(logs: CapturedLogs) -> CaptureLayer {
+        CaptureLayer { logs }
+    }
+}
+
+impl<S> Layer<S> for CaptureLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = RecordVisitor::default();
+
+        event.record(&mut visitor);
+
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| {
+                scope
+                    .from_root()
+                    .map(|span| span.name().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        self.logs.lock().push(CapturedRecord {
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            message: visitor.message,
+            fields: visitor.fields,
+            spans,
+        });
+    }
+}
+
+#[derive(Default)]
+struct RecordVisitor {
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl RecordVisitor {
+    fn add(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = value;
+        } else {
+            self.fields.push((field.name().to_string(), value));
+        }
+    }
+}
+
+impl Visit for RecordVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.add(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.add(field, format!("{value:?}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::with_env;
+    use crate::TracingInit;
+    use tracing::Level;
+
+    #[test]
+    fn test_capture_for_test() {
+        let mut builder = TracingInit::builder("test");
+        let logs = builder.capture_for_test();
+        let (dispatch, guard) = with_env(&[], || builder.into_dispatch().unwrap());
+
+        // Capturing counts as a destination, the console is not used as a fallback
+        assert!(!guard.config().fallback_console());
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::info_span!("request", id = 7).in_scope(|| {
+                tracing::info_span!("handler").in_scope(|| {
+                    tracing::warn!(user = "bob", attempts = 3, "login failed");
+                });
+            });
+            tracing::debug!("filtered out");
+        });
+
+        logs.assert_logged(Level::WARN, "login failed");
+
+        let record = logs.find(|record| record.level == Level::WARN).unwrap();
+        assert_eq!(record.target, module_path!());
+        assert_eq!(record.message, "login failed");
+        assert_eq!(record.field("user"), Some("bob"));
+        assert_eq!(record.field("attempts"), Some("3"));
+        assert_eq!(record.spans, ["request", "handler"]);
+        assert!(logs
+            .find(|record| record.message == "filtered out")
+            .is_none());
+
+        logs.clear();
+        assert!(logs.records().is_empty());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "no ERROR record containing \"failed\" was logged, captured records:\n  WARN"
+    )]
+    fn test_assert_logged() {
+        let mut builder = TracingInit::builder("test");
+        let logs = builder.capture_for_test();
+        let (dispatch, _guard) = with_env(&[], || builder.into_dispatch().unwrap());
+
+        tracing::dispatcher::with_default(&dispatch, || tracing::warn!("login failed"));
+        logs.assert_logged(Level::ERROR, "failed");
+    }
+}
@@ -0,0 +1,176 @@
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+
+use crate::{TracingInit, TracingInitError};
+
+// The recognized flags and the syntax of their values
+const FLAGS: [(&str, &str); 5] = [
+    ("--log-level", "trace, debug, info, warn or error"),
+    (
+        "--log-destination",
+        "a combination of c (console), f (log file) and s (log server)",
+    ),
+    ("--log-file", "the path of the log file directory"),
+    ("--log-filter", "filter directives, same syntax as RUST_LOG"),
+    ("--log-server", "<host>:<port>"),
+];
+
+// Apply the --log-* flags found in the arguments, the other arguments are ignored. The flags are parsed up to a --
+// argument
+pub(crate) fn apply_flags(
+    builder: &mut TracingInit,
+    args: impl Iterator<Item = String>,
+) -> Result<(), TracingInitError> {
+    let mut args = args.peekable();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+
+        let Some((flag, expected)) = FLAGS.into_iter().find(|(flag, _)| *flag == name) else {
+            continue;
+        };
+
+        // The --flag value form, a following flag is not taken as the value
+        let value = match value {
+            Some(value) => value,
+            None => args
+                .next_if(|next| !next.starts_with("--"))
+                .unwrap_or_default(),
+        };
+
+        let invalid = || TracingInitError::InvalidCliArg {
+            flag: flag.to_string(),
+            value: value.clone(),
+            expected,
+        };
+
+        if value.is_empty() {
+            return Err(invalid());
+        }
+
+        match flag {
+            "--log-level" => {
+                builder.level(value.parse::<Level>().map_err(|_| invalid())?);
+            }
+            "--log-destination" => {
+                if !value.chars().all(|c| "cfs".contains(c)) {
+                    return Err(invalid());
+                }
+
+                builder.enable_console = Some(value.contains('c'));
+                builder.enable_log_file = Some(value.contains('f'));
+                builder.enable_log_server = Some(value.contains('s'));
+            }
+            "--log-file" => builder.log_file_path = Some(value),
+            "--log-filter" => {
+                EnvFilter::try_new(&value).map_err(|_| invalid())?;
+                builder.filter(&value);
+            }
+            "--log-server" => match value.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                    builder.log_server_address = Some(value)
+                }
+                _ => return Err(invalid()),
+            },
+            _ => unreachable!("every flag is handled"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "file")]
+    use crate::tests::with_env;
+    use crate::{TracingInit, TracingInitError};
+    use tracing::Level;
+
+    fn args(args: &str) -> impl Iterator<Item = String> + '_ {
+        args.split_whitespace().map(String::from)
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_parse_cli_args() {
+        let config = with_env(&[("LOG_LEVEL", "warn"), ("LOG_DESTINATION", "s")], || {
+            TracingInit::builder("App")
+                .parse_cli_args(args(
+                    "tool -v --log-level=debug input.txt --log-destination cf --log-file /tmp/logs --log-filter=hyper=warn,info --count=3",
+                ))
+                .unwrap()
+                .resolve()
+                .unwrap()
+        });
+
+        assert_eq!(config.level(), Level::DEBUG);
+        assert!(config.log_to_console());
+        assert!(config.log_to_file());
+        assert!(!config.log_to_server());
+        assert_eq!(config.filter(), "hyper=warn,info");
+        assert_eq!(config.log_file_path(), std::path::Path::new("/tmp/logs"));
+    }
+
+    #[test]
+    fn test_parse_cli_args_precedence() {
+        // The last value set wins, the flags override the builder values set before parsing them
+        let config = TracingInit::builder("App")
+            .level(Level::INFO)
+            .parse_cli_args(args("--log-level trace"))
+            .unwrap()
+            .resolve()
+            .unwrap();
+        assert_eq!(config.level(), Level::TRACE);
+
+        let config = TracingInit::builder("App")
+            .parse_cli_args(args("--log-level trace"))
+            .unwrap()
+            .level(Level::INFO)
+            .resolve()
+            .unwrap();
+        assert_eq!(config.level(), Level::INFO);
+
+        // The arguments after -- are not parsed
+        let config = TracingInit::builder("App")
+            .parse_cli_args(args("--log-level=error -- --log-level=trace"))
+            .unwrap()
+            .resolve()
+            .unwrap();
+        assert_eq!(config.level(), Level::ERROR);
+    }
+
+    #[test]
+    fn test_parse_cli_args_invalid() {
+        let invalid =
+            |arguments: &str| match TracingInit::builder("App").parse_cli_args(args(arguments)) {
+                Err(e @ TracingInitError::InvalidCliArg { .. }) => e.to_string(),
+                other => panic!("{arguments}: {other:?}"),
+            };
+
+        assert_eq!(
+            invalid("--log-level=loud"),
+            "invalid value 'loud' for --log-level (expected trace, debug, info, warn or error)"
+        );
+        assert_eq!(
+            invalid("--log-destination cx"),
+            "invalid value 'cx' for --log-destination (expected a combination of c (console), f (log file) and s (log server))"
+        );
+        assert_eq!(
+            invalid("--log-server logs"),
+            "invalid value 'logs' for --log-server (expected <host>:<port>)"
+        );
+        assert!(invalid("--log-filter=app=loud")
+            .starts_with("invalid value 'app=loud' for --log-filter"));
+        assert!(
+            invalid("--log-level --log-file=/tmp").starts_with("invalid value '' for --log-level")
+        );
+        assert!(invalid("--log-file").starts_with("invalid value '' for --log-file"));
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+
+use crate::preinit::format_time;
+
+/// The source of the time read by the subscriber, set by [`TracingInit::with_clock`](crate::TracingInit::with_clock)
+///
+/// It gives the timestamps of the console and log file records, and the time measured by the span durations
+/// (see [`log_span_durations`](crate::TracingInit::log_span_durations)) and the rate limit windows (see
+/// [`rate_limit`](crate::TracingInit::rate_limit)).
+pub trait Clock: Debug + Send + Sync + 'static {
+    /// The wall clock time, for the timestamps
+    fn now(&self) -> SystemTime;
+
+    /// The monotonic time, for the durations
+    fn instant(&self) -> Instant;
+}
+
+/// The system clock, used unless another clock is set
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock which only moves when advanced, for deterministic timestamps and durations in tests
+///
+/// The clones share the time.
+///
+/// # Example
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use tracing_init::{ManualClock, TracingInit};
+///
+/// let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_792_108_800));
+/// let mut builder = TracingInit::builder("App");
+///
+/// builder.with_clock(clock.clone());
+/// // Every record is logged at 2026-10-16T00:00:00.000Z until the clock is advanced
+/// clock.advance(Duration::from_secs(60));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock(Arc<ManualTime>);
+
+#[derive(Debug)]
+struct ManualTime {
+    start: SystemTime,
+    start_instant: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// A clock showing the start time
+    pub fn new(start: SystemTime) -> ManualClock {
+        ManualClock(Arc::new(ManualTime {
+            start,
+            start_instant: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }))
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed() += duration;
+    }
+
+    fn elapsed(&self) -> std::sync::MutexGuard<'_, Duration> {
+        self.0
+            .elapsed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        self.0.start + *self.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        self.0.start_instant + *self.elapsed()
+    }
+}
+
+// The clock of the subscriber, the system clock is read directly when no clock is set
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ClockSource(Option<Arc<dyn Clock>>);
+
+impl ClockSource {
+    pub(crate) fn new(clock: Option<Arc<dyn Clock>>) -> ClockSource {
+        ClockSource(clock)
+    }
+
+    pub(crate) fn now(&self) -> SystemTime {
+        match self.0 {
+            None => SystemTime::now(),
+            Some(ref clock) => clock.now(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn instant(&self) -> Instant {
+        match self.0 {
+            None => Instant::now(),
+            Some(ref clock) => clock.instant(),
+        }
+    }
+}
+
+// The timer of the fmt layers, the timestamps of the system clock are formatted by tracing-subscriber as before
+impl FormatTime for ClockSource {
+    fn format_time(&self, w: &mut Writer<'_>) -> std::fmt::Result {
+        match self.0 {
+            None => tracing_subscriber::fmt::time::SystemTime.format_time(w),
+            Some(ref clock) => write!(w, "{}", format_time(clock.now())),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "file"))]
+mod tests {
+    use super::*;
+    use crate::tests::{test_log_dir, with_env};
+    use crate::{Rotation, TracingInit};
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_792_108_800));
+        let log_dir = test_log_dir("manual-clock");
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .log_span_durations(tracing::Level::INFO, None)
+                .with_clock(clock.clone())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::info!("started");
+                clock.advance(Duration::from_millis(1500));
+            });
+            tracing::info!("done");
+        });
+        drop(guard);
+
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        let lines = log
+            .lines()
+            .filter(|line| !line.contains("TracingGuard"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            [
+                "2026-10-16T00:00:00.000Z  INFO request: tracing_init::clock::tests: started",
+                "2026-10-16T00:00:01.500Z  INFO tracing_init::span_timing: request closed after 1500ms span=\"request\" span_target=\"tracing_init::clock::tests\" duration_ms=1500.0 busy_ms=1500.0 idle_ms=0.0",
+                "2026-10-16T00:00:01.500Z  INFO tracing_init::clock::tests: done",
+            ]
+        );
+    }
+}
@@ -0,0 +1,465 @@
+use std::fmt::Debug;
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::clock::ClockSource;
+use crate::preinit::format_time;
+use crate::{json_string, FieldValue};
+
+// The version of the Elastic Common Schema the ECS records follow
+const ECS_VERSION: &str = "1.6.0";
+
+// The provider whose field names are used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Cloud {
+    Gcp,
+    Aws,
+    // The Elastic Common Schema of Elasticsearch
+    Ecs,
+}
+
+// Formats each event as a single line JSON object with the field names recognized by the provider's log service
+pub(crate) struct CloudJson {
+    cloud: Cloud,
+    // The app name as a JSON string
+    app_name: String,
+    // The app name (but for ECS, where it is the service name) and the global fields, as JSON values
+    labels: Vec<(String, String)>,
+    source_location: bool,
+    clock: ClockSource,
+}
+
+impl CloudJson {
+    pub(crate) fn new(
+        cloud: Cloud,
+        app_name: &str,
+        global_fields: &[(String, FieldValue)],
+        source_location: bool,
+        clock: ClockSource,
+    ) -> CloudJson {
+        let app = match cloud {
+            Cloud::Gcp => Some("app"),
+            Cloud::Aws => Some("service"),
+            Cloud::Ecs => None,
+        };
+        let labels = app
+            .map(|app| (app.to_string(), json_string(app_name)))
+            .into_iter()
+            .chain(global_fields.iter().map(|(name, value)| {
+                let value = match (cloud, value) {
+                    // The GCP and ECS labels are strings
+                    (Cloud::Aws, FieldValue::I64(_) | FieldValue::U64(_) | FieldValue::Bool(_)) => {
+                        value.to_string()
+                    }
+                    _ => json_string(&value.to_string()),
+                };
+
+                (name.clone(), value)
+            }))
+            .collect();
+
+        CloudJson {
+            cloud,
+            app_name: json_string(app_name),
+            labels,
+            source_location,
+            clock,
+        }
+    }
+}
+
+// The GCP LogSeverity of a level, there is no TRACE severity
+fn gcp_severity(level: &Level) -> &'static str {
+    match *level {
+        Level::TRACE | Level::DEBUG => "DEBUG",
+        Level::INFO => "INFO",
+        Level::WARN => "WARNING",
+        _ => "ERROR",
+    }
+}
+
+// The level names of the AWS Lambda JSON log format
+fn aws_level(level: &Level) -> &'static str {
+    match *level {
+        Level::TRACE => "TRACE",
+        Level::DEBUG => "DEBUG",
+        Level::INFO => "INFO",
+        Level::WARN => "WARN",
+        _ => "ERROR",
+    }
+}
+
+// A JSON value as a JSON string, for the fields whose values are strings only
+fn json_keyword(value: &str) -> String {
+    if value.starts_with('"') {
+        value.to_string()
+    } else {
+        json_string(value)
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for CloudJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let metadata = event.metadata();
+        let mut fields = JsonFields::default();
+
+        event.record(&mut fields);
+
+        let message = json_string(fields.message.as_deref().unwrap_or_default());
+        let time = json_string(&format_time(self.clock.now()));
+        let labels = || {
+            self.labels
+                .iter()
+                .map(|(name, value)| format!("{}:{value}", json_string(name)))
+                .collect::<Vec<_>>()
+        };
+        let mut members = Vec::new();
+
+        match self.cloud {
+            Cloud::Gcp => {
+                members.push(format!(
+                    r#""severity":"{}""#,
+                    gcp_severity(metadata.level())
+                ));
+                members.push(format!(r#""message":{message}"#));
+                members.push(format!(r#""time":{time}"#));
+                members.push(format!(
+                    r#""logging.googleapis.com/labels":{{{}}}"#,
+                    labels().join(",")
+                ));
+
+                if let (true, Some(file), Some(line)) =
+                    (self.source_location, metadata.file(), metadata.line())
+                {
+                    members.push(format!(
+                        r#""logging.googleapis.com/sourceLocation":{{"file":{},"line":"{line}","function":{}}}"#,
+                        json_string(file),
+                        json_string(metadata.module_path().unwrap_or_default())
+                    ));
+                }
+            }
+            Cloud::Aws => {
+                members.push(format!(r#""timestamp":{time}"#));
+                members.push(format!(r#""level":"{}""#, aws_level(metadata.level())));
+                members.push(format!(r#""message":{message}"#));
+                members.extend(labels());
+
+                if let (true, Some(file), Some(line)) =
+                    (self.source_location, metadata.file(), metadata.line())
+                {
+                    members.push(format!(r#""file":{},"line":{line}"#, json_string(file)));
+                }
+            }
+            Cloud::Ecs => return self.format_ecs(ctx, writer, event, fields, message, time),
+        }
+
+        members.push(format!(r#""target":{}"#, json_string(metadata.target())));
+
+        if let Some(span) = ctx.lookup_current() {
+            members.push(format!(r#""span":{}"#, json_string(span.name())));
+        }
+
+        members.extend(
+            fields
+                .fields
+                .iter()
+                .map(|(name, value)| format!("{}:{value}", json_string(name))),
+        );
+
+        writeln!(writer, "{{{}}}", members.join(","))
+    }
+}
+
+impl CloudJson {
+    // The ECS fields, with the labels holding the global fields, the span and the event's fields. The `error` field
+    // of an error event is its `error.message`
+    fn format_ecs<S, N>(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+        mut fields: JsonFields,
+        message: String,
+        time: String,
+    ) -> std::fmt::Result
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static,
+    {
+        let metadata = event.metadata();
+        let mut members = vec![
+            format!(r#""@timestamp":{time}"#),
+            format!(
+                r#""log.level":"{}""#,
+                metadata.level().as_str().to_ascii_lowercase()
+            ),
+            format!(r#""message":{message}"#),
+            format!(r#""ecs.version":"{ECS_VERSION}""#),
+            format!(r#""service.name":{}"#, self.app_name),
+            format!(r#""log.logger":{}"#, json_string(metadata.target())),
+        ];
+
+        if let (true, Some(file), Some(line)) =
+            (self.source_location, metadata.file(), metadata.line())
+        {
+            members.push(format!(
+                r#""log.origin":{{"file":{{"name":{},"line":{line}}}}}"#,
+                json_string(file)
+            ));
+        }
+
+        if *metadata.level() == Level::ERROR {
+            if let Some(i) = fields.fields.iter().position(|(name, _)| *name == "error") {
+                let (_, error) = fields.fields.remove(i);
+
+                members.push(format!(r#""error":{{"message":{}}}"#, json_keyword(&error)));
+            }
+        }
+
+        let span = ctx
+            .lookup_current()
+            .map(|span| (String::from("span"), json_string(span.name())));
+        let labels = self
+            .labels
+            .iter()
+            .cloned()
+            .chain(span)
+            .chain(
+                fields
+                    .fields
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), json_keyword(&value))),
+            )
+            .map(|(name, value)| format!("{}:{value}", json_string(&name)))
+            .collect::<Vec<_>>();
+
+        if !labels.is_empty() {
+            members.push(format!(r#""labels":{{{}}}"#, labels.join(",")));
+        }
+
+        writeln!(writer, "{{{}}}", members.join(","))
+    }
+}
+
+// The fields of an event, the values as JSON values
+#[derive(Default)]
+struct JsonFields {
+    message: Option<String>,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Visit for JsonFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if value.is_finite() {
+            self.fields.push((field.name(), value.to_string()));
+        } else {
+            self.record_str(field, &value.to_string());
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.push((field.name(), value.to_string()));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.push((field.name(), value.to_string()));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.push((field.name(), value.to_string()));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.fields.push((field.name(), json_string(value)));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        let value = format!("{value:?}");
+
+        if field.name() == "message" {
+            self.message = Some(value);
+        } else {
+            self.fields.push((field.name(), json_string(&value)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::layers::{self, ConsoleOptions};
+    use crate::tests::MemoryWriter;
+    use crate::{FieldValue, LogFormat, ManualClock};
+
+    // An event of each level, then one with fields in a span
+    fn log_each_level() {
+        tracing::trace!("trace event");
+        tracing::debug!("debug event");
+        tracing::info!("info event");
+        tracing::warn!("warn event");
+        tracing::error!("error event");
+
+        tracing::info_span!("request").in_scope(|| {
+            tracing::warn!(user = "bob", attempts = 3, ok = false, "login \"failed\"");
+        });
+    }
+
+    // An event with fields, an error event with an error field in a span, and a warning with an error field
+    fn log_ecs_events() {
+        tracing::info!(user = "bob", attempts = 3, ok = false, "logged in");
+
+        tracing::info_span!("request").in_scope(|| {
+            tracing::error!(
+                error = "connection refused",
+                retries = 2,
+                "cannot reach the database"
+            );
+        });
+
+        tracing::warn!(error = "timeout", "slow query");
+    }
+
+    fn global_fields() -> Vec<(String, FieldValue)> {
+        vec![
+            (String::from("region"), FieldValue::from("eu-west-1")),
+            (String::from("shard"), FieldValue::from(7)),
+        ]
+    }
+
+    fn clock() -> Arc<ManualClock> {
+        Arc::new(ManualClock::new(
+            UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+        ))
+    }
+
+    fn format_events(format: LogFormat, events: fn()) -> String {
+        let console = MemoryWriter::default();
+        let options = ConsoleOptions {
+            format,
+            app_name: String::from("App"),
+            global_fields: global_fields(),
+            clock: Some(clock()),
+            ..ConsoleOptions::default()
+        };
+        let subscriber = tracing_subscriber::registry()
+            .with(layers::console_with_writer(&options, console.clone()))
+            .with(tracing_subscriber::filter::LevelFilter::TRACE);
+
+        tracing::subscriber::with_default(subscriber, events);
+        console.contents()
+    }
+
+    fn format(format: LogFormat) -> String {
+        format_events(format, log_each_level)
+    }
+
+    #[test]
+    fn test_gcp_json() {
+        assert_eq!(
+            format(LogFormat::GcpJson),
+            include_str!("../tests/golden/gcp_json.jsonl")
+        );
+    }
+
+    #[test]
+    fn test_aws_json() {
+        assert_eq!(
+            format(LogFormat::AwsJson),
+            include_str!("../tests/golden/aws_json.jsonl")
+        );
+    }
+
+    #[test]
+    fn test_ecs() {
+        assert_eq!(
+            format_events(LogFormat::Ecs, log_ecs_events),
+            include_str!("../tests/golden/ecs.jsonl")
+        );
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_ecs_log_file() {
+        let log_dir = crate::tests::test_log_dir("ecs");
+        let options = layers::FileOptions {
+            path: log_dir.clone(),
+            prefix: String::from("App"),
+            rotation: crate::Rotation::Never,
+            format: LogFormat::Ecs,
+            app_name: String::from("App"),
+            global_fields: global_fields(),
+            clock: Some(clock()),
+            ..layers::FileOptions::default()
+        };
+        let (layer, _guard, _dispatch) = layers::file_layer(&options).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        tracing::subscriber::with_default(subscriber, log_ecs_events);
+
+        assert_eq!(
+            std::fs::read_to_string(log_dir.join("App.log")).unwrap(),
+            include_str!("../tests/golden/ecs.jsonl")
+        );
+    }
+
+    #[test]
+    fn test_ecs_source_location() {
+        let console = MemoryWriter::default();
+        let options = ConsoleOptions {
+            format: LogFormat::Ecs,
+            source_location: true,
+            ..ConsoleOptions::default()
+        };
+        let subscriber = tracing_subscriber::registry()
+            .with(layers::console_with_writer(&options, console.clone()));
+
+        tracing::subscriber::with_default(subscriber, || tracing::info!("located"));
+
+        let record: serde_json::Value = serde_json::from_str(&console.contents()).unwrap();
+        let file = &record["log.origin"]["file"];
+        assert_eq!(file["name"], "src/cloud_json.rs");
+        assert!(file["line"].as_u64().is_some());
+        assert!(record.get("labels").is_none());
+    }
+
+    #[test]
+    fn test_cloud_json_source_location() {
+        let console = MemoryWriter::default();
+        let options = ConsoleOptions {
+            format: LogFormat::GcpJson,
+            source_location: true,
+            ..ConsoleOptions::default()
+        };
+        let subscriber = tracing_subscriber::registry()
+            .with(layers::console_with_writer(&options, console.clone()));
+
+        tracing::subscriber::with_default(subscriber, || tracing::info!("located"));
+
+        let record: serde_json::Value = serde_json::from_str(&console.contents()).unwrap();
+        let location = &record["logging.googleapis.com/sourceLocation"];
+        assert_eq!(location["file"], "src/cloud_json.rs");
+        assert_eq!(location["function"], "tracing_init::cloud_json::tests");
+        assert!(location["line"].as_str().unwrap().parse::<u32>().is_ok());
+    }
+}
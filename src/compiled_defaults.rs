@@ -0,0 +1,185 @@
+use crate::{is_valid_setting, TracingInitError};
+
+// The compile-time variables and the environment variables whose values they give by default
+const VARS: &[(&str, &str)] = &[
+    ("TRACING_INIT_DEFAULT_DESTINATION", "LOG_DESTINATION"),
+    ("TRACING_INIT_DEFAULT_LEVEL", "LOG_LEVEL"),
+    ("TRACING_INIT_DEFAULT_FILE_PATH", "LOG_FILE_PATH"),
+];
+
+/// Default settings baked into the program when it is built, see [`compiled_defaults!`](crate::compiled_defaults) and
+/// [`TracingInit::builder_with_defaults`](crate::TracingInit::builder_with_defaults)
+///
+/// Each value is used like the matching environment variable when neither the builder, the environment nor the config
+/// file sets it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompiledDefaults {
+    /// The destinations, like LOG_DESTINATION (e.g. `cf` or `c:info,f:debug`)
+    pub destination: Option<&'static str>,
+    /// The default level, like LOG_LEVEL (e.g. `debug`)
+    pub level: Option<&'static str>,
+    /// The log file directory, like LOG_FILE_PATH
+    pub file_path: Option<&'static str>,
+}
+
+impl CompiledDefaults {
+    fn values(&self) -> [Option<&'static str>; 3] {
+        [self.destination, self.level, self.file_path]
+    }
+
+    // The default value of an environment variable
+    pub(crate) fn setting(&self, name: &str) -> Option<String> {
+        VARS.iter()
+            .zip(self.values())
+            .find(|((_, env_var), _)| *env_var == name)
+            .and_then(|(_, value)| value.map(String::from))
+    }
+
+    // The values which cannot be parsed, checked whether they are used or not: a build flavor must not ship with a
+    // default that breaks once the environment stops overriding it
+    pub(crate) fn problems(&self) -> Vec<TracingInitError> {
+        VARS.iter()
+            .zip(self.values())
+            .filter_map(|(&(name, env_var), value)| match value {
+                Some(value) if !is_valid_setting(env_var, value) => {
+                    Some(TracingInitError::InvalidCompiledDefault {
+                        name,
+                        value: value.to_string(),
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The [`CompiledDefaults`] given by the environment variables set when the calling crate is built
+///
+/// The variables are `TRACING_INIT_DEFAULT_DESTINATION`, `TRACING_INIT_DEFAULT_LEVEL` and
+/// `TRACING_INIT_DEFAULT_FILE_PATH`, read by [`option_env!`] where the macro is invoked: a build flavor sets them, for
+/// example in the `[env]` section of its `.cargo/config.toml`, or on the command line:
+/// ```text
+/// TRACING_INIT_DEFAULT_DESTINATION=f TRACING_INIT_DEFAULT_LEVEL=debug cargo build --release
+/// ```
+///
+/// # Example
+/// ```
+/// use tracing_init::{compiled_defaults, TracingInit};
+///
+/// let _config = TracingInit::builder_with_defaults("firmware", compiled_defaults!());
+/// ```
+///
+#[macro_export]
+macro_rules! compiled_defaults {
+    () => {
+        $crate::CompiledDefaults {
+            destination: option_env!("TRACING_INIT_DEFAULT_DESTINATION"),
+            level: option_env!("TRACING_INIT_DEFAULT_LEVEL"),
+            file_path: option_env!("TRACING_INIT_DEFAULT_FILE_PATH"),
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "file")]
+    use tracing::Level;
+
+    use super::*;
+    use crate::tests::with_env;
+    use crate::TracingInit;
+
+    // Set for the builds of this crate by .cargo/config.toml
+    const DEFAULTS: CompiledDefaults = CompiledDefaults {
+        destination: Some("c:info,f"),
+        level: Some("debug"),
+        file_path: Some("target/compiled-defaults"),
+    };
+
+    #[test]
+    fn test_compiled_defaults_macro() {
+        assert_eq!(crate::compiled_defaults!(), DEFAULTS);
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_compiled_defaults_precedence() {
+        let resolve = |vars: &[(&str, &str)], builder: &mut TracingInit| {
+            with_env(vars, || builder.resolve().unwrap())
+        };
+
+        let config = resolve(
+            &[],
+            &mut TracingInit::builder_with_defaults("App", DEFAULTS),
+        );
+        assert!(config.log_to_console());
+        assert_eq!(config.console_level(), Some(Level::INFO));
+        assert_eq!(config.level(), Level::DEBUG);
+        assert!(config.log_to_file());
+        assert!(config.log_file_path().ends_with("target/compiled-defaults"));
+
+        // The environment variables override them
+        let config = resolve(
+            &[("LOG_DESTINATION", "c"), ("LOG_LEVEL", "warn")],
+            &mut TracingInit::builder_with_defaults("App", DEFAULTS),
+        );
+        assert!(!config.log_to_file());
+        assert_eq!(config.console_level(), None);
+        assert_eq!(config.level(), Level::WARN);
+
+        // And the builder calls override both
+        let config = resolve(
+            &[("LOG_LEVEL", "warn")],
+            TracingInit::builder_with_defaults("App", DEFAULTS)
+                .console_level(Level::WARN)
+                .level(Level::ERROR),
+        );
+        assert!(config.log_to_console());
+        assert_eq!(config.console_level(), Some(Level::WARN));
+        assert_eq!(config.level(), Level::ERROR);
+
+        // Without compiled defaults, the built-in ones
+        let config = resolve(&[], TracingInit::builder("App").allow_silent(true));
+        assert!(!config.log_to_console());
+        assert_eq!(config.level(), Level::INFO);
+    }
+
+    #[test]
+    fn test_invalid_compiled_defaults() {
+        let defaults = CompiledDefaults {
+            destination: Some("cx"),
+            level: Some("loud"),
+            file_path: None,
+        };
+
+        // Reported even though overridden
+        let problems = with_env(&[("LOG_LEVEL", "warn")], || {
+            TracingInit::builder_with_defaults("App", defaults)
+                .log_to_console(true)
+                .validate()
+        });
+        let messages = problems
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            messages,
+            [
+                "invalid value 'cx' for the compile-time default TRACING_INIT_DEFAULT_DESTINATION",
+                "invalid value 'loud' for the compile-time default TRACING_INIT_DEFAULT_LEVEL",
+            ]
+        );
+
+        let error = with_env(&[], || {
+            TracingInit::builder_with_defaults("App", defaults)
+                .init()
+                .err()
+        });
+        assert!(matches!(
+            error,
+            Some(TracingInitError::Multiple(ref problems)) if problems.len() == 2
+        ));
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::{is_valid_setting, parse_log_destination, FilterHandle, TracingInit, TracingInitError};
+
+// How often the watcher checks the config file for changes
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Config file keys and the environment variables they stand for
+const KEYS: &[(&str, &str)] = &[
+    ("destination", "LOG_DESTINATION"),
+    ("profile", "LOG_PROFILE"),
+    ("level", "LOG_LEVEL"),
+    ("filter", "LOG_FILTER"),
+    ("file_path", "LOG_FILE_PATH"),
+    ("file_rotation", "LOG_FILE_ROTATION"),
+    ("file_max_age", "LOG_FILE_MAX_AGE"),
+    ("server", "LOG_SERVER"),
+];
+
+// Keys whose changes can be applied to a running subscriber. The changes of the destination key are applied too when
+// only the levels of the destinations change (e.g. from c:info,f:debug to c:info,f:warn)
+const RELOADABLE_KEYS: &[&str] = &["level", "filter"];
+
+// Read the config file, the settings are keyed by the environment variables they stand for
+pub(crate) fn read_settings(
+    path: &Path,
+) -> Result<HashMap<&'static str, String>, TracingInitError> {
+    let content = std::fs::read_to_string(path).map_err(|e| config_file_error(path, e))?;
+
+    parse_settings(path, &content)
+}
+
+fn parse_settings(
+    path: &Path,
+    content: &str,
+) -> Result<HashMap<&'static str, String>, TracingInitError> {
+    let table = content
+        .parse::<toml::Table>()
+        .map_err(|e| config_file_error(path, e))?;
+
+    let mut settings = HashMap::new();
+
+    for (key, value) in table {
+        let name = match KEYS.iter().find(|(k, _)| *k == key) {
+            Some((_, name)) => *name,
+            None => return Err(config_file_error(path, format!("unknown key '{key}'"))),
+        };
+
+        let value = match value {
+            toml::Value::String(value) => value,
+            _ => {
+                return Err(config_file_error(
+                    path,
+                    format!("the value of '{key}' must be a string"),
+                ))
+            }
+        };
+
+        if !is_valid_setting(name, &value) {
+            return Err(config_file_error(
+                path,
+                format!("invalid value '{value}' for '{key}'"),
+            ));
+        }
+
+        settings.insert(name, value);
+    }
+
+    Ok(settings)
+}
+
+fn config_file_error(path: &Path, reason: impl ToString) -> TracingInitError {
+    TracingInitError::ConfigFile {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    }
+}
+
+// Polls the config file and applies the changes of the reloadable settings. Stopped when dropped
+pub(crate) struct ConfigWatcher {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ConfigWatcher {
+    // builder is the configuration as set before applying the environment variables, it must have a config file
+    pub(crate) fn start(
+        builder: TracingInit,
+        filter_handle: FilterHandle,
+        interval: Duration,
+    ) -> Result<ConfigWatcher, TracingInitError> {
+        let path = builder
+            .config_file
+            .clone()
+            .expect("watching requires a config file");
+        let mut content =
+            std::fs::read_to_string(&path).map_err(|e| config_file_error(&path, e))?;
+        let mut settings = parse_settings(&path, &content)?;
+        let (stop, stopped) = mpsc::channel::<()>();
+        let spawn_error = config_file_error(&path, "cannot start the watcher thread");
+
+        let thread = std::thread::Builder::new()
+            .name(String::from("tracing-init-config"))
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    match std::fs::read_to_string(&path) {
+                        Ok(new_content) if new_content != content => {
+                            if let Some(new_settings) = apply_changes(
+                                &builder,
+                                &filter_handle,
+                                &path,
+                                &settings,
+                                &new_content,
+                            ) {
+                                settings = new_settings;
+                            }
+
+                            content = new_content;
+                        }
+                        _ => (),
+                    }
+                }
+            })
+            .map_err(|_| spawn_error)?;
+
+        Ok(ConfigWatcher {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes up the watcher thread
+        self.stop.take();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Apply the changed config file, returns the new settings (None if the file cannot be parsed)
+fn apply_changes(
+    builder: &TracingInit,
+    filter_handle: &FilterHandle,
+    path: &Path,
+    settings: &HashMap<&'static str, String>,
+    content: &str,
+) -> Option<HashMap<&'static str, String>> {
+    let new_settings = match parse_settings(path, content) {
+        Ok(new_settings) => new_settings,
+        Err(e) => {
+            tracing::warn!(target: "tracing_init", error = %e, "config file changed but cannot be parsed, keeping the previous configuration");
+            return None;
+        }
+    };
+
+    let (reloadable, ignored) = KEYS
+        .iter()
+        .map(|(key, name)| (*key, settings.get(name), new_settings.get(name)))
+        .filter(|(_, old, new)| old != new)
+        .partition::<Vec<_>, _>(|(key, old, new)| {
+            RELOADABLE_KEYS.contains(key)
+                || (*key == "destination" && destination_set(*old) == destination_set(*new))
+        });
+
+    if !ignored.is_empty() {
+        let ignored = ignored
+            .iter()
+            .map(|(key, _, _)| *key)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        tracing::warn!(target: "tracing_init", %ignored, "config file changes which cannot be applied while running are ignored");
+    }
+
+    if !reloadable.is_empty() {
+        let changes = reloadable
+            .iter()
+            .map(|(key, old, new)| {
+                let describe =
+                    |value: Option<&String>| value.map_or("(not set)", String::as_str).to_string();
+
+                format!("{key}: {} -> {}", describe(*old), describe(*new))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut builder = builder.clone();
+
+        // Read the changed file once more when computing the filter
+        builder.config_file = Some(path.to_path_buf());
+
+        match filter_handle.reload_from(&builder) {
+            Ok((old, new)) => {
+                tracing::info!(target: "tracing_init", %changes, %old, %new, "config file changed")
+            }
+            Err(e) => {
+                tracing::warn!(target: "tracing_init", %changes, error = %e, "config file changed but the filter cannot be applied")
+            }
+        }
+    }
+
+    Some(new_settings)
+}
+
+// The destinations of a destination setting without their levels (e.g. c and f for c:info,f:debug)
+fn destination_set(value: Option<&String>) -> Option<BTreeSet<char>> {
+    value.map(|value| {
+        parse_log_destination(value)
+            .into_iter()
+            .map(|(destination, _)| destination)
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_log_dir, with_env, CaptureLayer};
+    use std::path::PathBuf;
+    use tracing::Level;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn write_config_file(name: &str, content: &str) -> PathBuf {
+        let path = test_log_dir(name).join("logging.toml");
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_config_file_settings() {
+        let path = write_config_file(
+            "config-file",
+            "destination = \"c\"\nlevel = \"debug\"\nfilter = \"hyper=warn\"\n",
+        );
+        let config = with_env(&[("LOG_LEVEL", "warn")], || {
+            TracingInit::builder("App")
+                .config_file(path.to_str().unwrap())
+                .resolve()
+                .unwrap()
+        });
+
+        assert!(config.log_to_console());
+        assert!(!config.log_to_file());
+        assert_eq!(config.level(), Level::WARN);
+        assert_eq!(config.filter(), "hyper=warn");
+
+        for (content, reason) in [
+            ("level = 3", "must be a string"),
+            ("level = \"loud\"", "invalid value"),
+            ("colour = \"red\"", "unknown key"),
+            ("level = ", "TOML parse error"),
+        ] {
+            let path = write_config_file("config-file-errors", content);
+            let problems = with_env(&[], || {
+                TracingInit::builder("App")
+                    .config_file(path.to_str().unwrap())
+                    .validate()
+                    .unwrap_err()
+            });
+
+            assert!(
+                matches!(&problems[..], [TracingInitError::ConfigFile { reason: r, .. }] if r.contains(reason)),
+                "{content}: {problems:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_watch_config_file() {
+        let path = write_config_file("watch", "destination = \"c\"\nlevel = \"info\"\n");
+        let mut builder = TracingInit::builder("App");
+
+        builder.config_file(path.to_str().unwrap());
+
+        let (_dispatch, guard) = with_env(&[], || builder.clone().into_dispatch().unwrap());
+        let filter_handle = guard.filter_handle().unwrap();
+        let watcher = ConfigWatcher::start(
+            builder.clone(),
+            filter_handle.clone(),
+            Duration::from_millis(10),
+        )
+        .unwrap();
+
+        std::fs::write(&path, "destination = \"c\"\nlevel = \"debug\"\n").unwrap();
+
+        let start = std::time::Instant::now();
+        while filter_handle.current() != "debug" {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "filter not reloaded"
+            );
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        drop(watcher);
+
+        // The events are emitted by the watcher thread, apply the changes on this thread to capture them
+        let capture = CaptureLayer::default();
+        let settings = read_settings(&path).unwrap();
+
+        tracing::subscriber::with_default(
+            tracing_subscriber::registry().with(capture.clone()),
+            || {
+                with_env(&[], || {
+                    let content = "destination = \"cf\"\nlevel = \"trace\"\n";
+
+                    std::fs::write(&path, content).unwrap();
+                    assert!(
+                        apply_changes(&builder, filter_handle, &path, &settings, content).is_some()
+                    );
+                    assert_eq!(filter_handle.current(), "trace");
+
+                    let content = "level = \"loud\"";
+
+                    std::fs::write(&path, content).unwrap();
+                    assert!(
+                        apply_changes(&builder, filter_handle, &path, &settings, content).is_none()
+                    );
+                    assert_eq!(filter_handle.current(), "trace");
+                })
+            },
+        );
+
+        let events = capture.events();
+        assert_eq!(events.len(), 3, "{events:?}");
+        assert!(
+            events[0].0 == Level::WARN && events[0].2.contains("cannot be applied while running")
+        );
+        assert!(events[1].0 == Level::INFO && events[1].2.contains("config file changed"));
+        assert!(events[2].0 == Level::WARN && events[2].2.contains("cannot be parsed"));
+    }
+
+    // Only the level of the log file changes, it is applied to the log file layer
+    #[test]
+    fn test_watch_destination_levels() {
+        let path = write_config_file("watch-levels", "destination = \"f:debug\"\n");
+        let log_dir = path.parent().unwrap();
+        let mut builder = TracingInit::builder("App");
+
+        builder
+            .config_file(path.to_str().unwrap())
+            .watch_config_file(true)
+            .log_file_path(log_dir.to_str().unwrap())
+            .log_file_rotation(crate::Rotation::Never);
+
+        let (dispatch, guard) = with_env(&[], || builder.clone().into_dispatch().unwrap());
+        let filter_handle = guard.filter_handle().unwrap();
+        let watcher = ConfigWatcher::start(
+            builder.clone(),
+            filter_handle.clone(),
+            Duration::from_millis(10),
+        )
+        .unwrap();
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::debug!("debug before");
+            std::fs::write(&path, "destination = \"f:warn\"\n").unwrap();
+
+            let start = std::time::Instant::now();
+            while filter_handle.current() != "info" {
+                assert!(
+                    start.elapsed() < Duration::from_secs(5),
+                    "levels not reloaded"
+                );
+                std::thread::sleep(Duration::from_millis(5));
+            }
+
+            tracing::info!("info after");
+            tracing::warn!("warn after");
+        });
+        drop(watcher);
+        drop(guard);
+
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        assert!(log.contains("debug before"), "{log}");
+        assert!(!log.contains("info after"), "{log}");
+        assert!(log.contains("warn after"), "{log}");
+        assert!(!log.contains("cannot be applied while running"), "{log}");
+    }
+}
@@ -0,0 +1,273 @@
+use crate::FieldValue;
+
+// Where the container runtimes leave a trace
+const DOCKER_ENV: &str = "/.dockerenv";
+const PODMAN_ENV: &str = "/run/.containerenv";
+const KUBERNETES_VAR: &str = "KUBERNETES_SERVICE_HOST";
+const INIT_CGROUP: &str = "/proc/1/cgroup";
+const CGROUP_HINTS: [&str; 5] = ["docker", "kubepods", "containerd", "libpod", "lxc"];
+
+// What the detection looks at, the tests replace the host with fixed answers
+pub(crate) trait Probe {
+    fn exists(&self, path: &str) -> bool;
+    fn env_var(&self, name: &str) -> Option<String>;
+    fn read(&self, path: &str) -> Option<String>;
+}
+
+pub(crate) struct Host;
+
+impl Probe for Host {
+    fn exists(&self, path: &str) -> bool {
+        std::path::Path::new(path).exists()
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        crate::env_var(name)
+    }
+
+    fn read(&self, path: &str) -> Option<String> {
+        std::fs::read_to_string(path).ok()
+    }
+}
+
+// Running in a container: a Docker or Podman marker file, the Kubernetes service variable or the container runtime
+// in the cgroup of the init process
+pub(crate) fn detect(probe: &dyn Probe) -> bool {
+    probe.exists(DOCKER_ENV)
+        || probe.exists(PODMAN_ENV)
+        || probe.env_var(KUBERNETES_VAR).is_some()
+        || probe.read(INIT_CGROUP).is_some_and(|cgroup| {
+            cgroup
+                .lines()
+                .any(|line| CGROUP_HINTS.iter().any(|hint| line.contains(hint)))
+        })
+}
+
+// The fields added by k8s_metadata and the environment variables they are read from (set through the downward API),
+// the first variable set gives the value of a field: the pod name is the host name unless POD_NAME is set
+pub(crate) const K8S_METADATA_VARS: [(&str, &str); 5] = [
+    ("pod_name", "POD_NAME"),
+    ("pod_name", "HOSTNAME"),
+    ("pod_namespace", "POD_NAMESPACE"),
+    ("node_name", "NODE_NAME"),
+    ("container_name", "CONTAINER_NAME"),
+];
+
+// The metadata fields whose environment variable is set, in the order of their first variable
+pub(crate) fn k8s_metadata(
+    probe: &dyn Probe,
+    vars: &[(String, String)],
+) -> Vec<(String, FieldValue)> {
+    let mut fields: Vec<(String, FieldValue)> = Vec::new();
+
+    for (field, var) in vars {
+        if fields.iter().any(|(name, _)| name == field) {
+            continue;
+        }
+
+        if let Some(value) = probe.env_var(var).filter(|value| !value.is_empty()) {
+            fields.push((field.clone(), FieldValue::Str(value)));
+        }
+    }
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers;
+    use crate::tests::{with_env, MemoryWriter};
+    use crate::{LogFormat, TracingInit};
+    #[cfg(feature = "file")]
+    use tracing::Level;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Default)]
+    struct FakeProbe {
+        files: Vec<(&'static str, &'static str)>,
+        vars: Vec<(&'static str, &'static str)>,
+    }
+
+    impl Probe for FakeProbe {
+        fn exists(&self, path: &str) -> bool {
+            self.files.iter().any(|(name, _)| *name == path)
+        }
+
+        fn env_var(&self, name: &str) -> Option<String> {
+            self.vars
+                .iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+
+        fn read(&self, path: &str) -> Option<String> {
+            self.files
+                .iter()
+                .find(|(name, _)| *name == path)
+                .map(|(_, contents)| contents.to_string())
+        }
+    }
+
+    #[test]
+    fn test_detect_container() {
+        let probe = |files, vars| FakeProbe { files, vars };
+
+        assert!(!detect(&FakeProbe::default()));
+        assert!(!detect(&probe(
+            vec![(INIT_CGROUP, "0::/init.scope\n")],
+            vec![]
+        )));
+        assert!(detect(&probe(vec![(DOCKER_ENV, "")], vec![])));
+        assert!(detect(&probe(vec![(PODMAN_ENV, "")], vec![])));
+        assert!(detect(&probe(vec![], vec![(KUBERNETES_VAR, "10.0.0.1")])));
+        assert!(detect(&probe(
+            vec![(INIT_CGROUP, "12:cpu:/kubepods/besteffort/pod1234\n")],
+            vec![]
+        )));
+    }
+
+    // The detection result is set, so the test does not depend on the host
+    #[cfg(feature = "file")]
+    fn resolve(container: bool, vars: &[(&str, &str)], builder: &mut TracingInit) -> String {
+        builder.auto_detect_environment(true).container = Some(container);
+
+        let config = with_env(vars, || builder.resolve().unwrap());
+        let options = config.console_options();
+
+        format!(
+            "{} {} {} {} {}",
+            config.log_to_console(),
+            config.console_format(),
+            options.ansi,
+            config.log_to_file(),
+            config
+        )
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_auto_detect_environment() {
+        let app = || TracingInit::builder("App");
+
+        assert_eq!(
+            resolve(true, &[], &mut app()),
+            "true json false false console: stdout (json), file: disabled, server: disabled, level: INFO, environment: container (auto-detected)"
+        );
+        assert_eq!(
+            resolve(false, &[], &mut app()),
+            "false full true false console: stdout (full), fallback console (no destination configured), file: disabled, server: disabled, level: INFO"
+        );
+
+        // The builder values and the environment variables take precedence
+        assert!(
+            resolve(true, &[], app().log_to_console(false).allow_silent(true))
+                .starts_with("false json false false")
+        );
+        assert!(resolve(true, &[("LOG_DESTINATION", "f")], &mut app())
+            .starts_with("false json false true"));
+        assert!(resolve(true, &[], app().console_format(LogFormat::Pretty))
+            .starts_with("true pretty false false"));
+        assert!(
+            resolve(true, &[("LOG_PROFILE", "dev")], app().level(Level::WARN))
+                .starts_with("true pretty false false")
+        );
+    }
+
+    const POD_VARS: [(&str, &str); 4] = [
+        ("POD_NAME", "billing-7d4f9"),
+        ("POD_NAMESPACE", "payments"),
+        ("NODE_NAME", "node-3"),
+        ("HOSTNAME", "billing-host"),
+    ];
+
+    #[test]
+    fn test_k8s_metadata_console() {
+        let console = MemoryWriter::default();
+        let config = with_env(&POD_VARS, || {
+            TracingInit::builder("App")
+                .log_to_console(true)
+                .console_format(LogFormat::Json)
+                .global_field("node_name", "explicit")
+                .k8s_metadata(true)
+                .resolve()
+                .unwrap()
+        });
+        let subscriber = tracing_subscriber::registry().with(layers::console_with_writer(
+            &config.console_options(),
+            console.clone(),
+        ));
+
+        tracing::subscriber::with_default(subscriber, || tracing::info!("started"));
+
+        let line = console.contents();
+        assert!(line.contains(r#""pod_name":"billing-7d4f9""#), "{line}");
+        assert!(line.contains(r#""pod_namespace":"payments""#), "{line}");
+        // The global field wins, the missing CONTAINER_NAME is skipped
+        assert!(line.contains(r#""node_name":"explicit""#), "{line}");
+        assert!(
+            !line.contains("node-3") && !line.contains("container_name"),
+            "{line}"
+        );
+
+        // HOSTNAME is the fallback of the pod name, other variables can be read
+        let fields = with_env(&[("HOSTNAME", "billing-host"), ("ZONE", "eu-1")], || {
+            TracingInit::builder("App")
+                .k8s_metadata(true)
+                .resolve()
+                .unwrap()
+                .global_fields()
+                .to_vec()
+        });
+        assert_eq!(
+            fields,
+            [(String::from("pod_name"), FieldValue::from("billing-host"))]
+        );
+
+        let fields = with_env(&[("HOSTNAME", "billing-host"), ("ZONE", "eu-1")], || {
+            TracingInit::builder("App")
+                .k8s_metadata(true)
+                .k8s_metadata_vars(&[("zone", "ZONE"), ("pod", "POD"), ("pod", "HOSTNAME")])
+                .resolve()
+                .unwrap()
+                .global_fields()
+                .to_vec()
+        });
+        assert_eq!(
+            fields,
+            [
+                (String::from("zone"), FieldValue::from("eu-1")),
+                (String::from("pod"), FieldValue::from("billing-host"))
+            ]
+        );
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_k8s_metadata_server() {
+        let server = crate::test::MockGelfServer::start();
+        let (dispatch, guard) = with_env(&POD_VARS, || {
+            TracingInit::builder("App")
+                .log_to_server(true)
+                .log_server_address(&server.address())
+                .k8s_metadata(true)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("started"));
+        drop(guard);
+
+        let message = server
+            .wait_for(
+                |message| message.short_message == "started",
+                std::time::Duration::from_secs(5),
+            )
+            .expect("the record was not sent to the log server");
+
+        assert_eq!(message.field("pod_name"), Some("billing-7d4f9"));
+        assert_eq!(message.field("pod_namespace"), Some("payments"));
+        assert_eq!(message.field("node_name"), Some("node-3"));
+        assert_eq!(message.field("container_name"), None);
+    }
+}
@@ -0,0 +1,286 @@
+use tracing::field::{Field, Visit};
+use tracing::{span, Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+// The fields carrying the correlation ids, added to the records of the events within the spans
+pub(crate) const TRACE_ID: &str = "trace_id";
+pub(crate) const SPAN_ID: &str = "span_id";
+
+// The correlation ids of a span, stored in its extensions
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CorrelationIds {
+    pub(crate) trace_id: Option<String>,
+    pub(crate) span_id: Option<String>,
+}
+
+impl CorrelationIds {
+    fn is_empty(&self) -> bool {
+        self.trace_id.is_none() && self.span_id.is_none()
+    }
+
+    fn set(&mut self, field: &Field, value: String) {
+        match field.name() {
+            TRACE_ID => self.trace_id = Some(value),
+            SPAN_ID => self.span_id = Some(value),
+            _ => {}
+        }
+    }
+
+    // Store the ids of a new span. Several layers may do it, the first one wins
+    pub(crate) fn on_new_span<S>(attrs: &span::Attributes<'_>, id: &span::Id, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        if !has_ids(attrs.metadata().fields()) {
+            return;
+        }
+
+        if let Some(span) = ctx.span(id) {
+            let mut ids = CorrelationIds::default();
+
+            attrs.record(&mut ids);
+
+            let mut extensions = span.extensions_mut();
+
+            if !ids.is_empty() && extensions.get_mut::<CorrelationIds>().is_none() {
+                extensions.insert(ids);
+            }
+        }
+    }
+
+    // Update the ids recorded after the span was created
+    pub(crate) fn on_record<S>(id: &span::Id, values: &span::Record<'_>, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut recorded = CorrelationIds::default();
+
+        values.record(&mut recorded);
+
+        if recorded.is_empty() {
+            return;
+        }
+
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+
+            match extensions.get_mut::<CorrelationIds>() {
+                Some(ids) => {
+                    ids.trace_id = recorded.trace_id.or(ids.trace_id.take());
+                    ids.span_id = recorded.span_id.or(ids.span_id.take());
+                }
+                None => extensions.insert(recorded),
+            }
+        }
+    }
+
+    // The ids of the closest spans carrying them, from the event's span to the root (None outside of such spans)
+    pub(crate) fn of_event<S>(event: &Event<'_>, ctx: &Context<'_, S>) -> Option<CorrelationIds>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut ids = CorrelationIds::default();
+
+        for span in ctx.event_scope(event)? {
+            if let Some(span_ids) = span.extensions().get::<CorrelationIds>() {
+                if ids.trace_id.is_none() {
+                    ids.trace_id.clone_from(&span_ids.trace_id);
+                }
+
+                if ids.span_id.is_none() {
+                    ids.span_id.clone_from(&span_ids.span_id);
+                }
+            }
+
+            if ids.trace_id.is_some() && ids.span_id.is_some() {
+                break;
+            }
+        }
+
+        (!ids.is_empty()).then_some(ids)
+    }
+
+    pub(crate) fn value(&self, name: &str) -> Option<&String> {
+        match name {
+            TRACE_ID => self.trace_id.as_ref(),
+            SPAN_ID => self.span_id.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+fn has_ids(fields: &tracing::field::FieldSet) -> bool {
+    fields.field(TRACE_ID).is_some() || fields.field(SPAN_ID).is_some()
+}
+
+impl Visit for CorrelationIds {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.set(field, format!("{value:?}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layers::{self, ConsoleOptions};
+    #[cfg(feature = "file")]
+    use crate::tests::test_log_dir;
+    #[cfg(any(feature = "file", feature = "server"))]
+    use crate::tests::with_env;
+    use crate::tests::MemoryWriter;
+    use crate::LogFormat;
+    #[cfg(any(feature = "file", feature = "server"))]
+    use crate::TracingInit;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const TRACE: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN: &str = "00f067aa0ba902b7";
+
+    // Only the root span carries the ids
+    fn log_request() {
+        tracing::info!("before request");
+
+        tracing::info_span!("request", trace_id = TRACE, span_id = SPAN).in_scope(|| {
+            tracing::info!("request received");
+
+            tracing::info_span!("handler", user = "bob").in_scope(|| {
+                tracing::info_span!("query").in_scope(|| tracing::info!("query executed"));
+            });
+        });
+
+        tracing::info!("after request");
+    }
+
+    #[test]
+    fn test_correlation_ids_console() {
+        let console = MemoryWriter::default();
+        let options = ConsoleOptions {
+            format: LogFormat::Json,
+            correlation_ids: true,
+            ..ConsoleOptions::default()
+        };
+        let subscriber = tracing_subscriber::registry()
+            .with(layers::console_with_writer(&options, console.clone()));
+
+        tracing::subscriber::with_default(subscriber, log_request);
+
+        let ids = format!(r#""trace_id":"{TRACE}","span_id":"{SPAN}"}}"#);
+        let lines = console.contents();
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert!(
+            lines[0].contains(r#""fields":{"message":"before request"}"#),
+            "{}",
+            lines[0]
+        );
+        assert!(
+            lines[1].contains(&format!(r#""fields":{{"message":"request received",{ids}"#)),
+            "{}",
+            lines[1]
+        );
+        assert!(
+            lines[2].contains(&format!(r#""fields":{{"message":"query executed",{ids}"#)),
+            "{}",
+            lines[2]
+        );
+        assert!(
+            lines[3].contains(r#""fields":{"message":"after request"}"#),
+            "{}",
+            lines[3]
+        );
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_correlation_ids_file() {
+        let log_dir = test_log_dir("correlation-ids");
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(crate::Rotation::Never)
+                .correlation_ids(true)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            log_request();
+
+            // Ids recorded after the span was created, the closest span's id wins
+            let span = tracing::info_span!("job", trace_id = tracing::field::Empty);
+            span.record("trace_id", "late");
+            span.in_scope(|| {
+                tracing::info_span!("step", span_id = 7).in_scope(|| tracing::info!("step done"));
+            });
+        });
+        drop(guard);
+
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        let lines = log
+            .lines()
+            .filter(|line| !line.contains("TracingGuard"))
+            .collect::<Vec<_>>();
+        let ids = format!(r#"trace_id="{TRACE}" span_id="{SPAN}""#);
+
+        assert_eq!(lines.len(), 5, "{log}");
+        assert!(lines[0].ends_with(": before request"), "{log}");
+        assert!(
+            lines[1].ends_with(&format!(": request received {ids}")),
+            "{log}"
+        );
+        assert!(
+            lines[2].ends_with(&format!(": query executed {ids}")),
+            "{log}"
+        );
+        assert!(lines[3].ends_with(": after request"), "{log}");
+        assert!(
+            lines[4].ends_with(r#": step done trace_id="late" span_id="7""#),
+            "{log}"
+        );
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_correlation_ids_server() {
+        let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let (dispatch, _guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_console(false)
+                .log_to_server(true)
+                .log_server_address(&address)
+                .correlation_ids(true)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        // Wait for the connection before logging
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::info_span!("request", trace_id = TRACE, span_id = SPAN).in_scope(|| {
+                tracing::info_span!("handler").in_scope(|| tracing::info!("handled"));
+            });
+        });
+
+        let mut buffer = vec![0; 8192];
+        let len = tokio::time::timeout(std::time::Duration::from_secs(5), server.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        let record = String::from_utf8_lossy(&buffer[..len]);
+
+        assert!(
+            record.contains(&format!(r#""_trace_id":"{TRACE}""#)),
+            "{record}"
+        );
+        assert!(
+            record.contains(&format!(r#""_span_id":"{SPAN}""#)),
+            "{record}"
+        );
+    }
+}
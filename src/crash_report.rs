@@ -0,0 +1,263 @@
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::SystemTime;
+
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::preinit::format_time;
+
+// The file name used when the template is a directory
+const DEFAULT_FILE_NAME: &str = "crash-{timestamp}-{pid}.log";
+
+// The last formatted records, dumped to a file when the program panics
+pub(crate) struct CrashReport {
+    app_name: String,
+    path_template: String,
+    capacity: usize,
+    records: Mutex<VecDeque<String>>,
+}
+
+impl CrashReport {
+    pub(crate) fn new(app_name: &str, path_template: &str, capacity: usize) -> CrashReport {
+        CrashReport {
+            app_name: app_name.to_string(),
+            path_template: path_template.to_string(),
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    // The layer formatting the records into the buffer
+    pub(crate) fn layer<S>(self: &Arc<Self>) -> Box<dyn Layer<S> + Send + Sync + 'static>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(RecordWriter(self.clone()))
+            .boxed()
+    }
+
+    fn push(&self, record: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // The oldest record's allocation is reused once the buffer is full
+        let mut buffer = if records.len() == self.capacity {
+            records.pop_front().unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        buffer.clear();
+        buffer.push_str(&String::from_utf8_lossy(record));
+        records.push_back(buffer);
+    }
+
+    // The path of the report, {timestamp}, {pid} and {app} in the template are replaced
+    fn path(&self, time: SystemTime) -> PathBuf {
+        let template = if self.path_template.contains('{') {
+            self.path_template.clone()
+        } else {
+            PathBuf::from(&self.path_template)
+                .join(DEFAULT_FILE_NAME)
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        // Without colons, which are not allowed in Windows file names
+        PathBuf::from(
+            template
+                .replace("{timestamp}", &format_time(time).replace(':', "-"))
+                .replace("{pid}", &std::process::id().to_string())
+                .replace("{app}", &self.app_name),
+        )
+    }
+
+    fn report(&self, info: &PanicHookInfo<'_>, time: SystemTime) -> String {
+        let payload = info.payload_as_str().unwrap_or("Box<dyn Any>");
+        let location = info
+            .location()
+            .map(|location| format!("{}:{}", location.file(), location.line()))
+            .unwrap_or_default();
+        let thread = std::thread::current();
+        let thread = thread.name().unwrap_or("<unnamed>");
+        let records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut report = String::new();
+
+        // Writing to a String does not fail
+        let _ = writeln!(
+            report,
+            "{app} crashed at {time}\n\nthread '{thread}' panicked at {location}:\n{payload}\n\nbacktrace:\n{backtrace}\n\nlast {count} records:",
+            app = self.app_name,
+            time = format_time(time),
+            backtrace = Backtrace::force_capture(),
+            count = records.len(),
+        );
+
+        for record in records.iter() {
+            report.push_str(record);
+        }
+
+        report
+    }
+
+    // Write the report, best effort: errors are printed to stderr
+    fn write(&self, info: &PanicHookInfo<'_>) {
+        let time = SystemTime::now();
+        let path = self.path(time);
+        let report = self.report(info, time);
+
+        let result = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, report));
+
+        match result {
+            Ok(()) => eprintln!("crash report written to {}", path.display()),
+            Err(e) => eprintln!("failed to write crash report {}: {e}", path.display()),
+        }
+    }
+}
+
+// Each record is formatted into a single write
+struct RecordWriter(Arc<CrashReport>);
+
+impl Write for &RecordWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.push(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for RecordWriter {
+    type Writer = &'a RecordWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+// Write a crash report on panic, then call the previously installed hook. The report is owned by the guard, the
+// hook does not keep it alive
+pub(crate) fn install(crash_report: Weak<CrashReport>) {
+    let previous = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        if let Some(crash_report) = crash_report.upgrade() {
+            crash_report.write(info);
+        }
+
+        previous(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_log_dir, with_env};
+    use crate::TracingInit;
+
+    #[test]
+    fn test_crash_report() {
+        let report_dir = test_log_dir("crash");
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .allow_silent(true)
+                .crash_report(report_dir.to_str().unwrap(), 3)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        install(Arc::downgrade(guard.crash_report().unwrap()));
+
+        let result = std::thread::Builder::new()
+            .name(String::from("worker"))
+            .spawn(move || {
+                tracing::dispatcher::with_default(&dispatch, || {
+                    for n in 0..5 {
+                        tracing::info!(n, "processing");
+                    }
+                    tracing::debug!("filtered out");
+
+                    std::panic::catch_unwind(|| panic!("power outage"))
+                })
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert!(result.is_err());
+
+        // Other tests may panic while the hook is installed
+        let reports = std::fs::read_dir(&report_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                std::fs::read_to_string(path)
+                    .unwrap()
+                    .contains("power outage")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(reports.len(), 1, "{reports:?}");
+
+        let name = reports[0].file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("crash-"), "{name}");
+        assert!(
+            name.ends_with(&format!("-{}.log", std::process::id())),
+            "{name}"
+        );
+
+        let report = std::fs::read_to_string(&reports[0]).unwrap();
+        assert!(report.starts_with("App crashed at "), "{report}");
+        assert!(
+            report.contains(&format!("thread 'worker' panicked at {}:", file!())),
+            "{report}"
+        );
+        assert!(report.contains("\npower outage\n"), "{report}");
+        assert!(report.contains("\nbacktrace:\n"), "{report}");
+        assert!(report.contains("last 3 records:"), "{report}");
+        assert!(!report.contains("n=1"), "{report}");
+        assert!(report.contains("processing n=2"), "{report}");
+        assert!(report.contains("processing n=4"), "{report}");
+        assert!(!report.contains("filtered out"), "{report}");
+
+        drop(guard);
+    }
+
+    #[test]
+    fn test_crash_report_path() {
+        let crash_report = CrashReport::new("App", "/var/log/{app}-{pid}.txt", 1);
+        let time = SystemTime::UNIX_EPOCH;
+
+        assert_eq!(
+            crash_report.path(time),
+            PathBuf::from(format!("/var/log/App-{}.txt", std::process::id()))
+        );
+
+        let crash_report = CrashReport::new("App", "/var/log", 1);
+        assert_eq!(
+            crash_report.path(time),
+            PathBuf::from(format!(
+                "/var/log/crash-1970-01-01T00-00-00.000Z-{}.log",
+                std::process::id()
+            ))
+        );
+    }
+}
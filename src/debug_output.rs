@@ -0,0 +1,143 @@
+// The debug output destination: the records are written by OutputDebugStringW, shown by an attached debugger (WinDbg,
+// Visual Studio) or by DebugView
+#[cfg(windows)]
+use std::io::Write;
+
+#[cfg(windows)]
+use tracing_subscriber::fmt::MakeWriter;
+
+// The UTF-16 units written by a call. The messages are read through the 4 KiB buffer of the debugger interface
+// (DBWIN_BUFFER) after their conversion to the ANSI code page, in which a character can take several bytes: the longer
+// ones are truncated
+const MAX_CHUNK: usize = 1024;
+
+// The text as null-terminated UTF-16 strings of at most MAX_CHUNK units (the terminator excluded). A character is not
+// split, so the surrogate pairs of the characters outside the BMP stay in the same chunk
+fn chunks(text: &str) -> Vec<Vec<u16>> {
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::with_capacity(MAX_CHUNK + 1);
+
+    for c in text.chars() {
+        if chunk.len() + c.len_utf16() > MAX_CHUNK {
+            chunk.push(0);
+            chunks.push(std::mem::replace(
+                &mut chunk,
+                Vec::with_capacity(MAX_CHUNK + 1),
+            ));
+        }
+
+        let mut units = [0; 2];
+        chunk.extend_from_slice(c.encode_utf16(&mut units));
+    }
+
+    if !chunk.is_empty() {
+        chunk.push(0);
+        chunks.push(chunk);
+    }
+
+    chunks
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn OutputDebugStringW(output_string: *const u16);
+}
+
+// Writes each record to the debug output
+#[cfg(windows)]
+pub(crate) struct DebugOutput;
+
+#[cfg(windows)]
+impl<'a> MakeWriter<'a> for DebugOutput {
+    type Writer = DebugOutputWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        DebugOutputWriter(Vec::new())
+    }
+}
+
+// Collects a formatted record, written as a whole when dropped
+#[cfg(windows)]
+pub(crate) struct DebugOutputWriter(Vec<u8>);
+
+#[cfg(windows)]
+impl Write for DebugOutputWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for DebugOutputWriter {
+    fn drop(&mut self) {
+        for chunk in chunks(&String::from_utf8_lossy(&self.0)) {
+            // SAFETY: the chunk is a null-terminated UTF-16 string
+            unsafe { OutputDebugStringW(chunk.as_ptr()) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The text of the chunks, checking that each one is terminated and not longer than allowed
+    fn decode(chunks: &[Vec<u16>]) -> Vec<String> {
+        chunks
+            .iter()
+            .map(|chunk| {
+                let (terminator, units) = chunk.split_last().unwrap();
+
+                assert_eq!(*terminator, 0);
+                assert!(units.len() <= MAX_CHUNK);
+                String::from_utf16(units).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunks() {
+        assert!(chunks("").is_empty());
+        assert_eq!(
+            decode(&chunks("INFO app: started\n")),
+            ["INFO app: started\n"]
+        );
+        assert_eq!(chunks("é"), [vec![0xe9, 0]]);
+
+        // Exactly one chunk, then several
+        let line = "x".repeat(MAX_CHUNK);
+        assert_eq!(decode(&chunks(&line)), [line]);
+
+        let line = "x".repeat(MAX_CHUNK * 2 + 10);
+        let decoded = decode(&chunks(&line));
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[2].len(), 10);
+        assert_eq!(decoded.concat(), line);
+    }
+
+    #[test]
+    fn test_chunks_surrogate_pairs() {
+        // Encoded as a surrogate pair
+        assert_eq!(chunks("🦀"), [vec![0xd83e, 0xdd80, 0]]);
+
+        // The pair which would end at MAX_CHUNK + 1 starts the next chunk
+        let line = format!("{}🦀🦀 done", "x".repeat(MAX_CHUNK - 1));
+        let decoded = decode(&chunks(&line));
+
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].len(), MAX_CHUNK - 1);
+        assert_eq!(decoded[1], "🦀🦀 done");
+
+        let line = "🦀".repeat(MAX_CHUNK);
+        let decoded = decode(&chunks(&line));
+
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded.concat(), line);
+    }
+}
@@ -0,0 +1,313 @@
+use std::any::TypeId;
+
+use tracing::level_filters::LevelFilter;
+use tracing::span;
+use tracing::subscriber::Interest;
+use tracing::{Dispatch, Event, Metadata, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+#[cfg(feature = "file")]
+use crate::clock::ClockSource;
+use crate::layers;
+use crate::{ConsoleStyle, LogFormat, ResolvedConfig, TracingInitError};
+
+type ConsoleLayer<S> = layers::StdoutLayer<S>;
+#[cfg(feature = "file")]
+type FileLayer<S> = tracing_subscriber::fmt::Layer<
+    S,
+    tracing_subscriber::fmt::format::DefaultFields,
+    tracing_subscriber::fmt::format::Format<tracing_subscriber::fmt::format::Full, ClockSource>,
+    tracing_appender::rolling::RollingFileAppender,
+>;
+
+// The destination layers of the subscriber. The common configurations (console, log file, or both, with nothing
+// wrapping them) are concrete layers, so the events are not dispatched through the vtables of boxed layers; the
+// other ones are the boxed layers
+pub(crate) enum Destinations<S> {
+    Console(ConsoleLayer<S>),
+    #[cfg(feature = "file")]
+    File(FileLayer<S>),
+    #[cfg(feature = "file")]
+    ConsoleAndFile(tracing_subscriber::layer::Layered<FileLayer<S>, ConsoleLayer<S>, S>),
+    Boxed(Vec<Box<dyn Layer<S> + Send + Sync + 'static>>),
+}
+
+impl<S> Destinations<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    // The concrete layers of the configuration, None if it needs the boxed ones: logging to the server or to the debug
+    // output, levels of the destinations (or reloadable ones), a console format other than full, an aligned console or
+    // one through the test writer, global fields, correlation ids, task ids or a non-blocking log file, one with a
+    // maximum age, one reopened if missing or one with hindsight
+    pub(crate) fn concrete(config: &ResolvedConfig) -> Result<Option<Self>, TracingInitError> {
+        let console = config.log_to_console() || config.fallback_console();
+        let console_options = config.console_options();
+
+        if config.log_to_server()
+            || config.log_to_debug_output()
+            || config.has_destination_levels()
+            || config.destination_filters().is_some()
+            || (console
+                && (console_options.format != LogFormat::Full
+                    || console_options.style != ConsoleStyle::Default
+                    || console_options.test_writer))
+            || !config.global_fields().is_empty()
+            || config.correlation_ids()
+        {
+            return Ok(None);
+        }
+
+        #[cfg(feature = "tokio-console")]
+        if config.tokio_console_address().is_some() {
+            return Ok(None);
+        }
+
+        #[cfg(feature = "task-ids")]
+        if config.task_ids() {
+            return Ok(None);
+        }
+
+        let console = console.then(|| layers::stdout_fmt_layer(&console_options));
+
+        #[cfg(feature = "file")]
+        let file = if config.log_to_file() {
+            let options = config.file_options();
+
+            if options.non_blocking
+                || options.max_age.is_some()
+                || options.reopen_if_missing
+                || options.hindsight.is_some()
+                || options.format != LogFormat::Full
+            {
+                return Ok(None);
+            }
+
+            #[cfg(feature = "encrypt")]
+            if options.encrypt_key.is_some() {
+                return Ok(None);
+            }
+
+            Some(
+                layers::file_fmt_layer(&options)
+                    .with_writer(layers::rolling_file_appender(&options)?),
+            )
+        } else {
+            None
+        };
+        #[cfg(not(feature = "file"))]
+        let file: Option<std::convert::Infallible> = None;
+
+        Ok(match (console, file) {
+            (Some(console), None) => Some(Destinations::Console(console)),
+            #[cfg(feature = "file")]
+            (None, Some(file)) => Some(Destinations::File(file)),
+            #[cfg(feature = "file")]
+            (Some(console), Some(file)) => {
+                Some(Destinations::ConsoleAndFile(console.and_then(file)))
+            }
+            _ => None,
+        })
+    }
+
+    pub(crate) fn into_boxed(self) -> Vec<Box<dyn Layer<S> + Send + Sync + 'static>> {
+        match self {
+            Destinations::Boxed(layers) => layers,
+            destinations => vec![destinations.boxed()],
+        }
+    }
+}
+
+// Call the method of the layer of the variant
+macro_rules! each_variant {
+    ($self:ident, $layer:ident => $call:expr) => {
+        match $self {
+            Destinations::Console($layer) => $call,
+            #[cfg(feature = "file")]
+            Destinations::File($layer) => $call,
+            #[cfg(feature = "file")]
+            Destinations::ConsoleAndFile($layer) => $call,
+            Destinations::Boxed($layer) => $call,
+        }
+    };
+}
+
+impl<S> Layer<S> for Destinations<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        each_variant!(self, layer => layer.on_register_dispatch(subscriber))
+    }
+
+    fn on_layer(&mut self, subscriber: &mut S) {
+        each_variant!(self, layer => layer.on_layer(subscriber))
+    }
+
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        each_variant!(self, layer => layer.register_callsite(metadata))
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        each_variant!(self, layer => layer.enabled(metadata, ctx))
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        each_variant!(self, layer => layer.max_level_hint())
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, S>) -> bool {
+        each_variant!(self, layer => layer.event_enabled(event, ctx))
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        each_variant!(self, layer => layer.on_new_span(attrs, id, ctx))
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        each_variant!(self, layer => layer.on_record(id, values, ctx))
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        each_variant!(self, layer => layer.on_follows_from(id, follows, ctx))
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        each_variant!(self, layer => layer.on_event(event, ctx))
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        each_variant!(self, layer => layer.on_enter(id, ctx))
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        each_variant!(self, layer => layer.on_exit(id, ctx))
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        each_variant!(self, layer => layer.on_close(id, ctx))
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        each_variant!(self, layer => layer.on_id_change(old, new, ctx))
+    }
+
+    // The boxed layers may have per-layer filters, which the subscriber finds by downcasting
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const _ as *const ())
+        } else {
+            each_variant!(self, layer => layer.downcast_raw(id))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "file"))]
+mod tests {
+    use super::*;
+    use crate::tests::{test_log_dir, with_env};
+    use crate::{Rotation, TracingInit};
+    use tracing_subscriber::Registry;
+
+    fn destinations(builder: &mut TracingInit) -> &'static str {
+        let config = with_env(&[], || builder.resolve().unwrap());
+
+        match Destinations::<Registry>::concrete(&config).unwrap() {
+            Some(Destinations::Console(_)) => "console",
+            Some(Destinations::File(_)) => "file",
+            Some(Destinations::ConsoleAndFile(_)) => "console and file",
+            Some(Destinations::Boxed(_)) => unreachable!(),
+            None => "boxed",
+        }
+    }
+
+    #[test]
+    fn test_concrete_destinations() {
+        let log_dir = test_log_dir("concrete-destinations");
+        let file = || {
+            let mut builder = TracingInit::builder("App");
+
+            builder
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never);
+            builder
+        };
+
+        assert_eq!(
+            destinations(TracingInit::builder("App").log_to_console(true)),
+            "console"
+        );
+        assert_eq!(destinations(&mut TracingInit::builder("App")), "console");
+        assert_eq!(destinations(&mut file()), "file");
+        assert_eq!(
+            destinations(file().log_to_console(true)),
+            "console and file"
+        );
+
+        assert_eq!(
+            destinations(TracingInit::builder("App").console_format(crate::LogFormat::Json)),
+            "boxed"
+        );
+        assert_eq!(destinations(file().log_file_non_blocking(true)), "boxed");
+        assert_eq!(
+            destinations(file().log_file_format(crate::LogFormat::Binary)),
+            "boxed"
+        );
+        assert_eq!(destinations(file().test_writer(true)), "boxed");
+        assert_eq!(destinations(file().correlation_ids(true)), "boxed");
+        assert_eq!(destinations(file().global_field("region", "eu")), "boxed");
+        #[cfg(feature = "task-ids")]
+        assert_eq!(destinations(file().task_ids(true)), "boxed");
+
+        // The level of the debug output is ignored where there is none
+        assert_eq!(
+            destinations(file().debug_output_level(tracing::Level::DEBUG)),
+            if cfg!(windows) { "boxed" } else { "file" }
+        );
+    }
+
+    // The records are identical whether the destinations are concrete or boxed layers
+    #[test]
+    fn test_concrete_destinations_output() {
+        let log = |name: &str, boxed: bool| {
+            let log_dir = test_log_dir(name);
+            let mut builder = TracingInit::builder("App");
+
+            builder
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .level(tracing::Level::DEBUG);
+
+            if boxed {
+                // Accepts every event, but makes the layers boxed
+                builder.with_filter_fn(|_| true);
+            }
+
+            let (dispatch, guard) = with_env(&[], || builder.into_dispatch().unwrap());
+
+            tracing::dispatcher::with_default(&dispatch, || {
+                tracing::info_span!("request", id = 7).in_scope(|| {
+                    tracing::debug!(user = "bob", "handling");
+                    tracing::trace!("filtered out");
+                });
+                tracing::warn!("done");
+            });
+            drop(guard);
+
+            std::fs::read_to_string(log_dir.join("App.log"))
+                .unwrap()
+                .lines()
+                .map(|line| line.split_once(' ').unwrap().1.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let concrete = log("concrete-output", false);
+
+        assert_eq!(concrete.len(), 2, "{concrete:?}");
+        assert_eq!(concrete, log("boxed-output", true));
+    }
+}
@@ -0,0 +1,468 @@
+use std::fmt::Display;
+use std::io::Write;
+#[cfg(feature = "file")]
+use std::time::Instant;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::Level;
+
+#[cfg(feature = "server")]
+use crate::layers::ServerGuard;
+use crate::ResolvedConfig;
+#[cfg(feature = "file")]
+use crate::{FieldValue, LogFormat};
+
+// How often the log file is read while waiting for the probe record
+#[cfg(feature = "file")]
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The result of [`TracingGuard::diagnose`](crate::TracingGuard::diagnose)
+///
+/// Each destination is `None` when it is not configured, otherwise `Ok` or `Err` with the details of the check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DiagnosisReport {
+    /// The value of the `diagnosis_probe` field of the probe record
+    pub probe_id: String,
+    /// The probe record was written to stdout and flushed
+    pub console: Option<Result<String, String>>,
+    /// The probe record was found in the current log file
+    pub file: Option<Result<String, String>>,
+    /// The probe record was handed to the log server transport
+    pub server: Option<Result<String, String>>,
+}
+
+impl DiagnosisReport {
+    /// Every configured destination passed its check
+    pub fn is_ok(&self) -> bool {
+        [&self.console, &self.file, &self.server]
+            .into_iter()
+            .all(|check| !matches!(check, Some(Err(_))))
+    }
+}
+
+impl Display for DiagnosisReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let checks = [
+            ("console", &self.console),
+            ("file", &self.file),
+            ("server", &self.server),
+        ];
+
+        for (index, (name, check)) in checks.into_iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+
+            match check {
+                Some(Ok(details)) => write!(f, "{name}: ok ({details})")?,
+                Some(Err(details)) => write!(f, "{name}: failed ({details})")?,
+                None => write!(f, "{name}: disabled")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Log the probe record to the current default subscriber and check that it reached each destination, waiting up to
+// timeout for it
+pub(crate) fn diagnose(
+    config: &ResolvedConfig,
+    #[cfg(feature = "server")] server: Option<&ServerGuard>,
+    timeout: Duration,
+) -> DiagnosisReport {
+    let probe_id = probe_id();
+
+    #[cfg(feature = "server")]
+    let server_events = server.map(ServerGuard::events);
+
+    // Only the end of the log file written from now on is looked for the probe
+    #[cfg(feature = "file")]
+    let file_end = config.log_to_file().then(|| log_file_end(config));
+
+    log_probe(probe_level(config), &probe_id);
+
+    let console = (config.log_to_console() || config.fallback_console()).then(|| {
+        std::io::stdout()
+            .flush()
+            .map(|_| String::from("written to stdout"))
+            .map_err(|e| format!("cannot flush stdout: {e}"))
+    });
+
+    #[cfg(feature = "file")]
+    let file = file_end.map(|end| check_log_file(config, &probe_id, end, timeout));
+    #[cfg(not(feature = "file"))]
+    let file = None;
+
+    #[cfg(feature = "server")]
+    let server = match (server, server_events) {
+        (Some(server), Some(events)) => Some(check_server(config, server, events)),
+        _ => config.log_to_server().then(|| {
+            Err(String::from(
+                "the log server layer is not owned by this guard",
+            ))
+        }),
+    };
+    #[cfg(not(feature = "server"))]
+    let server = None;
+    #[cfg(not(feature = "file"))]
+    let _ = timeout;
+
+    DiagnosisReport {
+        probe_id,
+        console,
+        file,
+        server,
+    }
+}
+
+// Unique enough to tell the probes of the processes writing to the same log file apart
+fn probe_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    format!("{:x}-{nanos:x}", std::process::id())
+}
+
+// The most severe of the levels of the configured destinations (and of the events), so that every destination logs
+// the probe once
+fn probe_level(config: &ResolvedConfig) -> Level {
+    let level = config.level();
+    let destination_levels = [
+        (
+            config.log_to_console() || config.fallback_console(),
+            config.console_level(),
+        ),
+        (config.log_to_file(), config.log_file_level()),
+        (config.log_to_server(), config.log_server_level()),
+        (config.log_to_debug_output(), config.debug_output_level()),
+    ];
+
+    destination_levels
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, destination_level)| destination_level.unwrap_or(level))
+        .chain(config.event_level().and_then(|filter| filter.into_level()))
+        .min()
+        .unwrap_or(level)
+}
+
+fn log_probe(level: Level, probe_id: &str) {
+    let message = "logging diagnosis probe, this record can be ignored";
+
+    match level {
+        Level::TRACE => {
+            tracing::trace!(target: "tracing_init", diagnosis_probe = probe_id, "{message}")
+        }
+        Level::DEBUG => {
+            tracing::debug!(target: "tracing_init", diagnosis_probe = probe_id, "{message}")
+        }
+        Level::INFO => {
+            tracing::info!(target: "tracing_init", diagnosis_probe = probe_id, "{message}")
+        }
+        Level::WARN => {
+            tracing::warn!(target: "tracing_init", diagnosis_probe = probe_id, "{message}")
+        }
+        Level::ERROR => {
+            tracing::error!(target: "tracing_init", diagnosis_probe = probe_id, "{message}")
+        }
+    }
+}
+
+// The most recent log file and its length, None if there is none yet
+#[cfg(feature = "file")]
+fn log_file_end(config: &ResolvedConfig) -> Option<(std::path::PathBuf, u64)> {
+    let path = current_log_file(config).ok()?;
+    let len = std::fs::metadata(&path).ok()?.len();
+
+    Some((path, len))
+}
+
+// The non-blocking writer writes the record later, so the end of the most recent log file (written since the probe
+// was logged) is read until the probe is found
+#[cfg(feature = "file")]
+fn check_log_file(
+    config: &ResolvedConfig,
+    probe_id: &str,
+    end: Option<(std::path::PathBuf, u64)>,
+    timeout: Duration,
+) -> Result<String, String> {
+    #[cfg(feature = "encrypt")]
+    if config.encrypt_key().is_some() {
+        return Err(String::from(
+            "the log file records are encrypted, the probe cannot be looked for",
+        ));
+    }
+
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let result = current_log_file(config).and_then(|path| {
+            let from = match &end {
+                Some((end_path, len)) if *end_path == path => *len,
+                // Rotated to a new file
+                _ => 0,
+            };
+            let found = contains_probe(config, &path, from, probe_id)
+                .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+
+            Ok((path, found))
+        });
+
+        match result {
+            Ok((path, true)) => return Ok(format!("found in {}", path.display())),
+            Ok((path, false)) if Instant::now() >= deadline => {
+                return Err(format!("not found in {} after {timeout:?}", path.display()))
+            }
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            _ => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+// Whether the records written after from hold the probe, the binary records are decoded
+#[cfg(feature = "file")]
+fn contains_probe(
+    config: &ResolvedConfig,
+    path: &std::path::Path,
+    from: u64,
+    probe_id: &str,
+) -> std::io::Result<bool> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    // The whole file if it is shorter, it was replaced
+    let from = if from <= len { from } else { 0 };
+    let bytes = crate::tail::read_end(&mut file, len, |pos, _| {
+        (pos <= from).then(|| (from - pos) as usize)
+    })?;
+
+    if config.log_file_format() == LogFormat::Binary {
+        let probe = (String::from("diagnosis_probe"), FieldValue::from(probe_id));
+
+        // The first record may be cut short by the start of the bytes read, it is skipped
+        return Ok(crate::binlog::Reader::new(bytes.as_slice())
+            .filter_map(Result::ok)
+            .any(|record| record.fields.contains(&probe)));
+    }
+
+    Ok(bytes
+        .windows(probe_id.len())
+        .any(|window| window == probe_id.as_bytes()))
+}
+
+// The most recently modified file named <prefix>[.<date>].log in the log directory
+#[cfg(feature = "file")]
+fn current_log_file(config: &ResolvedConfig) -> Result<std::path::PathBuf, String> {
+    let directory = config.log_file_path();
+    let prefix = config.log_file_prefix();
+    let entries = std::fs::read_dir(directory)
+        .map_err(|e| format!("cannot read the directory {}: {e}", directory.display()))?;
+
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            name.ends_with(".log")
+                && name
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max()
+        .map(|(_, path)| path)
+        .ok_or_else(|| {
+            format!(
+                "no {} file in {}",
+                crate::log_file_name(prefix, config.log_file_rotation()),
+                directory.display()
+            )
+        })
+}
+
+// UDP is not acknowledged, the check is that the record was handed to a live connection
+#[cfg(feature = "server")]
+fn check_server(
+    config: &ResolvedConfig,
+    server: &ServerGuard,
+    events: u64,
+) -> Result<String, String> {
+    if let Some(reason) = server.broken() {
+        Err(reason.to_string())
+    } else if server.events() == events {
+        Err(String::from(
+            "the probe was not handed to the log server layer",
+        ))
+    } else {
+        Ok(format!(
+            "handed to the GELF UDP transport for {} (not acknowledged)",
+            crate::mask_credentials(config.log_server_address())
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "file", feature = "server"))]
+mod tests {
+    use crate::tests::{test_log_dir, with_env};
+    use crate::{LogFormat, Rotation, TracingInit};
+    use std::time::Duration;
+
+    fn file_logging(
+        name: &str,
+        rotation: Rotation,
+    ) -> (std::path::PathBuf, tracing::Dispatch, crate::TracingGuard) {
+        let log_dir = test_log_dir(name);
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(rotation)
+                .log_file_non_blocking(true)
+                .shutdown_timeout(Duration::from_millis(200))
+                .into_dispatch()
+                .unwrap()
+        });
+
+        (log_dir, dispatch, guard)
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let (log_dir, dispatch, guard) = file_logging("diagnose", Rotation::Daily);
+        let report = tracing::dispatcher::with_default(&dispatch, || guard.diagnose());
+
+        assert!(report.is_ok(), "{report}");
+        assert_eq!(report.console, None);
+        assert_eq!(report.server, None);
+        assert!(report
+            .file
+            .as_ref()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .starts_with(&format!("found in {}", log_dir.join("App.").display())));
+
+        // A single probe record
+        drop(guard);
+        let log = std::fs::read_dir(&log_dir)
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect::<String>();
+        let probes = log
+            .lines()
+            .filter(|line| line.contains("logging diagnosis probe"))
+            .collect::<Vec<_>>();
+        assert_eq!(probes.len(), 1, "{log}");
+        assert!(probes[0].contains(&format!("diagnosis_probe=\"{}\"", report.probe_id)));
+    }
+
+    // The log file logs WARN records only, the default level is INFO
+    #[test]
+    fn test_diagnose_destination_level() {
+        let log_dir = test_log_dir("diagnose-level");
+        let (dispatch, guard) = with_env(&[("LOG_DESTINATION", "f:warn")], || {
+            TracingInit::builder("App")
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        assert_eq!(guard.config().level(), tracing::Level::INFO);
+        let report = tracing::dispatcher::with_default(&dispatch, || guard.diagnose());
+
+        assert!(report.is_ok(), "{report}");
+        drop(guard);
+
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        assert!(
+            log.contains(" WARN tracing_init: logging diagnosis probe"),
+            "{log}"
+        );
+    }
+
+    // The records before the probe are not looked at, the binary records are decoded
+    #[test]
+    fn test_diagnose_binary_file() {
+        let log_dir = test_log_dir("diagnose-binary");
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .log_file_format(LogFormat::Binary)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        let reports = tracing::dispatcher::with_default(&dispatch, || {
+            tracing::info!("before the probes");
+            [guard.diagnose(), guard.diagnose()]
+        });
+
+        for report in &reports {
+            assert!(report.is_ok(), "{report}");
+            assert_eq!(
+                report.file,
+                Some(Ok(format!(
+                    "found in {}",
+                    log_dir.join("App.log").display()
+                )))
+            );
+        }
+        assert_ne!(reports[0].probe_id, reports[1].probe_id);
+
+        drop(guard);
+        let probes = crate::binlog::Reader::open(log_dir.join("App.log"))
+            .unwrap()
+            .filter(|record| record.as_ref().unwrap().message.contains("diagnosis probe"))
+            .count();
+        assert_eq!(probes, 2);
+    }
+
+    #[cfg(feature = "encrypt")]
+    #[test]
+    fn test_diagnose_encrypted_file() {
+        let log_dir = test_log_dir("diagnose-encrypted");
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .encrypt_log_files(crate::EncryptKey::Bytes([7; 32]))
+                .into_dispatch()
+                .unwrap()
+        });
+        let report = tracing::dispatcher::with_default(&dispatch, || guard.diagnose());
+
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.file,
+            Some(Err(String::from(
+                "the log file records are encrypted, the probe cannot be looked for"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_diagnose_broken_file() {
+        let (log_dir, dispatch, guard) = file_logging("diagnose-broken", Rotation::Never);
+
+        // The log file is removed while the writer keeps it open
+        std::fs::remove_dir_all(&log_dir).unwrap();
+        let report = tracing::dispatcher::with_default(&dispatch, || guard.diagnose());
+
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "console: disabled, file: failed (cannot read the directory {}: No such file or directory (os error 2)), server: disabled",
+                log_dir.display()
+            )
+        );
+    }
+}
@@ -0,0 +1,784 @@
+//! Encrypted log files (AES-256-GCM)
+//!
+//! # File format
+//! An encrypted log file is a sequence of frames, each starting with a tag byte:
+//! * Header - the 8 bytes `TILOGENC`, the format version (1) and the algorithm (1 = AES-256-GCM). It starts each file
+//!   and each stream of chunks, a file appended to by several runs has several headers
+//! * Chunk - the byte `0xDA` (`0xDB` for the final chunk of the stream), the length of the ciphertext (u32, little
+//!   endian), the 12 bytes nonce and the ciphertext followed by its 16 bytes authentication tag. The plaintext is one
+//!   or more log records, the final chunk is empty
+//!
+//! The nonce is a random prefix of 8 bytes, drawn for each stream, followed by the number of the chunk in the stream
+//! (u32, big endian, from 0). The chunks are authenticated with the header bytes followed by 1 for the final chunk (0
+//! otherwise) as associated data.
+//!
+//! The final chunk is written when the file is rotated, when the [`TracingGuard`](crate::TracingGuard) is dropped
+//! and when the log file is disabled at runtime. [`decrypt_file`] fails if a chunk of a stream is missing, reordered
+//! or taken from another stream, and a stream cut at the end of a chunk (the program crashed, or the file was
+//! truncated) is reported by [`Decrypted::finished`]. A chunk cut short by a crash is skipped, up to the header of
+//! the stream appended by the next run if there is one.
+//!
+
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::layers::FileOptions;
+use crate::read_ahead::ReadAhead;
+use crate::reopen::current_file_path;
+use crate::{env_var, TracingInitError};
+
+// The magic bytes, the format version (1) and the algorithm (1 = AES-256-GCM)
+const HEADER: [u8; 10] = *b"TILOGENC\x01\x01";
+const CHUNK: u8 = 0xda;
+const FINAL_CHUNK: u8 = 0xdb;
+// The tag, the ciphertext length and the nonce
+const CHUNK_HEADER_LEN: usize = 1 + 4 + NONCE_LEN;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+// The ciphers of the log files being written, the guard ends their streams when it is dropped
+static CIPHERS: Mutex<Vec<Weak<Cipher>>> = Mutex::new(Vec::new());
+
+/// The key encrypting the log files, 32 bytes (64 hex digits when read from a file or an environment variable)
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum EncryptKey {
+    /// The key itself
+    Bytes([u8; KEY_LEN]),
+    /// A file holding the key as hex digits
+    File(PathBuf),
+    /// An environment variable holding the key as hex digits
+    EnvVar(String),
+}
+
+impl EncryptKey {
+    /// The key read from a file, when the log file is created
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        EncryptKey::File(path.into())
+    }
+
+    /// The key read from an environment variable, when the log file is created
+    pub fn env_var(name: &str) -> Self {
+        EncryptKey::EnvVar(name.to_string())
+    }
+
+    // The key bytes, read from the file or the environment variable
+    pub(crate) fn load(&self) -> Result<[u8; KEY_LEN], TracingInitError> {
+        let error = |reason: &str| TracingInitError::EncryptKey {
+            key: self.to_string(),
+            reason: reason.to_string(),
+        };
+        let hex = match self {
+            EncryptKey::Bytes(key) => return Ok(*key),
+            EncryptKey::File(path) => {
+                std::fs::read_to_string(path).map_err(|e| error(&e.to_string()))?
+            }
+            EncryptKey::EnvVar(name) => env_var(name).ok_or_else(|| error("not set"))?,
+        };
+
+        parse_hex_key(hex.trim()).ok_or_else(|| error("expected 64 hex digits"))
+    }
+}
+
+// The key bytes are never shown
+impl Debug for EncryptKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncryptKey::Bytes(_) => f.write_str("Bytes(..)"),
+            EncryptKey::File(path) => f.debug_tuple("File").field(path).finish(),
+            EncryptKey::EnvVar(name) => f.debug_tuple("EnvVar").field(name).finish(),
+        }
+    }
+}
+
+impl std::fmt::Display for EncryptKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncryptKey::Bytes(_) => write!(f, "key given by the app"),
+            EncryptKey::File(path) => write!(f, "key file {}", path.display()),
+            EncryptKey::EnvVar(name) => write!(f, "key from {name}"),
+        }
+    }
+}
+
+fn parse_hex_key(hex: &str) -> Option<[u8; KEY_LEN]> {
+    if hex.len() != KEY_LEN * 2 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut key = [0u8; KEY_LEN];
+
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).ok()?;
+    }
+
+    Some(key)
+}
+
+/// The result of [`decrypt_file`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Decrypted {
+    /// The number of bytes of log records written
+    pub bytes: u64,
+    /// true if a chunk was cut short (e.g. by a crash while it was written), it was skipped
+    pub truncated: bool,
+    /// true if every stream of chunks ends with its final chunk. false if the file is still written, or if the
+    /// program writing it crashed or the file was cut short
+    pub finished: bool,
+}
+
+/// Decrypt a log file encrypted by [`encrypt_log_files`](crate::TracingInit::encrypt_log_files), writing its log
+/// records to `out`
+///
+/// # Errors
+/// [`TracingInitError::EncryptKey`] if the key cannot be loaded, [`TracingInitError::DecryptFile`] if the file cannot
+/// be read, is not an encrypted log file, a chunk cannot be authenticated (the key is wrong or the file was altered)
+/// or a chunk is missing or out of order
+///
+pub fn decrypt_file(
+    path: impl AsRef<Path>,
+    key: &EncryptKey,
+    mut out: impl Write,
+) -> Result<Decrypted, TracingInitError> {
+    let path = path.as_ref();
+    let key = cipher_key(&key.load()?);
+    let error = |source| TracingInitError::DecryptFile {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let mut file = ReadAhead::new(BufReader::new(File::open(path).map_err(error)?));
+    let mut decrypted = Decrypted {
+        bytes: 0,
+        truncated: false,
+        finished: true,
+    };
+    // The nonce prefix and the number of the next chunk of the current stream, None once it is finished
+    let mut stream: Option<(Option<[u8; 8]>, u32)> = None;
+
+    loop {
+        let Some(&tag) = file.peek(1).map_err(error)?.first() else {
+            break;
+        };
+
+        match tag {
+            tag if tag == HEADER[0] => {
+                let header = file.peek(HEADER.len()).map_err(error)?;
+
+                if header.len() < HEADER.len() {
+                    decrypted.truncated = true;
+                    break;
+                }
+
+                if header != HEADER {
+                    if skip_cut_frame(&mut file, HEADER.len(), &mut decrypted).map_err(error)? {
+                        continue;
+                    }
+
+                    return Err(error(invalid_data(
+                        "unsupported encrypted log file version or algorithm",
+                    )));
+                }
+
+                file.consume(HEADER.len());
+
+                // The previous stream was not finished
+                if stream.is_some() {
+                    decrypted.finished = false;
+                }
+                stream = Some((None, 0));
+            }
+            CHUNK | FINAL_CHUNK => {
+                let chunk_header = file.peek(CHUNK_HEADER_LEN).map_err(error)?;
+
+                if chunk_header.len() < CHUNK_HEADER_LEN {
+                    decrypted.truncated = true;
+                    break;
+                }
+
+                let len = CHUNK_HEADER_LEN
+                    + u32::from_le_bytes([
+                        chunk_header[1],
+                        chunk_header[2],
+                        chunk_header[3],
+                        chunk_header[4],
+                    ]) as usize;
+                let mut nonce = [0u8; NONCE_LEN];
+                nonce.copy_from_slice(&chunk_header[5..]);
+
+                let chunk = file.peek(len).map_err(error)?;
+
+                if chunk.len() < len {
+                    if skip_cut_frame(&mut file, len, &mut decrypted).map_err(error)? {
+                        continue;
+                    }
+
+                    decrypted.truncated = true;
+                    break;
+                }
+
+                let mut chunk = chunk[CHUNK_HEADER_LEN..].to_vec();
+                let chunk_prefix: [u8; 8] = nonce[..8].try_into().expect("8 bytes nonce prefix");
+
+                // The chunk follows the previous one of the stream
+                let in_order = stream.as_mut().is_some_and(|(prefix, count)| {
+                    *prefix.get_or_insert(chunk_prefix) == chunk_prefix
+                        && nonce[8..] == count.to_be_bytes()
+                });
+
+                if !in_order {
+                    if skip_cut_frame(&mut file, len, &mut decrypted).map_err(error)? {
+                        continue;
+                    }
+
+                    return Err(error(invalid_data("a chunk is missing or out of order")));
+                }
+
+                let final_chunk = tag == FINAL_CHUNK;
+                let Ok(records) = key.open_in_place(
+                    Nonce::assume_unique_for_key(nonce),
+                    Aad::from(associated_data(final_chunk)),
+                    &mut chunk,
+                ) else {
+                    if skip_cut_frame(&mut file, len, &mut decrypted).map_err(error)? {
+                        continue;
+                    }
+
+                    return Err(error(invalid_data(
+                        "a chunk cannot be authenticated (wrong key or altered file)",
+                    )));
+                };
+
+                file.consume(len);
+
+                if let Some((_, count)) = stream.as_mut() {
+                    *count = count.wrapping_add(1);
+                }
+
+                out.write_all(records).map_err(error)?;
+                decrypted.bytes += records.len() as u64;
+
+                if final_chunk {
+                    stream = None;
+                }
+            }
+            _ => return Err(error(invalid_data("not an encrypted log file"))),
+        }
+    }
+
+    if stream.is_some() || decrypted.truncated {
+        decrypted.finished = false;
+    }
+
+    out.flush().map_err(error)?;
+    Ok(decrypted)
+}
+
+// Skip a frame cut short by a crash when the next run appended a new stream after it: the header of the stream starts
+// within the len bytes of the frame. false if there is no such header
+fn skip_cut_frame(
+    file: &mut ReadAhead<impl Read>,
+    len: usize,
+    decrypted: &mut Decrypted,
+) -> std::io::Result<bool> {
+    let frame = file.peek(len.saturating_add(HEADER.len() - 1))?;
+    let Some(pos) = frame
+        .windows(HEADER.len())
+        .skip(1)
+        .position(|window| window == HEADER)
+    else {
+        return Ok(false);
+    };
+
+    file.consume(pos + 1);
+    decrypted.truncated = true;
+    // The stream of the frame was not finished
+    decrypted.finished = false;
+    Ok(true)
+}
+
+fn invalid_data(reason: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, reason)
+}
+
+// The header bytes and whether the chunk is the final one of its stream
+fn associated_data(final_chunk: bool) -> [u8; HEADER.len() + 1] {
+    let mut aad = [0u8; HEADER.len() + 1];
+
+    aad[..HEADER.len()].copy_from_slice(&HEADER);
+    aad[HEADER.len()] = final_chunk.into();
+    aad
+}
+
+// End the streams of the log files written with these options, the records were flushed
+pub(crate) fn end_streams(options: &FileOptions) {
+    let ciphers = CIPHERS.lock().unwrap_or_else(PoisonError::into_inner);
+
+    for cipher in ciphers.iter().filter_map(Weak::upgrade) {
+        if cipher.options.path == options.path && cipher.options.prefix == options.prefix {
+            cipher.end_stream();
+        }
+    }
+}
+
+fn cipher_key(key: &[u8; KEY_LEN]) -> LessSafeKey {
+    // AES-256 accepts any 32 bytes key
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).expect("AES-256 key length"))
+}
+
+// Encrypts the records of the log files, a new stream of chunks is started for each file
+pub(crate) struct Cipher {
+    key: LessSafeKey,
+    random: SystemRandom,
+    options: FileOptions,
+    stream: Mutex<Stream>,
+}
+
+struct Stream {
+    // The file the chunks are written to, None before the first one and once the stream is ended
+    path: Option<PathBuf>,
+    nonce_prefix: [u8; 8],
+    count: u32,
+}
+
+impl Cipher {
+    // Fails if the key cannot be loaded, the records are never written unencrypted
+    pub(crate) fn new(
+        key: &EncryptKey,
+        options: &FileOptions,
+    ) -> Result<Arc<Cipher>, TracingInitError> {
+        let cipher = Arc::new(Cipher {
+            key: cipher_key(&key.load()?),
+            random: SystemRandom::new(),
+            options: options.clone(),
+            stream: Mutex::new(Stream {
+                path: None,
+                nonce_prefix: [0; 8],
+                count: 0,
+            }),
+        });
+        let mut ciphers = CIPHERS.lock().unwrap_or_else(PoisonError::into_inner);
+
+        ciphers.retain(|cipher| cipher.strong_count() > 0);
+        ciphers.push(Arc::downgrade(&cipher));
+        Ok(cipher)
+    }
+
+    // Encrypt the records and write them as a chunk, preceded by a header when a new file is started
+    fn write_encrypted(&self, writer: &mut impl Write, records: &[u8]) -> std::io::Result<()> {
+        let mut stream = self.stream.lock().unwrap_or_else(PoisonError::into_inner);
+        let path = current_file_path(&self.options);
+        let mut frames =
+            Vec::with_capacity(HEADER.len() + CHUNK_HEADER_LEN + records.len() + TAG_LEN);
+
+        // The file was recreated after it was deleted or renamed, the stream of the previous one cannot be ended
+        if self.options.reopen_if_missing && std::fs::metadata(&path).is_ok_and(|m| m.len() == 0) {
+            stream.path = None;
+        }
+
+        // The file is rotated, or the chunk numbers are exhausted (the last one numbers the final chunk). The
+        // previous file may have been deleted in the meantime
+        if stream.path.as_ref() != Some(&path) || stream.count == u32::MAX {
+            let _ = self.end(&mut stream);
+
+            self.random
+                .fill(&mut stream.nonce_prefix)
+                .map_err(|_| std::io::Error::other("no random nonce available"))?;
+            stream.path = Some(path);
+            stream.count = 0;
+            frames.extend_from_slice(&HEADER);
+        }
+
+        self.seal(&mut stream, records, false, &mut frames)?;
+
+        // Written while locked, the chunks of a file are in the order of their numbers
+        writer.write_all(&frames)
+    }
+
+    // End the stream with its final chunk, the next records start a new one
+    fn end_stream(&self) {
+        let mut stream = self.stream.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let _ = self.end(&mut stream);
+    }
+
+    // Append the final chunk to the file of the stream, if it was started
+    fn end(&self, stream: &mut Stream) -> std::io::Result<()> {
+        let Some(path) = stream.path.take() else {
+            return Ok(());
+        };
+        let mut frame = Vec::with_capacity(CHUNK_HEADER_LEN + TAG_LEN);
+
+        self.seal(stream, &[], true, &mut frame)?;
+        OpenOptions::new()
+            .append(true)
+            .open(path)?
+            .write_all(&frame)
+    }
+
+    // Encrypt the records as the next chunk of the stream, appended to the frames
+    fn seal(
+        &self,
+        stream: &mut Stream,
+        records: &[u8],
+        final_chunk: bool,
+        frames: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..8].copy_from_slice(&stream.nonce_prefix);
+        nonce[8..].copy_from_slice(&stream.count.to_be_bytes());
+        stream.count = stream.count.saturating_add(1);
+
+        let mut chunk = records.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(associated_data(final_chunk)),
+                &mut chunk,
+            )
+            .map_err(|_| std::io::Error::other("the records cannot be encrypted"))?;
+
+        frames.push(if final_chunk { FINAL_CHUNK } else { CHUNK });
+        frames.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        frames.extend_from_slice(&nonce);
+        frames.extend_from_slice(&chunk);
+        Ok(())
+    }
+}
+
+// The log file writer, encrypting each write as a chunk
+pub(crate) struct EncryptWriter<W> {
+    writer: W,
+    cipher: Arc<Cipher>,
+}
+
+impl<W> EncryptWriter<W> {
+    pub(crate) fn new(writer: W, cipher: Arc<Cipher>) -> EncryptWriter<W> {
+        EncryptWriter { writer, cipher }
+    }
+}
+
+// Dropped with the layer, or by the worker thread of a non-blocking writer once the queued records are written
+impl<W> Drop for EncryptWriter<W> {
+    fn drop(&mut self) {
+        self.cipher.end_stream();
+    }
+}
+
+// Writes the records of an event
+pub(crate) struct EncryptedFile<'a, W> {
+    writer: W,
+    cipher: &'a Cipher,
+}
+
+impl<W: Write> Write for EncryptedFile<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.cipher.write_encrypted(&mut self.writer, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<'a, W: MakeWriter<'a>> MakeWriter<'a> for EncryptWriter<W> {
+    type Writer = EncryptedFile<'a, W::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        EncryptedFile {
+            writer: self.writer.make_writer(),
+            cipher: &self.cipher,
+        }
+    }
+}
+
+// The non-blocking writer's worker thread writes the records
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.cipher.write_encrypted(&mut self.writer, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_log_dir, with_env};
+    use crate::{ManualClock, Rotation, TracingInit};
+    use std::time::{Duration, SystemTime};
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn encrypted_log(name: &str, non_blocking: bool) -> PathBuf {
+        let log_dir = test_log_dir(name);
+        let (dispatch, guard) = with_env(&[("LOG_KEY", KEY)], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .log_file_non_blocking(non_blocking)
+                .encrypt_log_files(EncryptKey::env_var("LOG_KEY"))
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::info!("first secret");
+            tracing::warn!(user = "bob", "second secret");
+        });
+        drop(guard);
+
+        log_dir.join("App.log")
+    }
+
+    // The frames of the chunks following the header of an encrypted log file
+    fn chunks(content: &[u8]) -> Vec<std::ops::Range<usize>> {
+        let mut chunks = Vec::new();
+        let mut start = HEADER.len();
+
+        while start < content.len() {
+            let len = u32::from_le_bytes(content[start + 1..start + 5].try_into().unwrap());
+            let end = start + CHUNK_HEADER_LEN + len as usize;
+
+            chunks.push(start..end);
+            start = end;
+        }
+
+        chunks
+    }
+
+    fn decrypt(path: &Path, key: &EncryptKey) -> Result<(String, Decrypted), TracingInitError> {
+        let mut out = Vec::new();
+        let decrypted = decrypt_file(path, key, &mut out)?;
+
+        Ok((String::from_utf8(out).unwrap(), decrypted))
+    }
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let key = EncryptKey::Bytes(parse_hex_key(KEY).unwrap());
+
+        for non_blocking in [false, true] {
+            let path = encrypted_log(&format!("encrypt-{non_blocking}"), non_blocking);
+            let content = std::fs::read(&path).unwrap();
+
+            assert!(content.starts_with(b"TILOGENC\x01\x01\xda"));
+            assert!(!String::from_utf8_lossy(&content).contains("secret"));
+
+            let (text, decrypted) = decrypt(&path, &key).unwrap();
+            let lines = text.lines().collect::<Vec<_>>();
+
+            assert_eq!(lines.len(), 2, "{text}");
+            assert!(lines[0].ends_with("first secret"));
+            assert!(lines[1].ends_with("second secret user=\"bob\""));
+            assert_eq!(
+                decrypted,
+                Decrypted {
+                    bytes: text.len() as u64,
+                    truncated: false,
+                    finished: true,
+                }
+            );
+
+            // The two records and the final chunk
+            let chunks = chunks(&content);
+            assert_eq!(chunks.len(), 3);
+            assert_eq!(content[chunks[2].start], FINAL_CHUNK);
+
+            // A file appended to by another run has two streams
+            std::fs::write(&path, [content.clone(), content].concat()).unwrap();
+            let (text, decrypted) = decrypt(&path, &key).unwrap();
+            assert_eq!(text.lines().count(), 4, "{text}");
+            assert!(decrypted.finished);
+        }
+    }
+
+    #[test]
+    fn test_decrypt_truncated() {
+        let key = EncryptKey::Bytes(parse_hex_key(KEY).unwrap());
+        let path = encrypted_log("encrypt-truncated", false);
+        let content = std::fs::read(&path).unwrap();
+        let second_chunk = chunks(&content)[1].clone();
+
+        // Cut in the middle of the second chunk, then in its header
+        for len in [second_chunk.end - 10, second_chunk.start + 3] {
+            std::fs::write(&path, &content[..len]).unwrap();
+
+            let (text, decrypted) = decrypt(&path, &key).unwrap();
+            assert!(text.ends_with("first secret\n"), "{text}");
+            assert!(decrypted.truncated);
+            assert!(!decrypted.finished);
+        }
+
+        // Cut at the end of a chunk, the stream has no final chunk
+        for len in [second_chunk.start, second_chunk.end] {
+            std::fs::write(&path, &content[..len]).unwrap();
+
+            let (_, decrypted) = decrypt(&path, &key).unwrap();
+            assert!(!decrypted.truncated);
+            assert!(!decrypted.finished);
+        }
+
+        // Cut anywhere in the header or the second chunk by a crash, the next run appended a new stream after it
+        for len in (1..HEADER.len()).chain(second_chunk.start + 1..second_chunk.end) {
+            std::fs::write(&path, [&content[..len], &content].concat()).unwrap();
+
+            let (text, decrypted) = decrypt(&path, &key).unwrap();
+            let lines = text.lines().collect::<Vec<_>>();
+            let expected = if len < HEADER.len() { 2 } else { 3 };
+            assert_eq!(lines.len(), expected, "{len}: {text}");
+            assert!(lines[expected - 1].ends_with("second secret user=\"bob\""));
+            assert!(decrypted.truncated);
+            assert!(!decrypted.finished);
+        }
+
+        // A wrong key is detected
+        std::fs::write(&path, &content).unwrap();
+        let wrong_key = EncryptKey::Bytes([7; KEY_LEN]);
+        assert!(matches!(
+            decrypt(&path, &wrong_key),
+            Err(TracingInitError::DecryptFile { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_altered() {
+        let key = EncryptKey::Bytes(parse_hex_key(KEY).unwrap());
+        let path = encrypted_log("encrypt-altered", false);
+        let content = std::fs::read(&path).unwrap();
+        let other = std::fs::read(encrypted_log("encrypt-altered-other", false)).unwrap();
+        let frames = chunks(&content);
+        let frame = |index: usize| &content[frames[index].clone()];
+        let header = &content[..HEADER.len()];
+
+        let mut not_final = content.clone();
+        not_final[frames[2].start] = CHUNK;
+
+        for altered in [
+            // The second chunk is removed
+            [header, frame(0), frame(2)].concat(),
+            // The chunks are reordered
+            [header, frame(1), frame(0), frame(2)].concat(),
+            // The second chunk is taken from another file with the same key
+            [
+                header,
+                frame(0),
+                &other[chunks(&other)[1].clone()],
+                frame(2),
+            ]
+            .concat(),
+            // The final chunk is marked as a record
+            not_final,
+        ] {
+            std::fs::write(&path, altered).unwrap();
+            assert!(matches!(
+                decrypt(&path, &key),
+                Err(TracingInitError::DecryptFile { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_encrypt_reopened() {
+        let key = EncryptKey::Bytes(parse_hex_key(KEY).unwrap());
+        let log_dir = test_log_dir("encrypt-reopened");
+        let log_file = log_dir.join("App.log");
+        let clock = ManualClock::new(SystemTime::now());
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .reopen_if_missing(true)
+                .encrypt_log_files(key.clone())
+                .with_clock(clock.clone())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("before"));
+        std::fs::remove_file(&log_file).unwrap();
+        clock.advance(Duration::from_secs(2));
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("after"));
+
+        let read = || {
+            decrypt(&log_file, &key)
+                .map(|(text, _)| text)
+                .unwrap_or_default()
+        };
+        wait_until(|| read().contains("it was recreated"));
+        drop(guard);
+
+        // The recreated file starts a new stream
+        let (text, decrypted) = decrypt(&log_file, &key).unwrap();
+        assert!(text.contains("after") && !text.contains("before"), "{text}");
+        assert!(decrypted.finished);
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..500 {
+            if condition() {
+                return;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("timed out");
+    }
+
+    #[test]
+    fn test_encrypt_key_errors() {
+        let log_dir = test_log_dir("encrypt-key-errors");
+        let init = |key: EncryptKey| {
+            with_env(&[("LOG_KEY", "0102")], || {
+                TracingInit::builder("App")
+                    .log_to_file(true)
+                    .log_file_path(log_dir.to_str().unwrap())
+                    .encrypt_log_files(key)
+                    .into_dispatch()
+                    .err()
+            })
+        };
+
+        assert!(matches!(
+            init(EncryptKey::env_var("LOG_KEY")),
+            Some(TracingInitError::EncryptKey { reason, .. }) if reason == "expected 64 hex digits"
+        ));
+        assert!(matches!(
+            init(EncryptKey::env_var("MISSING_LOG_KEY")),
+            Some(TracingInitError::EncryptKey { reason, .. }) if reason == "not set"
+        ));
+        assert!(matches!(
+            init(EncryptKey::file(log_dir.join("missing.key"))),
+            Some(TracingInitError::EncryptKey { .. })
+        ));
+
+        // Nothing was written unencrypted
+        assert!(!log_dir.exists() || std::fs::read_dir(&log_dir).unwrap().count() == 0);
+
+        let key_file = std::env::temp_dir().join("tracing-init-test.key");
+        std::fs::write(&key_file, format!("{KEY}\n")).unwrap();
+        assert_eq!(
+            EncryptKey::file(&key_file).load().unwrap(),
+            parse_hex_key(KEY).unwrap()
+        );
+        assert_eq!(
+            format!("{:?}", EncryptKey::Bytes([1; KEY_LEN])),
+            "Bytes(..)"
+        );
+    }
+}
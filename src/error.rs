@@ -0,0 +1,197 @@
+use std::path::PathBuf;
+
+/// Errors returned while resolving the configuration or initializing the tracing subscriber
+#[derive(Debug, thiserror::Error)]
+pub enum TracingInitError {
+    /// The filter directives could not be parsed
+    #[error("invalid filter directive: {0}")]
+    InvalidFilter(#[from] tracing_subscriber::filter::ParseError),
+
+    /// The log file path cannot be used as a log directory
+    #[error("invalid log file path {}: {reason}", path.display())]
+    InvalidLogFilePath { path: PathBuf, reason: String },
+
+    /// The log server address is not in the format <host>:<port>
+    #[error("invalid log server address '{address}' (expected <host>:<port>)")]
+    InvalidServerAddress { address: String },
+
+    /// The log file could not be created
+    #[cfg(feature = "file")]
+    #[error("failed to create log file in {}", path.display())]
+    FileAppender {
+        path: PathBuf,
+        #[source]
+        source: tracing_appender::rolling::InitError,
+    },
+
+    /// The connection to the log server could not be set up
+    #[error("failed to set up connection to log server {address}")]
+    ServerConnect {
+        address: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// The exporter of the spans could not be created
+    #[cfg(feature = "otel-traces")]
+    #[error("failed to set up the span export to {endpoint}")]
+    SpanExport {
+        endpoint: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A global subscriber (or a `log` logger) is already installed
+    #[error("tracing subscriber is already initialized")]
+    AlreadyInitialized(#[from] tracing::subscriber::SetGlobalDefaultError),
+
+    /// The log server host name cannot be resolved
+    #[error("cannot resolve log server address '{address}'")]
+    UnresolvedServerAddress {
+        address: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A destination was requested (e.g. by LOG_DESTINATION) but the cargo feature it requires is disabled
+    #[error(
+        "cannot log to the {destination}, tracing-init was built without the \"{feature}\" feature"
+    )]
+    DestinationDisabled {
+        destination: &'static str,
+        feature: &'static str,
+    },
+
+    /// The destination cannot write records in this format (e.g. binary records on the console)
+    #[error("the {destination} cannot write {format} records")]
+    UnsupportedFormat {
+        destination: &'static str,
+        format: crate::LogFormat,
+    },
+
+    /// The log file is rotated but no backups are kept
+    #[error("log file backups must be at least 1 when the log file is rotated ({rotation})")]
+    InvalidLogFileBackups { rotation: String },
+
+    /// An environment variable has a value which cannot be parsed (reported only when strict_env_vars is set)
+    #[error("invalid value '{value}' for environment variable {name}")]
+    InvalidEnvVar { name: String, value: String },
+
+    /// A default set when the program was built (see [`compiled_defaults!`](crate::compiled_defaults)) has a value
+    /// which cannot be parsed
+    #[error("invalid value '{value}' for the compile-time default {name}")]
+    InvalidCompiledDefault { name: &'static str, value: String },
+
+    /// A --log-* command line flag has a missing or invalid value
+    #[error("invalid value '{value}' for {flag} (expected {expected})")]
+    InvalidCliArg {
+        flag: String,
+        value: String,
+        expected: &'static str,
+    },
+
+    /// A redaction pattern is not a valid regular expression
+    #[error("invalid redaction pattern '{pattern}': {reason}")]
+    InvalidRedactPattern { pattern: String, reason: String },
+
+    /// A sampling ratio is not between 0 and 1
+    #[error("invalid sampling ratio {ratio} for level {level} (expected 0 to 1)")]
+    InvalidSampleRatio { level: tracing::Level, ratio: f64 },
+
+    /// The filter could not be replaced because the subscriber was dropped
+    #[error("failed to reload the filter")]
+    ReloadFilter(#[source] tracing_subscriber::reload::Error),
+
+    /// The thread ending the startup verbosity boost could not be started
+    #[error("failed to start the startup boost timer")]
+    StartupBoost(#[source] std::io::Error),
+
+    /// The handler of SIGHUP or of the logged signals could not be installed
+    #[cfg(all(unix, feature = "signals"))]
+    #[error("failed to install the signal handler")]
+    SignalHandler(#[source] std::io::Error),
+
+    /// A signal given to [`logged_signals`](crate::TracingInit::logged_signals) is not one which can be logged
+    #[cfg(all(unix, feature = "signals"))]
+    #[error(
+        "unknown signal '{name}' (expected SIGTERM, SIGINT, SIGQUIT, SIGHUP, SIGUSR1 or SIGUSR2)"
+    )]
+    UnknownSignal { name: String },
+
+    /// The admin socket could not be created
+    #[cfg(feature = "admin-socket")]
+    #[error("failed to create the admin socket {address}")]
+    AdminSocket {
+        address: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The config file cannot be read or parsed
+    #[cfg(feature = "config-file")]
+    #[error("invalid config file {}: {reason}", path.display())]
+    ConfigFile { path: PathBuf, reason: String },
+
+    /// The tokio-console address (set by the builder or TOKIO_CONSOLE_BIND) cannot be resolved
+    #[cfg(feature = "tokio-console")]
+    #[error("invalid tokio-console address '{address}' (expected <host>:<port>)")]
+    InvalidTokioConsoleAddress { address: String },
+
+    /// tokio-console is enabled but the runtime is not instrumented
+    #[cfg(feature = "tokio-console")]
+    #[error("tokio-console requires building with RUSTFLAGS=\"--cfg tokio_unstable\"")]
+    TokioUnstableRequired,
+
+    /// The alert webhook URL is not an http:// or https:// URL
+    #[cfg(feature = "webhook")]
+    #[error("invalid webhook URL '{url}' (expected http://... or https://...)")]
+    InvalidWebhookUrl { url: String },
+
+    /// The thread sending the webhook notifications could not be started
+    #[cfg(feature = "webhook")]
+    #[error("failed to start the webhook notification thread")]
+    WebhookThread(#[source] std::io::Error),
+
+    /// The key encrypting the log files cannot be loaded
+    #[cfg(feature = "encrypt")]
+    #[error("cannot load the log file encryption key ({key}): {reason}")]
+    EncryptKey { key: String, reason: String },
+
+    /// An encrypted log file cannot be decrypted
+    #[cfg(feature = "encrypt")]
+    #[error("cannot decrypt the log file {}", path.display())]
+    DecryptFile {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A destination was enabled or disabled on a guard built without
+    /// [`runtime_destinations`](crate::TracingInit::runtime_destinations)
+    #[cfg(any(feature = "file", feature = "server"))]
+    #[error("the destinations cannot be changed at runtime, runtime_destinations was not set")]
+    RuntimeDestinationsDisabled,
+
+    /// More than one configuration problem was found (see [`TracingInit::validate`](crate::TracingInit::validate))
+    #[error("{} configuration problems: {}", .0.len(), join_problems(.0))]
+    Multiple(Vec<TracingInitError>),
+}
+
+impl TracingInitError {
+    // A single problem is returned as is, several are wrapped in Multiple
+    pub(crate) fn from_problems(mut problems: Vec<TracingInitError>) -> TracingInitError {
+        if problems.len() == 1 {
+            problems.remove(0)
+        } else {
+            TracingInitError::Multiple(problems)
+        }
+    }
+}
+
+fn join_problems(problems: &[TracingInitError]) -> String {
+    problems
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
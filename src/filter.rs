@@ -0,0 +1,358 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tracing_subscriber::filter::{filter_fn, FilterFn, LevelFilter};
+use tracing_subscriber::layer::{Context, Filter};
+use tracing_subscriber::{reload, EnvFilter};
+
+use crate::TracingInitError;
+#[cfg(any(all(unix, feature = "signals"), feature = "config-file"))]
+use crate::{ResolvedConfig, TracingInit};
+
+type ReloadFn = dyn Fn(EnvFilter) -> Result<(), reload::Error> + Send + Sync;
+type CurrentFn = dyn Fn() -> Option<String> + Send + Sync;
+type ReloadLevelFn = dyn Fn(Option<LevelFilter>) -> Result<(), reload::Error> + Send + Sync;
+// The destination ('c', 'f', 's' or 'd' as in LOG_DESTINATION) and the reload of the level filter of its layer
+type DestinationFilter = (char, Arc<ReloadLevelFn>);
+
+/// Changes the filter of an installed subscriber at runtime
+///
+/// Obtained from [`TracingGuard::filter_handle`](crate::TracingGuard::filter_handle). Changes take effect for events
+/// emitted after the call, on all the destinations.
+///
+/// # Example
+/// ```
+/// use tracing::level_filters::LevelFilter;
+/// use tracing_init::TracingInit;
+///
+/// let (dispatch, guard) = TracingInit::builder("App")
+///     .log_to_console(true)
+///     .into_dispatch()
+///     .unwrap();
+/// let filter = guard.filter_handle().unwrap();
+///
+/// tracing::dispatcher::with_default(&dispatch, || {
+///     tracing::debug!("filtered");
+///     filter.set_level(LevelFilter::DEBUG).unwrap();
+///     tracing::debug!("logged");
+/// });
+/// ```
+#[derive(Clone)]
+pub struct FilterHandle {
+    reload: Arc<ReloadFn>,
+    current: Arc<CurrentFn>,
+    required_directives: &'static [&'static str],
+    // The number of times the filter was replaced, locked while replacing it
+    generation: Arc<Mutex<u64>>,
+    // The level filters of the destination layers, when they are reloaded with the filter
+    #[cfg_attr(
+        not(any(all(unix, feature = "signals"), feature = "config-file")),
+        allow(dead_code)
+    )]
+    destination_filters: Option<DestinationFilters>,
+}
+
+impl FilterHandle {
+    // The required directives are added to every filter set by the handle
+    pub(crate) fn new<S: 'static>(
+        handle: reload::Handle<EnvFilter, S>,
+        required_directives: &'static [&'static str],
+        destination_filters: Option<DestinationFilters>,
+    ) -> FilterHandle {
+        let current = handle.clone();
+
+        FilterHandle {
+            reload: Arc::new(move |filter| handle.reload(filter)),
+            current: Arc::new(move || current.with_current(|filter| filter.to_string()).ok()),
+            required_directives,
+            generation: Arc::default(),
+            destination_filters,
+        }
+    }
+
+    /// Set the default level, directives for specific targets are kept
+    ///
+    pub fn set_level(&self, level: LevelFilter) -> Result<(), TracingInitError> {
+        self.set_filter(&with_default_level(&self.current(), level))
+    }
+
+    /// Replace the filter with new directives (same syntax as RUST_LOG)
+    ///
+    /// # Errors
+    /// [`TracingInitError::InvalidFilter`] if the directives cannot be parsed, the active filter is left unchanged
+    ///
+    pub fn set_filter(&self, directives: &str) -> Result<(), TracingInitError> {
+        self.replace(EnvFilter::try_new(add_directives(
+            directives,
+            self.required_directives,
+        ))?)
+    }
+
+    pub(crate) fn replace(&self, filter: EnvFilter) -> Result<(), TracingInitError> {
+        let mut generation = self
+            .generation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        self.reload_locked(&mut generation, filter)
+    }
+
+    // Replace the filter unless it was replaced since generation was read. Returns false if it was
+    pub(crate) fn replace_if_unchanged(
+        &self,
+        generation: u64,
+        filter: EnvFilter,
+    ) -> Result<bool, TracingInitError> {
+        let mut current = self
+            .generation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if *current != generation {
+            return Ok(false);
+        }
+
+        self.reload_locked(&mut current, filter)?;
+        Ok(true)
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        *self
+            .generation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn reload_locked(
+        &self,
+        generation: &mut u64,
+        filter: EnvFilter,
+    ) -> Result<(), TracingInitError> {
+        (self.reload)(filter).map_err(TracingInitError::ReloadFilter)?;
+        *generation += 1;
+
+        #[cfg(feature = "log-compat")]
+        crate::log_compat::update_max_level();
+
+        Ok(())
+    }
+
+    // Compute the filter again from the builder values, the environment variables (and config file) and replace
+    // the active one, with the levels of the destinations. Returns the old and the new filter directives
+    #[cfg(any(all(unix, feature = "signals"), feature = "config-file"))]
+    pub(crate) fn reload_from(
+        &self,
+        builder: &TracingInit,
+    ) -> Result<(String, String), TracingInitError> {
+        let mut t = builder.clone();
+
+        t.set_from_environment_variables();
+
+        let old = self.current();
+        let config = ResolvedConfig::new(&t);
+        let filter = config.get_env_filter()?;
+        let new = filter.to_string();
+
+        if let Some(destination_filters) = &self.destination_filters {
+            destination_filters.set_levels(&config)?;
+        }
+
+        self.replace(filter)?;
+        Ok((old, new))
+    }
+
+    /// The directives of the active filter
+    ///
+    pub fn current(&self) -> String {
+        (self.current)().unwrap_or_default()
+    }
+}
+
+// The level filters of the destination layers, which can be reloaded when the levels of the destinations change while
+// running (e.g. from c:info,f:debug to c:info,f:warn in the config file)
+#[derive(Clone, Default)]
+pub(crate) struct DestinationFilters(Arc<Mutex<Vec<DestinationFilter>>>);
+
+impl DestinationFilters {
+    // The reloadable filter of a destination's layer. make builds the filter for the level of the destination (None
+    // if it has no level of its own)
+    pub(crate) fn add<S, F>(
+        &self,
+        destination: char,
+        level: Option<LevelFilter>,
+        make: impl Fn(Option<LevelFilter>) -> F + Send + Sync + 'static,
+    ) -> reload::Layer<F, S>
+    where
+        S: tracing::Subscriber,
+        F: Send + Sync + 'static,
+    {
+        let (filter, handle) = reload::Layer::new(make(level));
+
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).push((
+            destination,
+            Arc::new(move |level| handle.reload(make(level))),
+        ));
+        filter
+    }
+
+    // Set the levels of the destinations to those of the configuration
+    #[cfg(any(all(unix, feature = "signals"), feature = "config-file"))]
+    fn set_levels(&self, config: &ResolvedConfig) -> Result<(), TracingInitError> {
+        let filters = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+
+        for (destination, reload) in filters.iter() {
+            reload(config.destination_level_filter(*destination))
+                .map_err(TracingInitError::ReloadFilter)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for DestinationFilters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let filters = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+
+        f.debug_tuple("DestinationFilters")
+            .field(
+                &filters
+                    .iter()
+                    .map(|(destination, _)| *destination)
+                    .collect::<String>(),
+            )
+            .finish()
+    }
+}
+
+// Replace the default level of the directives, directives for specific targets are kept
+pub(crate) fn with_default_level(directives: &str, level: LevelFilter) -> String {
+    let mut directives = directives
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty() && directive.parse::<LevelFilter>().is_err())
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    directives.push(level.to_string());
+    directives.join(",")
+}
+
+// Add the directives which are not already present
+pub(crate) fn add_directives(directives: &str, required_directives: &[&str]) -> String {
+    let mut directives = directives
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .collect::<Vec<_>>();
+
+    for required in required_directives {
+        if !directives.contains(required) {
+            directives.push(required);
+        }
+    }
+
+    directives.join(",")
+}
+
+impl std::fmt::Debug for FilterHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FilterHandle")
+            .field("current", &self.current())
+            .finish()
+    }
+}
+
+// The filter of span_level and event_level, each kind of callsite has its level. The targets of the filter directives
+// are left to the directives
+pub(crate) fn span_event_levels(
+    span_level: LevelFilter,
+    event_level: LevelFilter,
+    directives: &str,
+) -> FilterFn<impl Fn(&tracing::Metadata<'_>) -> bool + Send + Sync + 'static> {
+    let targets = directive_targets(directives);
+
+    filter_fn(move |metadata| {
+        if targets
+            .iter()
+            .any(|target| metadata.target().starts_with(target.as_str()))
+        {
+            true
+        } else if metadata.is_span() {
+            span_level >= *metadata.level()
+        } else {
+            event_level >= *metadata.level()
+        }
+    })
+}
+
+// The targets the directives apply to, e.g. "hyper" for "hyper=debug" and "app::db" for "app::db[query]=trace"
+fn directive_targets(directives: &str) -> Vec<String> {
+    directives
+        .split(',')
+        .map(str::trim)
+        .filter_map(|directive| {
+            let target = directive
+                .split_once('=')
+                .map_or(directive, |(target, _)| target);
+            let target = target.split('[').next().unwrap_or(target).trim();
+
+            // A level alone applies to every target, a span filter alone to every target too
+            (!target.is_empty() && target.parse::<LevelFilter>().is_err())
+                .then(|| target.to_string())
+        })
+        .collect()
+}
+
+type MetadataFilterFn = dyn Fn(&tracing::Metadata<'_>) -> bool + Send + Sync;
+type EventFilterFn = dyn Fn(&tracing::Event<'_>) -> bool + Send + Sync;
+
+// The closures added by with_filter_fn and with_event_filter_fn, an event is logged only if all of them accept it
+#[derive(Clone, Default)]
+pub(crate) struct CustomFilters {
+    metadata: Vec<Arc<MetadataFilterFn>>,
+    event: Vec<Arc<EventFilterFn>>,
+}
+
+impl CustomFilters {
+    pub(crate) fn add_metadata_filter(
+        &mut self,
+        f: impl Fn(&tracing::Metadata<'_>) -> bool + Send + Sync + 'static,
+    ) {
+        self.metadata.push(Arc::new(f));
+    }
+
+    pub(crate) fn add_event_filter(
+        &mut self,
+        f: impl Fn(&tracing::Event<'_>) -> bool + Send + Sync + 'static,
+    ) {
+        self.event.push(Arc::new(f));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.metadata.is_empty() && self.event.is_empty()
+    }
+
+    // Both the filters and those of other
+    pub(crate) fn extend(&mut self, other: &CustomFilters) {
+        self.metadata.extend(other.metadata.iter().cloned());
+        self.event.extend(other.event.iter().cloned());
+    }
+}
+
+impl<S> Filter<S> for CustomFilters {
+    fn enabled(&self, metadata: &tracing::Metadata<'_>, _cx: &Context<'_, S>) -> bool {
+        self.metadata.iter().all(|f| f(metadata))
+    }
+
+    fn event_enabled(&self, event: &tracing::Event<'_>, _cx: &Context<'_, S>) -> bool {
+        self.event.iter().all(|f| f(event))
+    }
+}
+
+impl std::fmt::Debug for CustomFilters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomFilters")
+            .field("metadata", &self.metadata.len())
+            .field("event", &self.event.len())
+            .finish()
+    }
+}
@@ -0,0 +1,317 @@
+use std::cell::Cell;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tracing::{Event, Subscriber};
+use tracing_appender::non_blocking::{ErrorCounter, NonBlocking, NonBlockingBuilder, WorkerGuard};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+// How often the dropped lines are reported (at most, a report is emitted by the next event)
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+// How long the write of a report waits for room in a full queue
+const EXEMPT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+thread_local! {
+    // Set while a report is logged, its lines wait for room in the queue instead of being dropped
+    static EXEMPT: Cell<bool> = const { Cell::new(false) };
+}
+
+// Counts the bytes queued to a non-blocking writer and the bytes written by its worker thread, so the queued records
+// can be waited for without shutting the worker down
+#[derive(Debug, Default)]
+pub(crate) struct QueueTracker {
+    queued: AtomicU64,
+    written: Mutex<u64>,
+    written_changed: Condvar,
+}
+
+impl QueueTracker {
+    // Wait (up to timeout) until the bytes queued so far are written, returns false on timeout. Records dropped
+    // because the queue is full are never written, in this case the full timeout elapses
+    pub(crate) fn wait_until_written(&self, timeout: Duration) -> bool {
+        let queued = self.queued.load(Ordering::Acquire);
+        let written = self.written.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let (_written, result) = self
+            .written_changed
+            .wait_timeout_while(written, timeout, |written| *written < queued)
+            .unwrap_or_else(PoisonError::into_inner);
+
+        !result.timed_out()
+    }
+
+    fn add_queued(&self, bytes: usize) {
+        self.queued.fetch_add(bytes as u64, Ordering::AcqRel);
+    }
+
+    fn add_written(&self, bytes: usize) {
+        *self.written.lock().unwrap_or_else(PoisonError::into_inner) += bytes as u64;
+        self.written_changed.notify_all();
+    }
+}
+
+// Counts the lines dropped by a non-blocking writer because its queue is full
+pub(crate) struct DropTracker {
+    counter: ErrorCounter,
+    // Lines of reports which were dropped and written again, they are not lost
+    resent: AtomicU64,
+    // The dropped lines already reported, and when the next periodic report is due (ms since created)
+    reported: AtomicU64,
+    next_report: AtomicU64,
+    last_report: Mutex<Instant>,
+    created: Instant,
+}
+
+impl DropTracker {
+    fn new(counter: ErrorCounter) -> DropTracker {
+        let now = Instant::now();
+
+        DropTracker {
+            counter,
+            resent: AtomicU64::new(0),
+            reported: AtomicU64::new(0),
+            next_report: AtomicU64::new(REPORT_INTERVAL.as_millis() as u64),
+            last_report: Mutex::new(now),
+            created: now,
+        }
+    }
+
+    // The number of lines dropped so far
+    pub(crate) fn dropped(&self) -> u64 {
+        (self.counter.dropped_lines() as u64).saturating_sub(self.resent.load(Ordering::Acquire))
+    }
+
+    // Log a WARN event if lines were dropped since the last report
+    pub(crate) fn report(&self) {
+        let dropped = self.dropped();
+        let unreported = dropped.saturating_sub(self.reported.swap(dropped, Ordering::AcqRel));
+
+        if unreported == 0 {
+            return;
+        }
+
+        let elapsed = {
+            let mut last_report = self
+                .last_report
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let elapsed = last_report.elapsed();
+
+            *last_report = Instant::now();
+            elapsed
+        };
+
+        EXEMPT.set(true);
+        tracing::warn!(
+            target: "tracing_init",
+            writer = "file",
+            dropped = unreported,
+            "file writer dropped {unreported} lines in the last {}s",
+            elapsed.as_secs().max(1)
+        );
+        EXEMPT.set(false);
+    }
+
+    // Report if the report interval elapsed, only one of the threads calling it at the same time reports
+    fn report_periodically(&self) {
+        let now = self.created.elapsed().as_millis() as u64;
+        let next_report = self.next_report.load(Ordering::Acquire);
+
+        if now >= next_report
+            && self
+                .next_report
+                .compare_exchange(
+                    next_report,
+                    now + REPORT_INTERVAL.as_millis() as u64,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_ok()
+        {
+            self.report();
+        }
+    }
+}
+
+// Triggers the periodic report of the lines dropped by the log file writer
+pub(crate) struct DropReportLayer {
+    drops: Arc<DropTracker>,
+}
+
+impl DropReportLayer {
+    pub(crate) fn new(drops: Arc<DropTracker>) -> DropReportLayer {
+        DropReportLayer { drops }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for DropReportLayer {
+    fn on_event(&self, _event: &Event<'_>, _ctx: Context<'_, S>) {
+        // The report is an event as well, it finds the next report not due yet
+        self.drops.report_periodically();
+    }
+}
+
+// Keeps the non-blocking writer of the log file alive, dropping it flushes the queued records and stops the worker
+// thread
+pub(crate) struct FileGuard {
+    pub(crate) worker: WorkerGuard,
+    pub(crate) tracker: Arc<QueueTracker>,
+    pub(crate) drops: Arc<DropTracker>,
+}
+
+// The writer used by the layer, queues the records for the worker thread
+#[derive(Clone)]
+pub(crate) struct TrackedNonBlocking {
+    writer: NonBlocking,
+    tracker: Arc<QueueTracker>,
+    drops: Arc<DropTracker>,
+}
+
+// The writer used by the worker thread
+struct TrackedWriter<W> {
+    writer: W,
+    tracker: Arc<QueueTracker>,
+}
+
+// Same as tracing_appender::non_blocking (dropping lines when more than buffered_lines are queued), the guard can
+// also wait for the queued records to be written and count the dropped lines
+pub(crate) fn non_blocking<W: Write + Send + 'static>(
+    writer: W,
+    buffered_lines: usize,
+) -> (TrackedNonBlocking, FileGuard) {
+    let tracker = Arc::new(QueueTracker::default());
+    let (writer, worker) = NonBlockingBuilder::default()
+        .buffered_lines_limit(buffered_lines)
+        .lossy(true)
+        .finish(TrackedWriter {
+            writer,
+            tracker: tracker.clone(),
+        });
+    let drops = Arc::new(DropTracker::new(writer.error_counter()));
+
+    (
+        TrackedNonBlocking {
+            writer,
+            tracker: tracker.clone(),
+            drops: drops.clone(),
+        },
+        FileGuard {
+            worker,
+            tracker,
+            drops,
+        },
+    )
+}
+
+impl TrackedNonBlocking {
+    // Queue a line of a report, waiting for room if the queue is full. A line found dropped is written again, the
+    // report is never lost (unless the worker is stuck for longer than the timeout)
+    fn write_exempt(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let deadline = Instant::now() + EXEMPT_WRITE_TIMEOUT;
+
+        loop {
+            let dropped = self.drops.counter.dropped_lines();
+            let bytes = self.writer.write(buf)?;
+
+            if self.drops.counter.dropped_lines() == dropped || Instant::now() >= deadline {
+                return Ok(bytes);
+            }
+
+            self.drops.resent.fetch_add(1, Ordering::AcqRel);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+impl Write for TrackedNonBlocking {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let bytes = if EXEMPT.get() {
+            self.write_exempt(buf)?
+        } else {
+            self.writer.write(buf)?
+        };
+
+        self.tracker.add_queued(bytes);
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for TrackedNonBlocking {
+    type Writer = TrackedNonBlocking;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+impl<W: Write> Write for TrackedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let bytes = self.writer.write(buf)?;
+
+        self.tracker.add_written(bytes);
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{test_log_dir, with_env, CaptureLayer};
+    use crate::{Rotation, TracingInit};
+    use tracing::Level;
+
+    #[test]
+    fn test_dropped_lines_are_reported() {
+        let log_dir = test_log_dir("dropped");
+        let capture = CaptureLayer::default();
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .log_file_non_blocking(true)
+                .log_file_buffered_lines(1)
+                .with_layer(capture.clone())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            for i in 0..20_000 {
+                tracing::info!(i, "overflowing the queue with a long enough line");
+            }
+
+            assert!(guard.dropped_counts().file > 0);
+
+            // Reported when the guard is dropped
+            drop(guard);
+        });
+
+        let reports = capture
+            .events()
+            .into_iter()
+            .filter(|(level, target, message)| {
+                *level == Level::WARN
+                    && target == "tracing_init"
+                    && message.starts_with("file writer dropped ")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(reports.len(), 1, "{reports:?}");
+
+        // The report itself is not dropped
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        assert!(log.contains(&reports[0].2), "{}", reports[0].2);
+    }
+}
@@ -0,0 +1,549 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+
+use tracing::callsite::{Callsite, Identifier};
+use tracing::field::{Field, FieldSet, Value, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::metadata::Kind;
+use tracing::subscriber::Interest;
+use tracing::{span, Dispatch, Event, Metadata, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::correlation::{self, CorrelationIds};
+use crate::redact::Captured;
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+use crate::structured::has_structured_fields;
+use crate::task_ids::{self, TASK_ID};
+
+// The most fields a callsite can have
+pub(crate) const MAX_FIELDS: usize = 32;
+
+/// The value of a global field, see [`TracingInit::global_field`](crate::TracingInit::global_field)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    Str(String),
+    I64(i64),
+    U64(u64),
+    Bool(bool),
+}
+
+impl FieldValue {
+    fn as_value(&self) -> &dyn Value {
+        match self {
+            FieldValue::Str(value) => value,
+            FieldValue::I64(value) => value,
+            FieldValue::U64(value) => value,
+            FieldValue::Bool(value) => value,
+        }
+    }
+}
+
+impl std::fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Str(value) => write!(f, "{value}"),
+            FieldValue::I64(value) => write!(f, "{value}"),
+            FieldValue::U64(value) => write!(f, "{value}"),
+            FieldValue::Bool(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::Str(value.to_string())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        FieldValue::Str(value)
+    }
+}
+
+impl From<i64> for FieldValue {
+    fn from(value: i64) -> Self {
+        FieldValue::I64(value)
+    }
+}
+
+impl From<i32> for FieldValue {
+    fn from(value: i32) -> Self {
+        FieldValue::I64(value.into())
+    }
+}
+
+impl From<u64> for FieldValue {
+    fn from(value: u64) -> Self {
+        FieldValue::U64(value)
+    }
+}
+
+impl From<u32> for FieldValue {
+    fn from(value: u32) -> Self {
+        FieldValue::U64(value.into())
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        FieldValue::Bool(value)
+    }
+}
+
+// The callsite of leaked metadata
+pub(crate) struct ExtendedCallsite(OnceLock<&'static Metadata<'static>>);
+
+impl Callsite for ExtendedCallsite {
+    fn set_interest(&self, _interest: Interest) {}
+
+    fn metadata(&self) -> &Metadata<'_> {
+        self.0
+            .get()
+            .expect("the metadata is set when the callsite is created")
+    }
+}
+
+// Event metadata like the given metadata with another target and fields, with its own (never registered) callsite.
+// Leaked, so it should be created once per callsite
+pub(crate) fn leak_metadata(
+    metadata: &Metadata<'static>,
+    target: &'static str,
+    names: Vec<&'static str>,
+) -> &'static Metadata<'static> {
+    let callsite: &'static ExtendedCallsite =
+        Box::leak(Box::new(ExtendedCallsite(OnceLock::new())));
+    let extended: &'static Metadata<'static> = Box::leak(Box::new(Metadata::new(
+        metadata.name(),
+        target,
+        *metadata.level(),
+        metadata.file(),
+        metadata.line(),
+        metadata.module_path(),
+        FieldSet::new(Box::leak(names.into_boxed_slice()), Identifier(callsite)),
+        Kind::EVENT,
+    )));
+
+    let _ = callsite.0.set(extended);
+    extended
+}
+
+struct GlobalFields {
+    // Leaked once, they are part of the extended metadata. The correlation ids and the task id follow the fields
+    // with values
+    names: Vec<&'static str>,
+    values: Vec<FieldValue>,
+    correlation_ids: bool,
+    task_ids: bool,
+    // The extended metadata of each event callsite, None if the event has all the global fields
+    extended: RwLock<HashMap<Identifier, Option<&'static Metadata<'static>>>>,
+}
+
+impl GlobalFields {
+    fn extended(&self, metadata: &'static Metadata<'static>) -> Option<&'static Metadata<'static>> {
+        let callsite = metadata.callsite();
+
+        if let Some(extended) = self
+            .extended
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&callsite)
+        {
+            return *extended;
+        }
+
+        *self
+            .extended
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(callsite)
+            .or_insert_with(|| self.extend(metadata))
+    }
+
+    // The metadata of the event followed by the global fields it does not have. Leaked, once per callsite
+    fn extend(&self, metadata: &'static Metadata<'static>) -> Option<&'static Metadata<'static>> {
+        let fields = metadata.fields();
+        let mut names = fields.iter().map(|field| field.name()).collect::<Vec<_>>();
+        let count = names.len();
+
+        names.extend(
+            self.names
+                .iter()
+                .filter(|name| fields.field(*name).is_none())
+                .take(MAX_FIELDS.saturating_sub(count)),
+        );
+
+        if names.len() == count {
+            return None;
+        }
+
+        Some(leak_metadata(metadata, metadata.target(), names))
+    }
+
+    fn value(&self, name: &str) -> Option<&FieldValue> {
+        self.names
+            .iter()
+            .position(|n| *n == name)
+            .and_then(|index| self.values.get(index))
+    }
+}
+
+// Without valuable support the structured fields are recorded as Debug strings by every layer
+#[cfg(not(all(tracing_unstable, feature = "valuable")))]
+fn has_structured_fields(_event: &Event<'_>) -> bool {
+    false
+}
+
+// The values of the event (or span), by field index
+pub(crate) struct Capture(pub(crate) Vec<(usize, Captured)>);
+
+impl Visit for Capture {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.push((field.index(), Captured::I64(value)));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.push((field.index(), Captured::U64(value)));
+    }
+
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        self.0.push((field.index(), Captured::I128(value)));
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        self.0.push((field.index(), Captured::U128(value)));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.push((field.index(), Captured::F64(value)));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.push((field.index(), Captured::Bool(value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0
+            .push((field.index(), Captured::Str(value.to_string())));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push((
+            field.index(),
+            Captured::Display(tracing::field::display(format!("{value:?}"))),
+        ));
+    }
+
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    fn record_value(&mut self, field: &Field, value: valuable::Value<'_>) {
+        self.0.push((
+            field.index(),
+            Captured::Str(crate::structured::to_json(value)),
+        ));
+    }
+}
+
+// Passes the events to the wrapped layer with the global fields added, so they are formatted like the event's own
+// fields. Fields of the event with the same name as a global field are kept.
+//
+// With correlation ids, the trace_id and span_id fields of the closest spans carrying them are added as well, and
+// with task ids the task_id field of the events logged by a tokio task. The
+// fields recorded with tracing::field::valuable are passed on as JSON strings when the event is rewritten (always,
+// for a wrapped layer which can only record them as Debug strings)
+pub(crate) struct GlobalFieldsLayer<L> {
+    inner: L,
+    fields: Arc<GlobalFields>,
+    structured_as_json: bool,
+}
+
+impl<L> GlobalFieldsLayer<L> {
+    pub(crate) fn new(
+        inner: L,
+        fields: &[(String, FieldValue)],
+        correlation_ids: bool,
+        task_ids: bool,
+    ) -> GlobalFieldsLayer<L> {
+        let mut names = fields
+            .iter()
+            .map(|(name, _)| &*Box::leak(name.clone().into_boxed_str()))
+            .collect::<Vec<_>>();
+
+        if correlation_ids {
+            for name in [correlation::TRACE_ID, correlation::SPAN_ID] {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+
+        if task_ids && !names.contains(&TASK_ID) {
+            names.push(TASK_ID);
+        }
+
+        GlobalFieldsLayer {
+            inner,
+            fields: Arc::new(GlobalFields {
+                names,
+                values: fields.iter().map(|(_, value)| value.clone()).collect(),
+                correlation_ids,
+                task_ids,
+                extended: RwLock::default(),
+            }),
+            structured_as_json: false,
+        }
+    }
+
+    // Rewrite the events with structured fields, even if there is nothing to add (for the GELF layer)
+    #[cfg(feature = "server")]
+    pub(crate) fn structured_as_json(mut self, v: bool) -> GlobalFieldsLayer<L> {
+        self.structured_as_json = v;
+        self
+    }
+}
+
+impl<S, L> Layer<S> for GlobalFieldsLayer<L>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    L: Layer<S>,
+{
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        self.inner.on_register_dispatch(subscriber);
+    }
+
+    fn on_layer(&mut self, subscriber: &mut S) {
+        self.inner.on_layer(subscriber);
+    }
+
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        self.inner.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.enabled(metadata, ctx)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        self.inner.max_level_hint()
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.event_enabled(event, ctx)
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if self.fields.correlation_ids {
+            CorrelationIds::on_new_span(attrs, id, &ctx);
+        }
+
+        self.inner.on_new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        if self.fields.correlation_ids {
+            CorrelationIds::on_record(id, values, &ctx);
+        }
+
+        self.inner.on_record(id, values, ctx);
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_follows_from(id, follows, ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let ids = self
+            .fields
+            .correlation_ids
+            .then(|| CorrelationIds::of_event(event, &ctx))
+            .flatten();
+        let task_id = self.fields.task_ids.then(task_ids::current).flatten();
+
+        // Nothing to add: no global field the event does not have, no correlation ids outside of the spans and no task
+        // id outside of the tasks
+        let metadata = match self
+            .fields
+            .extended(event.metadata())
+            .filter(|_| !self.fields.values.is_empty() || ids.is_some() || task_id.is_some())
+        {
+            Some(metadata) => metadata,
+            None if self.structured_as_json && has_structured_fields(event) => event.metadata(),
+            None => return self.inner.on_event(event, ctx),
+        };
+
+        let mut capture = Capture(Vec::new());
+
+        event.record(&mut capture);
+
+        let fields = metadata.fields().iter().collect::<Vec<_>>();
+        let own = event.metadata().fields().len();
+        let mut entries: [(&Field, Option<&dyn Value>); MAX_FIELDS] =
+            [(&fields[0], None); MAX_FIELDS];
+
+        for (index, value) in &capture.0 {
+            entries[*index] = (&fields[*index], Some(value.as_value()));
+        }
+
+        for (index, field) in fields.iter().enumerate().skip(own) {
+            let value = match self.fields.value(field.name()) {
+                Some(value) => Some(value.as_value()),
+                None if field.name() == TASK_ID => task_id.as_ref().map(|id| id as &dyn Value),
+                None => ids
+                    .as_ref()
+                    .and_then(|ids| ids.value(field.name()))
+                    .map(|value| value as &dyn Value),
+            };
+
+            entries[index] = (field, value);
+        }
+
+        let values = metadata.fields().value_set(&entries);
+        let event = if event.is_contextual() {
+            Event::new(metadata, &values)
+        } else {
+            Event::new_child_of(event.parent().cloned(), metadata, &values)
+        };
+
+        self.inner.on_event(&event, ctx);
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.inner.on_close(id, ctx);
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_id_change(old, new, ctx);
+    }
+
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const _ as *const ())
+        } else {
+            self.inner.downcast_raw(id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{self, ConsoleOptions};
+    #[cfg(feature = "file")]
+    use crate::tests::test_log_dir;
+    #[cfg(any(feature = "file", feature = "server"))]
+    use crate::tests::with_env;
+    use crate::tests::MemoryWriter;
+    use crate::LogFormat;
+    #[cfg(any(feature = "file", feature = "server"))]
+    use crate::TracingInit;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn global_fields() -> Vec<(String, FieldValue)> {
+        vec![
+            (String::from("service"), FieldValue::from("billing")),
+            (String::from("instance_id"), FieldValue::from(7)),
+            (String::from("canary"), FieldValue::from(false)),
+        ]
+    }
+
+    #[test]
+    fn test_global_fields_console() {
+        let console = MemoryWriter::default();
+        let options = ConsoleOptions {
+            format: LogFormat::Json,
+            global_fields: global_fields(),
+            ..ConsoleOptions::default()
+        };
+        let subscriber = tracing_subscriber::registry()
+            .with(layers::console_with_writer(&options, console.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(user = "bob", "login");
+            // The event's own field is kept
+            tracing::info!(service = "auth", "forwarded");
+        });
+
+        let lines = console.contents();
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""user":"bob""#), "{}", lines[0]);
+        assert!(lines[0].contains(r#""service":"billing""#), "{}", lines[0]);
+        assert!(lines[0].contains(r#""instance_id":7"#), "{}", lines[0]);
+        assert!(lines[0].contains(r#""canary":false"#), "{}", lines[0]);
+        assert!(lines[1].contains(r#""service":"auth""#), "{}", lines[1]);
+        assert!(!lines[1].contains("billing"), "{}", lines[1]);
+        assert!(lines[1].contains(r#""instance_id":7"#), "{}", lines[1]);
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_global_fields_file() {
+        let log_dir = test_log_dir("global-fields");
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(crate::Rotation::Never)
+                .global_fields([("service", "billing"), ("region", "eu")])
+                .global_field("region", "us")
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            let span = tracing::info_span!("request", id = 12);
+            let _entered = span.enter();
+
+            tracing::info!("charged");
+        });
+        drop(guard);
+
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        assert!(
+            log.contains(r#"request{id=12}: tracing_init::global_fields::tests: charged service="billing" region="us""#),
+            "{log}"
+        );
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_global_fields_server() {
+        let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let (dispatch, _guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_console(false)
+                .log_to_server(true)
+                .log_server_address(&address)
+                .global_fields(global_fields())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        // Wait for the connection before logging
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!(canary = true, "login"));
+
+        let mut buffer = vec![0; 8192];
+        let len = tokio::time::timeout(std::time::Duration::from_secs(5), server.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        let record = String::from_utf8_lossy(&buffer[..len]);
+
+        assert!(record.contains(r#""_service":"billing""#), "{record}");
+        assert!(record.contains(r#""_instance_id":7"#), "{record}");
+        // GELF has no booleans, tracing-gelf sends the boolean fields of the events as strings
+        assert!(record.contains(r#""_canary":"true""#), "{record}");
+    }
+}
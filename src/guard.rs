@@ -0,0 +1,650 @@
+use std::fmt::Display;
+use std::sync::Arc;
+#[cfg(any(feature = "file", feature = "server"))]
+use std::sync::OnceLock;
+use std::time::Duration;
+
+#[cfg(any(feature = "file", feature = "server"))]
+use tracing::dispatcher::WeakDispatch;
+
+use crate::crash_report::CrashReport;
+#[cfg(feature = "file")]
+use crate::flush::FileGuard;
+#[cfg(feature = "file")]
+use crate::layers::FileOptions;
+#[cfg(feature = "server")]
+use crate::layers::ServerGuard;
+#[cfg(feature = "otel-traces")]
+use crate::otel::SpanExportGuard;
+#[cfg(any(feature = "file", feature = "server"))]
+use crate::runtime::DestinationSwitch;
+#[cfg(any(feature = "file", feature = "server"))]
+use crate::TracingInitError;
+use crate::{FilterHandle, MetricsHandle, ResolvedConfig};
+
+/// The number of log lines dropped by each destination, returned by [`TracingGuard::dropped_counts`]
+///
+/// Only the non-blocking log file writer drops lines (when its queue is full, see
+/// [`log_file_buffered_lines`](crate::TracingInit::log_file_buffered_lines)). The queue of the log server layer does
+/// not report the messages it drops, so they are not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DroppedCounts {
+    /// The lines dropped by the log file writer
+    pub file: u64,
+}
+
+/// A destination skipped by [`lenient`](crate::TracingInit::lenient) initialization, returned by
+/// [`TracingGuard::destination_failures`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct DestinationFailure {
+    /// The destination: "log file" or "log server"
+    pub destination: &'static str,
+    /// Why the destination could not be initialized
+    pub error: crate::TracingInitError,
+}
+
+impl Display for DestinationFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.destination, self.error)
+    }
+}
+
+/// Keeps the log destinations alive, returned by [`TracingInit::init`](crate::TracingInit::init)
+///
+/// The guard owns the background writers and the log server connection. Keep it alive for the duration of the
+/// program (e.g. `let _guard = ...` in `main`). When it is dropped the log file buffers are flushed and the
+/// queued log server messages are sent (bounded by the shutdown timeout).
+///
+/// `let _ = ...` does not bind the guard: it is dropped at the end of the statement, and the compiler does not warn
+/// about it as it does for an unused guard. Enable clippy's `let_underscore_must_use` lint to catch it.
+///
+/// Display prints the effective configuration.
+#[must_use = "dropping the guard immediately stops non-blocking writers and the log server connection"]
+pub struct TracingGuard {
+    config: Arc<ResolvedConfig>,
+
+    #[cfg(feature = "file")]
+    file_guard: Option<FileGuard>,
+    #[cfg(feature = "file")]
+    file_dispatch: Option<Arc<OnceLock<WeakDispatch>>>,
+    #[cfg(feature = "server")]
+    server: Option<Arc<ServerGuard>>,
+    #[cfg(any(feature = "file", feature = "server"))]
+    runtime_destinations: Option<Box<dyn DestinationSwitch>>,
+    // The dispatch given to set_dispatch, for the log file and log server enabled at runtime
+    #[cfg(any(feature = "file", feature = "server"))]
+    dispatch: OnceLock<WeakDispatch>,
+    filter_handle: Option<FilterHandle>,
+    metrics: Option<MetricsHandle>,
+    crash_report: Option<Arc<CrashReport>>,
+    destination_failures: Vec<DestinationFailure>,
+    #[cfg(feature = "otel-traces")]
+    span_export: Option<SpanExportGuard>,
+    startup_boost: Option<crate::startup_boost::StartupBoost>,
+    #[cfg(all(unix, feature = "signals"))]
+    sighup_reloader: Option<crate::signals::SighupReloader>,
+    #[cfg(all(unix, feature = "signals"))]
+    signal_logger: Option<crate::signals::SignalLogger>,
+    #[cfg(feature = "admin-socket")]
+    admin_socket: Option<crate::admin::AdminSocket>,
+    #[cfg(feature = "config-file")]
+    config_watcher: Option<crate::config_file::ConfigWatcher>,
+    #[cfg(feature = "webhook")]
+    webhook: Option<crate::webhook::Webhook>,
+}
+
+impl TracingGuard {
+    pub(crate) fn new(config: ResolvedConfig) -> TracingGuard {
+        TracingGuard {
+            config: Arc::new(config),
+            #[cfg(feature = "file")]
+            file_guard: None,
+            #[cfg(feature = "file")]
+            file_dispatch: None,
+            #[cfg(feature = "server")]
+            server: None,
+            #[cfg(any(feature = "file", feature = "server"))]
+            runtime_destinations: None,
+            #[cfg(any(feature = "file", feature = "server"))]
+            dispatch: OnceLock::new(),
+            filter_handle: None,
+            metrics: None,
+            crash_report: None,
+            destination_failures: Vec::new(),
+            #[cfg(feature = "otel-traces")]
+            span_export: None,
+            startup_boost: None,
+            #[cfg(all(unix, feature = "signals"))]
+            sighup_reloader: None,
+            #[cfg(all(unix, feature = "signals"))]
+            signal_logger: None,
+            #[cfg(feature = "admin-socket")]
+            admin_socket: None,
+            #[cfg(feature = "config-file")]
+            config_watcher: None,
+            #[cfg(feature = "webhook")]
+            webhook: None,
+        }
+    }
+
+    #[cfg(feature = "file")]
+    pub(crate) fn set_file_guard(&mut self, file_guard: Option<FileGuard>) {
+        self.file_guard = file_guard;
+    }
+
+    #[cfg(feature = "file")]
+    pub(crate) fn set_file_dispatch(&mut self, dispatch: Option<Arc<OnceLock<WeakDispatch>>>) {
+        self.file_dispatch = dispatch;
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_server(&mut self, server: Option<ServerGuard>) {
+        self.server = server.map(Arc::new);
+    }
+
+    #[cfg(any(feature = "file", feature = "server"))]
+    pub(crate) fn set_runtime_destinations(
+        &mut self,
+        runtime_destinations: Option<Box<dyn DestinationSwitch>>,
+    ) {
+        self.runtime_destinations = runtime_destinations;
+    }
+
+    pub(crate) fn set_filter_handle(&mut self, filter_handle: FilterHandle) {
+        self.filter_handle = Some(filter_handle);
+    }
+
+    pub(crate) fn set_metrics(&mut self, metrics: MetricsHandle) {
+        self.metrics = Some(metrics);
+    }
+
+    pub(crate) fn set_crash_report(&mut self, crash_report: Arc<CrashReport>) {
+        self.crash_report = Some(crash_report);
+    }
+
+    pub(crate) fn set_destination_failures(&mut self, failures: Vec<DestinationFailure>) {
+        self.destination_failures = failures;
+    }
+
+    #[cfg(feature = "webhook")]
+    pub(crate) fn set_webhook(&mut self, webhook: Option<crate::webhook::Webhook>) {
+        self.webhook = webhook;
+    }
+
+    #[cfg(feature = "otel-traces")]
+    pub(crate) fn set_span_export(&mut self, span_export: Option<SpanExportGuard>) {
+        self.span_export = span_export;
+    }
+
+    // The buffer of the crash report, the panic hook writing it holds a weak reference
+    pub(crate) fn crash_report(&self) -> Option<&Arc<CrashReport>> {
+        self.crash_report.as_ref()
+    }
+
+    // Start the timer ending the startup boost, it is stopped when the guard is dropped
+    pub(crate) fn with_startup_boost(
+        mut self,
+        steady: String,
+        duration: Duration,
+    ) -> Result<TracingGuard, crate::TracingInitError> {
+        let filter_handle = self
+            .filter_handle
+            .clone()
+            .expect("the subscriber has a reloadable filter");
+
+        self.startup_boost = Some(crate::startup_boost::StartupBoost::start(
+            filter_handle,
+            steady,
+            duration,
+        )?);
+        Ok(self)
+    }
+
+    // The background threads (the end of the startup boost, the closing of the console, the log file deletion
+    // failures and recreation, the log server resolution failures, the logged signals and the webhook delivery
+    // failures) log to this dispatch rather than to the global default one
+    pub(crate) fn set_dispatch(&self, dispatch: &tracing::Dispatch) {
+        let _ = self.config.console_dispatch().set(dispatch.downgrade());
+
+        if let Some(startup_boost) = &self.startup_boost {
+            startup_boost.set_dispatch(dispatch);
+        }
+
+        #[cfg(feature = "file")]
+        if let Some(file_dispatch) = &self.file_dispatch {
+            let _ = file_dispatch.set(dispatch.downgrade());
+        }
+
+        #[cfg(feature = "server")]
+        if let Some(server) = &self.server {
+            server.set_dispatch(dispatch.downgrade());
+        }
+
+        #[cfg(any(feature = "file", feature = "server"))]
+        let _ = self.dispatch.set(dispatch.downgrade());
+
+        #[cfg(all(unix, feature = "signals"))]
+        if let Some(signal_logger) = &self.signal_logger {
+            signal_logger.set_dispatch(dispatch);
+        }
+
+        #[cfg(feature = "webhook")]
+        if let Some(webhook) = &self.webhook {
+            webhook.set_dispatch(dispatch);
+        }
+    }
+
+    // Start reloading the filter on SIGHUP, the reloader is stopped when the guard is dropped
+    #[cfg(all(unix, feature = "signals"))]
+    pub(crate) fn with_sighup_reloader(
+        mut self,
+        builder: crate::TracingInit,
+    ) -> Result<TracingGuard, crate::TracingInitError> {
+        let filter_handle = self
+            .filter_handle
+            .clone()
+            .expect("the subscriber built by init has a reloadable filter");
+
+        self.sighup_reloader = Some(crate::signals::SighupReloader::start(
+            builder,
+            filter_handle,
+        )?);
+        Ok(self)
+    }
+
+    // Start logging the signals, the logger is stopped when the guard is dropped
+    #[cfg(all(unix, feature = "signals"))]
+    pub(crate) fn with_signal_logger(
+        mut self,
+        signals: &[String],
+    ) -> Result<TracingGuard, crate::TracingInitError> {
+        self.signal_logger = Some(crate::signals::SignalLogger::start(
+            signals,
+            self.panic_flush(),
+        )?);
+        Ok(self)
+    }
+
+    // Start serving the admin socket, it is stopped and removed when the guard is dropped
+    #[cfg(feature = "admin-socket")]
+    pub(crate) fn with_admin_socket(
+        mut self,
+        address: &str,
+    ) -> Result<TracingGuard, crate::TracingInitError> {
+        let filter_handle = self
+            .filter_handle
+            .clone()
+            .expect("the subscriber built by init has a reloadable filter");
+
+        self.admin_socket = Some(crate::admin::AdminSocket::start(
+            address,
+            self.config.clone(),
+            filter_handle,
+        )?);
+        Ok(self)
+    }
+
+    // Start watching the config file, the watcher is stopped when the guard is dropped
+    #[cfg(feature = "config-file")]
+    pub(crate) fn with_config_watcher(
+        mut self,
+        builder: crate::TracingInit,
+    ) -> Result<TracingGuard, crate::TracingInitError> {
+        let filter_handle = self
+            .filter_handle
+            .clone()
+            .expect("the subscriber built by init has a reloadable filter");
+
+        self.config_watcher = Some(crate::config_file::ConfigWatcher::start(
+            builder,
+            filter_handle,
+            crate::config_file::POLL_INTERVAL,
+        )?);
+        Ok(self)
+    }
+
+    // The destinations the panic hook flushes
+    pub(crate) fn panic_flush(&self) -> crate::panic_hook::Flush {
+        crate::panic_hook::Flush {
+            #[cfg(feature = "file")]
+            file: self
+                .file_guard
+                .as_ref()
+                .map(|file_guard| Arc::downgrade(&file_guard.tracker))
+                .unwrap_or_default(),
+            #[cfg(feature = "server")]
+            server: self.server.as_ref().map(Arc::downgrade).unwrap_or_default(),
+            #[cfg(feature = "file")]
+            timeout: self.config.shutdown_timeout(),
+        }
+    }
+
+    /// The configuration the subscriber was built from
+    pub fn config(&self) -> &ResolvedConfig {
+        &self.config
+    }
+
+    /// The handle for changing the filter at runtime
+    ///
+    /// Available when the subscriber was built by [`init`](crate::TracingInit::init),
+    /// [`into_subscriber`](crate::TracingInit::into_subscriber) or [`into_dispatch`](crate::TracingInit::into_dispatch),
+    /// but not by [`build_layers`](crate::TracingInit::build_layers) (the filter is handed over to the caller)
+    ///
+    pub fn filter_handle(&self) -> Option<&FilterHandle> {
+        self.filter_handle.as_ref()
+    }
+
+    /// The destinations skipped because they could not be initialized, when [`lenient`](crate::TracingInit::lenient)
+    /// is set (empty otherwise: [`init`](crate::TracingInit::init) fails instead)
+    pub fn destination_failures(&self) -> &[DestinationFailure] {
+        &self.destination_failures
+    }
+
+    /// The number of log lines dropped so far because a queue was full
+    pub fn dropped_counts(&self) -> DroppedCounts {
+        DroppedCounts {
+            #[cfg(feature = "file")]
+            file: self
+                .file_guard
+                .as_ref()
+                .map_or(0, |file_guard| file_guard.drops.dropped()),
+            ..Default::default()
+        }
+    }
+
+    /// Check that logging works: log a probe record and verify that it reached each configured destination
+    ///
+    /// The probe is an event of the `tracing_init` target with a `diagnosis_probe` field, at the most severe of the
+    /// levels of the configured destinations so that each of them logs it (e.g. WARN when the log file has the WARN
+    /// level and the console the default INFO level). It is logged to the current default subscriber, call it within
+    /// [`with_default`](tracing::dispatcher::with_default) when the subscriber was built by
+    /// [`into_dispatch`](crate::TracingInit::into_dispatch).
+    ///
+    /// - console: stdout is flushed without error
+    /// - file: the probe record is found in the end of the most recent log file, written since the probe was logged
+    ///   (binary records are decoded, an encrypted log file is not supported and fails the check)
+    /// - server: the probe record is handed to the connection, which is still running (UDP is not acknowledged)
+    ///
+    /// Waits up to the [shutdown timeout](crate::TracingInit::shutdown_timeout) for the log file to be written.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[cfg(feature = "file")] {
+    /// # use tracing_init::TracingInit;
+    /// let guard = TracingInit::builder("App").with_file(true).init().unwrap();
+    /// let report = guard.diagnose();
+    ///
+    /// if !report.is_ok() {
+    ///     eprintln!("logging is broken: {report}");
+    /// }
+    /// # }
+    /// ```
+    pub fn diagnose(&self) -> crate::DiagnosisReport {
+        crate::diagnose::diagnose(
+            &self.config,
+            #[cfg(feature = "server")]
+            self.server.as_deref(),
+            self.config.shutdown_timeout(),
+        )
+    }
+
+    /// The event counters, when enabled by [`with_metrics`](crate::TracingInit::with_metrics)
+    pub fn metrics(&self) -> Option<&MetricsHandle> {
+        self.metrics.as_ref()
+    }
+
+    /// Start writing the log records to a log file, replacing the current one if any
+    ///
+    /// The log file is checked and created as by [`init`](crate::TracingInit::init), the records are written to it as
+    /// soon as this returns. The records of a replaced log file are flushed. The [configuration](Self::config)
+    /// reflects the new log file, the settings shared with the console (source locations, global fields, correlation
+    /// ids and clock) are unchanged. Start from [`ResolvedConfig::file_options`] to keep the other settings:
+    /// ```no_run
+    /// # use tracing_init::TracingInit;
+    /// let mut guard = TracingInit::builder("App")
+    ///     .with_console(true)
+    ///     .with_runtime_destinations(true)
+    ///     .init()
+    ///     .unwrap();
+    ///
+    /// let mut options = guard.config().file_options();
+    /// options.prefix = String::from("App-debug");
+    /// guard.enable_file(&options).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    /// [`TracingInitError::RuntimeDestinationsDisabled`] if the subscriber was built without
+    /// [`runtime_destinations`](crate::TracingInit::runtime_destinations), otherwise the error
+    /// [`init`](crate::TracingInit::init) would return for these options. The current log file is kept on error
+    ///
+    #[cfg(feature = "file")]
+    pub fn enable_file(&mut self, options: &FileOptions) -> Result<(), TracingInitError> {
+        let mut config = ResolvedConfig::clone(&self.config);
+
+        config.set_log_file(Some(options));
+
+        let problems = config.log_file_problems();
+
+        if !problems.is_empty() {
+            return Err(TracingInitError::from_problems(problems));
+        }
+
+        let (file_guard, file_dispatch) = self.switch()?.set_file(Some(options))?;
+
+        if let (Some(file_dispatch), Some(dispatch)) = (&file_dispatch, self.dispatch.get()) {
+            let _ = file_dispatch.set(dispatch.clone());
+        }
+
+        self.replace_file_guard(file_guard);
+        self.file_dispatch = file_dispatch;
+        self.config = Arc::new(config);
+        Ok(())
+    }
+
+    /// Stop writing the log records to the log file, the records written so far are flushed
+    ///
+    /// # Errors
+    /// [`TracingInitError::RuntimeDestinationsDisabled`] if the subscriber was built without
+    /// [`runtime_destinations`](crate::TracingInit::runtime_destinations)
+    ///
+    #[cfg(feature = "file")]
+    pub fn disable_file(&mut self) -> Result<(), TracingInitError> {
+        self.switch()?.set_file(None)?;
+        self.replace_file_guard(None);
+        self.file_dispatch = None;
+        Arc::make_mut(&mut self.config).set_log_file(None);
+        Ok(())
+    }
+
+    /// Start sending the log records to a log server at the address (<host>:<port>), replacing the current one if any
+    ///
+    /// Must be called from within a tokio runtime, as [`init`](crate::TracingInit::init) with a log server. The
+    /// queued records of a replaced log server are sent (bounded by the shutdown timeout).
+    ///
+    /// # Errors
+    /// [`TracingInitError::RuntimeDestinationsDisabled`] if the subscriber was built without
+    /// [`runtime_destinations`](crate::TracingInit::runtime_destinations), otherwise the error
+    /// [`init`](crate::TracingInit::init) would return for this address. The current log server is kept on error
+    ///
+    #[cfg(feature = "server")]
+    pub fn enable_server(&mut self, address: &str) -> Result<(), TracingInitError> {
+        let mut config = ResolvedConfig::clone(&self.config);
+
+        config.set_log_server(Some(address));
+
+        let problems = config.log_server_problems();
+
+        if !problems.is_empty() {
+            return Err(TracingInitError::from_problems(problems));
+        }
+
+        let server = self.switch()?.set_server(Some(&config.server_options()))?;
+
+        if let (Some(server), Some(dispatch)) = (&server, self.dispatch.get()) {
+            server.set_dispatch(dispatch.clone());
+        }
+
+        self.set_server(server);
+        self.config = Arc::new(config);
+        Ok(())
+    }
+
+    /// Stop sending the log records to the log server, the queued records are sent (bounded by the shutdown timeout)
+    ///
+    /// # Errors
+    /// [`TracingInitError::RuntimeDestinationsDisabled`] if the subscriber was built without
+    /// [`runtime_destinations`](crate::TracingInit::runtime_destinations)
+    ///
+    #[cfg(feature = "server")]
+    pub fn disable_server(&mut self) -> Result<(), TracingInitError> {
+        self.switch()?.set_server(None)?;
+        self.set_server(None);
+        Arc::make_mut(&mut self.config).set_log_server(None);
+        Ok(())
+    }
+
+    /// Reopen the log file and restart the log server connection in the child process of a fork
+    ///
+    /// A child process (e.g. a daemon started by a double fork) inherits the subscriber but not the threads of its
+    /// parent: the writer thread of a non-blocking log file and the log server connection task are gone, so their
+    /// records would be queued and never written. A daemon closing the inherited file descriptors closes the log file
+    /// as well. Call this in the child, right after the fork and before logging, to replace the layers of the log file
+    /// and log server by new ones created with the same configuration. The subscriber stays installed.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[cfg(feature = "file")] {
+    /// use tracing_init::TracingInit;
+    ///
+    /// let mut guard = TracingInit::builder("App")
+    ///     .with_file(true)
+    ///     .with_runtime_destinations(true)
+    ///     .init()
+    ///     .unwrap();
+    ///
+    /// // Forked by daemonizing
+    /// guard.reinit_after_fork().unwrap();
+    /// tracing::info!("running as a daemon");
+    /// # }
+    /// ```
+    ///
+    /// # Notes
+    /// The subscriber must be built with [`runtime_destinations`](crate::TracingInit::runtime_destinations). With a
+    /// log server, this must be called from within the tokio runtime of the child, the runtime of the parent cannot be
+    /// used after a fork. Only the thread calling fork exists in the child: if another thread of the parent was
+    /// writing a record at that time, the child may deadlock on the lock it held, so fork before starting other
+    /// threads (as daemonizing usually does). The threads of the parent are not waited for: the records queued when
+    /// forking are written by the parent only, and the other background threads (the startup boost, the signal
+    /// handling, the admin socket, the config file watcher and the webhook) are not restarted.
+    ///
+    /// # Errors
+    /// [`TracingInitError::RuntimeDestinationsDisabled`] if the subscriber was built without
+    /// [`runtime_destinations`](crate::TracingInit::runtime_destinations), otherwise the error
+    /// [`init`](crate::TracingInit::init) would return when creating the log file or connecting to the log server
+    ///
+    #[cfg(all(unix, any(feature = "file", feature = "server")))]
+    pub fn reinit_after_fork(&mut self) -> Result<(), TracingInitError> {
+        self.switch()?;
+
+        #[cfg(feature = "file")]
+        if self.config.log_to_file() {
+            let (file_guard, file_dispatch) =
+                self.switch()?.set_file(Some(&self.config.file_options()))?;
+
+            if let (Some(file_dispatch), Some(dispatch)) = (&file_dispatch, self.dispatch.get()) {
+                let _ = file_dispatch.set(dispatch.clone());
+            }
+
+            // Dropping it would wait for the writer thread of the parent
+            std::mem::forget(std::mem::replace(&mut self.file_guard, file_guard));
+            self.file_dispatch = file_dispatch;
+        }
+
+        #[cfg(feature = "server")]
+        if self.config.log_to_server() {
+            let server = self
+                .switch()?
+                .set_server(Some(&self.config.server_options()))?;
+
+            if let (Some(server), Some(dispatch)) = (&server, self.dispatch.get()) {
+                server.set_dispatch(dispatch.clone());
+            }
+
+            // Dropping it would wait for the connection task of the parent
+            std::mem::forget(self.server.take());
+            self.set_server(server);
+        }
+
+        Ok(())
+    }
+
+    // The destinations which can be replaced, the layers are swapped before the previous guards are dropped
+    #[cfg(any(feature = "file", feature = "server"))]
+    fn switch(&self) -> Result<&dyn DestinationSwitch, TracingInitError> {
+        self.runtime_destinations
+            .as_deref()
+            .ok_or(TracingInitError::RuntimeDestinationsDisabled)
+    }
+
+    // Replace the guard of the log file, the lines dropped by the previous one are reported before it is flushed
+    #[cfg(feature = "file")]
+    fn replace_file_guard(&mut self, file_guard: Option<FileGuard>) {
+        if let Some(previous) = std::mem::replace(&mut self.file_guard, file_guard) {
+            previous.drops.report();
+        }
+    }
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        self.startup_boost.take();
+        #[cfg(all(unix, feature = "signals"))]
+        self.sighup_reloader.take();
+        #[cfg(all(unix, feature = "signals"))]
+        self.signal_logger.take();
+        #[cfg(feature = "admin-socket")]
+        self.admin_socket.take();
+        #[cfg(feature = "config-file")]
+        self.config_watcher.take();
+
+        // Shutting the tracer provider down exports the queued spans
+        #[cfg(feature = "otel-traces")]
+        self.span_export.take();
+
+        // Dropping the webhook sends the pending notification
+        #[cfg(feature = "webhook")]
+        self.webhook.take();
+
+        // Dropping the server guard sends the queued log server messages
+        #[cfg(feature = "server")]
+        self.server.take();
+
+        // Dropping the worker guard flushes the buffered log records, the lines dropped until now are reported first
+        #[cfg(feature = "file")]
+        if let Some(file_guard) = self.file_guard.take() {
+            file_guard.drops.report();
+        }
+
+        // The final chunk of an encrypted log file follows the records flushed above
+        #[cfg(feature = "encrypt")]
+        if self.config.log_to_file() {
+            crate::encrypt::end_streams(&self.config.file_options());
+        }
+    }
+}
+
+impl std::fmt::Debug for TracingGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TracingGuard")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for TracingGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.config.fmt(f)
+    }
+}
@@ -0,0 +1,444 @@
+use std::any::TypeId;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tracing::level_filters::LevelFilter;
+use tracing::subscriber::Interest;
+use tracing::{span, Dispatch, Event, Level, Metadata, Subscriber};
+use tracing_subscriber::fmt::writer::EitherWriter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::clock::ClockSource;
+use crate::global_fields::{FieldValue, GlobalFieldsLayer};
+
+// The field marking the records written after the fact
+const HINDSIGHT_FIELD: &str = "hindsight";
+
+/// Keep the recent events too verbose for the log file in memory, they are written to it when an error occurs (see
+/// [`TracingInit::hindsight`](crate::TracingInit::hindsight))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hindsight {
+    /// The level of the records written to the log file, the more verbose events are kept in memory
+    pub file_level: Level,
+    /// The most verbose level of the kept events
+    pub level: Level,
+    /// The most events kept, the oldest ones are dropped
+    pub capacity: usize,
+    /// The events kept for longer are not written
+    pub window: Duration,
+    /// The events of this level or more severe write the kept events before them
+    pub trigger: Level,
+}
+
+// Where the record being formatted is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Route {
+    File,
+    Kept,
+    // To the file, after writing the kept records
+    AfterKept,
+}
+
+thread_local! {
+    static ROUTE: Cell<Route> = const { Cell::new(Route::File) };
+}
+
+fn with_route(route: Route, f: impl FnOnce()) {
+    let previous = ROUTE.with(|current| current.replace(route));
+
+    f();
+    ROUTE.with(|current| current.set(previous));
+}
+
+// The most recent records too verbose for the log file, formatted as they would have been written
+pub(crate) struct Kept {
+    capacity: usize,
+    window: Duration,
+    clock: ClockSource,
+    records: Mutex<VecDeque<(Instant, Vec<u8>)>>,
+}
+
+impl Kept {
+    pub(crate) fn new(hindsight: &Hindsight, clock: ClockSource) -> Kept {
+        Kept {
+            capacity: hindsight.capacity,
+            window: hindsight.window,
+            clock,
+            records: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn push(&self, record: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let time = self.clock.instant();
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // The oldest record's allocation is reused once the buffer is full
+        let mut buffer = if records.len() == self.capacity {
+            records
+                .pop_front()
+                .map(|(_, buffer)| buffer)
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        buffer.clear();
+        buffer.extend_from_slice(record);
+        records.push_back((time, buffer));
+    }
+
+    // Take the records of the window. The lock is released before they are written, the records kept meanwhile are
+    // written by the next error, never twice
+    fn take(&self) -> VecDeque<(Instant, Vec<u8>)> {
+        let mut records =
+            std::mem::take(&mut *self.records.lock().unwrap_or_else(PoisonError::into_inner));
+        let now = self.clock.instant();
+
+        records.retain(|(time, _)| now.saturating_duration_since(*time) <= self.window);
+        records
+    }
+}
+
+// Writes a record to the kept ones, each record is formatted into a single write
+pub(crate) struct KeptWriter<'a>(&'a Kept);
+
+impl Write for KeptWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.push(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// The writer of the log file layer, the records of the kept events are written to memory
+pub(crate) struct HindsightWriter<W> {
+    writer: W,
+    kept: Arc<Kept>,
+}
+
+impl<W> HindsightWriter<W> {
+    pub(crate) fn new(writer: W, kept: Arc<Kept>) -> HindsightWriter<W> {
+        HindsightWriter { writer, kept }
+    }
+}
+
+impl<'a, W> MakeWriter<'a> for HindsightWriter<W>
+where
+    W: MakeWriter<'a>,
+{
+    type Writer = EitherWriter<W::Writer, KeptWriter<'a>>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        match ROUTE.with(Cell::get) {
+            Route::Kept => return EitherWriter::B(KeptWriter(&self.kept)),
+            Route::AfterKept => {
+                // Best effort, as the records written by the layer
+                for (_, record) in self.kept.take() {
+                    let _ = self.writer.make_writer().write_all(&record);
+                }
+            }
+            Route::File => {}
+        }
+
+        EitherWriter::A(self.writer.make_writer())
+    }
+}
+
+// Passes the events of the kept levels to the wrapped layer on their own
+struct Marked<L>(Arc<L>);
+
+impl<S, L> Layer<S> for Marked<L>
+where
+    S: Subscriber,
+    L: Layer<S>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        self.0.on_event(event, ctx);
+    }
+}
+
+// Routes the events of the log file layer: the ones too verbose for the file are kept (marked with the hindsight
+// field) and the errors write the kept ones before them. The layer writes to a HindsightWriter sharing the kept records
+pub(crate) struct HindsightLayer<L> {
+    inner: Arc<L>,
+    marked: GlobalFieldsLayer<Marked<L>>,
+    file_level: Level,
+    level: Level,
+    trigger: Level,
+}
+
+impl<L> HindsightLayer<L> {
+    pub(crate) fn new(inner: L, hindsight: &Hindsight) -> HindsightLayer<L> {
+        let inner = Arc::new(inner);
+
+        HindsightLayer {
+            marked: GlobalFieldsLayer::new(
+                Marked(inner.clone()),
+                &[(String::from(HINDSIGHT_FIELD), FieldValue::Bool(true))],
+                false,
+                false,
+            ),
+            inner,
+            file_level: hindsight.file_level,
+            level: hindsight.level,
+            trigger: hindsight.trigger,
+        }
+    }
+}
+
+impl<S, L> Layer<S> for HindsightLayer<L>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    L: Layer<S>,
+{
+    // The wrapped layer is shared with the marking layer, so on_layer is not passed on: the log file layers do not
+    // use it
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        self.inner.on_register_dispatch(subscriber);
+    }
+
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        self.inner.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.enabled(metadata, ctx)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        self.inner.max_level_hint()
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.event_enabled(event, ctx)
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        self.inner.on_record(id, values, ctx);
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_follows_from(id, follows, ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+
+        if level > self.file_level {
+            if level <= self.level {
+                with_route(Route::Kept, || self.marked.on_event(event, ctx));
+            }
+        } else if level <= self.trigger {
+            with_route(Route::AfterKept, || self.inner.on_event(event, ctx));
+        } else {
+            self.inner.on_event(event, ctx);
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.inner.on_close(id, ctx);
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_id_change(old, new, ctx);
+    }
+
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const _ as *const ())
+        } else {
+            self.inner.downcast_raw(id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use crate::tests::{test_log_dir, with_env};
+    use crate::{LogFormat, ManualClock, Rotation, TracingInit};
+
+    use super::*;
+
+    fn builder(log_dir: &std::path::Path) -> TracingInit {
+        let mut builder = TracingInit::builder("App");
+
+        builder
+            .log_to_file(true)
+            .log_file_path(log_dir.to_str().unwrap())
+            .log_file_rotation(Rotation::Never)
+            .level(Level::INFO);
+        builder
+    }
+
+    #[test]
+    fn test_hindsight() {
+        let log_dir = test_log_dir("hindsight");
+        let (dispatch, guard) = with_env(&[], || {
+            builder(&log_dir)
+                .hindsight(Level::DEBUG, 3, Duration::from_secs(60))
+                .into_dispatch()
+                .unwrap()
+        });
+
+        assert!(
+            guard.to_string().contains("hindsight DEBUG, 3 events"),
+            "{guard}"
+        );
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::info!("started");
+
+            for n in 0..5 {
+                tracing::debug!(n, "step");
+            }
+
+            tracing::trace!("too verbose");
+            tracing::error!("failed");
+            tracing::debug!("after the error");
+        });
+        drop(guard);
+
+        let contents = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+
+        // The last 3 DEBUG events, marked and written before the error
+        assert_eq!(lines.len(), 5, "{contents}");
+        assert!(lines[0].ends_with(": started"), "{contents}");
+
+        for (line, n) in lines[1..4].iter().zip(2..) {
+            assert!(line.contains(" DEBUG "), "{contents}");
+            assert!(
+                line.ends_with(&format!(": step n={n} hindsight=true")),
+                "{contents}"
+            );
+        }
+
+        assert!(lines[4].contains(" ERROR "), "{contents}");
+        assert!(lines[4].ends_with(": failed"), "{contents}");
+    }
+
+    #[test]
+    fn test_hindsight_window() {
+        let log_dir = test_log_dir("hindsight-window");
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_792_108_800));
+        let (dispatch, guard) = with_env(&[], || {
+            builder(&log_dir)
+                .log_file_format(LogFormat::Json)
+                .hindsight(Level::DEBUG, 100, Duration::from_secs(30))
+                .hindsight_trigger(Level::WARN)
+                .with_clock(clock.clone())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::debug!("too old");
+            clock.advance(Duration::from_secs(60));
+            tracing::debug!("recent");
+            tracing::warn!("slow response");
+            tracing::error!("failed");
+        });
+        drop(guard);
+
+        let contents = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        let messages = contents
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|record| {
+                (
+                    record["fields"]["message"].as_str().unwrap().to_string(),
+                    record["fields"]["hindsight"].as_bool(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Written once, by the first event of the trigger level
+        assert_eq!(
+            messages,
+            [
+                (String::from("recent"), Some(true)),
+                (String::from("slow response"), None),
+                (String::from("failed"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hindsight_concurrent_errors() {
+        let log_dir = test_log_dir("hindsight-concurrent");
+        let (dispatch, guard) = with_env(&[], || {
+            builder(&log_dir)
+                .hindsight(Level::DEBUG, 50, Duration::from_secs(60))
+                .into_dispatch()
+                .unwrap()
+        });
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let dispatch = dispatch.clone();
+
+                scope.spawn(move || {
+                    tracing::dispatcher::with_default(&dispatch, || {
+                        for n in 0..500 {
+                            tracing::debug!("record {thread}-{n}");
+
+                            if n % 50 == 49 {
+                                tracing::error!("failed {thread}-{n}");
+                            }
+                        }
+                    })
+                });
+            }
+        });
+        drop(guard);
+
+        let contents = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        let mut kept = contents
+            .lines()
+            .filter(|line| line.ends_with(" hindsight=true"))
+            .collect::<Vec<_>>();
+        let count = kept.len();
+
+        kept.sort_unstable();
+        kept.dedup();
+
+        // Each kept record is written whole, once at most
+        assert!(count > 0);
+        assert_eq!(kept.len(), count, "{contents}");
+        assert!(kept.iter().all(|line| line.contains(": record ")));
+        assert_eq!(
+            contents
+                .lines()
+                .filter(|line| line.contains(" ERROR "))
+                .count(),
+            40
+        );
+    }
+}
@@ -0,0 +1,723 @@
+//! Constructors for the individual console, log file and log server layers
+//!
+//! These are the building blocks used by [`TracingInit::init`](crate::TracingInit::init). Use them to add a single
+//! destination to a subscriber stack of your own:
+//! ```
+//! # #[cfg(feature = "file")] {
+//! use tracing_init::layers::{self, FileOptions};
+//! use tracing_subscriber::layer::SubscriberExt;
+//!
+//! let options = FileOptions {
+//!     path: std::env::temp_dir(),
+//!     prefix: String::from("my-app"),
+//!     ..Default::default()
+//! };
+//! let (file_layer, _file_guard) = layers::file(&options).unwrap();
+//!
+//! let subscriber = tracing_subscriber::registry()
+//!     .with(layers::console(&Default::default()))
+//!     .with(file_layer);
+//!
+//! tracing::subscriber::with_default(subscriber, || {
+//!     tracing::info!("logged to the console and to my-app.<date>.log");
+//! });
+//! # }
+//! ```
+//!
+//! [`ResolvedConfig`](crate::ResolvedConfig) provides the options matching a resolved configuration (e.g.
+//! [`ResolvedConfig::file_options`](crate::ResolvedConfig::file_options)).
+
+#[cfg(feature = "file")]
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+#[cfg(any(feature = "file", feature = "server"))]
+use std::time::Duration;
+
+use tracing::dispatcher::WeakDispatch;
+use tracing::Subscriber;
+#[cfg(feature = "file")]
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::aligned::AlignedFormat;
+#[cfg(feature = "file")]
+use crate::binlog::BinlogLayer;
+use crate::broken_pipe::ConsoleWriter;
+use crate::clock::ClockSource;
+use crate::cloud_json::{Cloud, CloudJson};
+#[cfg(windows)]
+use crate::debug_output::DebugOutput;
+#[cfg(feature = "encrypt")]
+use crate::encrypt::{Cipher, EncryptWriter};
+#[cfg(feature = "file")]
+use crate::flush::{self, FileGuard};
+use crate::global_fields::GlobalFieldsLayer;
+#[cfg(feature = "file")]
+use crate::hindsight::{Hindsight, HindsightLayer, HindsightWriter, Kept};
+#[cfg(feature = "file")]
+use crate::reopen::ReopenWriter;
+#[cfg(feature = "file")]
+use crate::retention::{RetainedWriter, Retention};
+#[cfg(feature = "server")]
+use crate::server::ServerLayer;
+#[cfg(feature = "encrypt")]
+use crate::EncryptKey;
+#[cfg(feature = "file")]
+use crate::Rotation;
+#[cfg(any(feature = "file", feature = "server"))]
+use crate::TracingInitError;
+use crate::{Clock, ConsoleStyle, FieldValue, LogFormat};
+
+#[cfg(feature = "server")]
+pub use crate::server::ServerGuard;
+
+// The fmt layer of the console and of the log file, before setting the writer
+pub(crate) type FmtLayer<S> = tracing_subscriber::fmt::Layer<
+    S,
+    tracing_subscriber::fmt::format::DefaultFields,
+    tracing_subscriber::fmt::format::Format<tracing_subscriber::fmt::format::Full, ClockSource>,
+>;
+
+// The full format console layer writing to stdout
+pub(crate) type StdoutLayer<S> = tracing_subscriber::fmt::Layer<
+    S,
+    tracing_subscriber::fmt::format::DefaultFields,
+    tracing_subscriber::fmt::format::Format<tracing_subscriber::fmt::format::Full, ClockSource>,
+    ConsoleWriter<fn() -> std::io::Stdout>,
+>;
+
+/// Options of the console layer
+#[derive(Debug, Clone)]
+pub struct ConsoleOptions {
+    /// The format of the log records, [`LogFormat::Binary`] is written as full (default: full)
+    pub format: LogFormat,
+    /// The layout of the [`LogFormat::Full`] records (default: [`ConsoleStyle::Default`])
+    pub style: ConsoleStyle,
+    /// The app name, a label of the [`LogFormat::GcpJson`] records, the `service` of the [`LogFormat::AwsJson`] ones
+    /// and the `service.name` of the [`LogFormat::Ecs`] ones (default: empty)
+    pub app_name: String,
+    /// Include the source file and line number (default: false)
+    pub source_location: bool,
+    /// Fields added to every record (default: none)
+    pub global_fields: Vec<(String, FieldValue)>,
+    /// Add the `trace_id` and `span_id` fields of the enclosing spans to every record (default: false)
+    pub correlation_ids: bool,
+    /// Add the `task_id` field to the records of the events logged by a tokio task (default: false)
+    #[cfg(feature = "task-ids")]
+    pub task_ids: bool,
+    /// Color the records with ANSI escape sequences, JSON records are never colored (default: true)
+    pub ansi: bool,
+    /// Write the records through libtest's output capture instead of directly to stdout (default: false)
+    pub test_writer: bool,
+    /// Pass the broken pipe errors of stdout on to the fmt layer, rather than stop writing to the console once its
+    /// reader is gone (default: false)
+    pub strict_broken_pipe: bool,
+    /// The clock of the record timestamps (default: None, the system clock)
+    pub clock: Option<Arc<dyn Clock>>,
+    // The dispatch the closing of stdout is logged to, set by the guard (the global default one if not set)
+    pub(crate) dispatch: Arc<OnceLock<WeakDispatch>>,
+}
+
+impl Default for ConsoleOptions {
+    fn default() -> Self {
+        ConsoleOptions {
+            format: LogFormat::default(),
+            style: ConsoleStyle::default(),
+            app_name: String::new(),
+            source_location: false,
+            global_fields: Vec::new(),
+            correlation_ids: false,
+            #[cfg(feature = "task-ids")]
+            task_ids: false,
+            ansi: true,
+            test_writer: false,
+            strict_broken_pipe: false,
+            clock: None,
+            dispatch: Arc::default(),
+        }
+    }
+}
+
+/// Options of the log file layer
+#[cfg(feature = "file")]
+#[derive(Debug, Clone)]
+pub struct FileOptions {
+    /// The directory of the log file (default: the current directory)
+    pub path: PathBuf,
+    /// The log file name prefix, the file is named `<prefix>.<date>.log` (default: "app")
+    pub prefix: String,
+    /// The log file rotation (default: Daily)
+    pub rotation: Rotation,
+    /// The number of log files to keep when rotating (default: 3)
+    pub backups: usize,
+    /// Delete the rotated log files older than this age (default: None, the files are only pruned by count)
+    pub max_age: Option<Duration>,
+    /// Recreate the log file when it was deleted or renamed while being written (default: false)
+    pub reopen_if_missing: bool,
+    /// The format of the records: [`LogFormat::Full`], [`LogFormat::Json`], [`LogFormat::Ecs`] or
+    /// [`LogFormat::Binary`], the other formats are written as full (default: full)
+    pub format: LogFormat,
+    /// The app name, the `service.name` of the [`LogFormat::Ecs`] records (default: empty)
+    pub app_name: String,
+    /// Encrypt the log files with this key (default: None, not encrypted)
+    #[cfg(feature = "encrypt")]
+    pub encrypt_key: Option<EncryptKey>,
+    /// Write the log records by a background thread (default: false)
+    pub non_blocking: bool,
+    /// The number of lines queued for the background thread, further lines are dropped (default: 128000)
+    pub buffered_lines: usize,
+    /// Include the source file and line number (default: false)
+    pub source_location: bool,
+    /// Fields added to every record (default: none)
+    pub global_fields: Vec<(String, FieldValue)>,
+    /// Add the `trace_id` and `span_id` fields of the enclosing spans to every record (default: false)
+    pub correlation_ids: bool,
+    /// Add the `task_id` field to the records of the events logged by a tokio task (default: false)
+    #[cfg(feature = "task-ids")]
+    pub task_ids: bool,
+    /// The clock of the record timestamps, the log files are still named and rotated by the system clock (default:
+    /// None, the system clock)
+    pub clock: Option<Arc<dyn Clock>>,
+    /// Keep the events too verbose for the log file in memory, they are written when an error occurs (default: None)
+    pub hindsight: Option<Hindsight>,
+}
+
+#[cfg(feature = "file")]
+impl Default for FileOptions {
+    fn default() -> Self {
+        FileOptions {
+            path: PathBuf::from("."),
+            prefix: String::from("app"),
+            rotation: Rotation::Daily,
+            backups: 3,
+            max_age: None,
+            reopen_if_missing: false,
+            format: LogFormat::Full,
+            app_name: String::new(),
+            #[cfg(feature = "encrypt")]
+            encrypt_key: None,
+            non_blocking: false,
+            buffered_lines: crate::DEFAULT_BUFFERED_LINES,
+            source_location: false,
+            global_fields: Vec::new(),
+            correlation_ids: false,
+            #[cfg(feature = "task-ids")]
+            task_ids: false,
+            clock: None,
+            hindsight: None,
+        }
+    }
+}
+
+/// Options of the log server (GELF) layer
+#[cfg(feature = "server")]
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// The address of the log server in the format <host>:<port> (default: "logging-server:12201")
+    pub address: String,
+    /// Sent as the `app` additional field of each record (default: empty)
+    pub app_name: String,
+    /// The maximum time to wait for queued records to be sent when the [`ServerGuard`] is dropped (default: 2 seconds)
+    pub shutdown_timeout: Duration,
+    /// Sent as additional fields of each record (default: none)
+    pub global_fields: Vec<(String, FieldValue)>,
+    /// Send the `trace_id` and `span_id` fields of the enclosing spans as additional fields (default: false)
+    pub correlation_ids: bool,
+    /// Send the id of the tokio task logging an event as the `task_id` additional field (default: false)
+    #[cfg(feature = "task-ids")]
+    pub task_ids: bool,
+}
+
+#[cfg(feature = "server")]
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            address: String::from("logging-server:12201"),
+            app_name: String::new(),
+            shutdown_timeout: Duration::from_secs(2),
+            global_fields: Vec::new(),
+            correlation_ids: false,
+            #[cfg(feature = "task-ids")]
+            task_ids: false,
+        }
+    }
+}
+
+/// Build a layer writing the log records to stdout
+///
+pub fn console<S>(options: &ConsoleOptions) -> impl Layer<S> + Send + Sync + 'static
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    if options.test_writer {
+        console_with_writer(options, tracing_subscriber::fmt::TestWriter::new())
+    } else {
+        console_with_writer(
+            options,
+            ConsoleWriter::new(
+                std::io::stdout,
+                options.strict_broken_pipe,
+                options.dispatch.clone(),
+            ),
+        )
+    }
+}
+
+/// Build a layer writing the log records to the debug output by OutputDebugStringW (Windows only)
+///
+/// The records have the full format without colors, the other options are those of the console.
+///
+#[cfg(windows)]
+pub fn debug_output<S>(options: &ConsoleOptions) -> impl Layer<S> + Send + Sync + 'static
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    #[cfg(feature = "task-ids")]
+    let task_ids = options.task_ids;
+    #[cfg(not(feature = "task-ids"))]
+    let task_ids = false;
+
+    with_global_fields(
+        console_fmt_layer(options)
+            .with_ansi(false)
+            .with_writer(DebugOutput)
+            .boxed(),
+        &options.global_fields,
+        options.correlation_ids,
+        task_ids,
+    )
+}
+
+// The console layer of the full format writing to stdout, as a concrete type
+pub(crate) fn stdout_fmt_layer<S>(options: &ConsoleOptions) -> StdoutLayer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    console_fmt_layer(options).with_writer(ConsoleWriter::new(
+        std::io::stdout as fn() -> std::io::Stdout,
+        options.strict_broken_pipe,
+        options.dispatch.clone(),
+    ))
+}
+
+// The console layer writing to another writer than stdout (for testing)
+pub(crate) fn console_with_writer<S, W>(
+    options: &ConsoleOptions,
+    writer: W,
+) -> Box<dyn Layer<S> + Send + Sync + 'static>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = console_fmt_layer(options).with_writer(writer);
+
+    let layer = match options.format {
+        LogFormat::Full => match options.style {
+            ConsoleStyle::Default => layer.boxed(),
+            ConsoleStyle::Aligned { target_width } => layer
+                .event_format(AlignedFormat::new(
+                    target_width,
+                    options.source_location,
+                    ClockSource::new(options.clock.clone()),
+                ))
+                .boxed(),
+        },
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Json => layer.json().with_ansi(false).boxed(),
+        LogFormat::GcpJson => return cloud_json(layer, Cloud::Gcp, options),
+        LogFormat::AwsJson => return cloud_json(layer, Cloud::Aws, options),
+        LogFormat::Ecs => return cloud_json(layer, Cloud::Ecs, options),
+        // A log file format, the records are written as full
+        LogFormat::Binary => layer.boxed(),
+    };
+
+    #[cfg(feature = "task-ids")]
+    let task_ids = options.task_ids;
+    #[cfg(not(feature = "task-ids"))]
+    let task_ids = false;
+
+    with_global_fields(
+        layer,
+        &options.global_fields,
+        options.correlation_ids,
+        task_ids,
+    )
+}
+
+// The console layer formatting the records for a cloud provider's log service
+fn cloud_json<S, W>(
+    layer: tracing_subscriber::fmt::Layer<
+        S,
+        tracing_subscriber::fmt::format::DefaultFields,
+        tracing_subscriber::fmt::format::Format<tracing_subscriber::fmt::format::Full, ClockSource>,
+        W,
+    >,
+    cloud: Cloud,
+    options: &ConsoleOptions,
+) -> Box<dyn Layer<S> + Send + Sync + 'static>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = layer.with_ansi(false).event_format(CloudJson::new(
+        cloud,
+        &options.app_name,
+        &options.global_fields,
+        options.source_location,
+        ClockSource::new(options.clock.clone()),
+    ));
+
+    #[cfg(feature = "task-ids")]
+    let task_ids = options.task_ids;
+    #[cfg(not(feature = "task-ids"))]
+    let task_ids = false;
+
+    // The global fields are labels of the records, only the correlation ids and task ids are added to the events
+    with_global_fields(layer.boxed(), &[], options.correlation_ids, task_ids)
+}
+
+// The fmt layer of the console, writing to stdout
+pub(crate) fn console_fmt_layer<S>(options: &ConsoleOptions) -> FmtLayer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_subscriber::fmt::layer()
+        .with_ansi(options.ansi)
+        .with_file(options.source_location)
+        .with_line_number(options.source_location)
+        .with_timer(ClockSource::new(options.clock.clone()))
+}
+
+// Wrap the layer when there are global fields, correlation ids or task ids to add
+fn with_global_fields<S>(
+    layer: Box<dyn Layer<S> + Send + Sync + 'static>,
+    global_fields: &[(String, FieldValue)],
+    correlation_ids: bool,
+    task_ids: bool,
+) -> Box<dyn Layer<S> + Send + Sync + 'static>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    if global_fields.is_empty() && !correlation_ids && !task_ids {
+        layer
+    } else {
+        GlobalFieldsLayer::new(layer, global_fields, correlation_ids, task_ids).boxed()
+    }
+}
+
+/// Build a layer writing the log records to a rotated log file
+///
+/// The [`WorkerGuard`] is returned when [`FileOptions::non_blocking`] is set, keep it alive while logging: dropping
+/// it flushes the buffered records and stops the background thread.
+///
+/// # Errors
+/// [`TracingInitError::FileAppender`] if the log file cannot be created
+///
+#[cfg(feature = "file")]
+pub fn file<S>(
+    options: &FileOptions,
+) -> Result<(impl Layer<S> + Send + Sync + 'static, Option<WorkerGuard>), TracingInitError>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let (layer, guard, _dispatch) = file_layer(options)?;
+
+    Ok((layer, guard.map(|guard| guard.worker)))
+}
+
+// The log file layer, the guard of a non-blocking writer can also wait for the queued records to be written. The
+// deletion of the files older than the maximum age and the recreation of a missing file are logged to the dispatch
+// set once the subscriber is built
+#[cfg(feature = "file")]
+#[allow(clippy::type_complexity)]
+pub(crate) fn file_layer<S>(
+    options: &FileOptions,
+) -> Result<
+    (
+        impl Layer<S> + Send + Sync + 'static,
+        Option<FileGuard>,
+        Arc<OnceLock<WeakDispatch>>,
+    ),
+    TracingInitError,
+>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    // Loaded first, the records are never written unencrypted
+    #[cfg(feature = "encrypt")]
+    let cipher = match options.encrypt_key {
+        Some(ref key) => Some(Cipher::new(key, options)?),
+        None => None,
+    };
+    #[cfg(not(feature = "encrypt"))]
+    let cipher = None;
+
+    let file_writer = rolling_file_appender(options)?;
+    let dispatch = Arc::new(OnceLock::new());
+    let retention = options
+        .max_age
+        .map(|max_age| Arc::new(Retention::new(options, max_age, dispatch.clone())));
+
+    let (layer, guard) = if options.reopen_if_missing {
+        let file_writer = ReopenWriter::new(file_writer, options, dispatch.clone());
+
+        retained_file_writer_layer(options, file_writer, retention, cipher)
+    } else {
+        retained_file_writer_layer(options, file_writer, retention, cipher)
+    };
+
+    #[cfg(feature = "task-ids")]
+    let task_ids = options.task_ids;
+    #[cfg(not(feature = "task-ids"))]
+    let task_ids = false;
+
+    // The global fields are labels of the ECS records
+    let global_fields = match options.format {
+        LogFormat::Ecs => &[],
+        _ => options.global_fields.as_slice(),
+    };
+
+    Ok((
+        with_global_fields(layer, global_fields, options.correlation_ids, task_ids),
+        guard,
+        dispatch,
+    ))
+}
+
+// The fmt layer of the log file, deleting the expired files when a maximum age is set
+#[cfg(feature = "file")]
+fn retained_file_writer_layer<S, W>(
+    options: &FileOptions,
+    writer: W,
+    retention: Option<Arc<Retention>>,
+    cipher: Option<FileCipher>,
+) -> (Box<dyn Layer<S> + Send + Sync + 'static>, Option<FileGuard>)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: std::io::Write + for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    match retention {
+        Some(retention) => {
+            encrypted_file_writer_layer(options, RetainedWriter::new(writer, retention), cipher)
+        }
+        None => encrypted_file_writer_layer(options, writer, cipher),
+    }
+}
+
+// The cipher of the encrypted log files, there is none without the encrypt feature
+#[cfg(feature = "encrypt")]
+type FileCipher = Arc<Cipher>;
+#[cfg(all(feature = "file", not(feature = "encrypt")))]
+type FileCipher = std::convert::Infallible;
+
+// The fmt layer of the log file, encrypting the records when a key is set
+#[cfg(feature = "file")]
+fn encrypted_file_writer_layer<S, W>(
+    options: &FileOptions,
+    writer: W,
+    cipher: Option<FileCipher>,
+) -> (Box<dyn Layer<S> + Send + Sync + 'static>, Option<FileGuard>)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: std::io::Write + for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    match cipher {
+        #[cfg(feature = "encrypt")]
+        Some(cipher) => file_writer_layer(options, EncryptWriter::new(writer, cipher)),
+        #[cfg(not(feature = "encrypt"))]
+        Some(cipher) => match cipher {},
+        None => file_writer_layer(options, writer),
+    }
+}
+
+// The fmt layer of the log file writing directly or by a background thread
+#[cfg(feature = "file")]
+fn file_writer_layer<S, W>(
+    options: &FileOptions,
+    writer: W,
+) -> (Box<dyn Layer<S> + Send + Sync + 'static>, Option<FileGuard>)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: std::io::Write + for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    if options.non_blocking {
+        let (writer, guard) = flush::non_blocking(writer, options.buffered_lines);
+
+        (formatted_file_layer(options, writer), Some(guard))
+    } else {
+        (formatted_file_layer(options, writer), None)
+    }
+}
+
+// The layer writing the log file records in their format, keeping the too verbose ones with hindsight
+#[cfg(feature = "file")]
+fn formatted_file_layer<S, W>(
+    options: &FileOptions,
+    writer: W,
+) -> Box<dyn Layer<S> + Send + Sync + 'static>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    match options.hindsight {
+        Some(ref hindsight) => {
+            let kept = Arc::new(Kept::new(
+                hindsight,
+                ClockSource::new(options.clock.clone()),
+            ));
+
+            HindsightLayer::new(
+                record_file_layer(options, HindsightWriter::new(writer, kept)),
+                hindsight,
+            )
+            .boxed()
+        }
+        None => record_file_layer(options, writer),
+    }
+}
+
+// The layer formatting the log file records
+#[cfg(feature = "file")]
+fn record_file_layer<S, W>(
+    options: &FileOptions,
+    writer: W,
+) -> Box<dyn Layer<S> + Send + Sync + 'static>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    match options.format {
+        LogFormat::Json => file_fmt_layer(options).with_writer(writer).json().boxed(),
+        LogFormat::Ecs => file_fmt_layer(options)
+            .with_writer(writer)
+            .event_format(CloudJson::new(
+                Cloud::Ecs,
+                &options.app_name,
+                &options.global_fields,
+                options.source_location,
+                ClockSource::new(options.clock.clone()),
+            ))
+            .boxed(),
+        LogFormat::Binary => BinlogLayer::new(
+            writer,
+            options.source_location,
+            ClockSource::new(options.clock.clone()),
+        )
+        .boxed(),
+        _ => file_fmt_layer(options).with_writer(writer).boxed(),
+    }
+}
+
+#[cfg(feature = "file")]
+pub(crate) fn rolling_file_appender(
+    options: &FileOptions,
+) -> Result<tracing_appender::rolling::RollingFileAppender, TracingInitError> {
+    tracing_appender::rolling::RollingFileAppender::builder()
+        .filename_prefix(&options.prefix)
+        .filename_suffix("log")
+        .rotation(options.rotation.into())
+        .max_log_files(options.backups)
+        .build(&options.path)
+        .map_err(|source| TracingInitError::FileAppender {
+            path: options.path.clone(),
+            source,
+        })
+}
+
+// The fmt layer of the log file, without a writer
+#[cfg(feature = "file")]
+pub(crate) fn file_fmt_layer<S>(options: &FileOptions) -> FmtLayer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_file(options.source_location)
+        .with_line_number(options.source_location)
+        .with_timer(ClockSource::new(options.clock.clone()))
+}
+
+/// Build a layer sending the log records to a log server using the GELF protocol (over UDP)
+///
+/// Must be called from within a tokio runtime, the connection is handled by a spawned task. Keep the returned
+/// [`ServerGuard`] alive while logging: dropping it sends the queued records (bounded by
+/// [`ServerOptions::shutdown_timeout`]) and closes the connection.
+///
+/// # Example
+/// ```no_run
+/// use tracing_init::layers::{self, ServerOptions};
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let options = ServerOptions {
+///         address: String::from("graylog:12201"),
+///         app_name: String::from("my-app"),
+///         ..Default::default()
+///     };
+///     let (server_layer, _server_guard) = layers::gelf(&options).unwrap();
+///     let subscriber = tracing_subscriber::registry().with(server_layer);
+///
+///     tracing::subscriber::with_default(subscriber, || {
+///         tracing::info!("sent to graylog");
+///     });
+/// }
+/// ```
+///
+/// # Errors
+/// [`TracingInitError::ServerConnect`] if not running within a tokio runtime or the address cannot be used
+///
+#[cfg(feature = "server")]
+pub fn gelf<S>(
+    options: &ServerOptions,
+) -> Result<(impl Layer<S> + Send + Sync + 'static, ServerGuard), TracingInitError>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let server_connect_error = |source| TracingInitError::ServerConnect {
+        address: options.address.clone(),
+        source,
+    };
+
+    let runtime =
+        tokio::runtime::Handle::try_current().map_err(|e| server_connect_error(Box::new(e)))?;
+
+    let builder = options.global_fields.iter().fold(
+        tracing_gelf::Logger::builder().additional_field("app", options.app_name.clone()),
+        |builder, (name, value)| match value {
+            FieldValue::Str(value) => builder.additional_field(name, value.clone()),
+            FieldValue::I64(value) => builder.additional_field(name, *value),
+            FieldValue::U64(value) => builder.additional_field(name, *value),
+            FieldValue::Bool(value) => builder.additional_field(name, *value),
+        },
+    );
+
+    let (gelf_layer, connection_task) = builder
+        .connect_udp(options.address.clone())
+        .map_err(|e| server_connect_error(Box::new(e)))?;
+
+    let (server_layer, server_guard, connection) =
+        ServerLayer::new(gelf_layer, &options.address, options.shutdown_timeout);
+
+    // The address is resolved by the connection task rather than at init, the records are queued until it is
+    runtime.spawn(connection.run(connection_task));
+
+    #[cfg(feature = "task-ids")]
+    let task_ids = options.task_ids;
+    #[cfg(not(feature = "task-ids"))]
+    let task_ids = false;
+
+    // The global fields are additional fields of the logger, the correlation ids and task ids vary by record. The
+    // GELF layer records the structured fields as Debug strings, they are sent as JSON strings instead
+    let structured_as_json = cfg!(all(tracing_unstable, feature = "valuable"));
+    let server_layer = if options.correlation_ids || task_ids || structured_as_json {
+        GlobalFieldsLayer::new(server_layer, &[], options.correlation_ids, task_ids)
+            .structured_as_json(structured_as_json)
+            .boxed()
+    } else {
+        server_layer.boxed()
+    };
+
+    Ok((server_layer, server_guard))
+}
@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing::level_filters::LevelFilter;
+use tracing_log::AsLog;
+
+// Set once the LogTracer is installed by init, the log max level then tracks the tracing max level
+static LOG_TRACER_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// Forward the records of the log crate to the tracing dispatcher
+pub(crate) fn install() -> Result<(), log::SetLoggerError> {
+    tracing_log::LogTracer::builder()
+        .with_max_level(LevelFilter::current().as_log())
+        .init()?;
+
+    LOG_TRACER_INSTALLED.store(true, Ordering::Release);
+    Ok(())
+}
+
+// Called after the filter is changed, so records which became enabled are not dropped by the log macros and
+// disabled ones stay cheap
+pub(crate) fn update_max_level() {
+    if LOG_TRACER_INSTALLED.load(Ordering::Acquire) {
+        log::set_max_level(LevelFilter::current().as_log());
+    }
+}
+
+#[cfg(all(test, feature = "file"))]
+mod tests {
+    use super::*;
+    use crate::tests::test_log_dir;
+    use crate::TracingInit;
+    use tracing::Level;
+
+    #[test]
+    fn test_log_records_are_captured() {
+        let log_dir = test_log_dir("log-compat");
+        let (dispatch, guard) = TracingInit::builder("App")
+            .log_to_file(true)
+            .log_file_path(log_dir.to_str().unwrap())
+            .log_file_rotation(crate::Rotation::Never)
+            .level(Level::INFO)
+            .into_dispatch()
+            .unwrap();
+
+        // The LogTracer may already be installed by a test calling init, before this dispatcher enabled INFO
+        let _ = install();
+        assert!(install().is_err());
+        update_max_level();
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            log::info!(target: "legacy_crate", "record from the log crate");
+            log::trace!(target: "legacy_crate", "filtered record");
+        });
+
+        drop(guard);
+
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        assert!(
+            log.contains(" INFO legacy_crate: record from the log crate"),
+            "{log}"
+        );
+        assert!(!log.contains("filtered record"));
+    }
+}
@@ -0,0 +1,197 @@
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+use crate::env_var;
+
+// The directory of the services' log files on Linux and the other Unix systems
+const SYSTEM_LOG_DIR: &str = "/var/log";
+
+/// Where the log files are written when no log file path is given (neither by the builder nor by LOG_FILE_PATH)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum DefaultLogDir {
+    /// The current directory
+    #[default]
+    CurrentDir,
+    /// The platform's log directory for the app:
+    /// * Linux and other Unix systems - `$XDG_STATE_HOME/<app>/logs`, otherwise `/var/log/<app>` when writable,
+    ///   otherwise `~/.local/state/<app>/logs`
+    /// * macOS - `~/Library/Logs/<app>`
+    /// * Windows - `%PROGRAMDATA%\<app>\logs`
+    Platform,
+}
+
+impl std::fmt::Display for DefaultLogDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DefaultLogDir::CurrentDir => write!(f, "current directory"),
+            DefaultLogDir::Platform => write!(f, "platform log directory"),
+        }
+    }
+}
+
+// The platform's log directory for the app (None if the variables it is based on are not set)
+pub(crate) fn platform_log_dir(app_name: &str) -> Option<PathBuf> {
+    log_dir_for(std::env::consts::OS, app_name, Path::new(SYSTEM_LOG_DIR))
+}
+
+fn log_dir_for(os: &str, app_name: &str, system_log_dir: &Path) -> Option<PathBuf> {
+    let var = |name: &str| {
+        env_var(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+
+    match os {
+        "windows" => var("PROGRAMDATA").map(|dir| dir.join(app_name).join("logs")),
+        "macos" => var("HOME").map(|home| home.join("Library").join("Logs").join(app_name)),
+        _ => {
+            if let Some(state) = var("XDG_STATE_HOME") {
+                return Some(state.join(app_name).join("logs"));
+            }
+
+            let system = system_log_dir.join(app_name);
+
+            if is_writable(&system) {
+                Some(system)
+            } else {
+                var("HOME").map(|home| {
+                    home.join(".local")
+                        .join("state")
+                        .join(app_name)
+                        .join("logs")
+                })
+            }
+        }
+    }
+}
+
+// Whether files can be created in the directory, or in its closest existing parent when it does not exist yet
+fn is_writable(dir: &Path) -> bool {
+    let Some(existing) = dir.ancestors().find(|ancestor| ancestor.exists()) else {
+        return false;
+    };
+
+    // The permission bits do not tell whether the process may write, a probe file is created and deleted
+    let probe = existing.join(format!(".tracing-init-probe-{}", std::process::id()));
+
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_log_dir, with_env};
+    #[cfg(all(unix, not(target_os = "macos")))]
+    use crate::TracingInit;
+
+    #[test]
+    fn test_platform_log_dir() {
+        let writable = test_log_dir("platform-log-dir");
+        std::fs::create_dir_all(&writable).unwrap();
+        let not_writable = Path::new(file!()).join("log");
+
+        with_env(
+            &[
+                ("HOME", "/home/user"),
+                ("XDG_STATE_HOME", "/state"),
+                ("PROGRAMDATA", "C:\\ProgramData"),
+            ],
+            || {
+                assert_eq!(
+                    log_dir_for("linux", "App", &writable),
+                    Some(PathBuf::from("/state/App/logs"))
+                );
+                assert_eq!(
+                    log_dir_for("macos", "App", &writable),
+                    Some(PathBuf::from("/home/user/Library/Logs/App"))
+                );
+                assert_eq!(
+                    log_dir_for("windows", "App", &writable),
+                    Some(PathBuf::from("C:\\ProgramData").join("App").join("logs"))
+                );
+            },
+        );
+
+        with_env(&[("HOME", "/home/user")], || {
+            assert_eq!(
+                log_dir_for("linux", "App", &writable),
+                Some(writable.join("App"))
+            );
+            assert_eq!(
+                log_dir_for("linux", "App", &not_writable),
+                Some(PathBuf::from("/home/user/.local/state/App/logs"))
+            );
+            assert_eq!(log_dir_for("windows", "App", &writable), None);
+        });
+
+        // Nothing is left behind by the probe
+        assert_eq!(std::fs::read_dir(&writable).unwrap().count(), 0);
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn test_default_log_dir() {
+        let state = test_log_dir("default-log-dir");
+        let state_home = state.to_str().unwrap();
+
+        let config = with_env(
+            &[("LOG_DESTINATION", "f"), ("XDG_STATE_HOME", state_home)],
+            || {
+                TracingInit::builder("App")
+                    .default_log_dir(DefaultLogDir::Platform)
+                    .resolve()
+                    .unwrap()
+            },
+        );
+
+        assert_eq!(config.log_file_path(), state.join("App").join("logs"));
+        assert!(config.to_string().contains(&format!(
+            "{}",
+            state.join("App/logs/App.YYYY-MM-DD.log").display()
+        )));
+        // Created when the layers are built
+        assert!(!state.exists());
+
+        // The path set by LOG_FILE_PATH wins
+        let config = with_env(
+            &[
+                ("LOG_DESTINATION", "f"),
+                ("XDG_STATE_HOME", state_home),
+                ("LOG_FILE_PATH", "/logs"),
+            ],
+            || {
+                TracingInit::builder("App")
+                    .default_log_dir(DefaultLogDir::Platform)
+                    .resolve()
+                    .unwrap()
+            },
+        );
+        assert_eq!(config.log_file_path(), Path::new("/logs"));
+
+        // Under a file, the directory cannot be created
+        let mut config = with_env(
+            &[("LOG_DESTINATION", "f"), ("XDG_STATE_HOME", file!())],
+            || {
+                TracingInit::builder("App")
+                    .default_log_dir(DefaultLogDir::Platform)
+                    .resolve()
+                    .unwrap()
+            },
+        );
+
+        config.create_platform_log_dir();
+        assert_eq!(
+            config.log_file_path(),
+            std::path::absolute(".").unwrap().as_path()
+        );
+        assert!(config.log_dir_fallback().is_some());
+        assert!(config.to_string().contains("App/logs cannot be created"));
+    }
+}
@@ -0,0 +1,40 @@
+// Used by the code generated by #[tracing_init::main]
+use std::borrow::BorrowMut;
+
+use crate::{parse_log_destination, TracingGuard, TracingInit};
+
+pub use tracing::Level;
+
+// Initialize the tracing subscriber of main, the program cannot go on without it. The builder is a value when the
+// attribute has no arguments. The destinations (checked by the attribute) are set as by the builder methods, also
+// those of the features which are not enabled: init reports them
+pub fn init(mut builder: impl BorrowMut<TracingInit>, destinations: Option<&str>) -> TracingGuard {
+    let builder = builder.borrow_mut();
+
+    if let Some(destinations) = destinations {
+        let destinations = parse_log_destination(destinations);
+        let level = |c: char| {
+            destinations
+                .iter()
+                .find_map(|(d, level)| if *d == c { *level } else { None })
+        };
+        let enabled = |c: char| Some(destinations.iter().any(|(d, _)| *d == c));
+
+        builder.enable_console = enabled('c');
+        builder.enable_log_file = enabled('f');
+        builder.enable_log_server = enabled('s');
+        builder.enable_debug_output = enabled('d');
+        builder.console_level = level('c');
+        builder.log_file_level = level('f');
+        builder.log_server_level = level('s');
+        builder.debug_output_level = level('d');
+    }
+
+    match builder.set_from_environment_variables().init() {
+        Ok(guard) => guard,
+        Err(error) => {
+            eprintln!("{}: cannot initialize logging: {error}", builder.app_name);
+            std::process::exit(1)
+        }
+    }
+}
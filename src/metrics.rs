@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+use crate::level_index;
+
+// The most targets counted separately, the events of other targets are counted under OTHER_TARGET
+const MAX_TARGETS: usize = 256;
+const OTHER_TARGET: &str = "<other>";
+
+/// The number of events of each level, see [`MetricsHandle`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LevelCounts {
+    pub trace: u64,
+    pub debug: u64,
+    pub info: u64,
+    pub warn: u64,
+    pub error: u64,
+}
+
+impl LevelCounts {
+    /// The number of events of this level
+    pub fn get(&self, level: Level) -> u64 {
+        match level {
+            Level::TRACE => self.trace,
+            Level::DEBUG => self.debug,
+            Level::INFO => self.info,
+            Level::WARN => self.warn,
+            Level::ERROR => self.error,
+        }
+    }
+
+    /// The number of events of all levels
+    pub fn total(&self) -> u64 {
+        self.trace + self.debug + self.info + self.warn + self.error
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters([AtomicU64; 5]);
+
+impl Counters {
+    fn add(&self, level: &Level) {
+        self.0[level_index(level)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn counts(&self) -> LevelCounts {
+        let count = |level| self.0[level_index(&level)].load(Ordering::Relaxed);
+
+        LevelCounts {
+            trace: count(Level::TRACE),
+            debug: count(Level::DEBUG),
+            info: count(Level::INFO),
+            warn: count(Level::WARN),
+            error: count(Level::ERROR),
+        }
+    }
+
+    fn reset(&self) {
+        for counter in &self.0 {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    levels: Counters,
+    // Keyed by the first component of the target (e.g. hyper for hyper::client), when counting per target
+    targets: Option<RwLock<HashMap<String, Counters>>>,
+}
+
+impl Metrics {
+    fn add(&self, level: &Level, target: &str) {
+        self.levels.add(level);
+
+        if let Some(ref targets) = self.targets {
+            let target = target.split("::").next().unwrap_or(target);
+
+            if let Some(counters) = targets
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(target)
+            {
+                return counters.add(level);
+            }
+
+            let mut targets = targets.write().unwrap_or_else(PoisonError::into_inner);
+            let target = if targets.len() < MAX_TARGETS {
+                target
+            } else {
+                OTHER_TARGET
+            };
+
+            targets.entry(target.to_string()).or_default().add(level);
+        }
+    }
+}
+
+/// Reads the event counters of the subscriber, returned by [`TracingGuard::metrics`](crate::TracingGuard::metrics)
+///
+/// The events are counted once they pass the filter, before sampling and rate limiting, so the counts reflect what
+/// the application logged even if the log destinations are unavailable.
+///
+/// # Example
+/// ```
+/// use tracing_init::TracingInit;
+///
+/// let (dispatch, guard) = TracingInit::builder("App")
+///     .allow_silent(true)
+///     .with_metrics(true)
+///     .into_dispatch()
+///     .unwrap();
+///
+/// tracing::dispatcher::with_default(&dispatch, || tracing::error!("disk full"));
+///
+/// assert_eq!(guard.metrics().unwrap().counts().error, 1);
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct MetricsHandle(Arc<Metrics>);
+
+impl MetricsHandle {
+    pub(crate) fn new(per_target: bool) -> MetricsHandle {
+        MetricsHandle(Arc::new(Metrics {
+            levels: Counters::default(),
+            targets: per_target.then(RwLock::default),
+        }))
+    }
+
+    // The layer counting the events
+    pub(crate) fn layer(&self) -> MetricsLayer {
+        MetricsLayer(self.0.clone())
+    }
+
+    /// The number of events of each level since the subscriber was built (or since [`reset`](Self::reset))
+    pub fn counts(&self) -> LevelCounts {
+        self.0.levels.counts()
+    }
+
+    /// The number of events of each level by target, sorted by target
+    ///
+    /// # Notes
+    /// Empty unless [`metrics_per_target`](crate::TracingInit::metrics_per_target) is enabled. Targets are counted by
+    /// their first component (e.g. `hyper` for `hyper::client::pool`). Once 256 targets are counted, the events of new
+    /// targets are counted under `<other>`.
+    ///
+    pub fn target_counts(&self) -> Vec<(String, LevelCounts)> {
+        let mut counts = match self.0.targets {
+            Some(ref targets) => targets
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .map(|(target, counters)| (target.clone(), counters.counts()))
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+
+        counts.sort_by(|(a, _), (b, _)| a.cmp(b));
+        counts
+    }
+
+    /// Set all the counters to zero
+    pub fn reset(&self) {
+        self.0.levels.reset();
+
+        if let Some(ref targets) = self.0.targets {
+            targets
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear();
+        }
+    }
+
+    /// The counters in the Prometheus text exposition format
+    ///
+    /// # Notes
+    /// The `log_events_total` counter has a `level` label. When counting per target, `log_target_events_total` has
+    /// `target` and `level` labels.
+    ///
+    #[cfg(feature = "prometheus")]
+    pub fn render_prometheus(&self) -> String {
+        use std::fmt::Write;
+
+        const LEVELS: [(Level, &str); 5] = [
+            (Level::TRACE, "trace"),
+            (Level::DEBUG, "debug"),
+            (Level::INFO, "info"),
+            (Level::WARN, "warn"),
+            (Level::ERROR, "error"),
+        ];
+
+        let mut text = String::from(
+            "# HELP log_events_total Number of events logged, by level\n# TYPE log_events_total counter\n",
+        );
+        let counts = self.counts();
+
+        for (level, name) in LEVELS {
+            let _ = writeln!(
+                text,
+                "log_events_total{{level=\"{name}\"}} {}",
+                counts.get(level)
+            );
+        }
+
+        if self.0.targets.is_some() {
+            text.push_str("# HELP log_target_events_total Number of events logged, by target and level\n# TYPE log_target_events_total counter\n");
+
+            for (target, counts) in self.target_counts() {
+                let target = target.replace('\\', "\\\\").replace('"', "\\\"");
+
+                for (level, name) in LEVELS {
+                    let _ = writeln!(
+                        text,
+                        "log_target_events_total{{target=\"{target}\",level=\"{name}\"}} {}",
+                        counts.get(level)
+                    );
+                }
+            }
+        }
+
+        text
+    }
+}
+
+// Counts the events passing the filter
+pub(crate) struct MetricsLayer(Arc<Metrics>);
+
+impl<S: Subscriber> Layer<S> for MetricsLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+
+        self.0.add(metadata.level(), metadata.target());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::with_env;
+    use crate::{RateLimit, TracingInit};
+
+    #[test]
+    fn test_metrics() {
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .allow_silent(true)
+                .level(Level::DEBUG)
+                .with_metrics(true)
+                .metrics_per_target(true)
+                .rate_limit(RateLimit {
+                    max_per_key: 1,
+                    ..RateLimit::default()
+                })
+                .into_dispatch()
+                .unwrap()
+        });
+        let metrics = guard.metrics().unwrap().clone();
+
+        let threads = (0..4)
+            .map(|_| {
+                let dispatch = dispatch.clone();
+
+                std::thread::spawn(move || {
+                    tracing::dispatcher::with_default(&dispatch, || {
+                        for _ in 0..1000 {
+                            tracing::warn!(target: "hyper::client", "reconnecting");
+                            tracing::error!("failed");
+                            tracing::trace!("filtered out");
+                        }
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // Counted before rate limiting, but after filtering
+        let counts = metrics.counts();
+        assert_eq!(
+            counts,
+            LevelCounts {
+                warn: 4000,
+                error: 4000,
+                ..LevelCounts::default()
+            }
+        );
+        assert_eq!(counts.total(), 8000);
+
+        let target_counts = metrics.target_counts();
+        assert_eq!(target_counts.len(), 2);
+        assert_eq!(target_counts[0].0, "hyper");
+        assert_eq!(target_counts[0].1.get(Level::WARN), 4000);
+        assert_eq!(target_counts[1].0, "tracing_init");
+        assert_eq!(target_counts[1].1.get(Level::ERROR), 4000);
+
+        metrics.reset();
+        assert_eq!(metrics.counts(), LevelCounts::default());
+        assert!(metrics.target_counts().is_empty());
+    }
+
+    #[test]
+    fn test_metrics_targets_bounded() {
+        let metrics = MetricsHandle::new(true);
+
+        for n in 0..MAX_TARGETS + 10 {
+            metrics.0.add(&Level::INFO, &format!("target{n}::module"));
+        }
+
+        let target_counts = metrics.target_counts();
+        assert_eq!(target_counts.len(), MAX_TARGETS + 1);
+        assert_eq!(
+            target_counts[0],
+            (
+                String::from(OTHER_TARGET),
+                LevelCounts {
+                    info: 10,
+                    ..LevelCounts::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "prometheus")]
+    fn test_render_prometheus() {
+        let metrics = MetricsHandle::new(true);
+
+        metrics.0.add(&Level::ERROR, "db::pool");
+        metrics.0.add(&Level::ERROR, "db");
+
+        let text = metrics.render_prometheus();
+        assert!(text.contains("# TYPE log_events_total counter\n"), "{text}");
+        assert!(
+            text.contains("log_events_total{level=\"error\"} 2\n"),
+            "{text}"
+        );
+        assert!(
+            text.contains("log_events_total{level=\"info\"} 0\n"),
+            "{text}"
+        );
+        assert!(
+            text.contains("log_target_events_total{target=\"db\",level=\"error\"} 2\n"),
+            "{text}"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::Arc;
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::{SdkTracerProvider, TracerProviderBuilder};
+use opentelemetry_sdk::Resource;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+pub use opentelemetry_sdk::trace::Sampler;
+
+use crate::TracingInitError;
+
+/// The span export settings (see [`export_spans`](crate::TracingInit::export_spans))
+#[derive(Debug, Clone)]
+pub struct OtelConfig {
+    /// The OTLP/HTTP traces endpoint of the collector (default: "http://localhost:4318/v1/traces")
+    pub endpoint: String,
+    /// The traces exported (default: all the root spans, the other spans follow their parent)
+    pub sampler: Sampler,
+    /// Use the app name as the `service.name` resource attribute, unless OTEL_SERVICE_NAME is set (default: true)
+    pub service_name_default_from_app_name: bool,
+    /// The spans of this level and above are exported, whatever the log filter is (default: INFO)
+    pub level: Level,
+}
+
+impl Default for OtelConfig {
+    fn default() -> Self {
+        OtelConfig {
+            endpoint: String::from("http://localhost:4318/v1/traces"),
+            sampler: Sampler::ParentBased(Box::new(Sampler::AlwaysOn)),
+            service_name_default_from_app_name: true,
+            level: Level::INFO,
+        }
+    }
+}
+
+type WithExporterFn =
+    dyn Fn(TracerProviderBuilder) -> Result<TracerProviderBuilder, TracingInitError> + Send + Sync;
+
+// The span export set on the builder, the exporter is created when the layers are built
+#[derive(Clone)]
+pub(crate) struct SpanExport {
+    config: OtelConfig,
+    with_exporter: Arc<WithExporterFn>,
+}
+
+impl SpanExport {
+    pub(crate) fn otlp(config: OtelConfig) -> SpanExport {
+        let endpoint = config.endpoint.clone();
+
+        SpanExport {
+            config,
+            with_exporter: Arc::new(move |builder| {
+                let exporter = opentelemetry_otlp::SpanExporter::builder()
+                    .with_http()
+                    .with_endpoint(endpoint.clone())
+                    .build()
+                    .map_err(|e| TracingInitError::SpanExport {
+                        endpoint: endpoint.clone(),
+                        source: Box::new(e),
+                    })?;
+
+                // Exported in batches by a background thread
+                Ok(builder.with_batch_exporter(exporter))
+            }),
+        }
+    }
+
+    // Export to a test exporter, each span when it is closed
+    #[cfg(test)]
+    pub(crate) fn in_memory(
+        config: OtelConfig,
+        exporter: opentelemetry_sdk::trace::InMemorySpanExporter,
+    ) -> SpanExport {
+        SpanExport {
+            config,
+            with_exporter: Arc::new(move |builder| {
+                Ok(builder.with_simple_exporter(exporter.clone()))
+            }),
+        }
+    }
+
+    pub(crate) fn config(&self) -> &OtelConfig {
+        &self.config
+    }
+
+    // The layer exporting the spans, with its own filter, and the guard shutting the provider down
+    pub(crate) fn build<S>(
+        &self,
+        app_name: &str,
+    ) -> Result<(Box<dyn Layer<S> + Send + Sync + 'static>, SpanExportGuard), TracingInitError>
+    where
+        S: Subscriber + Send + Sync + for<'a> LookupSpan<'a>,
+    {
+        let mut builder = SdkTracerProvider::builder().with_sampler(self.config.sampler.clone());
+
+        if self.config.service_name_default_from_app_name
+            && std::env::var_os("OTEL_SERVICE_NAME").is_none()
+        {
+            builder = builder.with_resource(
+                Resource::builder()
+                    .with_service_name(app_name.to_string())
+                    .build(),
+            );
+        }
+
+        let provider = (self.with_exporter)(builder)?.build();
+        let layer = tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("tracing-init"))
+            .with_filter(LevelFilter::from_level(self.config.level))
+            .boxed();
+
+        Ok((layer, SpanExportGuard(provider)))
+    }
+}
+
+impl std::fmt::Debug for SpanExport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SpanExport").field(&self.config).finish()
+    }
+}
+
+// Shuts the tracer provider down when dropped, the spans still queued are exported
+pub(crate) struct SpanExportGuard(SdkTracerProvider);
+
+impl Drop for SpanExportGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.shutdown() {
+            eprintln!("Failed to export the remaining spans: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{with_env, MemoryWriter};
+    use crate::TracingInit;
+    use opentelemetry_sdk::trace::InMemorySpanExporter;
+
+    #[test]
+    fn test_export_spans() {
+        let exporter = InMemorySpanExporter::default();
+        let logs = MemoryWriter::default();
+        let (dispatch, guard) = with_env(&[], || {
+            let mut builder = TracingInit::builder("App");
+
+            builder.span_export = Some(SpanExport::in_memory(
+                OtelConfig::default(),
+                exporter.clone(),
+            ));
+            builder
+                .log_to_console(false)
+                .level(Level::WARN)
+                .with_layer(
+                    tracing_subscriber::fmt::layer()
+                        .without_time()
+                        .with_ansi(false)
+                        .with_writer(logs.clone()),
+                )
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::info_span!("request", id = 7).in_scope(|| {
+                tracing::warn_span!("query").in_scope(|| tracing::warn!("slow query"));
+                // Below the level of the export
+                tracing::debug_span!("parse").in_scope(|| tracing::info!("parsed"));
+            });
+        });
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let names = spans.iter().map(|span| &*span.name).collect::<Vec<_>>();
+        assert_eq!(names, ["query", "request"]);
+
+        let (query, request) = (&spans[0], &spans[1]);
+        assert_eq!(
+            query.span_context.trace_id(),
+            request.span_context.trace_id()
+        );
+        assert_eq!(query.parent_span_id, request.span_context.span_id());
+
+        // The log filter is not affected by the export: the request span is exported but not logged
+        let logs = logs.contents();
+        assert_eq!(
+            logs, " WARN query: tracing_init::otel::tests: slow query\n",
+            "{logs}"
+        );
+
+        drop(guard);
+    }
+
+    #[test]
+    fn test_export_spans_invalid_endpoint() {
+        let result = with_env(&[], || {
+            TracingInit::builder("App")
+                .export_spans(OtelConfig {
+                    endpoint: String::from("not a url"),
+                    ..Default::default()
+                })
+                .into_dispatch()
+        });
+
+        assert!(
+            matches!(result, Err(TracingInitError::SpanExport { ref endpoint, .. }) if endpoint == "not a url"),
+            "{result:?}"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::panic::PanicHookInfo;
+#[cfg(any(feature = "file", feature = "server"))]
+use std::sync::Weak;
+#[cfg(feature = "file")]
+use std::time::Duration;
+
+#[cfg(feature = "file")]
+use crate::flush::QueueTracker;
+#[cfg(feature = "server")]
+use crate::layers::ServerGuard;
+
+// The destinations flushed by the panic hook and before the process is ended by a logged signal. They are owned by
+// the guard, the hook does not keep them alive
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Flush {
+    #[cfg(feature = "file")]
+    pub(crate) file: Weak<QueueTracker>,
+    #[cfg(feature = "server")]
+    pub(crate) server: Weak<ServerGuard>,
+    #[cfg(feature = "file")]
+    pub(crate) timeout: Duration,
+}
+
+impl Flush {
+    // Wait for the queued log file records to be written
+    pub(crate) fn flush(&self) {
+        #[cfg(feature = "file")]
+        if let Some(file) = self.file.upgrade() {
+            file.wait_until_written(self.timeout);
+        }
+    }
+
+    // Also drain the log server connection, when the process ends without dropping the guard
+    pub(crate) fn flush_all(&self) {
+        self.flush();
+
+        #[cfg(feature = "server")]
+        if let Some(server) = self.server.upgrade() {
+            server.shutdown();
+        }
+    }
+}
+
+// Record panics as ERROR events, then call the previously installed hook
+pub(crate) fn install(flush: Flush, force_backtrace: bool) {
+    let previous = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        record_panic(info, force_backtrace);
+
+        // When panics abort, the process ends right after the hook (otherwise the guard drains the log server
+        // connection while unwinding)
+        if cfg!(panic = "abort") {
+            flush.flush_all();
+        } else {
+            flush.flush();
+        }
+
+        previous(info);
+    }));
+}
+
+fn record_panic(info: &PanicHookInfo<'_>, force_backtrace: bool) {
+    let payload = info.payload_as_str().unwrap_or("Box<dyn Any>");
+    let location = info
+        .location()
+        .map(|location| format!("{}:{}", location.file(), location.line()));
+    let thread = std::thread::current();
+    let thread = thread.name().unwrap_or("<unnamed>");
+
+    // Captured when RUST_BACKTRACE (or RUST_LIB_BACKTRACE) enables backtraces, unless forced
+    let backtrace = if force_backtrace {
+        Backtrace::force_capture()
+    } else {
+        Backtrace::capture()
+    };
+    let backtrace =
+        (backtrace.status() == BacktraceStatus::Captured).then(|| backtrace.to_string());
+
+    tracing::error!(
+        target: "panic",
+        payload,
+        location = location.as_deref(),
+        thread,
+        backtrace = backtrace.as_deref(),
+        "thread panicked"
+    );
+}
+
+#[cfg(all(test, feature = "file"))]
+mod tests {
+    use super::*;
+    use crate::tests::{test_log_dir, MemoryWriter};
+    use crate::TracingInit;
+
+    #[test]
+    fn test_panics_are_recorded() {
+        let log_dir = test_log_dir("panic");
+        let memory = MemoryWriter::default();
+        let (dispatch, guard) = TracingInit::builder("App")
+            .log_to_file(true)
+            .log_file_path(log_dir.to_str().unwrap())
+            .log_file_rotation(crate::Rotation::Never)
+            .log_file_non_blocking(true)
+            .with_layer(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(memory.clone()),
+            )
+            .into_dispatch()
+            .unwrap();
+
+        install(guard.panic_flush(), true);
+
+        let worker_dispatch = dispatch.clone();
+        let result = std::thread::Builder::new()
+            .name(String::from("worker"))
+            .spawn(move || {
+                tracing::dispatcher::with_default(&worker_dispatch, || {
+                    std::panic::catch_unwind(|| panic!("disk is on fire"))
+                })
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert!(result.is_err());
+
+        let record = memory.contents();
+        assert!(record.contains("ERROR panic: thread panicked"), "{record}");
+        assert!(record.contains("payload=\"disk is on fire\""), "{record}");
+        assert!(
+            record.contains(&format!("location=\"{}:", file!())),
+            "{record}"
+        );
+        assert!(record.contains("thread=\"worker\""), "{record}");
+        assert!(record.contains("backtrace="), "{record}");
+
+        // Flushed by the hook, while the guard is still alive
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        assert!(log.contains("disk is on fire"), "{log}");
+
+        drop(guard);
+    }
+}
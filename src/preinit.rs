@@ -0,0 +1,383 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::callsite::{DefaultCallsite, Identifier};
+use tracing::field::{display, Field, FieldSet, Value};
+use tracing::level_filters::LevelFilter;
+use tracing::metadata::Kind;
+use tracing::span;
+use tracing::subscriber::Interest;
+use tracing::{Dispatch, Event, Level, Metadata, Subscriber};
+use tracing_core::span::Current;
+
+use crate::global_fields::{leak_metadata, Capture, MAX_FIELDS};
+use crate::redact::Captured;
+use crate::TracingInitError;
+
+// The ids of the spans created before init, never used by the registry
+const PREINIT_SPAN: u64 = 1 << 63;
+
+static PREINIT: OnceLock<Arc<Preinit>> = OnceLock::new();
+
+static SUMMARY_CALLSITE: DefaultCallsite = DefaultCallsite::new(&SUMMARY_METADATA);
+static SUMMARY_METADATA: Metadata<'static> = Metadata::new(
+    "preinit summary",
+    "tracing_init",
+    Level::WARN,
+    Some(file!()),
+    Some(line!()),
+    Some(module_path!()),
+    FieldSet::new(&["message", "dropped"], Identifier(&SUMMARY_CALLSITE)),
+    Kind::EVENT,
+);
+
+/// Record the events logged before [`TracingInit::init`](crate::TracingInit::init) and log them once it is called
+///
+/// Installs a global subscriber keeping the last `capacity` events (with their fields and time) until `init` is
+/// called. `init` then forwards everything to the configured subscriber and replays the buffered events through it,
+/// in order and with a `replayed=true` field and the original time (`logged_at`). The replayed events are filtered
+/// like any other event, so buffered TRACE events are not written by an INFO configuration. If older events were
+/// dropped to make room, a WARN event with their number is logged first.
+///
+/// # Example
+/// ```no_run
+/// use tracing_init::TracingInit;
+///
+/// tracing_init::preinit_buffer(1000).unwrap();
+///
+/// // Logged to the console once logging is initialized
+/// tracing::warn!("config file not found, using the defaults");
+///
+/// let _guard = TracingInit::builder("App").log_to_console(true).init().unwrap();
+/// ```
+///
+/// # Notes
+/// Spans are not recorded before `init`: the buffered events have no span context. The subscriber stays installed
+/// (forwarding to the configured subscriber), so [`TracingInit::into_subscriber`](crate::TracingInit::into_subscriber)
+/// and [`TracingInit::into_dispatch`](crate::TracingInit::into_dispatch) do not replay the events.
+///
+/// # Errors
+/// [`TracingInitError::AlreadyInitialized`] if a global subscriber is already installed
+///
+pub fn preinit_buffer(capacity: usize) -> Result<(), TracingInitError> {
+    let preinit = Arc::new(Preinit {
+        subscriber: OnceLock::new(),
+        buffer: Mutex::new(Buffer {
+            events: VecDeque::with_capacity(capacity.min(1024)),
+            capacity,
+            dropped: 0,
+        }),
+        next_span: AtomicU64::new(PREINIT_SPAN | 1),
+    });
+
+    tracing::subscriber::set_global_default(PreinitSubscriber(preinit.clone()))?;
+    let _ = PREINIT.set(preinit);
+
+    Ok(())
+}
+
+// Forward to the subscriber of init if preinit_buffer installed the global subscriber, and replay the buffered events.
+// Returns the subscriber back if it should be installed as the global default
+pub(crate) fn attach<S>(subscriber: S) -> Result<(), S>
+where
+    S: Subscriber + Send + Sync + 'static,
+{
+    let Some(preinit) = PREINIT.get() else {
+        return Err(subscriber);
+    };
+
+    let mut buffer = preinit
+        .buffer
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+
+    if preinit.subscriber.get().is_some() {
+        return Err(subscriber);
+    }
+
+    // The global dispatch, forwarding to the subscriber
+    let dispatch = tracing::dispatcher::get_default(Dispatch::clone);
+    let subscriber: Arc<dyn Subscriber + Send + Sync> = Arc::new(subscriber);
+
+    subscriber.on_register_dispatch(&dispatch);
+    let _ = preinit.subscriber.set(subscriber.clone());
+
+    let events = std::mem::take(&mut buffer.events);
+    let dropped = std::mem::take(&mut buffer.dropped);
+
+    drop(buffer);
+
+    // The callsites registered before init get the interest of the subscriber
+    tracing::callsite::rebuild_interest_cache();
+    replay(&*subscriber, events, dropped);
+
+    Ok(())
+}
+
+struct Buffered {
+    metadata: &'static Metadata<'static>,
+    values: Vec<(usize, Captured)>,
+    time: SystemTime,
+}
+
+struct Buffer {
+    events: VecDeque<Buffered>,
+    capacity: usize,
+    dropped: u64,
+}
+
+struct Preinit {
+    // Set by init, then everything is forwarded to it
+    subscriber: OnceLock<Arc<dyn Subscriber + Send + Sync>>,
+    buffer: Mutex<Buffer>,
+    next_span: AtomicU64,
+}
+
+fn is_preinit(id: &span::Id) -> bool {
+    id.into_u64() & PREINIT_SPAN != 0
+}
+
+struct PreinitSubscriber(Arc<Preinit>);
+
+impl PreinitSubscriber {
+    fn subscriber(&self) -> Option<&(dyn Subscriber + Send + Sync)> {
+        self.0.subscriber.get().map(|subscriber| &**subscriber)
+    }
+}
+
+impl Subscriber for PreinitSubscriber {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        match self.subscriber() {
+            Some(subscriber) => subscriber.register_callsite(metadata),
+            None => Interest::sometimes(),
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        match self.subscriber() {
+            Some(subscriber) => subscriber.enabled(metadata),
+            // Filtered when replayed
+            None => metadata.is_event(),
+        }
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        match self.subscriber() {
+            Some(subscriber) => subscriber.max_level_hint(),
+            None => Some(LevelFilter::TRACE),
+        }
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        match self.subscriber() {
+            Some(subscriber) => subscriber.new_span(attrs),
+            None => span::Id::from_u64(self.0.next_span.fetch_add(1, Ordering::Relaxed)),
+        }
+    }
+
+    fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+        if let (Some(subscriber), false) = (self.subscriber(), is_preinit(id)) {
+            subscriber.record(id, values);
+        }
+    }
+
+    fn record_follows_from(&self, id: &span::Id, follows: &span::Id) {
+        if let (Some(subscriber), false, false) =
+            (self.subscriber(), is_preinit(id), is_preinit(follows))
+        {
+            subscriber.record_follows_from(id, follows);
+        }
+    }
+
+    fn event_enabled(&self, event: &Event<'_>) -> bool {
+        match self.subscriber() {
+            Some(subscriber) => subscriber.event_enabled(event),
+            None => true,
+        }
+    }
+
+    fn event(&self, event: &Event<'_>) {
+        if let Some(subscriber) = self.subscriber() {
+            return subscriber.event(event);
+        }
+
+        // Recorded before locking, formatting the values may log
+        let mut capture = Capture(Vec::new());
+
+        event.record(&mut capture);
+
+        let mut buffer = self.0.buffer.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Attached while recording
+        if let Some(subscriber) = self.subscriber() {
+            drop(buffer);
+            return subscriber.event(event);
+        }
+
+        if buffer.capacity == 0 {
+            buffer.dropped += 1;
+            return;
+        }
+
+        if buffer.events.len() == buffer.capacity {
+            buffer.events.pop_front();
+            buffer.dropped += 1;
+        }
+
+        buffer.events.push_back(Buffered {
+            metadata: event.metadata(),
+            values: capture.0,
+            time: SystemTime::now(),
+        });
+    }
+
+    fn enter(&self, id: &span::Id) {
+        if let (Some(subscriber), false) = (self.subscriber(), is_preinit(id)) {
+            subscriber.enter(id);
+        }
+    }
+
+    fn exit(&self, id: &span::Id) {
+        if let (Some(subscriber), false) = (self.subscriber(), is_preinit(id)) {
+            subscriber.exit(id);
+        }
+    }
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        match self.subscriber() {
+            Some(subscriber) if !is_preinit(id) => subscriber.clone_span(id),
+            _ => id.clone(),
+        }
+    }
+
+    fn try_close(&self, id: span::Id) -> bool {
+        match self.subscriber() {
+            Some(subscriber) if !is_preinit(&id) => subscriber.try_close(id),
+            _ => false,
+        }
+    }
+
+    fn current_span(&self) -> Current {
+        match self.subscriber() {
+            Some(subscriber) => subscriber.current_span(),
+            None => Current::none(),
+        }
+    }
+}
+
+// Log the buffered events through the configured subscriber, if its filter enables them
+fn replay(subscriber: &(dyn Subscriber + Send + Sync), events: VecDeque<Buffered>, dropped: u64) {
+    if dropped > 0 && subscriber.enabled(&SUMMARY_METADATA) {
+        let fields = SUMMARY_METADATA.fields();
+        let field = |name| fields.field(name).expect("summary field");
+        let (message_field, dropped_field) = (field("message"), field("dropped"));
+        let message = display(format!(
+            "dropped {dropped} events logged before init (the oldest ones)"
+        ));
+        let values: [(&Field, Option<&dyn Value>); 2] = [
+            (&message_field, Some(&message)),
+            (&dropped_field, Some(&dropped)),
+        ];
+
+        subscriber.event(&Event::new_child_of(
+            None,
+            &SUMMARY_METADATA,
+            &fields.value_set(&values),
+        ));
+    }
+
+    // The metadata of the replayed events of each callsite, with the replayed and logged_at fields
+    let mut replayed_metadata = HashMap::new();
+
+    for buffered in events {
+        let metadata = *replayed_metadata
+            .entry(buffered.metadata.callsite())
+            .or_insert_with(|| {
+                let names = buffered
+                    .metadata
+                    .fields()
+                    .iter()
+                    .map(|field| field.name())
+                    .chain(["replayed", "logged_at"])
+                    .take(MAX_FIELDS)
+                    .collect();
+
+                leak_metadata(buffered.metadata, buffered.metadata.target(), names)
+            });
+
+        if !subscriber.enabled(metadata) {
+            continue;
+        }
+
+        let fields = metadata.fields().iter().collect::<Vec<_>>();
+        let logged_at = display(format_time(buffered.time));
+        let mut entries: [(&Field, Option<&dyn Value>); MAX_FIELDS] =
+            [(&fields[0], None); MAX_FIELDS];
+
+        for (index, value) in &buffered.values {
+            entries[*index] = (&fields[*index], Some(value.as_value()));
+        }
+
+        let own = buffered.metadata.fields().len();
+
+        for (index, value) in [(own, &true as &dyn Value), (own + 1, &logged_at)] {
+            if index < fields.len() {
+                entries[index] = (&fields[index], Some(value));
+            }
+        }
+
+        subscriber.event(&Event::new_child_of(
+            None,
+            metadata,
+            &metadata.fields().value_set(&entries),
+        ));
+    }
+}
+
+// The time in the RFC 3339 format, in UTC with milliseconds
+pub(crate) fn format_time(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // The civil date of a day number (see http://howardhinnant.github.io/date_algorithms.html)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            format_time(UNIX_EPOCH + Duration::from_millis(951_782_400_250)),
+            "2000-02-29T00:00:00.250Z"
+        );
+        assert_eq!(
+            format_time(UNIX_EPOCH + Duration::from_secs(1_792_151_999)),
+            "2026-10-16T11:59:59.000Z"
+        );
+    }
+}
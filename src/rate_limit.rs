@@ -0,0 +1,563 @@
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tracing::callsite::{DefaultCallsite, Identifier};
+use tracing::field::{display, Field, FieldSet, Value, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::metadata::Kind;
+use tracing::subscriber::Interest;
+use tracing::{span, Dispatch, Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::clock::ClockSource;
+use crate::Clock;
+
+/// Limits the number of repeated events logged, see [`TracingInit::rate_limit`](crate::TracingInit::rate_limit)
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use tracing_init::{RateLimit, TracingInit};
+///
+/// // At most 10 occurrences of each event every 30 seconds
+/// TracingInit::builder("App").rate_limit(RateLimit {
+///     max_per_key: 10,
+///     window: Duration::from_secs(30),
+///     ..RateLimit::default()
+/// });
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The number of occurrences of an event logged in each window, at least one (default: 100)
+    pub max_per_key: u32,
+    /// The window length (default: 1 minute)
+    pub window: Duration,
+    /// Key the events by their message as well, so events from the same callsite with different messages are limited
+    /// separately (default: false)
+    pub include_message: bool,
+    /// Never suppress ERROR events (default: true)
+    pub exempt_errors: bool,
+    /// The number of keys tracked, when exceeded the least recently used key is dropped (default: 1024)
+    pub max_keys: usize,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            max_per_key: 100,
+            window: Duration::from_secs(60),
+            include_message: false,
+            exempt_errors: true,
+            max_keys: 1024,
+        }
+    }
+}
+
+// The events counted together: the same callsite (and level), optionally with the same message
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Key {
+    callsite: Identifier,
+    level: Level,
+    message: Option<String>,
+}
+
+struct Entry {
+    target: &'static str,
+    window_start: Instant,
+    count: u32,
+    suppressed: u64,
+    last_used: u64,
+}
+
+impl Entry {
+    fn summary(&self) -> Option<Summary> {
+        (self.suppressed > 0).then_some(Summary {
+            target: self.target,
+            suppressed: self.suppressed,
+        })
+    }
+}
+
+struct State {
+    keys: HashMap<Key, Entry>,
+    // The keys by their last use, least recently used first
+    lru: BTreeMap<u64, Key>,
+    tick: u64,
+    next_sweep: Instant,
+}
+
+// The events suppressed from a target in a window which rolled over
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Summary {
+    target: &'static str,
+    suppressed: u64,
+}
+
+static SUMMARY_CALLSITE: DefaultCallsite = DefaultCallsite::new(&SUMMARY_METADATA);
+static SUMMARY_METADATA: Metadata<'static> = Metadata::new(
+    "rate limit summary",
+    "tracing_init",
+    Level::WARN,
+    Some(file!()),
+    Some(line!()),
+    Some(module_path!()),
+    FieldSet::new(
+        &["message", "suppressed", "suppressed_target"],
+        Identifier(&SUMMARY_CALLSITE),
+    ),
+    Kind::EVENT,
+);
+
+impl Summary {
+    // Call f with the summary event. It is passed to the destinations directly, it is not dispatched
+    fn with_event<R>(&self, f: impl FnOnce(&Event<'_>) -> R) -> R {
+        let fields = SUMMARY_METADATA.fields();
+        let field = |name| fields.field(name).expect("summary field");
+        let (message_field, suppressed_field, target_field) = (
+            field("message"),
+            field("suppressed"),
+            field("suppressed_target"),
+        );
+        // Recorded like the messages of the tracing macros
+        let message = display(format!(
+            "suppressed {} duplicate events from target {}",
+            self.suppressed, self.target
+        ));
+        let values: [(&Field, Option<&dyn Value>); 3] = [
+            (&message_field, Some(&message)),
+            (&suppressed_field, Some(&self.suppressed)),
+            (&target_field, Some(&self.target)),
+        ];
+
+        f(&Event::new_child_of(
+            None,
+            &SUMMARY_METADATA,
+            &fields.value_set(&values),
+        ))
+    }
+}
+
+// Counts the occurrences of each key in its window
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    state: Mutex<State>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit, now: Instant) -> RateLimiter {
+        RateLimiter {
+            limit,
+            state: Mutex::new(State {
+                keys: HashMap::new(),
+                lru: BTreeMap::new(),
+                tick: 0,
+                next_sweep: now + limit.window,
+            }),
+        }
+    }
+
+    fn is_exempt(&self, level: &Level) -> bool {
+        self.limit.exempt_errors && *level == Level::ERROR
+    }
+
+    fn key(&self, event: &Event<'_>) -> Key {
+        let metadata = event.metadata();
+
+        Key {
+            callsite: metadata.callsite(),
+            level: *metadata.level(),
+            message: self.limit.include_message.then(|| {
+                let mut visitor = MessageVisitor(String::new());
+
+                event.record(&mut visitor);
+                visitor.0
+            }),
+        }
+    }
+
+    // Count an occurrence of key, returns None if it is suppressed. Otherwise returns the summaries of the windows
+    // which rolled over (of this key and of the keys not seen since their window ended) and of the evicted keys.
+    // Summaries are only returned with an event which is logged, see RateLimitLayer::on_event
+    pub(crate) fn check(
+        &self,
+        key: Key,
+        target: &'static str,
+        now: Instant,
+    ) -> Option<Vec<Summary>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let state = &mut *state;
+        let window = self.limit.window;
+        let mut summaries = Vec::new();
+
+        state.tick += 1;
+
+        let tick = state.tick;
+
+        match state.keys.get_mut(&key) {
+            Some(entry) => {
+                state.lru.remove(&entry.last_used);
+                state.lru.insert(tick, key);
+                entry.last_used = tick;
+
+                // now may be a little older than the window start, when taken before another thread got the lock
+                if now.saturating_duration_since(entry.window_start) >= window {
+                    summaries.extend(entry.summary());
+                    entry.window_start = now;
+                    entry.count = 0;
+                    entry.suppressed = 0;
+                }
+
+                if entry.count >= self.limit.max_per_key.max(1) {
+                    entry.suppressed += 1;
+                    return None;
+                }
+
+                entry.count += 1;
+            }
+            None => {
+                while state.keys.len() >= self.limit.max_keys.max(1) {
+                    let Some((_, evicted)) = state.lru.pop_first() else {
+                        break;
+                    };
+
+                    summaries.extend(state.keys.remove(&evicted).and_then(|e| e.summary()));
+                }
+
+                state.keys.insert(
+                    key.clone(),
+                    Entry {
+                        target,
+                        window_start: now,
+                        count: 1,
+                        suppressed: 0,
+                        last_used: tick,
+                    },
+                );
+                state.lru.insert(tick, key);
+            }
+        }
+
+        // Once per window, drop the keys whose window ended
+        if now >= state.next_sweep {
+            let lru = &mut state.lru;
+
+            state.keys.retain(|_, entry| {
+                let ended = now.saturating_duration_since(entry.window_start) >= window;
+
+                if ended {
+                    summaries.extend(entry.summary());
+                    lru.remove(&entry.last_used);
+                }
+
+                !ended
+            });
+            state.next_sweep = now + window;
+        }
+
+        Some(merge(summaries))
+    }
+
+    #[cfg(test)]
+    fn key_count(&self) -> usize {
+        let state = self.state.lock().unwrap();
+
+        assert_eq!(state.keys.len(), state.lru.len());
+        state.keys.len()
+    }
+}
+
+// One summary per target
+fn merge(summaries: Vec<Summary>) -> Vec<Summary> {
+    let mut merged: Vec<Summary> = Vec::with_capacity(summaries.len());
+
+    for summary in summaries {
+        match merged.iter_mut().find(|m| m.target == summary.target) {
+            Some(m) => m.suppressed += summary.suppressed,
+            None => merged.push(summary),
+        }
+    }
+
+    merged
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_string();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+// Passes the events to the wrapped layer unless their key exceeded its limit. The summaries of the suppressed events
+// are passed after an event which is not suppressed, once the per-layer filters of the wrapped layers processed it
+pub(crate) struct RateLimitLayer<L> {
+    inner: L,
+    limiter: RateLimiter,
+    clock: ClockSource,
+}
+
+impl<L> RateLimitLayer<L> {
+    pub(crate) fn new(
+        inner: L,
+        limit: RateLimit,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> RateLimitLayer<L> {
+        let clock = ClockSource::new(clock);
+
+        RateLimitLayer {
+            inner,
+            limiter: RateLimiter::new(limit, clock.instant()),
+            clock,
+        }
+    }
+}
+
+impl<S, L> Layer<S> for RateLimitLayer<L>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    L: Layer<S>,
+{
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        self.inner.on_register_dispatch(subscriber);
+    }
+
+    fn on_layer(&mut self, subscriber: &mut S) {
+        self.inner.on_layer(subscriber);
+    }
+
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        self.inner.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.enabled(metadata, ctx)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        self.inner.max_level_hint()
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.event_enabled(event, ctx)
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        self.inner.on_record(id, values, ctx);
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_follows_from(id, follows, ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+
+        if self.limiter.is_exempt(metadata.level()) {
+            return self.inner.on_event(event, ctx);
+        }
+
+        let key = self.limiter.key(event);
+
+        if let Some(summaries) = self
+            .limiter
+            .check(key, metadata.target(), self.clock.instant())
+        {
+            self.inner.on_event(event, ctx.clone());
+
+            for summary in summaries {
+                summary.with_event(|summary| self.inner.on_event(summary, ctx.clone()));
+            }
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.inner.on_close(id, ctx);
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_id_change(old, new, ctx);
+    }
+
+    // The wrapped layers may have per-layer filters, which the subscriber finds by downcasting
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const _ as *const ())
+        } else {
+            self.inner.downcast_raw(id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{with_env, CaptureLayer};
+    use crate::TracingInit;
+    use std::sync::Arc;
+
+    fn key(n: usize) -> Key {
+        Key {
+            callsite: SUMMARY_METADATA.callsite(),
+            level: Level::WARN,
+            message: Some(n.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_counts() {
+        let limit = RateLimit {
+            max_per_key: 10,
+            window: Duration::from_secs(3600),
+            ..RateLimit::default()
+        };
+        let start = Instant::now();
+        let limiter = Arc::new(RateLimiter::new(limit, start));
+
+        let threads = (0..8)
+            .map(|_| {
+                let limiter = limiter.clone();
+
+                std::thread::spawn(move || {
+                    (0..1000)
+                        .filter(|_| limiter.check(key(1), "flood", start).is_some())
+                        .count()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let logged: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(logged, 10);
+
+        // The next window starts with the summary of the previous one
+        let summaries = limiter.check(key(1), "flood", start + limit.window);
+        assert_eq!(
+            summaries,
+            Some(vec![Summary {
+                target: "flood",
+                suppressed: 7990
+            }])
+        );
+        assert_eq!(
+            limiter.check(key(1), "flood", start + limit.window),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_bounded_keys() {
+        let limit = RateLimit {
+            max_per_key: 1,
+            max_keys: 3,
+            ..RateLimit::default()
+        };
+        let now = Instant::now();
+        let limiter = RateLimiter::new(limit, now);
+
+        for n in 0..3 {
+            assert_eq!(limiter.check(key(n), "keys", now), Some(vec![]));
+            assert_eq!(limiter.check(key(n), "keys", now), None);
+        }
+
+        // Key 0 is the least recently used
+        assert_eq!(limiter.check(key(1), "keys", now), None);
+        assert_eq!(limiter.check(key(2), "keys", now), None);
+        assert_eq!(
+            limiter.check(key(3), "keys", now),
+            Some(vec![Summary {
+                target: "keys",
+                suppressed: 1
+            }])
+        );
+        assert_eq!(limiter.key_count(), 3);
+
+        // Keys not seen since their window ended are summarized and dropped by the next sweep
+        assert_eq!(
+            limiter.check(key(4), "other", now + limit.window),
+            Some(vec![Summary {
+                target: "keys",
+                suppressed: 4
+            }])
+        );
+        assert_eq!(limiter.key_count(), 1);
+    }
+
+    #[test]
+    fn test_rate_limit_layer() {
+        let capture = CaptureLayer::default();
+        let (dispatch, _guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_console(false)
+                .with_layer(capture.clone())
+                .with_event_filter_fn(|event| event.metadata().target() != "noise")
+                .rate_limit(RateLimit {
+                    max_per_key: 2,
+                    window: Duration::from_millis(500),
+                    ..RateLimit::default()
+                })
+                .into_dispatch()
+                .unwrap()
+        });
+
+        let reconnect = |attempt: usize| tracing::warn!(target: "client", attempt, "reconnecting");
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            for attempt in 0..100 {
+                reconnect(attempt);
+                tracing::warn!(target: "noise", "filtered out");
+                tracing::error!(target: "client", "connection failed");
+            }
+
+            std::thread::sleep(Duration::from_millis(600));
+            reconnect(100);
+        });
+
+        let events = capture.events();
+        let count = |level, message: &str| {
+            events
+                .iter()
+                .filter(|(l, _, m)| *l == level && m == message)
+                .count()
+        };
+
+        assert_eq!(count(Level::WARN, "reconnecting"), 3, "{events:?}");
+        assert!(
+            !events.iter().any(|(_, _, m)| m.contains("noise")),
+            "{events:?}"
+        );
+        assert_eq!(count(Level::ERROR, "connection failed"), 100);
+        assert_eq!(
+            count(
+                Level::WARN,
+                "suppressed 98 duplicate events from target client"
+            ),
+            1,
+            "{events:?}"
+        );
+        assert_eq!(events.last().unwrap().1, "tracing_init");
+    }
+}
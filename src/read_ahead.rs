@@ -0,0 +1,33 @@
+use std::io::Read;
+
+// Reads the records of the binary and the encrypted log files: the bytes ahead are looked at before they are
+// consumed, so a record can be checked before it is taken (and skipped when it is not one)
+pub(crate) struct ReadAhead<R> {
+    reader: R,
+    ahead: Vec<u8>,
+}
+
+impl<R: Read> ReadAhead<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        ReadAhead {
+            reader,
+            ahead: Vec::new(),
+        }
+    }
+
+    // The next len bytes, fewer at the end of the file
+    pub(crate) fn peek(&mut self, len: usize) -> std::io::Result<&[u8]> {
+        if self.ahead.len() < len {
+            // Grows with the data actually read, a corrupt length does not allocate it all
+            (&mut self.reader)
+                .take((len - self.ahead.len()) as u64)
+                .read_to_end(&mut self.ahead)?;
+        }
+
+        Ok(&self.ahead[..len.min(self.ahead.len())])
+    }
+
+    pub(crate) fn consume(&mut self, len: usize) {
+        self.ahead.drain(..len.min(self.ahead.len()));
+    }
+}
@@ -0,0 +1,616 @@
+use std::any::TypeId;
+use std::sync::Arc;
+
+use regex::Regex;
+use tracing::field::{DisplayValue, Field, Value, ValueSet, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::subscriber::Interest;
+use tracing::{span, Dispatch, Event, Metadata, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::TracingInitError;
+
+// Replaces the values of the redacted fields and the parts of values matching a redaction pattern
+pub(crate) const REDACTED: &str = "[REDACTED]";
+
+// Values of redacted fields shorter than this are not removed from the message, they would match unrelated text
+const MIN_MESSAGE_REDACTION_LEN: usize = 4;
+
+// The most fields a callsite can have
+const MAX_FIELDS: usize = 32;
+
+// The field names and value patterns set by redact_fields and redact_pattern
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Redaction {
+    fields: Vec<String>,
+    patterns: Vec<String>,
+}
+
+impl Redaction {
+    pub(crate) fn add_fields(&mut self, fields: &[&str]) {
+        self.fields
+            .extend(fields.iter().map(|field| field.to_lowercase()));
+    }
+
+    pub(crate) fn add_pattern(&mut self, pattern: &str) {
+        self.patterns.push(pattern.to_string());
+    }
+
+    // Redact the fields and patterns of other as well
+    pub(crate) fn extend(&mut self, other: &Redaction) {
+        for field in &other.fields {
+            if !self.fields.contains(field) {
+                self.fields.push(field.clone());
+            }
+        }
+
+        for pattern in &other.patterns {
+            if !self.patterns.contains(pattern) {
+                self.patterns.push(pattern.clone());
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.patterns.is_empty()
+    }
+
+    pub(crate) fn problems(&self) -> Vec<TracingInitError> {
+        self.compile_patterns().err().into_iter().collect()
+    }
+
+    pub(crate) fn redactor(&self) -> Result<Redactor, TracingInitError> {
+        Ok(Redactor {
+            fields: self.fields.clone(),
+            patterns: self.compile_patterns()?,
+        })
+    }
+
+    fn compile_patterns(&self) -> Result<Vec<Regex>, TracingInitError> {
+        self.patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| TracingInitError::InvalidRedactPattern {
+                    pattern: pattern.clone(),
+                    reason: e.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Redactor {
+    // Lower case
+    fields: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    fn is_redacted(&self, field: &Field) -> bool {
+        self.fields
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(field.name()))
+    }
+
+    // Replace the parts of the value matching a pattern, None if nothing matches
+    fn scrub(&self, value: &str) -> Option<String> {
+        let mut scrubbed = None::<String>;
+
+        for pattern in &self.patterns {
+            let current = scrubbed.as_deref().unwrap_or(value);
+
+            if pattern.is_match(current) {
+                scrubbed = Some(pattern.replace_all(current, REDACTED).into_owned());
+            }
+        }
+
+        scrubbed
+    }
+
+    // Record the values, returns None if nothing had to be redacted
+    fn redact(
+        &self,
+        metadata: &Metadata<'_>,
+        record: impl FnOnce(&mut dyn Visit),
+    ) -> Option<Vec<(Field, Captured)>> {
+        if self.patterns.is_empty()
+            && !metadata
+                .fields()
+                .iter()
+                .any(|field| self.is_redacted(&field))
+        {
+            return None;
+        }
+
+        let mut capture = Capture {
+            redactor: self,
+            values: Vec::new(),
+            redacted_values: Vec::new(),
+            message: None,
+            changed: false,
+        };
+
+        record(&mut capture);
+
+        if let Some((index, ref original)) = capture.message {
+            let mut message = original.clone();
+
+            for value in &capture.redacted_values {
+                if value.len() >= MIN_MESSAGE_REDACTION_LEN {
+                    message = message.replace(value.as_str(), REDACTED);
+                }
+            }
+
+            let message = self.scrub(&message).unwrap_or(message);
+
+            if message != *original {
+                capture.values[index].1 = Captured::Display(tracing::field::display(message));
+                capture.changed = true;
+            }
+        }
+
+        capture.changed.then_some(capture.values)
+    }
+}
+
+// A recorded value, passed on with its original type unless it was redacted
+pub(crate) enum Captured {
+    I64(i64),
+    U64(u64),
+    I128(i128),
+    U128(u128),
+    F64(f64),
+    Bool(bool),
+    Str(String),
+    Display(DisplayValue<String>),
+}
+
+impl Captured {
+    fn render(&self) -> String {
+        match self {
+            Captured::I64(value) => value.to_string(),
+            Captured::U64(value) => value.to_string(),
+            Captured::I128(value) => value.to_string(),
+            Captured::U128(value) => value.to_string(),
+            Captured::F64(value) => value.to_string(),
+            Captured::Bool(value) => value.to_string(),
+            Captured::Str(value) => value.clone(),
+            Captured::Display(value) => format!("{value:?}"),
+        }
+    }
+
+    pub(crate) fn as_value(&self) -> &dyn Value {
+        match self {
+            Captured::I64(value) => value,
+            Captured::U64(value) => value,
+            Captured::I128(value) => value,
+            Captured::U128(value) => value,
+            Captured::F64(value) => value,
+            Captured::Bool(value) => value,
+            Captured::Str(value) => value,
+            Captured::Display(value) => value,
+        }
+    }
+}
+
+struct Capture<'r> {
+    redactor: &'r Redactor,
+    values: Vec<(Field, Captured)>,
+    // Original values of the redacted fields, removed from the message as well
+    redacted_values: Vec<String>,
+    // The index and the rendered message
+    message: Option<(usize, String)>,
+    changed: bool,
+}
+
+impl Capture<'_> {
+    fn record_value(&mut self, field: &Field, value: Captured) {
+        if self.redactor.is_redacted(field) {
+            self.record_redacted(field, Some(value.render()));
+        } else {
+            self.values.push((field.clone(), value));
+        }
+    }
+
+    fn record_redacted(&mut self, field: &Field, original: Option<String>) {
+        self.redacted_values.extend(original);
+        self.values
+            .push((field.clone(), Captured::Str(String::from(REDACTED))));
+        self.changed = true;
+    }
+}
+
+impl Visit for Capture<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record_value(field, Captured::I64(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record_value(field, Captured::U64(value));
+    }
+
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        self.record_value(field, Captured::I128(value));
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        self.record_value(field, Captured::U128(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record_value(field, Captured::F64(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record_value(field, Captured::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if self.redactor.is_redacted(field) {
+            self.record_redacted(field, Some(value.to_string()));
+        } else {
+            let scrubbed = self.redactor.scrub(value);
+
+            self.changed |= scrubbed.is_some();
+            self.values.push((
+                field.clone(),
+                Captured::Str(scrubbed.unwrap_or_else(|| value.to_string())),
+            ));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let rendered = format!("{value:?}");
+
+        if self.redactor.is_redacted(field) {
+            // A string recorded with ? is rendered with quotes, the message contains it without them
+            let unquoted = rendered
+                .strip_prefix('"')
+                .and_then(|rendered| rendered.strip_suffix('"'))
+                .map(String::from);
+
+            self.redacted_values.extend(unquoted);
+            self.record_redacted(field, Some(rendered));
+        } else if field.name() == "message" {
+            // Scrubbed after all the fields are recorded
+            self.message = Some((self.values.len(), rendered.clone()));
+            self.values.push((
+                field.clone(),
+                Captured::Display(tracing::field::display(rendered)),
+            ));
+        } else {
+            let scrubbed = self.redactor.scrub(&rendered);
+
+            self.changed |= scrubbed.is_some();
+            self.values.push((
+                field.clone(),
+                Captured::Display(tracing::field::display(scrubbed.unwrap_or(rendered))),
+            ));
+        }
+    }
+
+    // Redacted and scrubbed as the JSON document, passed on as a JSON string
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    fn record_value(&mut self, field: &Field, value: valuable::Value<'_>) {
+        self.record_str(field, &crate::structured::to_json(value));
+    }
+}
+
+// Call f with the redacted values (there is at least one, since something was redacted)
+fn with_value_set<R>(
+    metadata: &'static Metadata<'static>,
+    values: &[(Field, Captured)],
+    f: impl FnOnce(&ValueSet<'_>) -> R,
+) -> R {
+    let mut entries: [(&Field, Option<&dyn Value>); MAX_FIELDS] =
+        [(&values[0].0, None); MAX_FIELDS];
+
+    for (entry, (field, value)) in entries.iter_mut().zip(values) {
+        *entry = (field, Some(value.as_value()));
+    }
+
+    f(&metadata.fields().value_set(&entries))
+}
+
+// Passes redacted copies of the events and span fields to the wrapped layer, so field values are redacted before
+// they are formatted (console, file, JSON) or sent (log server)
+pub(crate) struct RedactLayer<L> {
+    inner: L,
+    redactor: Arc<Redactor>,
+}
+
+impl<L> RedactLayer<L> {
+    pub(crate) fn new(inner: L, redactor: Redactor) -> RedactLayer<L> {
+        RedactLayer {
+            inner,
+            redactor: Arc::new(redactor),
+        }
+    }
+}
+
+impl<S, L> Layer<S> for RedactLayer<L>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    L: Layer<S>,
+{
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        self.inner.on_register_dispatch(subscriber);
+    }
+
+    fn on_layer(&mut self, subscriber: &mut S) {
+        self.inner.on_layer(subscriber);
+    }
+
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        self.inner.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.enabled(metadata, ctx)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        self.inner.max_level_hint()
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.event_enabled(event, ctx)
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let metadata = attrs.metadata();
+
+        match self
+            .redactor
+            .redact(metadata, |visitor| attrs.record(visitor))
+        {
+            Some(values) => with_value_set(metadata, &values, |values| {
+                let attrs = if attrs.is_contextual() {
+                    span::Attributes::new(metadata, values)
+                } else if let Some(parent) = attrs.parent() {
+                    span::Attributes::child_of(parent.clone(), metadata, values)
+                } else {
+                    span::Attributes::new_root(metadata, values)
+                };
+
+                self.inner.on_new_span(&attrs, id, ctx)
+            }),
+            None => self.inner.on_new_span(attrs, id, ctx),
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let redacted = ctx.metadata(id).and_then(|metadata| {
+            let redacted = self
+                .redactor
+                .redact(metadata, |visitor| values.record(visitor))?;
+
+            Some((metadata, redacted))
+        });
+
+        match redacted {
+            Some((metadata, redacted)) => with_value_set(metadata, &redacted, |values| {
+                self.inner.on_record(id, &span::Record::new(values), ctx)
+            }),
+            None => self.inner.on_record(id, values, ctx),
+        }
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_follows_from(id, follows, ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+
+        match self
+            .redactor
+            .redact(metadata, |visitor| event.record(visitor))
+        {
+            Some(values) => with_value_set(metadata, &values, |values| {
+                let event = if event.is_contextual() {
+                    Event::new(metadata, values)
+                } else {
+                    Event::new_child_of(event.parent().cloned(), metadata, values)
+                };
+
+                self.inner.on_event(&event, ctx)
+            }),
+            None => self.inner.on_event(event, ctx),
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.inner.on_close(id, ctx);
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_id_change(old, new, ctx);
+    }
+
+    // The wrapped layers may have per-layer filters, which the subscriber finds by downcasting
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const _ as *const ())
+        } else {
+            self.inner.downcast_raw(id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "file")]
+    use crate::tests::{test_log_dir, with_env, MemoryWriter};
+    use crate::{TracingInit, TracingInitError};
+
+    // Log with the given redaction to a JSON layer and to the log file, returns both outputs
+    #[cfg(feature = "file")]
+    fn redacted_output(
+        name: &str,
+        configure: impl FnOnce(&mut TracingInit),
+        log: impl FnOnce(),
+    ) -> (String, String) {
+        let log_dir = test_log_dir(name);
+        let json = MemoryWriter::default();
+        let mut builder = TracingInit::builder("App");
+
+        builder
+            .log_to_file(true)
+            .log_file_path(log_dir.to_str().unwrap())
+            .log_file_rotation(crate::Rotation::Never)
+            .with_layer(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_writer(json.clone()),
+            );
+        configure(&mut builder);
+
+        let (dispatch, guard) = with_env(&[], || builder.into_dispatch().unwrap());
+
+        tracing::dispatcher::with_default(&dispatch, log);
+        drop(guard);
+
+        let file = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+
+        (json.contents(), file)
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_redact_event_fields() {
+        let (json, file) = redacted_output(
+            "redact-event",
+            |builder| {
+                builder.redact_fields(&["password", "authorization", "ssn"]);
+            },
+            || {
+                tracing::info!(
+                    password = "hunter22",
+                    Authorization = %"Basic dXNlcjpwYXNz",
+                    ssn = 123456789u64,
+                    user = "bob",
+                    attempts = 3,
+                    "login"
+                );
+            },
+        );
+
+        for output in [&json, &file] {
+            assert!(!output.contains("hunter22"), "{output}");
+            assert!(!output.contains("dXNlcjpwYXNz"), "{output}");
+            assert!(!output.contains("123456789"), "{output}");
+        }
+
+        assert!(json.contains(r#""password":"[REDACTED]""#), "{json}");
+        assert!(json.contains(r#""Authorization":"[REDACTED]""#), "{json}");
+        assert!(json.contains(r#""ssn":"[REDACTED]""#), "{json}");
+        assert!(json.contains(r#""user":"bob""#), "{json}");
+        assert!(json.contains(r#""attempts":3"#), "{json}");
+        assert!(file.contains(r#"login password="[REDACTED]""#), "{file}");
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_redact_span_fields() {
+        let (json, file) = redacted_output(
+            "redact-span",
+            |builder| {
+                builder.redact_fields(&["PASSWORD", "token"]);
+            },
+            || {
+                let span = tracing::info_span!(
+                    "login",
+                    user = "bob",
+                    password = "hunter22",
+                    token = tracing::field::Empty
+                );
+                let _entered = span.enter();
+
+                span.record("token", "secret-token");
+                tracing::info!("logged in");
+            },
+        );
+
+        for output in [&json, &file] {
+            assert!(!output.contains("hunter22"), "{output}");
+            assert!(!output.contains("secret-token"), "{output}");
+        }
+
+        assert!(json.contains(r#""password":"[REDACTED]""#), "{json}");
+        assert!(json.contains(r#""token":"[REDACTED]""#), "{json}");
+        assert!(json.contains(r#""user":"bob""#), "{json}");
+        assert!(
+            file.contains(r#"login{user="bob" password="[REDACTED]" token="[REDACTED]"}: "#),
+            "{file}"
+        );
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_redact_message() {
+        let (json, file) = redacted_output(
+            "redact-message",
+            |builder| {
+                builder
+                    .redact_fields(&["password"])
+                    .redact_pattern(r"Bearer [A-Za-z0-9.]+");
+            },
+            || {
+                let password = "hunter22";
+                let header = "Bearer abc.def";
+
+                tracing::info!(password, "login with password {password}");
+                tracing::info!(header, "sending {header}");
+                tracing::info!(count = 2, "nothing to redact in {}", "here");
+            },
+        );
+
+        for output in [&json, &file] {
+            assert!(!output.contains("hunter22"), "{output}");
+            assert!(!output.contains("abc.def"), "{output}");
+        }
+
+        assert!(
+            json.contains(r#""message":"login with password [REDACTED]""#),
+            "{json}"
+        );
+        assert!(
+            json.contains(r#""message":"sending [REDACTED]","header":"[REDACTED]""#),
+            "{json}"
+        );
+        assert!(
+            json.contains(r#""message":"nothing to redact in here","count":2"#),
+            "{json}"
+        );
+        assert!(
+            file.contains("login with password [REDACTED] password=\"[REDACTED]\""),
+            "{file}"
+        );
+    }
+
+    #[test]
+    fn test_invalid_redact_pattern() {
+        let problems = TracingInit::builder("App")
+            .log_to_console(true)
+            .redact_pattern("token=(")
+            .validate()
+            .unwrap_err();
+
+        assert!(matches!(
+            &problems[..],
+            [TracingInitError::InvalidRedactPattern { pattern, .. }] if pattern == "token=("
+        ));
+    }
+}
@@ -0,0 +1,304 @@
+use std::fs::Metadata;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock, RwLockReadGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use tracing::dispatcher::WeakDispatch;
+use tracing_appender::rolling::RollingFileAppender;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::clock::ClockSource;
+use crate::layers::{self, FileOptions};
+use crate::Rotation;
+
+// The path of the log file is checked at most this often, not for every record
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// The log file writer, recreating the log file when it was deleted or renamed while being written
+pub(crate) struct ReopenWriter {
+    appender: RwLock<RollingFileAppender>,
+    options: FileOptions,
+    clock: ClockSource,
+    start: Instant,
+    // The time of the next check, in milliseconds since the start
+    next_check: AtomicU64,
+    // The path of the file written to and its identity when last checked (None if it did not exist yet)
+    file: Mutex<(PathBuf, Option<FileId>)>,
+    // The dispatch the recreation is logged to, the global default one if not set
+    dispatch: Arc<OnceLock<WeakDispatch>>,
+}
+
+impl ReopenWriter {
+    pub(crate) fn new(
+        appender: RollingFileAppender,
+        options: &FileOptions,
+        dispatch: Arc<OnceLock<WeakDispatch>>,
+    ) -> ReopenWriter {
+        let clock = ClockSource::new(options.clock.clone());
+        let path = current_file_path(options);
+        let id = file_id(&path);
+
+        ReopenWriter {
+            appender: RwLock::new(appender),
+            options: options.clone(),
+            start: clock.instant(),
+            clock,
+            next_check: AtomicU64::new(CHECK_INTERVAL.as_millis() as u64),
+            file: Mutex::new((path, id)),
+            dispatch,
+        }
+    }
+
+    // Reopen the file if its path no longer leads to it, a single thread checks once the interval has elapsed
+    fn check(&self) {
+        let elapsed = self
+            .clock
+            .instant()
+            .saturating_duration_since(self.start)
+            .as_millis() as u64;
+        let next_check = self.next_check.load(Ordering::Acquire);
+
+        if elapsed < next_check
+            || self
+                .next_check
+                .compare_exchange(
+                    next_check,
+                    elapsed + CHECK_INTERVAL.as_millis() as u64,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_err()
+        {
+            return;
+        }
+
+        let path = current_file_path(&self.options);
+        let id = file_id(&path);
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Once a file was seen at this path, a missing file or another one in its place is the file being deleted or
+        // renamed. A new path is the file being rotated, its file may not be created yet
+        let missing = file.0 == path && file.1.is_some() && id != file.1;
+
+        *file = (path, id);
+
+        if missing {
+            let path = file.0.clone();
+
+            drop(file);
+            self.reopen(path);
+        }
+    }
+
+    fn reopen(&self, path: PathBuf) {
+        let result = layers::rolling_file_appender(&self.options).map(|appender| {
+            *self
+                .appender
+                .write()
+                .unwrap_or_else(PoisonError::into_inner) = appender;
+            *self.file.lock().unwrap_or_else(PoisonError::into_inner) =
+                (path.clone(), file_id(&path));
+        });
+
+        // Logged by a thread: the file is reopened while an event is dispatched, or by the background writer
+        let dispatch = self.dispatch.clone();
+        let _ = std::thread::Builder::new()
+            .name(String::from("tracing-init-reopen"))
+            .spawn(move || {
+                let report = || match result {
+                    Ok(()) => tracing::info!(
+                        target: "tracing_init",
+                        file = %path.display(),
+                        "the log file was deleted or renamed, it was recreated"
+                    ),
+                    Err(error) => tracing::warn!(
+                        target: "tracing_init",
+                        file = %path.display(),
+                        %error,
+                        "the log file was deleted or renamed, it cannot be recreated"
+                    ),
+                };
+
+                match dispatch.get().map(WeakDispatch::upgrade) {
+                    Some(Some(dispatch)) => tracing::dispatcher::with_default(&dispatch, report),
+                    // The subscriber was dropped
+                    Some(None) => {}
+                    None => report(),
+                }
+            });
+    }
+}
+
+// Writes to the current file, it is not replaced while the record is written
+pub(crate) struct ReopenedFile<'a>(RwLockReadGuard<'a, RollingFileAppender>);
+
+impl Write for ReopenedFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.make_writer().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.make_writer().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for ReopenWriter {
+    type Writer = ReopenedFile<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.check();
+        ReopenedFile(self.appender.read().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+// The non-blocking writer's worker thread writes the records
+impl Write for ReopenWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.check();
+        self.appender
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.appender
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .flush()
+    }
+}
+
+// The file tracing-appender writes to now, named after the current period in UTC
+pub(crate) fn current_file_path(options: &FileOptions) -> PathBuf {
+    let time = crate::preinit::format_time(SystemTime::now());
+    let date = match options.rotation {
+        Rotation::Minutely => format!(".{}-{}-{}", &time[..10], &time[11..13], &time[14..16]),
+        Rotation::Hourly => format!(".{}-{}", &time[..10], &time[11..13]),
+        Rotation::Daily => format!(".{}", &time[..10]),
+        Rotation::Never => String::new(),
+    };
+
+    options.path.join(format!("{}{date}.log", options.prefix))
+}
+
+// The identity of a file, the file replacing a renamed one has another identity although it has the same path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileId(u64, u64);
+
+fn file_id(path: &std::path::Path) -> Option<FileId> {
+    std::fs::metadata(path)
+        .ok()
+        .map(|metadata| identity(&metadata))
+}
+
+#[cfg(unix)]
+fn identity(metadata: &Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+
+    FileId(metadata.dev(), metadata.ino())
+}
+
+// Only a deleted file is detected, an open file cannot be renamed on Windows
+#[cfg(not(unix))]
+fn identity(_metadata: &Metadata) -> FileId {
+    FileId(0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{test_log_dir, with_env};
+    use crate::{ManualClock, Rotation, TracingInit};
+    use std::time::{Duration, SystemTime};
+
+    // Wait for the thread logging the recreation
+    fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..500 {
+            if condition() {
+                return;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("timed out");
+    }
+
+    #[test]
+    fn test_reopen_if_missing() {
+        let log_dir = test_log_dir("reopen-if-missing");
+        let log_file = log_dir.join("App.log");
+        let clock = ManualClock::new(SystemTime::now());
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .reopen_if_missing(true)
+                .with_clock(clock.clone())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        assert!(guard.to_string().contains("reopened if missing"), "{guard}");
+
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("before"));
+        std::fs::remove_file(&log_file).unwrap();
+
+        // Not checked again before the interval elapsed
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("lost"));
+        assert!(!log_file.exists());
+
+        clock.advance(Duration::from_secs(2));
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("after"));
+
+        let read = || std::fs::read_to_string(&log_file).unwrap_or_default();
+        wait_until(|| read().contains("it was recreated"));
+        drop(guard);
+
+        let log = read();
+        assert!(log.contains(
+            "INFO tracing_init: the log file was deleted or renamed, it was recreated file="
+        ));
+        assert!(log.contains("tracing_init::reopen::tests: after"));
+        assert!(!log.contains("before") && !log.contains("lost"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reopen_if_renamed() {
+        let log_dir = test_log_dir("reopen-if-renamed");
+        let log_file = log_dir.join("App.log");
+        let clock = ManualClock::new(SystemTime::now());
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .log_file_non_blocking(true)
+                .reopen_if_missing(true)
+                .with_clock(clock.clone())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        let read = |name: &str| std::fs::read_to_string(log_dir.join(name)).unwrap_or_default();
+
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("before"));
+        wait_until(|| read("App.log").contains("before"));
+        std::fs::rename(&log_file, log_dir.join("App.log.1")).unwrap();
+        // Another file in its place
+        std::fs::write(&log_file, "").unwrap();
+
+        clock.advance(Duration::from_secs(2));
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("after"));
+        wait_until(|| read("App.log").contains("it was recreated"));
+        drop(guard);
+
+        assert!(read("App.log").contains("tracing_init::reopen::tests: after"));
+        let renamed = read("App.log.1");
+        assert!(renamed.contains("before") && !renamed.contains("after"));
+    }
+}
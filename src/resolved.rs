@@ -0,0 +1,1370 @@
+use std::fmt::Display;
+#[cfg(feature = "server")]
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+#[cfg(feature = "server")]
+use std::time::Instant;
+
+use tracing::dispatcher::WeakDispatch;
+use tracing::Level;
+use tracing_subscriber::filter::LevelFilter;
+#[cfg(feature = "file")]
+use tracing_subscriber::filter::{filter_fn, FilterFn};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::filter::{add_directives, DestinationFilters};
+#[cfg(feature = "file")]
+use crate::flush::FileGuard;
+#[cfg(feature = "file")]
+use crate::layers::FileOptions;
+use crate::layers::{self, ConsoleOptions};
+#[cfg(feature = "server")]
+use crate::layers::{ServerGuard, ServerOptions};
+#[cfg(feature = "file")]
+use crate::log_dir::{self, DefaultLogDir};
+#[cfg(feature = "file")]
+use crate::retention::describe_max_age;
+#[cfg(any(feature = "file", feature = "server"))]
+use crate::runtime::{DestinationSwitch, RuntimeDestinations};
+#[cfg(feature = "tokio-console")]
+use crate::tokio_console;
+#[cfg(feature = "encrypt")]
+use crate::EncryptKey;
+use crate::{
+    describe_debug_output, describe_global_fields, env_var, mask_credentials, write_settings,
+    BoxedLayer, Clock, ConsoleStyle, DestinationFailure, FieldValue, LogFormat, Profile,
+    TracingInit, TracingInitError, DEFAULT_BUFFERED_LINES, DEFAULT_LOG_FILE_BACKUPS,
+    DEFAULT_SHUTDOWN_TIMEOUT,
+};
+#[cfg(feature = "file")]
+use crate::{describe_file_rotation, log_file_name, Hindsight, Rotation};
+
+/// The effective configuration after applying environment variables, profile and default values
+///
+/// Returned by [`TracingInit::resolve`]; [`TracingInit::init`] builds the subscriber from the very same values
+#[derive(Debug, Clone)]
+pub struct ResolvedConfig {
+    app_name: String,
+    profile: Option<Profile>,
+    container: bool,
+
+    console: bool,
+    // No destination is configured, the console is used
+    fallback_console: bool,
+    console_format: LogFormat,
+    console_style: ConsoleStyle,
+    source_location: bool,
+    test_writer: bool,
+    strict_broken_pipe: bool,
+    // The dispatch the closing of the console is logged to, shared by its layers
+    console_dispatch: Arc<OnceLock<WeakDispatch>>,
+
+    log_file: bool,
+    log_file_path: PathBuf,
+    // The log file path is the platform's log directory, created when the layers are built
+    #[cfg(feature = "file")]
+    platform_log_dir: bool,
+    // The platform's log directory and the reason it could not be created, the current directory is used instead
+    #[cfg(feature = "file")]
+    log_dir_fallback: Option<(PathBuf, String)>,
+    log_file_prefix: String,
+    #[cfg(feature = "file")]
+    log_file_rotation: Rotation,
+    log_file_backups: usize,
+    #[cfg(feature = "file")]
+    log_file_max_age: Option<Duration>,
+    #[cfg(feature = "file")]
+    reopen_if_missing: bool,
+    #[cfg(feature = "file")]
+    log_file_format: LogFormat,
+    #[cfg(feature = "encrypt")]
+    encrypt_key: Option<EncryptKey>,
+    log_file_non_blocking: bool,
+    log_file_buffered_lines: usize,
+    #[cfg(feature = "file")]
+    hindsight: Option<(Level, usize, Duration)>,
+    #[cfg(feature = "file")]
+    hindsight_trigger: Level,
+
+    log_server: bool,
+    log_server_address: String,
+
+    // Set on any platform, there is a debug output on Windows only
+    debug_output: bool,
+
+    level: Level,
+    console_level: Option<Level>,
+    log_file_level: Option<Level>,
+    log_server_level: Option<Level>,
+    debug_output_level: Option<Level>,
+    span_level: Option<LevelFilter>,
+    event_level: Option<LevelFilter>,
+    explicit_filter: bool,
+    filter: String,
+    // The level filters of the destination layers, when the config file is watched or reloaded on SIGHUP: the
+    // levels of the destinations can change while running
+    destination_filters: Option<DestinationFilters>,
+
+    shutdown_timeout: Duration,
+    server_resolve_timeout: Option<Duration>,
+    clock: Option<Arc<dyn Clock>>,
+
+    global_fields: Vec<(String, FieldValue)>,
+    correlation_ids: bool,
+    #[cfg(feature = "task-ids")]
+    task_ids: bool,
+
+    #[cfg(feature = "tokio-console")]
+    tokio_console_address: Option<String>,
+}
+
+impl ResolvedConfig {
+    // Build from a TracingInit whose values were already set from the environment variables (call problems() to validate)
+    pub(crate) fn new(t: &TracingInit) -> ResolvedConfig {
+        #[cfg(feature = "file")]
+        let platform_log_dir = t
+            .log_file_path
+            .as_deref()
+            .is_none_or(str::is_empty)
+            .then(|| match t.default_log_dir.unwrap_or_default() {
+                DefaultLogDir::Platform => log_dir::platform_log_dir(&t.app_name),
+                DefaultLogDir::CurrentDir => None,
+            })
+            .flatten();
+        #[cfg(not(feature = "file"))]
+        let platform_log_dir: Option<PathBuf> = None;
+
+        // Not a destination on the other platforms
+        let debug_output = cfg!(windows) && t.enable_debug_output.unwrap_or(false);
+
+        let (explicit_filter, filter) = if let Some(ref filter) = t.filter {
+            (true, t.add_quiet_directives(filter))
+        } else {
+            let rust_log = env_var(EnvFilter::DEFAULT_ENV).unwrap_or_default();
+            (false, t.add_quiet_directives(&rust_log))
+        };
+
+        ResolvedConfig {
+            app_name: t.app_name.clone(),
+            profile: t.profile,
+            container: t.is_container(),
+
+            console: t.enable_console.unwrap_or(false),
+            fallback_console: t.allow_silent != Some(true)
+                && !t.enable_console.unwrap_or(false)
+                && !t.enable_log_file.unwrap_or(false)
+                && !t.enable_log_server.unwrap_or(false)
+                && !debug_output
+                && t.user_layers.lock().is_empty(),
+            console_format: t.console_format.unwrap_or_default(),
+            console_style: t.console_style.unwrap_or_default(),
+            source_location: t.source_location.unwrap_or(false),
+            test_writer: t.test_writer.unwrap_or(false),
+            strict_broken_pipe: t.strict_broken_pipe.unwrap_or(false),
+            console_dispatch: Arc::default(),
+
+            log_file: t.enable_log_file.unwrap_or(false),
+            #[cfg(feature = "file")]
+            platform_log_dir: platform_log_dir.is_some(),
+            #[cfg(feature = "file")]
+            log_dir_fallback: None,
+            log_file_path: match platform_log_dir {
+                Some(dir) => std::path::absolute(&dir).unwrap_or(dir),
+                None => expand_path(t.log_file_path.as_deref().unwrap_or_default()),
+            },
+            log_file_prefix: t
+                .log_file_prefix
+                .clone()
+                .unwrap_or_else(|| t.app_name.clone()),
+            #[cfg(feature = "file")]
+            log_file_rotation: t.log_file_rotation.unwrap_or_default(),
+            log_file_backups: t.log_file_backups.unwrap_or(DEFAULT_LOG_FILE_BACKUPS),
+            #[cfg(feature = "file")]
+            log_file_max_age: t.log_file_max_age,
+            #[cfg(feature = "file")]
+            reopen_if_missing: t.reopen_if_missing.unwrap_or(false),
+            #[cfg(feature = "file")]
+            log_file_format: t.log_file_format.unwrap_or_default(),
+            #[cfg(feature = "encrypt")]
+            encrypt_key: t.encrypt_key.clone(),
+            log_file_non_blocking: t.log_file_non_blocking.unwrap_or(false),
+            log_file_buffered_lines: t.log_file_buffered_lines.unwrap_or(DEFAULT_BUFFERED_LINES),
+            #[cfg(feature = "file")]
+            hindsight: t.hindsight,
+            #[cfg(feature = "file")]
+            hindsight_trigger: t.hindsight_trigger.unwrap_or(Level::ERROR),
+
+            log_server: t.enable_log_server.unwrap_or(false),
+            log_server_address: t.log_server_address.clone().unwrap_or_default(),
+
+            debug_output: t.enable_debug_output.unwrap_or(false),
+
+            level: t.level.unwrap_or(Level::INFO),
+            console_level: t.console_level,
+            log_file_level: t.log_file_level,
+            log_server_level: t.log_server_level,
+            debug_output_level: t.debug_output_level,
+            span_level: t.span_level,
+            event_level: t.event_level,
+            explicit_filter,
+            filter,
+            destination_filters: t
+                .reloads_destination_levels()
+                .then(DestinationFilters::default),
+
+            shutdown_timeout: t.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT),
+            server_resolve_timeout: t.server_resolve_timeout,
+            clock: t.clock.clone(),
+
+            global_fields: t.global_fields_with_k8s_metadata(),
+            correlation_ids: t.correlation_ids.unwrap_or(false),
+            #[cfg(feature = "task-ids")]
+            task_ids: t.task_ids.unwrap_or(false),
+
+            #[cfg(feature = "tokio-console")]
+            tokio_console_address: (t.tokio_console == Some(true)).then(|| {
+                t.tokio_console_addr
+                    .map(|address| address.to_string())
+                    .or_else(|| env_var("TOKIO_CONSOLE_BIND"))
+                    .unwrap_or_else(tokio_console::default_address)
+            }),
+        }
+    }
+
+    // Every check init would perform before creating the layers, without side effects
+    pub(crate) fn problems(&self) -> Vec<TracingInitError> {
+        let mut problems = Vec::new();
+
+        if let Err(e) = self.get_env_filter() {
+            problems.push(e);
+        }
+
+        if (self.console || self.fallback_console) && self.console_format == LogFormat::Binary {
+            problems.push(TracingInitError::UnsupportedFormat {
+                destination: "console",
+                format: self.console_format,
+            });
+        }
+
+        #[cfg(feature = "file")]
+        problems.extend(self.log_file_problems());
+
+        #[cfg(not(feature = "file"))]
+        if self.log_file {
+            problems.push(TracingInitError::DestinationDisabled {
+                destination: "log file",
+                feature: "file",
+            });
+        }
+
+        #[cfg(feature = "server")]
+        problems.extend(self.log_server_problems());
+
+        #[cfg(not(feature = "server"))]
+        if self.log_server {
+            problems.push(TracingInitError::DestinationDisabled {
+                destination: "log server",
+                feature: "server",
+            });
+        }
+
+        #[cfg(feature = "tokio-console")]
+        if let Some(ref address) = self.tokio_console_address {
+            problems.extend(tokio_console::check_tokio_unstable().err());
+            problems.extend(tokio_console::check_address(address).err());
+
+            // Read by the console layer even when the address is set by the builder
+            if let Some(bind) = env_var("TOKIO_CONSOLE_BIND").filter(|bind| bind != address) {
+                problems.extend(tokio_console::check_address(&bind).err());
+            }
+        }
+
+        problems
+    }
+
+    // The checks of the log file, when enabled
+    #[cfg(feature = "file")]
+    pub(crate) fn log_file_problems(&self) -> Vec<TracingInitError> {
+        let mut problems = Vec::new();
+
+        if self.log_file {
+            // The current directory is used when the platform's log directory cannot be created
+            if !self.platform_log_dir {
+                problems.extend(check_log_file_path(&self.log_file_path).err());
+            }
+
+            if !matches!(
+                self.log_file_format,
+                LogFormat::Full | LogFormat::Json | LogFormat::Ecs | LogFormat::Binary
+            ) {
+                problems.push(TracingInitError::UnsupportedFormat {
+                    destination: "log file",
+                    format: self.log_file_format,
+                });
+            }
+
+            #[cfg(feature = "encrypt")]
+            if let Some(ref key) = self.encrypt_key {
+                problems.extend(key.load().err());
+            }
+
+            if self.log_file_backups == 0 && self.log_file_rotation != Rotation::Never {
+                problems.push(TracingInitError::InvalidLogFileBackups {
+                    rotation: self.log_file_rotation.to_string(),
+                });
+            }
+        }
+
+        problems
+    }
+
+    // The checks of the log server, when enabled
+    #[cfg(feature = "server")]
+    pub(crate) fn log_server_problems(&self) -> Vec<TracingInitError> {
+        if self.log_server {
+            check_server_address(&self.log_server_address, self.server_resolve_timeout)
+                .err()
+                .into_iter()
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    // Disable the log file and the log server if they have problems, returned as their failures
+    pub(crate) fn disable_failing_destinations(&mut self) -> Vec<DestinationFailure> {
+        let failures = [
+            #[cfg(feature = "file")]
+            ("log file", self.log_file_problems()),
+            #[cfg(feature = "server")]
+            ("log server", self.log_server_problems()),
+        ]
+        .into_iter()
+        .filter_map(
+            |(destination, problems): (&'static str, Vec<TracingInitError>)| {
+                (!problems.is_empty()).then(|| DestinationFailure {
+                    destination,
+                    error: TracingInitError::from_problems(problems),
+                })
+            },
+        )
+        .collect::<Vec<_>>();
+
+        for failure in &failures {
+            self.disable_destination(failure.destination);
+        }
+
+        failures
+    }
+
+    // Disable the destination which failed to initialize
+    pub(crate) fn disable_destination(&mut self, destination: &str) {
+        match destination {
+            "log file" => self.log_file = false,
+            "log server" => self.log_server = false,
+            _ => {}
+        }
+    }
+
+    // Log to the console when every destination failed to initialize
+    pub(crate) fn fall_back_on_failures(&mut self, failures: &[DestinationFailure]) {
+        if !failures.is_empty()
+            && !self.console
+            && !self.log_file
+            && !self.log_server
+            && !self.log_to_debug_output()
+        {
+            self.fallback_console = true;
+        }
+    }
+
+    // The log file enabled at runtime with the options, or disabled. The settings shared with the console (source
+    // locations, global fields, correlation ids and clock) are kept
+    #[cfg(feature = "file")]
+    pub(crate) fn set_log_file(&mut self, options: Option<&FileOptions>) {
+        self.log_file = options.is_some();
+
+        if let Some(options) = options {
+            self.platform_log_dir = false;
+            self.log_dir_fallback = None;
+            self.log_file_path = options.path.clone();
+            self.log_file_prefix = options.prefix.clone();
+            self.log_file_rotation = options.rotation;
+            self.log_file_backups = options.backups;
+            self.log_file_max_age = options.max_age;
+            self.reopen_if_missing = options.reopen_if_missing;
+            self.log_file_format = options.format;
+            #[cfg(feature = "encrypt")]
+            {
+                self.encrypt_key = options.encrypt_key.clone();
+            }
+            self.log_file_non_blocking = options.non_blocking;
+            self.log_file_buffered_lines = options.buffered_lines;
+        }
+    }
+
+    // The log server enabled at runtime with the address, or disabled
+    #[cfg(feature = "server")]
+    pub(crate) fn set_log_server(&mut self, address: Option<&str>) {
+        self.log_server = address.is_some();
+
+        if let Some(address) = address {
+            self.log_server_address = address.to_string();
+        }
+    }
+
+    /// The application name
+    pub fn app_name(&self) -> &str {
+        &self.app_name
+    }
+
+    /// The active profile (if any)
+    pub fn profile(&self) -> Option<Profile> {
+        self.profile
+    }
+
+    /// Running in a container, as detected when [`auto_detect_environment`](TracingInit::auto_detect_environment) is set
+    pub fn container(&self) -> bool {
+        self.container
+    }
+
+    /// true if logging to the console
+    pub fn log_to_console(&self) -> bool {
+        self.console
+    }
+
+    /// true if logging to the console because no destination is configured (see [`TracingInit::allow_silent`])
+    pub fn fallback_console(&self) -> bool {
+        self.fallback_console
+    }
+
+    // The layers added by with_layer are a destination
+    pub(crate) fn without_fallback_console(&mut self) {
+        self.fallback_console = false;
+    }
+
+    /// The format of the console log records
+    pub fn console_format(&self) -> LogFormat {
+        self.console_format
+    }
+
+    /// The layout of the console log records in the full format
+    pub fn console_style(&self) -> ConsoleStyle {
+        self.console_style
+    }
+
+    /// true if log records include the source file and line number
+    pub fn source_location(&self) -> bool {
+        self.source_location
+    }
+
+    /// true if logging to a log file
+    pub fn log_to_file(&self) -> bool {
+        self.log_file
+    }
+
+    /// The (absolute) directory of the log file
+    pub fn log_file_path(&self) -> &Path {
+        &self.log_file_path
+    }
+
+    // Create the platform's log directory, the current directory is used if it cannot be created
+    #[cfg(feature = "file")]
+    pub(crate) fn create_platform_log_dir(&mut self) {
+        if !self.log_file || !self.platform_log_dir {
+            return;
+        }
+
+        if let Err(e) = std::fs::create_dir_all(&self.log_file_path) {
+            self.log_dir_fallback = Some((
+                std::mem::replace(&mut self.log_file_path, expand_path("")),
+                e.to_string(),
+            ));
+            self.platform_log_dir = false;
+        }
+    }
+
+    // The platform's log directory which could not be created and the reason
+    #[cfg(feature = "file")]
+    pub(crate) fn log_dir_fallback(&self) -> Option<&(PathBuf, String)> {
+        self.log_dir_fallback.as_ref()
+    }
+
+    /// The log file name prefix
+    pub fn log_file_prefix(&self) -> &str {
+        &self.log_file_prefix
+    }
+
+    /// The log file rotation
+    #[cfg(feature = "file")]
+    pub fn log_file_rotation(&self) -> Rotation {
+        self.log_file_rotation
+    }
+
+    // The path of the log file, with a placeholder for the date when rotated (e.g. /var/log/App.YYYY-MM-DD.log)
+    #[cfg(feature = "file")]
+    pub(crate) fn log_file_pattern(&self) -> String {
+        self.log_file_path
+            .join(log_file_name(&self.log_file_prefix, self.log_file_rotation))
+            .display()
+            .to_string()
+    }
+
+    /// The log file the records are written to now, named after the current period when rotated (None if not
+    /// logging to a log file)
+    #[cfg(feature = "file")]
+    pub fn current_log_file(&self) -> Option<PathBuf> {
+        self.log_file
+            .then(|| crate::reopen::current_file_path(&self.file_options()))
+    }
+
+    /// The last n lines of the current log file, fewer if it has less
+    ///
+    /// The lines are read backwards from the end of the file, in blocks, without blocking the writing of the records.
+    /// The record being written at that time and those queued for a non-blocking writer are not included. If the file
+    /// is rotated while it is read, the lines of the new file are returned instead; none if it was not created yet.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[cfg(feature = "file")] {
+    /// use tracing_init::TracingInit;
+    ///
+    /// let guard = TracingInit::builder("App").with_file(true).init().unwrap();
+    ///
+    /// for line in guard.config().tail(200).unwrap() {
+    ///     println!("{line}");
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    /// [`ErrorKind::Unsupported`](std::io::ErrorKind::Unsupported) if not logging to a log file or if its records
+    /// are binary or encrypted, otherwise the error reading the file
+    ///
+    #[cfg(feature = "file")]
+    pub fn tail(&self, n: usize) -> std::io::Result<Vec<String>> {
+        #[cfg(feature = "encrypt")]
+        let encrypted = self.encrypt_key.is_some();
+        #[cfg(not(feature = "encrypt"))]
+        let encrypted = false;
+
+        let unsupported = |message| {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                message,
+            ))
+        };
+
+        if !self.log_file {
+            return unsupported("not logging to a log file");
+        }
+
+        if self.log_file_format == LogFormat::Binary || encrypted {
+            return unsupported("the log file records are not lines of text");
+        }
+
+        let options = self.file_options();
+
+        crate::tail::tail(|| crate::reopen::current_file_path(&options), n)
+    }
+
+    /// The number of log file backups to keep
+    pub fn log_file_backups(&self) -> usize {
+        self.log_file_backups
+    }
+
+    /// The age after which the rotated log files are deleted (None if they are only pruned by count)
+    #[cfg(feature = "file")]
+    pub fn log_file_max_age(&self) -> Option<Duration> {
+        self.log_file_max_age
+    }
+
+    /// true if the log file is recreated when it was deleted or renamed
+    #[cfg(feature = "file")]
+    pub fn reopen_if_missing(&self) -> bool {
+        self.reopen_if_missing
+    }
+
+    /// The format of the log file records
+    #[cfg(feature = "file")]
+    pub fn log_file_format(&self) -> LogFormat {
+        self.log_file_format
+    }
+
+    /// The key encrypting the log files (None if they are not encrypted)
+    #[cfg(feature = "encrypt")]
+    pub fn encrypt_key(&self) -> Option<&EncryptKey> {
+        self.encrypt_key.as_ref()
+    }
+
+    /// true if log file records are written by a background thread
+    pub fn log_file_non_blocking(&self) -> bool {
+        self.log_file_non_blocking
+    }
+
+    /// The number of log file lines queued for the background thread before lines are dropped
+    pub fn log_file_buffered_lines(&self) -> usize {
+        self.log_file_buffered_lines
+    }
+
+    /// The events kept in memory and written to the log file when an error occurs (None if not set, or if the log
+    /// file records every event the filter lets through)
+    #[cfg(feature = "file")]
+    pub fn hindsight(&self) -> Option<Hindsight> {
+        let (level, capacity, window) = self.hindsight?;
+        let file_level = self.destination_filter(self.log_file_level)?.into_level()?;
+
+        (level > file_level).then_some(Hindsight {
+            file_level,
+            level,
+            capacity,
+            window,
+            trigger: self.hindsight_trigger,
+        })
+    }
+
+    // The most verbose level of the events kept for the log file
+    #[cfg(feature = "file")]
+    fn hindsight_level(&self) -> Option<Level> {
+        self.hindsight.map(|(level, _, _)| level)
+    }
+
+    #[cfg(not(feature = "file"))]
+    fn hindsight_level(&self) -> Option<Level> {
+        None
+    }
+
+    /// true if sending logs to a log server
+    pub fn log_to_server(&self) -> bool {
+        self.log_server
+    }
+
+    /// The address of the log server in the format <host>:<port>
+    pub fn log_server_address(&self) -> &str {
+        &self.log_server_address
+    }
+
+    /// true if writing the logs to the debug output (Windows only, false on the other platforms)
+    pub fn log_to_debug_output(&self) -> bool {
+        cfg!(windows) && self.debug_output
+    }
+
+    /// The default log level
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// The level of the console records (None if they have the default level)
+    pub fn console_level(&self) -> Option<Level> {
+        self.console_level
+    }
+
+    /// The level of the log file records (None if they have the default level)
+    pub fn log_file_level(&self) -> Option<Level> {
+        self.log_file_level
+    }
+
+    /// The level of the records sent to the log server (None if they have the default level)
+    pub fn log_server_level(&self) -> Option<Level> {
+        self.log_server_level
+    }
+
+    /// The level of the debug output records (None if they have the default level)
+    pub fn debug_output_level(&self) -> Option<Level> {
+        self.debug_output_level
+    }
+
+    // Whether a destination has its own level, there is no debug output on the other platforms than Windows
+    pub(crate) fn has_destination_levels(&self) -> bool {
+        self.console_level.is_some()
+            || self.log_file_level.is_some()
+            || self.log_server_level.is_some()
+            || (cfg!(windows) && self.debug_output_level.is_some())
+    }
+
+    /// The level of the spans (None if they have the default level)
+    pub fn span_level(&self) -> Option<LevelFilter> {
+        self.span_level
+    }
+
+    /// The level of the events (None if they have the default level)
+    pub fn event_level(&self) -> Option<LevelFilter> {
+        self.event_level
+    }
+
+    // The levels of the spans and of the events when either is set
+    pub(crate) fn span_event_levels(&self) -> Option<(LevelFilter, LevelFilter)> {
+        let level = LevelFilter::from_level(self.destinations_level());
+
+        (self.span_level.is_some() || self.event_level.is_some()).then(|| {
+            (
+                self.span_level.unwrap_or(level),
+                self.event_level.unwrap_or(level),
+            )
+        })
+    }
+
+    // The most verbose of the default level and the levels of the enabled destinations
+    fn destinations_level(&self) -> Level {
+        [
+            (self.console || self.fallback_console, self.console_level),
+            (self.log_file, self.log_file_level),
+            (self.log_file, self.hindsight_level()),
+            (self.log_server, self.log_server_level),
+            (self.log_to_debug_output(), self.debug_output_level),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, level)| level.filter(|_| enabled))
+        .fold(self.level, Level::max)
+    }
+
+    // The level of the filter's default directive, also letting through the spans and events of their own levels
+    fn filter_level(&self) -> Level {
+        [self.span_level, self.event_level]
+            .into_iter()
+            .filter_map(|level| level.and_then(LevelFilter::into_level))
+            .fold(self.destinations_level(), Level::max)
+    }
+
+    // The level filter of the layer of a destination ('c', 'f', 's' or 'd' as in LOG_DESTINATION)
+    pub(crate) fn destination_level_filter(&self, destination: char) -> Option<LevelFilter> {
+        self.destination_filter(match destination {
+            'c' => self.console_level,
+            'f' => self.log_file_level,
+            's' => self.log_server_level,
+            _ => self.debug_output_level,
+        })
+    }
+
+    // The level filters of the destination layers, None if they are not reloaded
+    pub(crate) fn destination_filters(&self) -> Option<DestinationFilters> {
+        self.destination_filters.clone()
+    }
+
+    // The level filter of a destination's layer, the destinations without their own level are limited to the default
+    // level when the filter lets through more verbose records for another destination
+    fn destination_filter(&self, level: Option<Level>) -> Option<LevelFilter> {
+        match level {
+            Some(level) => Some(LevelFilter::from_level(level)),
+            None if !self.explicit_filter && self.destinations_level() > self.level => {
+                Some(LevelFilter::from_level(self.level))
+            }
+            None => None,
+        }
+    }
+
+    /// The effective filter directives (including quiet dependencies directives)
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    /// The maximum time to wait for queued log server messages to be sent on shutdown
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.shutdown_timeout
+    }
+
+    /// The maximum time to wait for the log server address to be resolved when validating (None if it is resolved
+    /// by the connection task only)
+    pub fn server_resolve_timeout(&self) -> Option<Duration> {
+        self.server_resolve_timeout
+    }
+
+    /// The clock set by [`TracingInit::with_clock`] (None for the system clock)
+    pub fn clock(&self) -> Option<Arc<dyn Clock>> {
+        self.clock.clone()
+    }
+
+    /// The address the tokio-console server is bound to (None if not enabled)
+    #[cfg(feature = "tokio-console")]
+    pub fn tokio_console_address(&self) -> Option<&str> {
+        self.tokio_console_address.as_deref()
+    }
+
+    // Directives which are added to the filter, also when it is changed at runtime
+    pub(crate) fn required_directives(&self) -> &'static [&'static str] {
+        #[cfg(feature = "tokio-console")]
+        if self.tokio_console_address.is_some() {
+            return tokio_console::DIRECTIVES;
+        }
+
+        &[]
+    }
+
+    pub(crate) fn get_env_filter(&self) -> Result<EnvFilter, TracingInitError> {
+        let filter = add_directives(&self.filter, self.required_directives());
+
+        if self.explicit_filter {
+            Ok(EnvFilter::try_new(filter)?)
+        } else {
+            Ok(EnvFilter::builder()
+                .with_default_directive(self.filter_level().into())
+                .parse_lossy(filter))
+        }
+    }
+
+    /// The fields added to every record
+    pub fn global_fields(&self) -> &[(String, FieldValue)] {
+        &self.global_fields
+    }
+
+    /// Add the trace_id and span_id of the enclosing spans to every record
+    pub fn correlation_ids(&self) -> bool {
+        self.correlation_ids
+    }
+
+    /// Add the id of the tokio task logging the event to every record
+    #[cfg(feature = "task-ids")]
+    pub fn task_ids(&self) -> bool {
+        self.task_ids
+    }
+
+    /// The options of the console layer
+    pub fn console_options(&self) -> ConsoleOptions {
+        ConsoleOptions {
+            format: self.console_format,
+            style: self.console_style,
+            app_name: self.app_name.clone(),
+            source_location: self.source_location,
+            global_fields: self.global_fields.clone(),
+            correlation_ids: self.correlation_ids,
+            #[cfg(feature = "task-ids")]
+            task_ids: self.task_ids,
+            ansi: !self.container,
+            test_writer: self.test_writer,
+            strict_broken_pipe: self.strict_broken_pipe,
+            clock: self.clock.clone(),
+            dispatch: self.console_dispatch.clone(),
+        }
+    }
+
+    pub(crate) fn console_dispatch(&self) -> &OnceLock<WeakDispatch> {
+        &self.console_dispatch
+    }
+
+    /// The options of the log file layer
+    #[cfg(feature = "file")]
+    pub fn file_options(&self) -> FileOptions {
+        FileOptions {
+            path: self.log_file_path.clone(),
+            prefix: self.log_file_prefix.clone(),
+            rotation: self.log_file_rotation,
+            backups: self.log_file_backups,
+            max_age: self.log_file_max_age,
+            reopen_if_missing: self.reopen_if_missing,
+            format: self.log_file_format,
+            app_name: self.app_name.clone(),
+            #[cfg(feature = "encrypt")]
+            encrypt_key: self.encrypt_key.clone(),
+            non_blocking: self.log_file_non_blocking,
+            buffered_lines: self.log_file_buffered_lines,
+            source_location: self.source_location,
+            global_fields: self.global_fields.clone(),
+            correlation_ids: self.correlation_ids,
+            #[cfg(feature = "task-ids")]
+            task_ids: self.task_ids,
+            clock: self.clock.clone(),
+            hindsight: self.hindsight(),
+        }
+    }
+
+    /// The options of the log server layer
+    #[cfg(feature = "server")]
+    pub fn server_options(&self) -> ServerOptions {
+        ServerOptions {
+            address: self.log_server_address.clone(),
+            app_name: self.app_name.clone(),
+            shutdown_timeout: self.shutdown_timeout,
+            global_fields: self.global_fields.clone(),
+            correlation_ids: self.correlation_ids,
+            #[cfg(feature = "task-ids")]
+            task_ids: self.task_ids,
+        }
+    }
+
+    pub(crate) fn get_console_layer<S>(&self) -> BoxedLayer<S>
+    where
+        S: tracing::Subscriber,
+        for<'a> S: LookupSpan<'a>,
+    {
+        if self.console || self.fallback_console {
+            Some(self.with_destination_filter(layers::console(&self.console_options()), 'c'))
+        } else {
+            None
+        }
+    }
+
+    #[cfg(feature = "file")]
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn get_log_file_layer<S>(
+        &self,
+    ) -> Result<
+        (
+            BoxedLayer<S>,
+            Option<FileGuard>,
+            Option<Arc<OnceLock<WeakDispatch>>>,
+        ),
+        TracingInitError,
+    >
+    where
+        S: tracing::Subscriber,
+        for<'a> S: LookupSpan<'a>,
+    {
+        if self.log_file {
+            let (layer, guard, dispatch) = layers::file_layer(&self.file_options())?;
+
+            Ok((
+                Some(self.with_log_file_filter(layer)),
+                guard,
+                Some(dispatch),
+            ))
+        } else {
+            Ok((None, None, None))
+        }
+    }
+
+    // The log file layer which can be replaced at runtime, set to the configured log file if enabled
+    #[cfg(feature = "file")]
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn get_runtime_log_file_layer<S>(
+        &self,
+        runtime: &RuntimeDestinations<S>,
+    ) -> Result<
+        (
+            BoxedLayer<S>,
+            Option<FileGuard>,
+            Option<Arc<OnceLock<WeakDispatch>>>,
+        ),
+        TracingInitError,
+    >
+    where
+        S: tracing::Subscriber,
+        for<'a> S: LookupSpan<'a>,
+    {
+        let (guard, dispatch) =
+            runtime.set_file(self.log_file.then(|| self.file_options()).as_ref())?;
+
+        Ok((
+            Some(self.with_log_file_filter(runtime.file_layer())),
+            guard,
+            dispatch,
+        ))
+    }
+
+    // Box the log file layer, limited to its level. With hindsight, the events kept in memory are let through as well
+    #[cfg(feature = "file")]
+    fn with_log_file_filter<S, L>(&self, layer: L) -> Box<dyn Layer<S> + Send + Sync>
+    where
+        S: tracing::Subscriber,
+        for<'a> S: LookupSpan<'a>,
+        L: Layer<S> + Send + Sync + 'static,
+    {
+        let filter = self.destination_level_filter('f');
+
+        match (&self.destination_filters, self.hindsight()) {
+            (Some(filters), Some(hindsight)) => {
+                let kept = LevelFilter::from_level(hindsight.level);
+
+                layer
+                    .with_filter(
+                        filters.add('f', filter, move |filter| hindsight_filter(filter, kept)),
+                    )
+                    .boxed()
+            }
+            (None, Some(hindsight)) if filter.is_some() => layer
+                .with_filter(hindsight_filter(
+                    filter,
+                    LevelFilter::from_level(hindsight.level),
+                ))
+                .boxed(),
+            _ => self.with_destination_filter(layer, 'f'),
+        }
+    }
+
+    // Box the layer of a destination, limited to its level. The level is reloadable when the config file is watched
+    // or reloaded on SIGHUP
+    fn with_destination_filter<S, L>(
+        &self,
+        layer: L,
+        destination: char,
+    ) -> Box<dyn Layer<S> + Send + Sync>
+    where
+        S: tracing::Subscriber,
+        for<'a> S: LookupSpan<'a>,
+        L: Layer<S> + Send + Sync + 'static,
+    {
+        let filter = self.destination_level_filter(destination);
+
+        match &self.destination_filters {
+            // Without a level of its own the layer lets through all the records of the filter
+            Some(filters) => layer
+                .with_filter(filters.add(destination, filter, |filter| {
+                    filter.unwrap_or(LevelFilter::TRACE)
+                }))
+                .boxed(),
+            None => with_level_filter(layer, filter),
+        }
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn get_debug_output_layer<S>(&self) -> BoxedLayer<S>
+    where
+        S: tracing::Subscriber,
+        for<'a> S: LookupSpan<'a>,
+    {
+        self.debug_output.then(|| {
+            self.with_destination_filter(layers::debug_output(&self.console_options()), 'd')
+        })
+    }
+
+    #[cfg(feature = "tokio-console")]
+    pub(crate) fn get_tokio_console_layer<S>(&self) -> BoxedLayer<S>
+    where
+        S: tracing::Subscriber,
+        for<'a> S: LookupSpan<'a>,
+    {
+        self.tokio_console_address
+            .as_ref()
+            .map(|address| tokio_console::layer(address).boxed())
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn get_log_server_layer<S>(
+        &self,
+    ) -> Result<(BoxedLayer<S>, Option<ServerGuard>), TracingInitError>
+    where
+        S: tracing::Subscriber,
+        for<'a> S: LookupSpan<'a>,
+    {
+        if self.log_server {
+            let (layer, guard) = layers::gelf(&self.server_options())?;
+
+            Ok((Some(self.with_destination_filter(layer, 's')), Some(guard)))
+        } else {
+            Ok((None, None))
+        }
+    }
+
+    // The log server layer which can be replaced at runtime, set to the configured log server if enabled
+    #[cfg(feature = "server")]
+    pub(crate) fn get_runtime_log_server_layer<S>(
+        &self,
+        runtime: &RuntimeDestinations<S>,
+    ) -> Result<(BoxedLayer<S>, Option<ServerGuard>), TracingInitError>
+    where
+        S: tracing::Subscriber,
+        for<'a> S: LookupSpan<'a>,
+    {
+        let guard = runtime.set_server(self.log_server.then(|| self.server_options()).as_ref())?;
+
+        Ok((
+            Some(self.with_destination_filter(runtime.server_layer(), 's')),
+            guard,
+        ))
+    }
+}
+
+impl Display for ResolvedConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut settings = Vec::new();
+
+        if f.alternate() {
+            settings.push(("app", self.app_name.clone()));
+        }
+
+        settings.push(("console", self.describe_console()));
+        settings.push(("file", self.describe_log_file()));
+        settings.push(("server", self.describe_log_server()));
+
+        if self.debug_output {
+            settings.push((
+                "debug output",
+                describe_debug_output(self.debug_output_level),
+            ));
+        }
+        settings.push(("level", self.level.to_string()));
+
+        if let Some(level) = self.span_level {
+            settings.push(("span level", level.to_string()));
+        }
+
+        if let Some(level) = self.event_level {
+            settings.push(("event level", level.to_string()));
+        }
+
+        if f.alternate() {
+            settings.push((
+                "filter",
+                Some(self.filter.clone())
+                    .filter(|filter| !filter.is_empty())
+                    .unwrap_or_else(|| String::from("none")),
+            ));
+            settings.push((
+                "directives",
+                self.get_env_filter()
+                    .map_or_else(|e| format!("invalid ({e})"), |filter| filter.to_string()),
+            ));
+        } else if !self.filter.is_empty() {
+            settings.push(("filter", self.filter.clone()));
+        }
+
+        if let Some(profile) = self.profile {
+            settings.push(("profile", profile.to_string()));
+        }
+
+        if self.container {
+            settings.push(("environment", String::from("container (auto-detected)")));
+        }
+
+        #[cfg(feature = "tokio-console")]
+        if let Some(ref address) = self.tokio_console_address {
+            settings.push(("tokio-console", address.clone()));
+        }
+
+        if f.alternate() {
+            settings.push(("global fields", describe_global_fields(&self.global_fields)));
+            settings.push(("correlation ids", self.correlation_ids.to_string()));
+
+            #[cfg(feature = "task-ids")]
+            if self.task_ids {
+                settings.push(("task ids", String::from("true")));
+            }
+        }
+
+        write_settings(f, &settings)
+    }
+}
+
+impl ResolvedConfig {
+    fn describe_console(&self) -> String {
+        if !self.console && !self.fallback_console {
+            return String::from("disabled");
+        }
+
+        let mut details = vec![self.console_format.to_string()];
+
+        if self.console_format == LogFormat::Full && self.console_style != ConsoleStyle::Default {
+            details.push(self.console_style.to_string());
+        }
+
+        if let Some(level) = self.console_level {
+            details.push(format!("level {level}"));
+        }
+
+        if self.source_location {
+            details.push(String::from("source locations"));
+        }
+
+        if self.container && !self.console_format.is_json() {
+            details.push(String::from("no colors"));
+        }
+
+        if self.test_writer {
+            details.push(String::from("captured by the test harness"));
+        }
+
+        if self.fallback_console {
+            return format!(
+                "stdout ({}), fallback console (no destination configured)",
+                details.join(", ")
+            );
+        }
+
+        format!("stdout ({})", details.join(", "))
+    }
+
+    #[cfg(feature = "file")]
+    fn describe_log_file(&self) -> String {
+        if !self.log_file {
+            return String::from("disabled");
+        }
+
+        let mut details = vec![describe_file_rotation(
+            self.log_file_rotation,
+            self.log_file_backups,
+        )];
+
+        if let Some(level) = self.log_file_level {
+            details.push(format!("level {level}"));
+        }
+
+        if let Some(max_age) = self.log_file_max_age {
+            details.push(format!("max age {}", describe_max_age(max_age)));
+        }
+
+        if self.reopen_if_missing {
+            details.push(String::from("reopened if missing"));
+        }
+
+        if self.log_file_format != LogFormat::Full {
+            details.push(format!("{} records", self.log_file_format));
+        }
+
+        #[cfg(feature = "encrypt")]
+        if let Some(ref key) = self.encrypt_key {
+            details.push(format!("encrypted, {key}"));
+        }
+
+        if let Some((ref dir, _)) = self.log_dir_fallback {
+            details.push(format!("fallback, {} cannot be created", dir.display()));
+        }
+
+        if self.log_file_non_blocking {
+            details.push(format!(
+                "non-blocking, {} buffered lines",
+                self.log_file_buffered_lines
+            ));
+        }
+
+        if let Some(hindsight) = self.hindsight() {
+            details.push(format!(
+                "hindsight {}, {} events",
+                hindsight.level, hindsight.capacity
+            ));
+        }
+
+        format!("{} ({})", self.log_file_pattern(), details.join(", "))
+    }
+
+    #[cfg(not(feature = "file"))]
+    fn describe_log_file(&self) -> String {
+        String::from("disabled")
+    }
+
+    fn describe_log_server(&self) -> String {
+        if !self.log_server {
+            return String::from("disabled");
+        }
+
+        let server = format!(
+            "GELF over UDP to {}",
+            mask_credentials(&self.log_server_address)
+        );
+
+        match self.log_server_level {
+            Some(level) => format!("{server} (level {level})"),
+            None => server,
+        }
+    }
+}
+
+// Box the layer of a destination, limited to its level if any
+// The filter of the log file with hindsight: the events kept in memory are let through as well, the spans are
+// limited to the level of the log file as without hindsight
+#[cfg(feature = "file")]
+fn hindsight_filter(
+    filter: Option<LevelFilter>,
+    kept: LevelFilter,
+) -> FilterFn<impl Fn(&tracing::Metadata<'_>) -> bool + Send + Sync + 'static> {
+    filter_fn(move |metadata| match filter {
+        Some(filter) => *metadata.level() <= if metadata.is_event() { kept } else { filter },
+        None => true,
+    })
+    .with_max_level_hint(filter.map_or(LevelFilter::TRACE, |_| kept))
+}
+
+fn with_level_filter<S, L>(layer: L, filter: Option<LevelFilter>) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber,
+    for<'a> S: LookupSpan<'a>,
+    L: Layer<S> + Send + Sync + 'static,
+{
+    match filter {
+        Some(filter) => layer.with_filter(filter).boxed(),
+        None => layer.boxed(),
+    }
+}
+
+// Expand a leading ~ to the home directory and make the path absolute (an empty path is the current directory)
+fn expand_path(path: &str) -> PathBuf {
+    let path = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') || rest.starts_with('\\') => {
+            match env_var("HOME").or_else(|| env_var("USERPROFILE")) {
+                Some(home) => PathBuf::from(home).join(rest.trim_start_matches(['/', '\\'])),
+                None => PathBuf::from(path),
+            }
+        }
+        _ if path.is_empty() => PathBuf::from("."),
+        _ => PathBuf::from(path),
+    };
+
+    std::path::absolute(&path).unwrap_or(path)
+}
+
+// Check that the log directory exists or can be created (without creating it)
+#[cfg(feature = "file")]
+fn check_log_file_path(path: &Path) -> Result<(), TracingInitError> {
+    let invalid = |reason: &str| TracingInitError::InvalidLogFilePath {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    };
+
+    match path.ancestors().find(|ancestor| ancestor.exists()) {
+        Some(existing) if existing.is_dir() => Ok(()),
+        Some(existing) if existing == path => Err(invalid("not a directory")),
+        Some(_) => Err(invalid("a parent of the path is not a directory")),
+        None => Err(invalid("no parent directory exists")),
+    }
+}
+
+// Check that the address is in the format <host>:<port>, and that the host can be resolved within the timeout if set
+#[cfg(feature = "server")]
+fn check_server_address(
+    address: &str,
+    resolve_timeout: Option<Duration>,
+) -> Result<(), TracingInitError> {
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+            match resolve_timeout.map(|timeout| resolve_server_address(address, timeout)) {
+                Some(Err(source)) => Err(TracingInitError::UnresolvedServerAddress {
+                    address: address.to_string(),
+                    source,
+                }),
+                _ => Ok(()),
+            }
+        }
+        _ => Err(TracingInitError::InvalidServerAddress {
+            address: address.to_string(),
+        }),
+    }
+}
+
+// Resolve the address, retrying until the timeout since the resolver may not be ready yet (e.g. at boot). The lookups
+// are done by a thread, a lookup which does not complete is not waited for beyond the timeout
+#[cfg(feature = "server")]
+fn resolve_server_address(address: &str, timeout: Duration) -> std::io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    let (sender, attempts) = std::sync::mpsc::channel();
+    let lookup_address = address.to_string();
+
+    std::thread::Builder::new()
+        .name(String::from("tracing-init-resolve"))
+        .spawn(move || {
+            let mut delay = Duration::from_millis(100);
+
+            // Stops once resolved, or when the result is not waited for anymore
+            while let Ok(()) = sender.send(lookup_address.to_socket_addrs().map(|_| ())) {
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(Duration::from_secs(2));
+            }
+        })?;
+
+    let mut last_error = None;
+
+    loop {
+        match attempts.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(error)) => last_error = Some(error),
+            Err(_) => {
+                return Err(last_error.unwrap_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("not resolved within {timeout:?}"),
+                    )
+                }))
+            }
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::dispatcher::WeakDispatch;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::clock::ClockSource;
+use crate::layers::FileOptions;
+use crate::Rotation;
+
+// The period of the first check, before any file was written
+const NO_PERIOD: u64 = u64::MAX;
+
+// Deletes the log files older than the maximum age, when the file is rotated
+pub(crate) struct Retention {
+    directory: PathBuf,
+    prefix: String,
+    rotation: Rotation,
+    max_age: Duration,
+    clock: ClockSource,
+    // The rotation period of the last write, the files are pruned when it changes
+    period: AtomicU64,
+    // The dispatch the deletion failures are logged to, the global default one if not set
+    dispatch: Arc<OnceLock<WeakDispatch>>,
+}
+
+impl Retention {
+    pub(crate) fn new(
+        options: &FileOptions,
+        max_age: Duration,
+        dispatch: Arc<OnceLock<WeakDispatch>>,
+    ) -> Retention {
+        Retention {
+            directory: options.path.clone(),
+            prefix: options.prefix.clone(),
+            rotation: options.rotation,
+            max_age,
+            clock: ClockSource::new(options.clock.clone()),
+            period: AtomicU64::new(NO_PERIOD),
+            dispatch,
+        }
+    }
+
+    // Prune the files by a background thread on the first write of each rotation period: the write is made while the
+    // event is dispatched, the events logged by the thread reach the subscriber
+    fn check(self: &Arc<Self>) {
+        let now = self.clock.now();
+        let period = match period_length(self.rotation) {
+            Some(length) => seconds_since_epoch(now) / length.as_secs(),
+            None => 0,
+        };
+
+        if self.period.swap(period, Ordering::AcqRel) == period {
+            return;
+        }
+
+        let retention = self.clone();
+        let spawned = std::thread::Builder::new()
+            .name(String::from("tracing-init-retention"))
+            .spawn(move || retention.prune_and_report(now));
+
+        // Pruned on the next write instead
+        if spawned.is_err() {
+            self.period.store(NO_PERIOD, Ordering::Release);
+        }
+    }
+
+    fn prune_and_report(&self, now: SystemTime) {
+        let report = || {
+            for (path, error) in self.prune(now) {
+                match path {
+                    Some(path) => tracing::warn!(
+                        target: "tracing_init",
+                        file = %path.display(),
+                        %error,
+                        "cannot delete the expired log file"
+                    ),
+                    None => tracing::warn!(
+                        target: "tracing_init",
+                        directory = %self.directory.display(),
+                        %error,
+                        "cannot list the log files to delete the expired ones"
+                    ),
+                }
+            }
+        };
+
+        match self.dispatch.get().map(WeakDispatch::upgrade) {
+            Some(Some(dispatch)) => tracing::dispatcher::with_default(&dispatch, report),
+            // The subscriber was dropped
+            Some(None) => {}
+            None => report(),
+        }
+    }
+
+    // Delete the expired files of this prefix, the errors are returned with the path of the file (None if the
+    // directory cannot be read)
+    fn prune(&self, now: SystemTime) -> Vec<(Option<PathBuf>, std::io::Error)> {
+        let cutoff = now.checked_sub(self.max_age).unwrap_or(UNIX_EPOCH);
+        let entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) => return vec![(None, e)],
+        };
+
+        entries
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                self.expires_at(&entry.path())
+                    .is_some_and(|expires_at| expires_at <= cutoff)
+            })
+            .filter_map(|entry| {
+                std::fs::remove_file(entry.path())
+                    .err()
+                    .map(|e| (Some(entry.path()), e))
+            })
+            .collect()
+    }
+
+    // When the newest record of a log file of this prefix was written: the end of the period in its name, or its
+    // modification time if the name has no date. None for the other files and for the file which is never rotated
+    fn expires_at(&self, path: &Path) -> Option<SystemTime> {
+        let name = path.file_name()?.to_str()?;
+        let rest = name.strip_prefix(&self.prefix)?;
+
+        if rest == ".log" {
+            return match self.rotation {
+                Rotation::Never => None,
+                _ => std::fs::metadata(path).ok()?.modified().ok(),
+            };
+        }
+
+        let (start, length) = parse_file_date(rest.strip_prefix('.')?.strip_suffix(".log")?)?;
+
+        Some(start + length)
+    }
+}
+
+// The log file writer, triggering the deletion of the expired files when the file is rotated
+pub(crate) struct RetainedWriter<W> {
+    writer: W,
+    retention: Arc<Retention>,
+}
+
+impl<W> RetainedWriter<W> {
+    pub(crate) fn new(writer: W, retention: Arc<Retention>) -> RetainedWriter<W> {
+        RetainedWriter { writer, retention }
+    }
+}
+
+impl<'a, W: MakeWriter<'a>> MakeWriter<'a> for RetainedWriter<W> {
+    type Writer = W::Writer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.retention.check();
+        self.writer.make_writer()
+    }
+}
+
+// The non-blocking writer's worker thread writes the records
+impl<W: Write> Write for RetainedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.retention.check();
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Parse a maximum age such as 14d, 12h, 30m or 90s
+pub(crate) fn parse_max_age(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit = match value.chars().last()? {
+        'd' => 86400,
+        'h' => 3600,
+        'm' => 60,
+        's' => 1,
+        _ => return None,
+    };
+
+    let count = value[..value.len() - 1].parse::<u64>().ok()?;
+
+    count.checked_mul(unit).map(Duration::from_secs)
+}
+
+// The maximum age in the unit it was most likely given in
+pub(crate) fn describe_max_age(max_age: Duration) -> String {
+    let seconds = max_age.as_secs();
+
+    match seconds {
+        0 => String::from("0s"),
+        _ if seconds.is_multiple_of(86400) => format!("{}d", seconds / 86400),
+        _ if seconds.is_multiple_of(3600) => format!("{}h", seconds / 3600),
+        _ if seconds.is_multiple_of(60) => format!("{}m", seconds / 60),
+        _ => format!("{seconds}s"),
+    }
+}
+
+fn period_length(rotation: Rotation) -> Option<Duration> {
+    match rotation {
+        Rotation::Minutely => Some(Duration::from_secs(60)),
+        Rotation::Hourly => Some(Duration::from_secs(3600)),
+        Rotation::Daily => Some(Duration::from_secs(86400)),
+        Rotation::Never => None,
+    }
+}
+
+fn seconds_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// The date in the name of a log file (YYYY-MM-DD, YYYY-MM-DD-HH or YYYY-MM-DD-HH-mm, in UTC as written by
+// tracing-appender), whatever the current rotation, as the start and length of its period
+fn parse_file_date(date: &str) -> Option<(SystemTime, Duration)> {
+    // The year has 4 digits, the other parts 2
+    let parts = date
+        .split('-')
+        .enumerate()
+        .map(|(index, part)| {
+            let digits = if index == 0 { 4 } else { 2 };
+
+            (part.len() == digits && part.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| part.parse::<u32>().ok())
+                .flatten()
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let (year, month, day, seconds_of_day, length) = match parts[..] {
+        [year, month, day] => (year, month, day, 0, 86400),
+        [year, month, day, hour] if hour < 24 => (year, month, day, hour * 3600, 3600),
+        [year, month, day, hour, minute] if hour < 24 && minute < 60 => {
+            (year, month, day, hour * 3600 + minute * 60, 60)
+        }
+        _ => return None,
+    };
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let days = days_from_civil(i64::from(year), month, day);
+    let seconds = u64::try_from(days * 86400).ok()? + u64::from(seconds_of_day);
+
+    Some((
+        UNIX_EPOCH + Duration::from_secs(seconds),
+        Duration::from_secs(length),
+    ))
+}
+
+// The day number of a civil date (see http://howardhinnant.github.io/date_algorithms.html)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+// The date in the name of the daily log file of this time
+#[cfg(test)]
+fn daily_file_date(time: SystemTime) -> String {
+    crate::preinit::format_time(time)[..10].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_log_dir, with_env, CaptureLayer};
+    use crate::{ManualClock, TracingInit};
+    use tracing::Level;
+
+    const DAY: Duration = Duration::from_secs(86400);
+
+    #[test]
+    fn test_parse_file_date() {
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+
+        assert_eq!(
+            parse_file_date("2026-10-16"),
+            Some((at(1_792_108_800), DAY))
+        );
+        assert_eq!(
+            parse_file_date("2026-10-16-11"),
+            Some((at(1_792_148_400), Duration::from_secs(3600)))
+        );
+        assert_eq!(
+            parse_file_date("2026-10-16-11-59"),
+            Some((at(1_792_151_940), Duration::from_secs(60)))
+        );
+        assert_eq!(parse_file_date("2000-02-29"), Some((at(951_782_400), DAY)));
+
+        for invalid in [
+            "2026-13-01",
+            "2026-10",
+            "worker",
+            "2026-10-16-24",
+            "2026-1-6",
+        ] {
+            assert_eq!(parse_file_date(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_parse_max_age() {
+        assert_eq!(parse_max_age("14d"), Some(14 * DAY));
+        assert_eq!(parse_max_age("12h"), Some(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_max_age("30m"), Some(Duration::from_secs(1800)));
+        assert_eq!(parse_max_age("90s"), Some(Duration::from_secs(90)));
+
+        for invalid in ["14", "d", "14w", "-1d", "1.5d"] {
+            assert_eq!(parse_max_age(invalid), None, "{invalid}");
+        }
+
+        assert_eq!(describe_max_age(14 * DAY), "14d");
+        assert_eq!(describe_max_age(Duration::from_secs(90)), "90s");
+    }
+
+    // Wait for the background thread deleting the files
+    fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..500 {
+            if condition() {
+                return;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("timed out");
+    }
+
+    #[test]
+    fn test_log_file_max_age() {
+        let log_dir = test_log_dir("max-age");
+        let now = SystemTime::now();
+        std::fs::create_dir_all(&log_dir).unwrap();
+        let clock = ManualClock::new(now);
+        let file = |name: String| {
+            std::fs::write(log_dir.join(&name), "").unwrap();
+            log_dir.join(name)
+        };
+
+        let stale = file(format!("App.{}.log", daily_file_date(now - 20 * DAY)));
+        let stale_hourly = file(format!("App.{}-07.log", daily_file_date(now - 20 * DAY)));
+        let recent = file(format!("App.{}.log", daily_file_date(now - 3 * DAY)));
+        // Not files of this instance
+        let others = [
+            file(format!(
+                "App-worker.{}.log",
+                daily_file_date(now - 20 * DAY)
+            )),
+            file(format!("Other.{}.log", daily_file_date(now - 20 * DAY))),
+            file(format!("App.{}.txt", daily_file_date(now - 20 * DAY))),
+            file(String::from("App.notes.log")),
+        ];
+
+        let (dispatch, guard) = with_env(&[("LOG_FILE_MAX_AGE", "14d")], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_backups(10)
+                .with_clock(clock.clone())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        assert!(
+            guard.to_string().contains("10 backups, max age 14d"),
+            "{guard}"
+        );
+
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("first"));
+        wait_until(|| !stale.exists() && !stale_hourly.exists());
+        assert!(recent.exists());
+
+        // The next day's first record prunes again: the recent file is now older than 14 days
+        clock.advance(12 * DAY);
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("second"));
+        wait_until(|| !recent.exists());
+
+        for other in &others {
+            assert!(other.exists(), "{}", other.display());
+        }
+
+        // The current file is kept
+        assert!(log_dir
+            .join(format!("App.{}.log", daily_file_date(SystemTime::now())))
+            .exists());
+    }
+
+    #[test]
+    fn test_log_file_max_age_failure() {
+        let log_dir = test_log_dir("max-age-failure");
+        let stale = log_dir.join(format!(
+            "App.{}.log",
+            daily_file_date(SystemTime::now() - 20 * DAY)
+        ));
+        let capture = CaptureLayer::default();
+
+        // A directory is not deleted as a file
+        std::fs::create_dir_all(stale.join("nested")).unwrap();
+
+        let (dispatch, _guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_max_age(14 * DAY)
+                .with_layer(capture.clone())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("first"));
+        wait_until(|| {
+            capture.events().iter().any(|(level, target, message)| {
+                *level == Level::WARN
+                    && target == "tracing_init"
+                    && message == "cannot delete the expired log file"
+            })
+        });
+        assert!(stale.exists());
+    }
+}
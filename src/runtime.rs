@@ -0,0 +1,311 @@
+use std::any::TypeId;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+
+#[cfg(feature = "file")]
+use tracing::dispatcher::WeakDispatch;
+use tracing::{span, Dispatch, Event, Subscriber};
+use tracing_subscriber::fmt::format::{DefaultFields, JsonFields};
+use tracing_subscriber::fmt::{FormatFields, FormattedFields};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+use tracing_subscriber::Layer;
+
+#[cfg(feature = "file")]
+use crate::flush::{DropReportLayer, FileGuard};
+#[cfg(feature = "file")]
+use crate::layers::FileOptions;
+#[cfg(feature = "server")]
+use crate::layers::{ServerGuard, ServerOptions};
+use crate::TracingInitError;
+
+type SharedLayer<S> = Arc<dyn Layer<S> + Send + Sync + 'static>;
+
+// The layer of a destination, replaced while the subscriber is in use
+struct Slot<S> {
+    layer: RwLock<Option<SharedLayer<S>>>,
+    // The dispatch the subscriber was registered with, the layers set later are registered with it as well
+    dispatch: OnceLock<tracing::dispatcher::WeakDispatch>,
+}
+
+impl<S: Subscriber> Slot<S> {
+    fn new() -> Arc<Slot<S>> {
+        Arc::new(Slot {
+            layer: RwLock::new(None),
+            dispatch: OnceLock::new(),
+        })
+    }
+
+    // The current layer, the lock is released before the layer is called so a swap never waits for a record to be
+    // written
+    fn current(&self) -> Option<SharedLayer<S>> {
+        self.layer
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    // Replace the layer, the previous one is dropped once the records being written by it are done
+    fn set(&self, layer: Option<SharedLayer<S>>) {
+        if let (Some(layer), Some(dispatch)) = (&layer, self.dispatch.get()) {
+            if let Some(dispatch) = dispatch.upgrade() {
+                layer.on_register_dispatch(&dispatch);
+            }
+        }
+
+        *self.layer.write().unwrap_or_else(PoisonError::into_inner) = layer;
+    }
+}
+
+// The fields of a span formatted as the log file layers format them. The fmt layers expect to find their formatted
+// fields in the extensions of the spans in scope, they are copied from here for the spans created before the layer
+// was set
+struct SpanFields {
+    full: FormattedFields<DefaultFields>,
+    json: FormattedFields<JsonFields>,
+}
+
+impl SpanFields {
+    fn new(attrs: &span::Attributes<'_>) -> SpanFields {
+        let mut full = FormattedFields::new(String::new());
+        let mut json = FormattedFields::new(String::new());
+
+        let _ = DefaultFields::default().format_fields(full.as_writer(), attrs);
+        let _ = JsonFields::default().format_fields(json.as_writer(), attrs);
+
+        SpanFields { full, json }
+    }
+
+    fn record(&mut self, values: &span::Record<'_>) {
+        let _ = DefaultFields::default().add_fields(&mut self.full, values);
+        let _ = JsonFields::default().add_fields(&mut self.json, values);
+    }
+}
+
+// Forwards to the current layer of a slot, nothing is done while it is empty. The level filter and the sampling of
+// the destination wrap it, the layers set in the slot are not filtered
+pub(crate) struct DynamicLayer<S> {
+    slot: Arc<Slot<S>>,
+    // Keep the formatted fields of the spans for the fmt layers (of the log file)
+    span_fields: bool,
+}
+
+impl<S> DynamicLayer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    // Copy the formatted fields of the span to the extensions read by the fmt layers, unless already there
+    fn provide_fields(span: &SpanRef<'_, S>) {
+        let mut extensions = span.extensions_mut();
+        let Some(fields) = extensions.get_mut::<SpanFields>() else {
+            return;
+        };
+        let full = FormattedFields::<DefaultFields>::new(fields.full.fields.clone());
+        let json = FormattedFields::<JsonFields>::new(fields.json.fields.clone());
+
+        if extensions
+            .get_mut::<FormattedFields<DefaultFields>>()
+            .is_none()
+        {
+            extensions.insert(full);
+        }
+
+        if extensions
+            .get_mut::<FormattedFields<JsonFields>>()
+            .is_none()
+        {
+            extensions.insert(json);
+        }
+    }
+}
+
+impl<S> Layer<S> for DynamicLayer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        let _ = self.slot.dispatch.set(subscriber.downgrade());
+
+        if let Some(layer) = self.slot.current() {
+            layer.on_register_dispatch(subscriber);
+        }
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if let (true, Some(span)) = (self.span_fields, ctx.span(id)) {
+            span.extensions_mut().insert(SpanFields::new(attrs));
+        }
+
+        if let Some(layer) = self.slot.current() {
+            layer.on_new_span(attrs, id, ctx);
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let layer = self.slot.current();
+
+        if let (true, Some(span)) = (self.span_fields, ctx.span(id)) {
+            // The fmt layer adds the values to its formatted fields, they must not be there yet
+            if layer.is_some() {
+                Self::provide_fields(&span);
+            }
+
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+                fields.record(values);
+            }
+        }
+
+        if let Some(layer) = layer {
+            layer.on_record(id, values, ctx);
+        }
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        if let Some(layer) = self.slot.current() {
+            layer.on_follows_from(id, follows, ctx);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if let Some(layer) = self.slot.current() {
+            if let (true, Some(scope)) = (self.span_fields, ctx.event_scope(event)) {
+                for span in scope {
+                    Self::provide_fields(&span);
+                }
+            }
+
+            layer.on_event(event, ctx);
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(layer) = self.slot.current() {
+            layer.on_enter(id, ctx);
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(layer) = self.slot.current() {
+            layer.on_exit(id, ctx);
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        if let Some(layer) = self.slot.current() {
+            layer.on_close(id, ctx);
+        }
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        if let Some(layer) = self.slot.current() {
+            layer.on_id_change(old, new, ctx);
+        }
+    }
+
+    // The layers in the slot have no per-layer filters, only the wrapper itself is found by downcasting
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const _ as *const ())
+        } else {
+            None
+        }
+    }
+}
+
+// Sets the log file and log server layers of the subscriber, used by the guard to enable or disable them at runtime
+pub(crate) trait DestinationSwitch: Send + Sync {
+    // Replace the log file layer by one writing with the options, or remove it. Returns what keeps the new one alive
+    #[cfg(feature = "file")]
+    #[allow(clippy::type_complexity)]
+    fn set_file(
+        &self,
+        options: Option<&FileOptions>,
+    ) -> Result<(Option<FileGuard>, Option<Arc<OnceLock<WeakDispatch>>>), TracingInitError>;
+
+    // Replace the log server layer by one sending with the options, or remove it
+    #[cfg(feature = "server")]
+    fn set_server(
+        &self,
+        options: Option<&ServerOptions>,
+    ) -> Result<Option<ServerGuard>, TracingInitError>;
+}
+
+// The slots of the destinations which can be enabled and disabled at runtime
+pub(crate) struct RuntimeDestinations<S> {
+    #[cfg(feature = "file")]
+    file: Arc<Slot<S>>,
+    #[cfg(feature = "server")]
+    server: Arc<Slot<S>>,
+}
+
+impl<S> RuntimeDestinations<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    pub(crate) fn new() -> RuntimeDestinations<S> {
+        RuntimeDestinations {
+            #[cfg(feature = "file")]
+            file: Slot::new(),
+            #[cfg(feature = "server")]
+            server: Slot::new(),
+        }
+    }
+
+    #[cfg(feature = "file")]
+    pub(crate) fn file_layer(&self) -> DynamicLayer<S> {
+        DynamicLayer {
+            slot: self.file.clone(),
+            span_fields: true,
+        }
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn server_layer(&self) -> DynamicLayer<S> {
+        DynamicLayer {
+            slot: self.server.clone(),
+            span_fields: false,
+        }
+    }
+}
+
+impl<S> DestinationSwitch for RuntimeDestinations<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    #[cfg(feature = "file")]
+    fn set_file(
+        &self,
+        options: Option<&FileOptions>,
+    ) -> Result<(Option<FileGuard>, Option<Arc<OnceLock<WeakDispatch>>>), TracingInitError> {
+        let Some(options) = options else {
+            self.file.set(None);
+            return Ok((None, None));
+        };
+
+        let (layer, guard, dispatch) = crate::layers::file_layer(options)?;
+
+        // The lines dropped by this file are reported while it is written
+        let layer: SharedLayer<S> = match guard {
+            Some(ref guard) => Arc::new(layer.and_then(DropReportLayer::new(guard.drops.clone()))),
+            None => Arc::new(layer),
+        };
+
+        self.file.set(Some(layer));
+        Ok((guard, Some(dispatch)))
+    }
+
+    #[cfg(feature = "server")]
+    fn set_server(
+        &self,
+        options: Option<&ServerOptions>,
+    ) -> Result<Option<ServerGuard>, TracingInitError> {
+        let Some(options) = options else {
+            self.server.set(None);
+            return Ok(None);
+        };
+
+        let (layer, guard) = crate::layers::gelf(options)?;
+
+        self.server.set(Some(Arc::new(layer)));
+        Ok(Some(guard))
+    }
+}
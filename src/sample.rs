@@ -0,0 +1,376 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Filter};
+use tracing_subscriber::Layer;
+
+use crate::{level_index, BoxedLayer, TracingInitError};
+
+thread_local! {
+    // xorshift64* state, never 0
+    static RANDOM: Cell<u64> = Cell::new(RandomState::new().hash_one(std::thread::current().id()) | 1);
+
+    // The roll of the event being dispatched, shared by the destinations when sampling consistently
+    static EVENT_ROLL: Cell<u64> = const { Cell::new(0) };
+}
+
+fn random() -> u64 {
+    RANDOM.with(|random| {
+        let mut x = random.get();
+
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        random.set(x);
+
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Destination {
+    Console,
+    File,
+    Server,
+}
+
+// The sampling ratio of each level, None when not set
+#[derive(Debug, Clone, Copy, Default)]
+struct Ratios([Option<f64>; 5]);
+
+const LEVELS: [Level; 5] = [
+    Level::TRACE,
+    Level::DEBUG,
+    Level::INFO,
+    Level::WARN,
+    Level::ERROR,
+];
+
+// The ratios set by sample and by the per destination variants
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Sampling {
+    all: Ratios,
+    console: Ratios,
+    file: Ratios,
+    server: Ratios,
+    // Default: false
+    consistent: Option<bool>,
+}
+
+impl Sampling {
+    // Set the ratio of a level for a destination, or for all of them
+    pub(crate) fn set(&mut self, destination: Option<Destination>, level: Level, ratio: f64) {
+        let ratios = match destination {
+            None => &mut self.all,
+            Some(Destination::Console) => &mut self.console,
+            Some(Destination::File) => &mut self.file,
+            Some(Destination::Server) => &mut self.server,
+        };
+
+        ratios.0[level_index(&level)] = Some(ratio);
+    }
+
+    pub(crate) fn set_consistent(&mut self, v: bool) {
+        self.consistent = Some(v);
+    }
+
+    // The ratios set by the overrides, the others set by self
+    pub(crate) fn merge(&self, overrides: &Sampling) -> Sampling {
+        let merge = |ratios: Ratios, overrides: Ratios| {
+            Ratios(std::array::from_fn(|i| overrides.0[i].or(ratios.0[i])))
+        };
+
+        Sampling {
+            all: merge(self.all, overrides.all),
+            console: merge(self.console, overrides.console),
+            file: merge(self.file, overrides.file),
+            server: merge(self.server, overrides.server),
+            consistent: overrides.consistent.or(self.consistent),
+        }
+    }
+
+    pub(crate) fn problems(&self) -> Vec<TracingInitError> {
+        [self.all, self.console, self.file, self.server]
+            .iter()
+            .flat_map(|ratios| LEVELS.iter().zip(ratios.0))
+            .filter_map(|(level, ratio)| match ratio {
+                Some(ratio) if !(0.0..=1.0).contains(&ratio) => {
+                    Some(TracingInitError::InvalidSampleRatio {
+                        level: *level,
+                        ratio,
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // The filter of a destination (None for one without ratios of its own, like the debug output), None if none of its
+    // events are sampled out
+    fn filter(&self, destination: Option<Destination>) -> Option<SampleFilter> {
+        let ratios = match destination {
+            None => Ratios::default(),
+            Some(Destination::Console) => self.console,
+            Some(Destination::File) => self.file,
+            Some(Destination::Server) => self.server,
+        };
+        let thresholds = std::array::from_fn(|i| Threshold::new(ratios.0[i].or(self.all.0[i])));
+
+        (thresholds != [Threshold::All; 5]).then_some(SampleFilter {
+            thresholds,
+            consistent: self.consistent == Some(true),
+        })
+    }
+
+    // Apply the sampling of a destination to its layer
+    pub(crate) fn apply<S>(
+        &self,
+        destination: Option<Destination>,
+        layer: BoxedLayer<S>,
+    ) -> BoxedLayer<S>
+    where
+        S: Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        let layer = layer?;
+
+        Some(match self.filter(destination) {
+            Some(filter) => layer.with_filter(filter).boxed(),
+            None => layer,
+        })
+    }
+
+    // The layer rolling the dice for each event, placed before the destinations when sampling consistently
+    pub(crate) fn roll_layer<S: Subscriber>(&self) -> BoxedLayer<S> {
+        (self.consistent == Some(true) && !self.is_empty()).then(|| EventRoll.boxed())
+    }
+
+    // None of the events of any destination are sampled out
+    pub(crate) fn is_empty(&self) -> bool {
+        [Destination::Console, Destination::File, Destination::Server]
+            .into_iter()
+            .all(|destination| self.filter(Some(destination)).is_none())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Threshold {
+    All,
+    None,
+    // Events whose roll is below this value are kept
+    Below(u64),
+}
+
+impl Threshold {
+    fn new(ratio: Option<f64>) -> Threshold {
+        match ratio {
+            None => Threshold::All,
+            Some(ratio) if ratio >= 1.0 => Threshold::All,
+            Some(ratio) if ratio <= 0.0 => Threshold::None,
+            // Saturating conversion, the ratio is less than 1
+            Some(ratio) => Threshold::Below((ratio * u64::MAX as f64) as u64),
+        }
+    }
+}
+
+// Keeps a ratio of the events of each level. Ratios of 1 and 0 enable and disable the level, without rolling
+#[derive(Debug)]
+pub(crate) struct SampleFilter {
+    thresholds: [Threshold; 5],
+    consistent: bool,
+}
+
+impl SampleFilter {
+    fn threshold(&self, metadata: &Metadata<'_>) -> Threshold {
+        self.thresholds[level_index(metadata.level())]
+    }
+}
+
+impl<S> Filter<S> for SampleFilter {
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: &Context<'_, S>) -> bool {
+        self.threshold(metadata) != Threshold::None
+    }
+
+    fn callsite_enabled(&self, metadata: &'static Metadata<'static>) -> Interest {
+        match self.threshold(metadata) {
+            Threshold::All => Interest::always(),
+            Threshold::None => Interest::never(),
+            Threshold::Below(_) => Interest::sometimes(),
+        }
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, _ctx: &Context<'_, S>) -> bool {
+        match self.threshold(event.metadata()) {
+            Threshold::Below(threshold) => {
+                let roll = if self.consistent {
+                    EVENT_ROLL.with(Cell::get)
+                } else {
+                    random()
+                };
+
+                roll < threshold
+            }
+            _ => true,
+        }
+    }
+}
+
+// Rolls once per event, so the destinations sampling consistently share the decision
+pub(crate) struct EventRoll;
+
+impl<S: Subscriber> Layer<S> for EventRoll {
+    fn event_enabled(&self, _event: &Event<'_>, _ctx: Context<'_, S>) -> bool {
+        EVENT_ROLL.with(|roll| roll.set(random()));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::CaptureLayer;
+    #[cfg(feature = "file")]
+    use crate::tests::{test_log_dir, with_env};
+    #[cfg(feature = "file")]
+    use crate::TracingInit;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    // Log count DEBUG events to two destinations, returns the messages each one kept
+    fn sampled(sampling: &Sampling, count: usize) -> (Vec<String>, Vec<String>) {
+        let (file, server) = (CaptureLayer::default(), CaptureLayer::default());
+        let subscriber = tracing_subscriber::registry().with(
+            [
+                sampling.roll_layer(),
+                sampling.apply(Some(Destination::File), Some(file.clone().boxed())),
+                sampling.apply(Some(Destination::Server), Some(server.clone().boxed())),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>(),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            for n in 0..count {
+                tracing::debug!("event {n}");
+            }
+        });
+
+        let messages = |capture: CaptureLayer| {
+            capture
+                .events()
+                .into_iter()
+                .map(|(_, _, message)| message)
+                .collect::<Vec<_>>()
+        };
+
+        (messages(file), messages(server))
+    }
+
+    #[test]
+    fn test_sample_ratio() {
+        let mut sampling = Sampling::default();
+
+        sampling.set(None, Level::DEBUG, 0.5);
+        sampling.set(Some(Destination::Server), Level::DEBUG, 0.01);
+
+        let (file, server) = sampled(&sampling, 100_000);
+
+        // The standard deviations are 158 and 31
+        assert!((48_500..=51_500).contains(&file.len()), "{}", file.len());
+        assert!((700..=1_300).contains(&server.len()), "{}", server.len());
+    }
+
+    #[test]
+    fn test_sample_all_or_nothing() {
+        let mut sampling = Sampling::default();
+
+        sampling.set(Some(Destination::File), Level::DEBUG, 1.0);
+        sampling.set(Some(Destination::Server), Level::DEBUG, 0.0);
+
+        let (file, server) = sampled(&sampling, 10_000);
+        assert_eq!(file.len(), 10_000);
+        assert!(server.is_empty());
+
+        // Without a ratio below 1 there is nothing to filter
+        assert!(sampling.filter(Some(Destination::File)).is_none());
+        assert!(sampling.filter(Some(Destination::Console)).is_none());
+        let server_filter = sampling.filter(Some(Destination::Server)).unwrap();
+        assert!(Filter::<()>::callsite_enabled(&server_filter, &DEBUG_METADATA).is_never());
+    }
+
+    static DEBUG_CALLSITE: tracing::callsite::DefaultCallsite =
+        tracing::callsite::DefaultCallsite::new(&DEBUG_METADATA);
+    static DEBUG_METADATA: Metadata<'static> = Metadata::new(
+        "debug",
+        "sample",
+        Level::DEBUG,
+        None,
+        None,
+        None,
+        tracing::field::FieldSet::new(&[], tracing::callsite::Identifier(&DEBUG_CALLSITE)),
+        tracing::metadata::Kind::EVENT,
+    );
+
+    #[test]
+    fn test_consistent_sampling() {
+        let mut sampling = Sampling::default();
+
+        sampling.set(Some(Destination::File), Level::DEBUG, 0.5);
+        sampling.set(Some(Destination::Server), Level::DEBUG, 0.1);
+
+        // Independent decisions: about 5% of the events are kept by both destinations
+        let (file, server) = sampled(&sampling, 10_000);
+        let both = server.iter().filter(|m| file.contains(m)).count();
+        assert!(both < server.len() * 3 / 4, "{both} of {}", server.len());
+
+        // Shared decisions: the events kept by the server are kept by the file as well
+        sampling.set_consistent(true);
+
+        let (file, server) = sampled(&sampling, 10_000);
+        assert!((800..=1_200).contains(&server.len()), "{}", server.len());
+        assert!(server.iter().all(|m| file.contains(m)));
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_sample_destinations() {
+        let log_dir = test_log_dir("sample");
+        let capture = CaptureLayer::default();
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(crate::Rotation::Never)
+                .level(Level::DEBUG)
+                .with_layer(capture.clone())
+                .file_sample(Level::DEBUG, 0.0)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::debug!("not sampled");
+            tracing::info!("always written");
+        });
+        drop(guard);
+
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        assert!(!log.contains("not sampled"), "{log}");
+        assert!(log.contains("always written"), "{log}");
+
+        // The layers added by with_layer are not sampled
+        assert_eq!(capture.events().len(), 2);
+
+        let problems = TracingInit::builder("App")
+            .log_to_console(true)
+            .sample(Level::DEBUG, 1.5)
+            .validate()
+            .unwrap_err();
+        assert!(matches!(
+            &problems[..],
+            [TracingInitError::InvalidSampleRatio { ratio, .. }] if *ratio == 1.5
+        ));
+    }
+}
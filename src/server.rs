@@ -0,0 +1,359 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+use tracing::dispatcher::WeakDispatch;
+use tracing::span;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+// The delays between the attempts to resolve the log server address, doubled after each failure
+const RESOLVE_RETRY_MIN: Duration = Duration::from_secs(1);
+const RESOLVE_RETRY_MAX: Duration = Duration::from_secs(30);
+
+// The minimum time between two warnings that the log server address cannot be resolved (or that the records cannot
+// be sent to it)
+const RESOLVE_WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+thread_local! {
+    // Set while the connection task reports on the resolution, the report is logged by the other destinations only
+    static REPORTING: Cell<bool> = const { Cell::new(false) };
+}
+
+// Wraps the GELF layer so it can be dropped on shutdown - dropping it closes the channel feeding the connection
+// task, which then sends the queued messages and completes
+pub(crate) struct ServerLayer {
+    logger: Arc<RwLock<Option<tracing_gelf::Logger>>>,
+    // The number of events handed to the logger
+    events: Arc<AtomicU64>,
+}
+
+/// Keeps the log server connection alive, returned by [`layers::gelf`](crate::layers::gelf)
+///
+/// When dropped, the log server layer stops accepting records and the queued ones are sent (bounded by the
+/// shutdown timeout)
+#[must_use = "dropping the guard immediately closes the log server connection"]
+pub struct ServerGuard {
+    logger: Arc<RwLock<Option<tracing_gelf::Logger>>>,
+    events: Arc<AtomicU64>,
+    done: Mutex<mpsc::Receiver<()>>,
+    shutdown_timeout: Duration,
+    // Wakes the connection task while it waits to resolve the address again
+    closed: Arc<Notify>,
+    dispatch: Arc<OnceLock<WeakDispatch>>,
+}
+
+// Run by the connection task: resolves the log server address and reports the completion of the task
+pub(crate) struct Connection {
+    address: String,
+    done: mpsc::Sender<()>,
+    closed: Arc<Notify>,
+    // The dispatch the resolution failures are logged to, the global default one if not set
+    dispatch: Arc<OnceLock<WeakDispatch>>,
+}
+
+impl std::fmt::Debug for ServerGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerGuard")
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ServerLayer {
+    // Returns the layer, the guard used to shut it down and the state of the connection task
+    pub(crate) fn new(
+        logger: tracing_gelf::Logger,
+        address: &str,
+        shutdown_timeout: Duration,
+    ) -> (ServerLayer, ServerGuard, Connection) {
+        let logger = Arc::new(RwLock::new(Some(logger)));
+        let events = Arc::new(AtomicU64::new(0));
+        let (done_sender, done) = mpsc::channel();
+        let closed = Arc::new(Notify::new());
+        let dispatch = Arc::new(OnceLock::new());
+
+        (
+            ServerLayer {
+                logger: logger.clone(),
+                events: events.clone(),
+            },
+            ServerGuard {
+                logger,
+                events,
+                done: Mutex::new(done),
+                shutdown_timeout,
+                closed: closed.clone(),
+                dispatch: dispatch.clone(),
+            },
+            Connection {
+                address: address.to_string(),
+                done: done_sender,
+                closed,
+                dispatch,
+            },
+        )
+    }
+
+    fn with_logger(&self, f: impl FnOnce(&tracing_gelf::Logger)) {
+        if let Some(logger) = self
+            .logger
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+        {
+            f(logger)
+        }
+    }
+}
+
+impl Connection {
+    // Resolve the log server address, retrying until it is resolved (e.g. DNS is not ready yet at boot). The records
+    // stay queued meanwhile. Returns false if the layer was shut down first
+    async fn resolve(&self) -> bool {
+        let mut delay = RESOLVE_RETRY_MIN;
+        let mut failures = 0u64;
+        let mut warned: Option<Instant> = None;
+
+        loop {
+            let resolved = tokio::net::lookup_host(self.address.as_str())
+                .await
+                .map(|mut addresses| addresses.next().is_some());
+            let error = match resolved {
+                Ok(true) => break,
+                Ok(false) => std::io::Error::new(std::io::ErrorKind::NotFound, "no address found"),
+                Err(error) => error,
+            };
+
+            failures += 1;
+
+            if warned.is_none_or(|warned| warned.elapsed() >= RESOLVE_WARN_INTERVAL) {
+                self.report(|| {
+                    tracing::warn!(
+                        target: "tracing_init",
+                        address = %self.address,
+                        %error,
+                        failures,
+                        "the log server address cannot be resolved, retrying"
+                    )
+                });
+                warned = Some(Instant::now());
+            }
+
+            if !self.wait(delay).await {
+                return false;
+            }
+
+            delay = (delay * 2).min(RESOLVE_RETRY_MAX);
+        }
+
+        if failures > 0 {
+            self.report(|| {
+                tracing::info!(
+                    target: "tracing_init",
+                    address = %self.address,
+                    failures,
+                    "the log server address was resolved, sending the queued records"
+                )
+            });
+        }
+
+        true
+    }
+
+    // Resolve the address and send the records, connecting again after a delay when the records cannot be sent (e.g.
+    // the network is unreachable). Completes once the layer was shut down and the queued records were sent
+    pub(crate) async fn run(
+        self,
+        mut task: tracing_gelf::ConnectionHandle<String, tracing_gelf::UdpConnection>,
+    ) {
+        let mut delay = RESOLVE_RETRY_MIN;
+        let mut failures = 0u64;
+        let mut warned: Option<Instant> = None;
+
+        while self.resolve().await {
+            let connected = Instant::now();
+            let errors = task.connect().await;
+
+            // The channel was closed by the shutdown
+            if errors.0.is_empty() {
+                break;
+            }
+
+            // The records were sent for a while, the network failed since
+            if connected.elapsed() >= RESOLVE_RETRY_MAX {
+                delay = RESOLVE_RETRY_MIN;
+            }
+
+            failures += 1;
+
+            if warned.is_none_or(|warned| warned.elapsed() >= RESOLVE_WARN_INTERVAL) {
+                let errors = errors
+                    .0
+                    .iter()
+                    .map(|(address, error)| format!("{address}: {error}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                self.report(|| {
+                    tracing::warn!(
+                        target: "tracing_init",
+                        address = %self.address,
+                        %errors,
+                        failures,
+                        "the records cannot be sent to the log server, connecting again"
+                    )
+                });
+                warned = Some(Instant::now());
+            }
+
+            if !self.wait(delay).await {
+                break;
+            }
+
+            delay = (delay * 2).min(RESOLVE_RETRY_MAX);
+        }
+
+        let _ = self.done.send(());
+    }
+
+    // Wait before the next attempt, false if the layer was shut down meanwhile
+    async fn wait(&self, delay: Duration) -> bool {
+        tokio::time::timeout(jitter(delay), self.closed.notified())
+            .await
+            .is_err()
+    }
+
+    // Log to the subscriber the layer belongs to, the record is not queued for the log server
+    fn report(&self, report: impl FnOnce()) {
+        let report = || {
+            REPORTING.with(|reporting| reporting.set(true));
+            report();
+            REPORTING.with(|reporting| reporting.set(false));
+        };
+
+        match self.dispatch.get().map(WeakDispatch::upgrade) {
+            Some(Some(dispatch)) => tracing::dispatcher::with_default(&dispatch, report),
+            // The subscriber was dropped
+            Some(None) => {}
+            None => report(),
+        }
+    }
+}
+
+// The delay shortened by up to a half at random, so the programs started together do not retry together
+fn jitter(delay: Duration) -> Duration {
+    let random = RandomState::new().hash_one(Instant::now()) % 1000;
+
+    delay.mul_f64(1.0 - random as f64 / 2000.0)
+}
+
+impl ServerGuard {
+    // The subscriber the resolution failures are logged to
+    pub(crate) fn set_dispatch(&self, dispatch: WeakDispatch) {
+        let _ = self.dispatch.set(dispatch);
+    }
+
+    // The number of events handed to the connection task so far
+    pub(crate) fn events(&self) -> u64 {
+        self.events.load(Ordering::Acquire)
+    }
+
+    // Why the records cannot be sent anymore: the layer was shut down or the connection task ended
+    pub(crate) fn broken(&self) -> Option<&'static str> {
+        if self
+            .logger
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_none()
+        {
+            return Some("the log server connection was shut down");
+        }
+
+        match self
+            .done
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .try_recv()
+        {
+            Err(TryRecvError::Empty) => None,
+            _ => Some("the connection task ended, the log server could not be reached"),
+        }
+    }
+
+    // Stop accepting new events and wait (up to the shutdown timeout) for the queued ones to be sent
+    pub(crate) fn shutdown(&self) {
+        self.logger
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        self.closed.notify_one();
+
+        // On a current thread runtime the connection task cannot make progress while this thread is blocked
+        let can_wait = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle.runtime_flavor() != tokio::runtime::RuntimeFlavor::CurrentThread,
+            Err(_) => true,
+        };
+
+        if can_wait {
+            let _ = self
+                .done
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv_timeout(self.shutdown_timeout);
+        }
+    }
+}
+
+impl Drop for ServerGuard {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl<S> Layer<S> for ServerLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        self.with_logger(|logger| logger.on_new_span(attrs, id, ctx));
+    }
+
+    fn on_record(&self, span: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        self.with_logger(|logger| logger.on_record(span, values, ctx));
+    }
+
+    fn on_follows_from(&self, span: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.with_logger(|logger| logger.on_follows_from(span, follows, ctx));
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if REPORTING.with(Cell::get) {
+            return;
+        }
+
+        self.with_logger(|logger| {
+            logger.on_event(event, ctx);
+            self.events.fetch_add(1, Ordering::AcqRel);
+        });
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.with_logger(|logger| logger.on_enter(id, ctx));
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.with_logger(|logger| logger.on_exit(id, ctx));
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.with_logger(|logger| logger.on_close(id, ctx));
+    }
+}
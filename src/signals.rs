@@ -0,0 +1,457 @@
+use std::io::Read;
+use std::os::raw::c_int;
+use std::os::unix::io::{IntoRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2};
+use signal_hook::iterator::{Handle, Signals};
+use signal_hook::low_level::{emulate_default_handler, signal_name};
+use tracing::dispatcher::WeakDispatch;
+use tracing::Dispatch;
+
+use crate::panic_hook::Flush;
+use crate::{FilterHandle, TracingInit, TracingInitError};
+
+// The signals logged by default
+pub(crate) const DEFAULT_LOGGED_SIGNALS: [&str; 3] = ["SIGTERM", "SIGINT", "SIGQUIT"];
+
+// The signals which can be logged, the others cannot be handled or are not termination requests
+const LOGGABLE_SIGNALS: [(&str, c_int); 6] = [
+    ("SIGTERM", SIGTERM),
+    ("SIGINT", SIGINT),
+    ("SIGQUIT", SIGQUIT),
+    ("SIGHUP", SIGHUP),
+    ("SIGUSR1", SIGUSR1),
+    ("SIGUSR2", SIGUSR2),
+];
+
+// The signals caught so far, each one once for the whole process (whatever the number of loggers) and until it ends
+static CAUGHT: Mutex<Vec<c_int>> = Mutex::new(Vec::new());
+
+// The running loggers
+static LOGGERS: Mutex<Vec<Arc<Logger>>> = Mutex::new(Vec::new());
+
+// The write end of the pipe read by the thread logging the signals, -1 until it is started. It is open until the
+// process ends
+static WAKE_FD: AtomicI32 = AtomicI32::new(-1);
+
+// The number of signals written to the pipe, and of those logged by the thread
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+static LOGGED: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+
+// How long a stopped logger waits for the signals received before to be logged
+const LOG_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Reloads the filter on SIGHUP. The signal handler only wakes up the reloader thread (signal-hook writes to a
+// self-pipe), the filter is computed and replaced by the thread
+pub(crate) struct SighupReloader {
+    signals: Handle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SighupReloader {
+    // builder is the configuration as set before applying the environment variables
+    pub(crate) fn start(
+        builder: TracingInit,
+        filter_handle: FilterHandle,
+    ) -> Result<SighupReloader, TracingInitError> {
+        let mut signals = Signals::new([SIGHUP]).map_err(TracingInitError::SignalHandler)?;
+        let handle = signals.handle();
+
+        let thread = std::thread::Builder::new()
+            .name(String::from("tracing-init-sighup"))
+            .spawn(move || {
+                for _ in signals.forever() {
+                    reload_filter(&builder, &filter_handle);
+                }
+            })
+            .map_err(TracingInitError::SignalHandler)?;
+
+        Ok(SighupReloader {
+            signals: handle,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for SighupReloader {
+    fn drop(&mut self) {
+        self.signals.close();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Logs the signals as WARN events. As for SIGHUP, the signal handler only wakes up a thread, which logs the signal to
+// the running loggers. When the program does not handle the signal itself, the thread then flushes the destinations
+// and ends the process as the default action would, otherwise the program's handler decides
+pub(crate) struct SignalLogger {
+    logger: Arc<Logger>,
+}
+
+struct Logger {
+    signals: Vec<c_int>,
+    flush: Flush,
+    // The dispatch the signals are logged to, the global default one if not set
+    dispatch: OnceLock<WeakDispatch>,
+}
+
+impl SignalLogger {
+    pub(crate) fn start(names: &[String], flush: Flush) -> Result<SignalLogger, TracingInitError> {
+        let signals = names
+            .iter()
+            .map(|name| signal_number(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let logger = Arc::new(Logger {
+            signals,
+            flush,
+            dispatch: OnceLock::new(),
+        });
+
+        // Added before the signals are caught: a signal received from then on is logged before any default action
+        loggers().push(logger.clone());
+
+        let signal_logger = SignalLogger { logger };
+        catch(&signal_logger.logger.signals)?;
+
+        Ok(signal_logger)
+    }
+
+    // Log the signals to this dispatch rather than to the global default one
+    pub(crate) fn set_dispatch(&self, dispatch: &Dispatch) {
+        let _ = self.logger.dispatch.set(dispatch.downgrade());
+    }
+}
+
+impl Drop for SignalLogger {
+    // The signals stay caught, with no logger the thread performs the default actions
+    fn drop(&mut self) {
+        // The signals received until now are logged first, such as the SIGTERM the program is shutting down for
+        let received = RECEIVED.load(Ordering::Acquire);
+        let (logged, logged_changed) = &LOGGED;
+        let _ = logged_changed.wait_timeout_while(
+            logged.lock().unwrap_or_else(PoisonError::into_inner),
+            LOG_TIMEOUT,
+            |logged| *logged < received,
+        );
+
+        loggers().retain(|logger| !Arc::ptr_eq(logger, &self.logger));
+    }
+}
+
+fn loggers() -> MutexGuard<'static, Vec<Arc<Logger>>> {
+    LOGGERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Catch the signals which are not caught yet. A signal which ends the process is caught by on_terminating_signal,
+// installed directly rather than through signal-hook: a handler the program installs later (e.g. by tokio::signal or
+// signal-hook) then replaces it and calls it first, and the thread sees that the program handles the signal. The
+// disposition is read before the signal is caught, afterwards it is the handler of the logger
+fn catch(signals: &[c_int]) -> Result<(), TracingInitError> {
+    let mut caught = CAUGHT.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if WAKE_FD.load(Ordering::Acquire) < 0 {
+        let wake = start_thread().map_err(TracingInitError::SignalHandler)?;
+        WAKE_FD.store(wake, Ordering::Release);
+    }
+
+    for &signal in signals {
+        if caught.contains(&signal) {
+            continue;
+        }
+
+        if has_disposition(signal, libc::SIG_DFL) {
+            install_terminating_handler(signal)
+        } else {
+            // SAFETY: wake is async-signal-safe
+            unsafe { signal_hook::low_level::register(signal, move || wake(signal)) }.map(drop)
+        }
+        .map_err(TracingInitError::SignalHandler)?;
+
+        caught.push(signal);
+    }
+
+    Ok(())
+}
+
+// Start the thread logging the signals written to the pipe, returns its write end
+fn start_thread() -> std::io::Result<RawFd> {
+    let (mut reader, writer) = UnixStream::pair()?;
+
+    // A signal is dropped rather than blocking the handler if the pipe is full
+    writer.set_nonblocking(true)?;
+
+    std::thread::Builder::new()
+        .name(String::from("tracing-init-signals"))
+        .spawn(move || {
+            let mut signal = [0];
+
+            while reader.read_exact(&mut signal).is_ok() {
+                log_signal(c_int::from(signal[0]));
+
+                let (logged, logged_changed) = &LOGGED;
+                *logged.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+                logged_changed.notify_all();
+            }
+        })?;
+
+    Ok(writer.into_raw_fd())
+}
+
+// Log the signal to the loggers of this signal. If the handler of the logger is still installed, the program does not
+// handle the signal: the destinations are flushed and the process is ended as by the default action. Whether it is
+// installed is read now, the program may have installed its handler after the logger was started
+fn log_signal(signal: c_int) {
+    let name = signal_name(signal).unwrap_or("unknown");
+    let terminating = has_disposition(signal, terminating_handler());
+    let loggers = loggers();
+
+    for logger in loggers
+        .iter()
+        .filter(|logger| logger.signals.contains(&signal))
+    {
+        let log = || tracing::warn!(target: "tracing_init", signal = name, "signal received");
+
+        match logger.dispatch.get().map(WeakDispatch::upgrade) {
+            Some(Some(dispatch)) => tracing::dispatcher::with_default(&dispatch, log),
+            // The subscriber was dropped
+            Some(None) => {}
+            None => log(),
+        }
+
+        if terminating {
+            logger.flush.flush_all();
+        } else {
+            logger.flush.flush();
+        }
+    }
+
+    if terminating {
+        let _ = emulate_default_handler(signal);
+    }
+}
+
+// The handler of the signals which end the process unless the program handles them
+extern "C" fn on_terminating_signal(signal: c_int) {
+    let errno = errno::errno();
+
+    wake(signal);
+    errno::set_errno(errno);
+}
+
+fn terminating_handler() -> libc::sighandler_t {
+    on_terminating_signal as extern "C" fn(c_int) as libc::sighandler_t
+}
+
+fn install_terminating_handler(signal: c_int) -> std::io::Result<()> {
+    // SAFETY: the action is initialized before it is installed, the handler is async-signal-safe
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+
+        action.sa_sigaction = terminating_handler();
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+// Wake up the thread, called by the signal handlers
+fn wake(signal: c_int) {
+    let signal = signal as u8;
+
+    RECEIVED.fetch_add(1, Ordering::AcqRel);
+
+    // SAFETY: write is async-signal-safe, the pipe is open until the process ends
+    let written = unsafe {
+        libc::write(
+            WAKE_FD.load(Ordering::Acquire),
+            (&signal as *const u8).cast(),
+            1,
+        )
+    };
+
+    // Dropped, the pipe is full
+    if written != 1 {
+        RECEIVED.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn signal_number(name: &str) -> Result<c_int, TracingInitError> {
+    LOGGABLE_SIGNALS
+        .iter()
+        .find(|(signal_name, _)| *signal_name == name)
+        .map(|(_, signal)| *signal)
+        .ok_or_else(|| TracingInitError::UnknownSignal {
+            name: name.to_string(),
+        })
+}
+
+// true if the current handler of the signal is this one (SIG_DFL: neither the program nor another library handles or
+// ignores it)
+fn has_disposition(signal: c_int, handler: libc::sighandler_t) -> bool {
+    // SAFETY: without a new action, sigaction only reads the current one into the zeroed struct
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+
+        libc::sigaction(signal, std::ptr::null(), &mut action) == 0
+            && action.sa_sigaction == handler
+    }
+}
+
+// Compute the filter from the current environment variables and replace the active one
+pub(crate) fn reload_filter(builder: &TracingInit, filter_handle: &FilterHandle) {
+    let old = filter_handle.current();
+
+    match filter_handle.reload_from(builder) {
+        Ok((old, new)) => {
+            tracing::info!(target: "tracing_init", %old, %new, "filter reloaded on SIGHUP")
+        }
+        Err(e) => {
+            tracing::warn!(target: "tracing_init", %old, error = %e, "filter not reloaded on SIGHUP")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{with_env, CaptureLayer};
+    use tracing::Level;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_reload_filter_from_environment() {
+        let (dispatch, guard) = TracingInit::builder("App")
+            .test_writer(true)
+            .into_dispatch()
+            .unwrap();
+        let filter_handle = guard.filter_handle().unwrap();
+        let capture = CaptureLayer::default();
+
+        tracing::subscriber::with_default(
+            tracing_subscriber::registry().with(capture.clone()),
+            || {
+                with_env(
+                    &[("LOG_LEVEL", "debug"), ("LOG_FILTER", "hyper=warn")],
+                    || reload_filter(&TracingInit::builder("App"), filter_handle),
+                );
+                assert_eq!(filter_handle.current(), "hyper=warn");
+
+                with_env(&[("LOG_LEVEL", "debug")], || {
+                    reload_filter(&TracingInit::builder("App"), filter_handle)
+                });
+                assert_eq!(filter_handle.current(), "debug");
+
+                // Builder values take precedence
+                with_env(&[("LOG_LEVEL", "debug")], || {
+                    reload_filter(
+                        TracingInit::builder("App").level(Level::WARN),
+                        filter_handle,
+                    )
+                });
+                assert_eq!(filter_handle.current(), "warn");
+
+                with_env(&[("LOG_FILTER", "app=loud")], || {
+                    reload_filter(&TracingInit::builder("App"), filter_handle)
+                });
+                assert_eq!(filter_handle.current(), "warn");
+            },
+        );
+
+        drop(dispatch);
+
+        let events = capture.events();
+        assert_eq!(events.len(), 4);
+        assert!(events[1].0 == Level::INFO && events[1].2.contains("filter reloaded on SIGHUP"));
+        assert!(events[3].0 == Level::WARN && events[3].2.contains("not reloaded"));
+    }
+
+    #[test]
+    fn test_reload_on_sighup() {
+        let (_dispatch, guard) = TracingInit::builder("App")
+            .test_writer(true)
+            .level(Level::ERROR)
+            .into_dispatch()
+            .unwrap();
+        let filter_handle = guard.filter_handle().unwrap().clone();
+        let mut builder = TracingInit::builder("App");
+
+        builder.level(Level::DEBUG);
+
+        let reloader = SighupReloader::start(builder, filter_handle.clone()).unwrap();
+
+        signal_hook::low_level::raise(SIGHUP).unwrap();
+
+        let start = std::time::Instant::now();
+        while filter_handle.current() != "debug" {
+            assert!(
+                start.elapsed() < std::time::Duration::from_secs(5),
+                "filter not reloaded"
+            );
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        drop(reloader);
+    }
+
+    // The program handles SIGUSR1 itself: the signal is logged and delivered to it, the process is not ended
+    #[test]
+    fn test_log_signals_with_tokio_signal() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut usr1 = runtime.block_on(async {
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1()).unwrap()
+        });
+        let capture = CaptureLayer::default();
+        let dispatch = Dispatch::new(tracing_subscriber::registry().with(capture.clone()));
+        let logger = SignalLogger::start(&[String::from("SIGUSR1")], Flush::default()).unwrap();
+
+        logger.set_dispatch(&dispatch);
+        signal_hook::low_level::raise(SIGUSR1).unwrap();
+
+        runtime.block_on(async {
+            tokio::time::timeout(std::time::Duration::from_secs(5), usr1.recv())
+                .await
+                .expect("SIGUSR1 not delivered to tokio")
+        });
+
+        let start = std::time::Instant::now();
+        while capture.events().is_empty() {
+            assert!(
+                start.elapsed() < std::time::Duration::from_secs(5),
+                "signal not logged"
+            );
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        drop(logger);
+        assert_eq!(
+            capture.events(),
+            [(
+                Level::WARN,
+                String::from("tracing_init"),
+                String::from("signal received")
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unknown_signal() {
+        let result = SignalLogger::start(&[String::from("SIGKILL")], Flush::default());
+
+        assert!(matches!(
+            result,
+            Err(TracingInitError::UnknownSignal { name }) if name == "SIGKILL"
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use tracing::callsite::Identifier;
+use tracing::field::{display, Field, Value};
+use tracing::{span, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::clock::ClockSource;
+use crate::global_fields::{leak_metadata, Capture, MAX_FIELDS};
+use crate::redact::Captured;
+use crate::Clock;
+
+const TARGET: &str = "tracing_init::span_timing";
+
+// The fields of the records, followed by the fields of the span
+const FIELDS: [&str; 6] = [
+    "message",
+    "span",
+    "span_target",
+    "duration_ms",
+    "busy_ms",
+    "idle_ms",
+];
+
+// Stored in the extensions of the timed spans
+struct Timing {
+    created: Instant,
+    // When the span was last entered or exited
+    last: Instant,
+    busy: Duration,
+    idle: Duration,
+    values: Vec<(usize, Captured)>,
+}
+
+impl Timing {
+    fn record(&mut self, values: Vec<(usize, Captured)>) {
+        for (index, value) in values {
+            match self.values.iter_mut().find(|(i, _)| *i == index) {
+                Some(entry) => entry.1 = value,
+                None => self.values.push((index, value)),
+            }
+        }
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+// Logs the duration of the spans when they close, as an event of the span's level with target
+// tracing_init::span_timing, the span's name and target, its duration, busy and idle times and its fields
+pub(crate) struct SpanTimingLayer {
+    min_level: Level,
+    min_duration: Duration,
+    // The metadata of the records of each span callsite
+    metadata: RwLock<HashMap<Identifier, &'static Metadata<'static>>>,
+    clock: ClockSource,
+}
+
+impl SpanTimingLayer {
+    pub(crate) fn new(
+        min_level: Level,
+        min_duration: Option<Duration>,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> SpanTimingLayer {
+        SpanTimingLayer {
+            min_level,
+            min_duration: min_duration.unwrap_or_default(),
+            metadata: RwLock::default(),
+            clock: ClockSource::new(clock),
+        }
+    }
+
+    fn metadata(&self, span: &'static Metadata<'static>) -> &'static Metadata<'static> {
+        let callsite = span.callsite();
+
+        if let Some(metadata) = self
+            .metadata
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&callsite)
+        {
+            return metadata;
+        }
+
+        self.metadata
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(callsite)
+            .or_insert_with(|| {
+                let names = FIELDS
+                    .into_iter()
+                    .chain(
+                        span.fields()
+                            .iter()
+                            .map(|field| field.name())
+                            .filter(|name| !FIELDS.contains(name)),
+                    )
+                    .take(MAX_FIELDS)
+                    .collect();
+
+                leak_metadata(span, TARGET, names)
+            })
+    }
+}
+
+impl<S> Layer<S> for SpanTimingLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if *attrs.metadata().level() > self.min_level {
+            return;
+        }
+
+        if let Some(span) = ctx.span(id) {
+            let mut capture = Capture(Vec::new());
+            let now = self.clock.instant();
+
+            attrs.record(&mut capture);
+            span.extensions_mut().insert(Timing {
+                created: now,
+                last: now,
+                busy: Duration::ZERO,
+                idle: Duration::ZERO,
+                values: capture.0,
+            });
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<Timing>() {
+                let mut capture = Capture(Vec::new());
+
+                values.record(&mut capture);
+                timing.record(capture.0);
+            }
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<Timing>() {
+                let now = self.clock.instant();
+
+                timing.idle += now.saturating_duration_since(timing.last);
+                timing.last = now;
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<Timing>() {
+                let now = self.clock.instant();
+
+                timing.busy += now.saturating_duration_since(timing.last);
+                timing.last = now;
+            }
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(mut timing) = span.extensions_mut().remove::<Timing>() else {
+            return;
+        };
+
+        let now = self.clock.instant();
+        let duration = now.saturating_duration_since(timing.created);
+
+        if duration < self.min_duration {
+            return;
+        }
+
+        timing.idle += now.saturating_duration_since(timing.last);
+
+        let span_metadata = span.metadata();
+        let metadata = self.metadata(span_metadata);
+        let fields = metadata.fields().iter().collect::<Vec<_>>();
+        let (duration_ms, busy_ms, idle_ms) = (
+            milliseconds(duration),
+            milliseconds(timing.busy),
+            milliseconds(timing.idle),
+        );
+        let (span_name, span_target) = (span_metadata.name(), span_metadata.target());
+        let message = display(format!("{span_name} closed after {duration_ms}ms"));
+        let mut entries: [(&Field, Option<&dyn Value>); MAX_FIELDS] =
+            [(&fields[0], None); MAX_FIELDS];
+
+        entries[0] = (&fields[0], Some(&message));
+        entries[1] = (&fields[1], Some(&span_name));
+        entries[2] = (&fields[2], Some(&span_target));
+        entries[3] = (&fields[3], Some(&duration_ms));
+        entries[4] = (&fields[4], Some(&busy_ms));
+        entries[5] = (&fields[5], Some(&idle_ms));
+
+        for (index, value) in &timing.values {
+            let name = span_metadata.fields().iter().nth(*index).map(|f| f.name());
+
+            if let Some(position) = fields
+                .iter()
+                .skip(FIELDS.len())
+                .position(|field| Some(field.name()) == name)
+            {
+                let position = position + FIELDS.len();
+
+                entries[position] = (&fields[position], Some(value.as_value()));
+            }
+        }
+
+        let values = metadata.fields().value_set(&entries);
+        let parent = span.parent().map(|parent| parent.id());
+
+        // Dispatched (rather than passed to the next layer) so the record is filtered and written like any other event
+        drop(span);
+        tracing::dispatcher::get_default(|dispatch| {
+            if dispatch.enabled(metadata) {
+                dispatch.event(&tracing::Event::new_child_of(
+                    parent.clone(),
+                    metadata,
+                    &values,
+                ));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{with_env, MemoryWriter};
+    use crate::TracingInit;
+    use std::time::Duration;
+    use tracing::Level;
+
+    // The value of a numeric JSON field
+    fn number(record: &str, name: &str) -> f64 {
+        let start = record.find(&format!("\"{name}\":")).unwrap() + name.len() + 3;
+        let end = record[start..]
+            .find([',', '}'])
+            .map(|end| start + end)
+            .unwrap();
+
+        record[start..end].parse().unwrap()
+    }
+
+    fn span_records(
+        min_level: Level,
+        min_duration: Option<Duration>,
+        log: impl FnOnce(),
+    ) -> Vec<String> {
+        let json = MemoryWriter::default();
+        let (dispatch, _guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_console(false)
+                .level(Level::DEBUG)
+                .log_span_durations(min_level, min_duration)
+                .with_layer(
+                    tracing_subscriber::fmt::layer()
+                        .json()
+                        .with_writer(json.clone()),
+                )
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, log);
+
+        json.contents()
+            .lines()
+            .filter(|line| line.contains(r#""target":"tracing_init::span_timing""#))
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_span_durations() {
+        let records = span_records(Level::INFO, None, || {
+            let span = tracing::info_span!("request", id = 5, user = tracing::field::Empty);
+
+            span.in_scope(|| std::thread::sleep(Duration::from_millis(60)));
+            span.record("user", "bob");
+            std::thread::sleep(Duration::from_millis(40));
+            drop(span);
+
+            // Below the minimum level
+            let _ = tracing::debug_span!("lookup").entered();
+        });
+
+        assert_eq!(records.len(), 1, "{records:?}");
+
+        let record = &records[0];
+        assert!(record.contains(r#""level":"INFO""#), "{record}");
+        assert!(record.contains(r#""span":"request""#), "{record}");
+        assert!(
+            record.contains(r#""span_target":"tracing_init::span_timing::tests""#),
+            "{record}"
+        );
+        assert!(record.contains(r#""id":5"#), "{record}");
+        assert!(record.contains(r#""user":"bob""#), "{record}");
+
+        let (duration, busy, idle) = (
+            number(record, "duration_ms"),
+            number(record, "busy_ms"),
+            number(record, "idle_ms"),
+        );
+        assert!((100.0..1_000.0).contains(&duration), "{record}");
+        assert!((60.0..duration).contains(&busy), "{record}");
+        assert!((40.0..duration).contains(&idle), "{record}");
+    }
+
+    #[test]
+    fn test_span_durations_threshold() {
+        let records = span_records(Level::DEBUG, Some(Duration::from_millis(50)), || {
+            let _ = tracing::info_span!("fast").entered();
+
+            tracing::debug_span!("slow").in_scope(|| {
+                std::thread::sleep(Duration::from_millis(60));
+
+                // Closed within the slow span
+                let _ = tracing::info_span!("nested").entered();
+            });
+        });
+
+        assert_eq!(records.len(), 1, "{records:?}");
+        assert!(records[0].contains(r#""span":"slow""#), "{}", records[0]);
+        assert!(records[0].contains(r#""level":"DEBUG""#), "{}", records[0]);
+    }
+}
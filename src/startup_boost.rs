@@ -0,0 +1,185 @@
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, OnceLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use tracing::dispatcher::WeakDispatch;
+use tracing::Dispatch;
+use tracing_subscriber::EnvFilter;
+
+use crate::{FilterHandle, TracingInitError};
+
+// Restores the steady-state filter when the startup boost window elapses, unless the filter was replaced in the
+// meantime. Dropping it before then stops the thread without changing the filter
+pub(crate) struct StartupBoost {
+    stop: Option<Sender<()>>,
+    // The dispatch the end of the boost is logged to, the global default one if not set
+    dispatch: Arc<OnceLock<WeakDispatch>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StartupBoost {
+    // The boosted filter is active, steady is the filter replacing it after duration
+    pub(crate) fn start(
+        filter_handle: FilterHandle,
+        steady: String,
+        duration: Duration,
+    ) -> Result<StartupBoost, TracingInitError> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let dispatch = Arc::new(OnceLock::<WeakDispatch>::new());
+        let generation = filter_handle.generation();
+        let thread_dispatch = dispatch.clone();
+
+        let thread = std::thread::Builder::new()
+            .name(String::from("tracing-init-boost"))
+            .spawn(move || {
+                // Disconnected when the boost is dropped
+                if let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(duration) {
+                    match thread_dispatch.get().map(WeakDispatch::upgrade) {
+                        Some(Some(dispatch)) => {
+                            tracing::dispatcher::with_default(&dispatch, || {
+                                end_boost(&filter_handle, generation, &steady)
+                            })
+                        }
+                        // The subscriber was dropped
+                        Some(None) => {}
+                        None => end_boost(&filter_handle, generation, &steady),
+                    }
+                }
+            })
+            .map_err(TracingInitError::StartupBoost)?;
+
+        Ok(StartupBoost {
+            stop: Some(stop),
+            dispatch,
+            thread: Some(thread),
+        })
+    }
+
+    // Log the end of the boost to this dispatch rather than to the global default one
+    pub(crate) fn set_dispatch(&self, dispatch: &Dispatch) {
+        let _ = self.dispatch.set(dispatch.downgrade());
+    }
+}
+
+impl Drop for StartupBoost {
+    fn drop(&mut self) {
+        self.stop.take();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn end_boost(filter_handle: &FilterHandle, generation: u64, steady: &str) {
+    let boosted = filter_handle.current();
+    let result = EnvFilter::try_new(steady)
+        .map_err(TracingInitError::from)
+        .and_then(|filter| filter_handle.replace_if_unchanged(generation, filter));
+
+    match result {
+        Ok(true) => {
+            tracing::info!(target: "tracing_init", old = %boosted, new = %steady, "startup verbosity boost ended")
+        }
+        Ok(false) => {
+            tracing::info!(target: "tracing_init", filter = %filter_handle.current(), "startup verbosity boost ended, the filter changed since startup is kept")
+        }
+        Err(e) => {
+            tracing::warn!(target: "tracing_init", filter = %boosted, error = %e, "startup verbosity boost not ended")
+        }
+    }
+}
+
+#[cfg(all(test, feature = "file"))]
+mod tests {
+    use crate::tests::{test_log_dir, with_env};
+    use crate::{Rotation, TracingInit};
+    use std::time::Duration;
+    use tracing::level_filters::LevelFilter;
+    use tracing::Level;
+
+    const BOOST: Duration = Duration::from_millis(100);
+
+    fn boosted(name: &str) -> (std::path::PathBuf, tracing::Dispatch, crate::TracingGuard) {
+        let log_dir = test_log_dir(name);
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(Rotation::Never)
+                .filter("hyper=warn,info")
+                .startup_boost(Level::DEBUG, BOOST)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        (log_dir, dispatch, guard)
+    }
+
+    #[test]
+    fn test_startup_boost() {
+        let (log_dir, dispatch, guard) = boosted("startup-boost");
+        let filter = guard.filter_handle().unwrap();
+
+        assert_eq!(filter.current(), "hyper=warn,debug");
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::debug!("during boost");
+            tracing::debug!(target: "hyper", "hyper during boost");
+        });
+
+        std::thread::sleep(BOOST * 3);
+        assert_eq!(filter.current(), "hyper=warn,info");
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::debug!("after boost");
+            tracing::info!("info after boost");
+        });
+        drop(guard);
+
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        let lines = log
+            .lines()
+            .filter(|line| !line.contains("TracingGuard"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3, "{log}");
+        assert!(lines[0].ends_with(": during boost"), "{log}");
+        assert!(
+            lines[1].contains(
+                r#"INFO tracing_init: startup verbosity boost ended old=hyper=warn,debug new=hyper=warn,info"#
+            ),
+            "{log}"
+        );
+        assert!(lines[2].ends_with(": info after boost"), "{log}");
+    }
+
+    #[test]
+    fn test_startup_boost_manual_change() {
+        let (log_dir, dispatch, guard) = boosted("startup-boost-manual");
+        let filter = guard.filter_handle().unwrap();
+
+        filter.set_level(LevelFilter::TRACE).unwrap();
+        std::thread::sleep(BOOST * 3);
+
+        assert_eq!(filter.current(), "hyper=warn,trace");
+        tracing::dispatcher::with_default(&dispatch, || tracing::trace!("after boost"));
+        drop(guard);
+
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        assert!(
+            log.contains("startup verbosity boost ended, the filter changed since startup is kept filter=hyper=warn,trace"),
+            "{log}"
+        );
+        assert!(log.contains(": after boost"), "{log}");
+    }
+
+    #[test]
+    fn test_startup_boost_dropped() {
+        let (_log_dir, _dispatch, guard) = boosted("startup-boost-dropped");
+        let started = std::time::Instant::now();
+
+        // The timer thread is stopped right away
+        drop(guard);
+        assert!(started.elapsed() < BOOST);
+    }
+}
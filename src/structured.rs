@@ -0,0 +1,149 @@
+use tracing::field::{Field, Visit};
+use tracing::Event;
+
+// A value recorded with tracing::field::valuable, as a JSON document
+pub(crate) fn to_json(value: valuable::Value<'_>) -> String {
+    serde_json::to_string(&valuable_serde::Serializable::new(value))
+        .unwrap_or_else(|_| format!("{value:?}"))
+}
+
+// Whether some fields of the event were recorded with tracing::field::valuable
+pub(crate) fn has_structured_fields(event: &Event<'_>) -> bool {
+    struct Detect(bool);
+
+    impl Visit for Detect {
+        fn record_value(&mut self, _field: &Field, _value: valuable::Value<'_>) {
+            self.0 = true;
+        }
+
+        fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+    }
+
+    let mut detect = Detect(false);
+
+    event.record(&mut detect);
+    detect.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+    use tracing::field::valuable;
+    use tracing_subscriber::layer::SubscriberExt;
+    use valuable::Valuable;
+
+    use crate::layers::{self, ConsoleOptions};
+    #[cfg(feature = "server")]
+    use crate::tests::with_env;
+    use crate::tests::MemoryWriter;
+    use crate::LogFormat;
+    #[cfg(feature = "server")]
+    use crate::TracingInit;
+
+    #[derive(Valuable)]
+    struct Item {
+        sku: String,
+        quantity: u32,
+    }
+
+    #[derive(Valuable)]
+    struct Order {
+        id: u64,
+        items: Vec<Item>,
+        attributes: BTreeMap<String, Vec<String>>,
+        coupon: Option<String>,
+    }
+
+    fn order() -> Order {
+        Order {
+            id: 7,
+            items: vec![
+                Item {
+                    sku: String::from("A-1"),
+                    quantity: 2,
+                },
+                Item {
+                    sku: String::from("B-2"),
+                    quantity: 1,
+                },
+            ],
+            attributes: BTreeMap::from([(
+                String::from("channel"),
+                vec![String::from("web"), String::from("mobile")],
+            )]),
+            coupon: None,
+        }
+    }
+
+    fn order_json() -> serde_json::Value {
+        json!({
+            "id": 7,
+            "items": [{"sku": "A-1", "quantity": 2}, {"sku": "B-2", "quantity": 1}],
+            "attributes": {"channel": ["web", "mobile"]},
+            "coupon": null,
+        })
+    }
+
+    #[test]
+    fn test_structured_fields_json() {
+        let console = MemoryWriter::default();
+        let options = ConsoleOptions {
+            format: LogFormat::Json,
+            ..ConsoleOptions::default()
+        };
+        let subscriber = tracing_subscriber::registry()
+            .with(layers::console_with_writer(&options, console.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let order = order();
+
+            tracing::info!(order = valuable(&order), user = "bob", lines = ?[1, 2], "order placed");
+        });
+
+        let record: serde_json::Value = serde_json::from_str(&console.contents()).unwrap();
+        let fields = &record["fields"];
+
+        assert_eq!(fields["order"], order_json(), "{record}");
+        // The other fields are not affected
+        assert_eq!(fields["user"], json!("bob"), "{record}");
+        assert_eq!(fields["lines"], json!("[1, 2]"), "{record}");
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_structured_fields_server() {
+        let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let (dispatch, _guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_console(false)
+                .log_to_server(true)
+                .log_server_address(&address)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        // Wait for the connection before logging
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        tracing::dispatcher::with_default(&dispatch, || {
+            let order = order();
+
+            tracing::info!(order = valuable(&order), count = 3, "order placed");
+        });
+
+        let mut buffer = vec![0; 8192];
+        let len = tokio::time::timeout(std::time::Duration::from_secs(5), server.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        // The GELF messages end with a null byte
+        let record: serde_json::Value = serde_json::from_slice(&buffer[..len - 1]).unwrap();
+        let order: serde_json::Value =
+            serde_json::from_str(record["_order"].as_str().unwrap()).unwrap();
+
+        assert_eq!(order, order_json(), "{record}");
+        assert_eq!(record["_count"], json!(3), "{record}");
+    }
+}
@@ -0,0 +1,280 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+// The size of the blocks read from the end of the file
+const BLOCK_SIZE: u64 = 64 * 1024;
+
+// The last n lines of the current log file. The file is opened on its own, the writer is not blocked: if it rotates
+// to a new file while it is read, the new file is read instead (once)
+pub(crate) fn tail(current_file: impl Fn() -> PathBuf, n: usize) -> std::io::Result<Vec<String>> {
+    let path = current_file();
+    let lines = tail_file(&path, n)?;
+    let rotated = current_file();
+
+    if rotated != path {
+        return tail_file(&rotated, n);
+    }
+
+    Ok(lines)
+}
+
+// The last n complete lines of a file, none if it was not created yet. The line being written, not ended by a
+// newline yet, is left out
+fn tail_file(path: &Path, n: usize) -> std::io::Result<Vec<String>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    // The lines appended from now on are not read
+    let len = file.metadata()?.len();
+
+    if n == 0 || len == 0 {
+        return Ok(Vec::new());
+    }
+
+    // The first newline found ends the last complete line, the one before the n last lines is where they start
+    let mut newlines = 0;
+    let bytes = read_end(&mut file, len, |_, block| {
+        block
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, &b)| b == b'\n')
+            .find_map(|(i, _)| {
+                newlines += 1;
+                (newlines == n + 1).then_some(i + 1)
+            })
+    })?;
+    let end = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+
+    Ok(bytes[..end]
+        .split(|&b| b == b'\n')
+        .take(n.min(newlines))
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect())
+}
+
+// The end of the first len bytes of a file, read in blocks from the end until start_in finds where it starts in a
+// block (given the offset of the block in the file), the whole file if it does not
+pub(crate) fn read_end(
+    file: &mut File,
+    len: u64,
+    mut start_in: impl FnMut(u64, &[u8]) -> Option<usize>,
+) -> std::io::Result<Vec<u8>> {
+    let mut blocks = Vec::new();
+    let mut pos = len;
+    let mut start = None;
+
+    while pos > 0 && start.is_none() {
+        let size = BLOCK_SIZE.min(pos);
+        let mut block = vec![0; size as usize];
+
+        pos -= size;
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut block)?;
+        start = start_in(pos, &block);
+        blocks.push(block);
+    }
+
+    let mut bytes = blocks.into_iter().rev().flatten().collect::<Vec<_>>();
+
+    bytes.drain(..start.unwrap_or(0));
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::tests::{test_log_dir, with_env};
+    use crate::{LogFormat, TracingInit};
+
+    fn write_lines(path: &Path, count: usize) {
+        let mut text = String::new();
+
+        for i in 0..count {
+            text.push_str(&format!("line {i}\n"));
+        }
+
+        std::fs::write(path, text).unwrap();
+    }
+
+    fn lines(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| format!("line {i}")).collect()
+    }
+
+    #[test]
+    fn test_tail_small_files() {
+        let dir = test_log_dir("tail-small");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("App.log");
+
+        // Not created yet
+        assert!(tail_file(&path, 10).unwrap().is_empty());
+
+        std::fs::write(&path, "").unwrap();
+        assert!(tail_file(&path, 10).unwrap().is_empty());
+
+        write_lines(&path, 3);
+        assert_eq!(tail_file(&path, 10).unwrap(), lines(0..3));
+        assert_eq!(tail_file(&path, 2).unwrap(), lines(1..3));
+        assert!(tail_file(&path, 0).unwrap().is_empty());
+
+        // Exactly n lines
+        assert_eq!(tail_file(&path, 3).unwrap(), lines(0..3));
+
+        // The line being written is left out
+        std::fs::write(&path, "line 0\nline 1\nline").unwrap();
+        assert_eq!(tail_file(&path, 3).unwrap(), lines(0..2));
+        std::fs::write(&path, "line").unwrap();
+        assert!(tail_file(&path, 3).unwrap().is_empty());
+
+        // Empty lines are kept
+        std::fs::write(&path, "\n\nline 2\n").unwrap();
+        assert_eq!(tail_file(&path, 3).unwrap(), ["", "", "line 2"]);
+    }
+
+    #[test]
+    fn test_tail_large_file() {
+        let dir = test_log_dir("tail-large");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("App.log");
+
+        // About 4.5 MB, the lines span the blocks
+        write_lines(&path, 400_000);
+        assert!(std::fs::metadata(&path).unwrap().len() > 4_000_000);
+
+        assert_eq!(tail_file(&path, 200).unwrap(), lines(399_800..400_000));
+        assert_eq!(tail_file(&path, 20_000).unwrap(), lines(380_000..400_000));
+        assert_eq!(tail_file(&path, 1_000_000).unwrap(), lines(0..400_000));
+    }
+
+    #[test]
+    fn test_tail_concurrent_writer() {
+        let dir = test_log_dir("tail-concurrent");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("App.log");
+        write_lines(&path, 1000);
+
+        let done = Arc::new(AtomicBool::new(false));
+        let writer = std::thread::spawn({
+            let path = path.clone();
+            let done = done.clone();
+
+            move || {
+                let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+
+                for i in 1000..200_000 {
+                    // A line written in two parts, the tail can see the first one only
+                    write!(file, "line ").unwrap();
+                    writeln!(file, "{i}").unwrap();
+
+                    if done.load(Ordering::Relaxed) {
+                        break;
+                    }
+                }
+            }
+        });
+
+        for _ in 0..200 {
+            let tail = tail_file(&path, 100).unwrap();
+            let first = tail[0]
+                .strip_prefix("line ")
+                .and_then(|i| i.parse::<usize>().ok())
+                .unwrap_or_else(|| panic!("{tail:?}"));
+
+            // Complete consecutive lines
+            assert_eq!(tail, lines(first..first + 100));
+        }
+
+        done.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_tail_rotated() {
+        let dir = test_log_dir("tail-rotated");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (old, new) = (dir.join("App.1.log"), dir.join("App.2.log"));
+        write_lines(&old, 10);
+        std::fs::write(&new, "line 10\n").unwrap();
+
+        // Rotated while the old file was read
+        let calls = std::cell::Cell::new(0);
+        let current_file = || {
+            calls.set(calls.get() + 1);
+
+            if calls.get() == 1 {
+                old.clone()
+            } else {
+                new.clone()
+            }
+        };
+
+        assert_eq!(tail(current_file, 5).unwrap(), lines(10..11));
+        assert_eq!(tail(|| old.clone(), 5).unwrap(), lines(5..10));
+    }
+
+    #[test]
+    fn test_tail_log_file() {
+        let log_dir = test_log_dir("tail-log-file");
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        assert_eq!(
+            guard.config().current_log_file().unwrap(),
+            crate::reopen::current_file_path(&guard.config().file_options())
+        );
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            for i in 0..5 {
+                tracing::info!("record {i}");
+            }
+        });
+
+        let tail = guard.config().tail(2).unwrap();
+
+        assert_eq!(tail.len(), 2);
+        assert!(
+            tail[0].ends_with("INFO tracing_init::tail::tests: record 3"),
+            "{tail:?}"
+        );
+        assert!(
+            tail[1].ends_with("INFO tracing_init::tail::tests: record 4"),
+            "{tail:?}"
+        );
+
+        // Not lines of text
+        let config = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_format(LogFormat::Binary)
+                .resolve()
+                .unwrap()
+        });
+
+        assert_eq!(config.tail(2).unwrap_err().kind(), ErrorKind::Unsupported);
+
+        let config = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_console(true)
+                .resolve()
+                .unwrap()
+        });
+
+        assert_eq!(config.current_log_file(), None);
+        assert_eq!(config.tail(2).unwrap_err().kind(), ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,120 @@
+// The field carrying the id of the tokio task, added to the records of the events logged by a task
+pub(crate) const TASK_ID: &str = "task_id";
+
+// The id of the task being polled by this thread, None outside of a task (including block_on) or of a runtime. A
+// thread local read
+#[cfg(feature = "task-ids")]
+pub(crate) fn current() -> Option<u64> {
+    // The number is only exposed by Display
+    tokio::task::try_id().and_then(|id| id.to_string().parse().ok())
+}
+
+// Without the task-ids feature the events are never logged with a task id
+#[cfg(not(feature = "task-ids"))]
+pub(crate) fn current() -> Option<u64> {
+    None
+}
+
+#[cfg(all(test, feature = "task-ids"))]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::layers::{self, ConsoleOptions};
+    #[cfg(feature = "server")]
+    use crate::tests::with_env;
+    use crate::tests::MemoryWriter;
+    use crate::LogFormat;
+    #[cfg(feature = "server")]
+    use crate::TracingInit;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_task_ids_console() {
+        let console = MemoryWriter::default();
+        let options = ConsoleOptions {
+            format: LogFormat::Json,
+            task_ids: true,
+            ..ConsoleOptions::default()
+        };
+        let dispatch = tracing::Dispatch::new(
+            tracing_subscriber::registry()
+                .with(layers::console_with_writer(&options, console.clone())),
+        );
+        let spawn = |name: &'static str| {
+            let dispatch = dispatch.clone();
+
+            tokio::spawn(async move {
+                tracing::dispatcher::with_default(&dispatch, || tracing::info!(name, "in task"));
+                current()
+            })
+        };
+
+        // Both tasks are alive, so their ids differ
+        let (first, second) = tokio::join!(spawn("first"), spawn("second"));
+        let ids = [first.unwrap().unwrap(), second.unwrap().unwrap()];
+
+        // Not in a task: the test's future is run by block_on
+        tracing::dispatcher::with_default(&dispatch, || tracing::info!("outside of a task"));
+        std::thread::spawn(move || {
+            tracing::dispatcher::with_default(&dispatch, || tracing::info!("outside of a runtime"))
+        })
+        .join()
+        .unwrap();
+
+        let records = console
+            .contents()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let task_id = |name: &str| {
+            records
+                .iter()
+                .find(|record| record["fields"]["name"] == name)
+                .and_then(|record| record["fields"][TASK_ID].as_u64())
+        };
+
+        assert_eq!(records.len(), 4);
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(task_id("first"), Some(ids[0]));
+        assert_eq!(task_id("second"), Some(ids[1]));
+        assert!(records[2..]
+            .iter()
+            .all(|record| record["fields"].get(TASK_ID).is_none()));
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_task_ids_server() {
+        let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let (dispatch, _guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_console(false)
+                .log_to_server(true)
+                .log_server_address(&address)
+                .task_ids(true)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        // Wait for the connection before logging
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        let id = tokio::spawn(async move {
+            tracing::dispatcher::with_default(&dispatch, || tracing::info!("in task"));
+            current().unwrap()
+        })
+        .await
+        .unwrap();
+
+        let mut buffer = vec![0; 8192];
+        let len = tokio::time::timeout(std::time::Duration::from_secs(5), server.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        let record = String::from_utf8_lossy(&buffer[..len]);
+
+        assert!(record.contains(&format!(r#""_task_id":{id}"#)), "{record}");
+    }
+}
@@ -0,0 +1,526 @@
+//! Test utilities (requires the `test-util` feature)
+//!
+//! [`MockGelfServer`] receives the messages sent by the log server layer, so a test can check what was logged
+//! without a Graylog server.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+// The receiving threads check for the server being stopped this often
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// The first bytes of a chunk of a chunked GELF message
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+// The magic bytes, the message id, the sequence number and the sequence count
+const CHUNK_HEADER_LENGTH: usize = 12;
+
+/// A GELF message received by [`MockGelfServer`]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct GelfMessage {
+    /// The syslog severity, 3 for ERROR to 7 for TRACE (1 if the message has no level, as in the GELF specification)
+    pub level: u8,
+    pub short_message: String,
+    pub full_message: Option<String>,
+    pub host: String,
+    /// The seconds since the epoch
+    pub timestamp: Option<f64>,
+    /// The additional fields without their `_` prefix (e.g. `app`), the values other than strings are in JSON
+    pub additional_fields: HashMap<String, String>,
+}
+
+impl GelfMessage {
+    /// The value of an additional field, the name is given without the `_` prefix
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.additional_fields.get(name).map(String::as_str)
+    }
+
+    /// The tracing level of the syslog severity (None for the severities tracing does not log)
+    pub fn tracing_level(&self) -> Option<tracing::Level> {
+        match self.level {
+            3 => Some(tracing::Level::ERROR),
+            4 => Some(tracing::Level::WARN),
+            5 => Some(tracing::Level::INFO),
+            6 => Some(tracing::Level::DEBUG),
+            7 => Some(tracing::Level::TRACE),
+            _ => None,
+        }
+    }
+
+    // A message as sent over UDP (plain, gzip or zlib compressed JSON) or TCP (plain JSON). The null bytes ending
+    // the messages (the GELF frame delimiter, tracing-gelf sends it over UDP as well) are not part of the JSON
+    fn parse(payload: &[u8]) -> Option<GelfMessage> {
+        let json = decompress(payload)?;
+        let end = json
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |last| last + 1);
+        let Value::Object(object) = serde_json::from_slice::<Value>(&json[..end]).ok()? else {
+            return None;
+        };
+
+        let string = |key: &str| object.get(key).and_then(Value::as_str).map(String::from);
+
+        Some(GelfMessage {
+            level: object
+                .get("level")
+                .and_then(Value::as_u64)
+                .map_or(1, |level| level as u8),
+            short_message: string("short_message")?,
+            full_message: string("full_message"),
+            host: string("host").unwrap_or_default(),
+            timestamp: object.get("timestamp").and_then(Value::as_f64),
+            additional_fields: object
+                .iter()
+                .filter_map(|(key, value)| {
+                    let value = match value {
+                        Value::String(value) => value.clone(),
+                        value => value.to_string(),
+                    };
+
+                    Some((key.strip_prefix('_')?.to_string(), value))
+                })
+                .collect(),
+        })
+    }
+}
+
+/// A log server receiving GELF messages on a local port, for tests
+///
+/// It listens on an ephemeral UDP port of 127.0.0.1 (and on a TCP port when started by
+/// [`start_with_tcp`](Self::start_with_tcp)), reassembles the chunked messages, decompresses them and keeps them in
+/// the order they were received. The server is stopped when dropped.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use tracing_init::test::MockGelfServer;
+/// use tracing_init::TracingInit;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let server = MockGelfServer::start();
+///     let _guard = TracingInit::builder("App")
+///         .log_to_server(true)
+///         .log_server_address(&server.address())
+///         .init()
+///         .unwrap();
+///
+///     tracing::info!("started");
+///     assert!(server
+///         .wait_for(|message| message.short_message == "started", Duration::from_secs(5))
+///         .is_some());
+/// }
+/// ```
+pub struct MockGelfServer {
+    udp_address: SocketAddr,
+    tcp_address: Option<SocketAddr>,
+    received: Arc<Received>,
+    stopped: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Received {
+    messages: Mutex<Vec<GelfMessage>>,
+    arrived: Condvar,
+}
+
+impl Received {
+    fn push(&self, payload: &[u8]) {
+        if let Some(message) = GelfMessage::parse(payload) {
+            self.messages
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(message);
+            self.arrived.notify_all();
+        }
+    }
+}
+
+impl MockGelfServer {
+    /// Listen on an ephemeral UDP port
+    ///
+    /// # Panics
+    /// If the port cannot be bound
+    pub fn start() -> MockGelfServer {
+        MockGelfServer::start_server(false)
+    }
+
+    /// Listen on an ephemeral UDP port and an ephemeral TCP port
+    ///
+    /// # Panics
+    /// If the ports cannot be bound
+    pub fn start_with_tcp() -> MockGelfServer {
+        MockGelfServer::start_server(true)
+    }
+
+    fn start_server(tcp: bool) -> MockGelfServer {
+        let received = Arc::new(Received::default());
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let socket = UdpSocket::bind("127.0.0.1:0")
+            .expect("cannot bind the UDP port of the mock GELF server");
+        socket
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .expect("cannot set the read timeout of the mock GELF server");
+        let udp_address = socket.local_addr().expect("no UDP port");
+
+        let mut threads = vec![spawn("tracing-init-mock-gelf-udp", {
+            let received = received.clone();
+            let stopped = stopped.clone();
+
+            move || receive_udp(socket, &received, &stopped)
+        })];
+
+        let tcp_address = tcp.then(|| {
+            let listener = TcpListener::bind("127.0.0.1:0")
+                .expect("cannot bind the TCP port of the mock GELF server");
+            listener
+                .set_nonblocking(true)
+                .expect("cannot set the TCP port of the mock GELF server to non-blocking");
+            let tcp_address = listener.local_addr().expect("no TCP port");
+            let received = received.clone();
+            let stopped = stopped.clone();
+
+            threads.push(spawn("tracing-init-mock-gelf-tcp", move || {
+                accept_tcp(listener, &received, &stopped)
+            }));
+            tcp_address
+        });
+
+        MockGelfServer {
+            udp_address,
+            tcp_address,
+            received,
+            stopped,
+            threads,
+        }
+    }
+
+    /// The UDP address, for [`log_server_address`](crate::TracingInit::log_server_address)
+    pub fn address(&self) -> String {
+        self.udp_address.to_string()
+    }
+
+    /// The TCP address, None unless started by [`start_with_tcp`](Self::start_with_tcp)
+    pub fn tcp_address(&self) -> Option<String> {
+        self.tcp_address.map(|address| address.to_string())
+    }
+
+    /// The messages received so far, in the order they were received
+    pub fn messages(&self) -> Vec<GelfMessage> {
+        self.lock().clone()
+    }
+
+    /// Wait until a message for which the predicate returns true is received, None if none is within the timeout
+    ///
+    /// The messages received before the call are considered as well.
+    pub fn wait_for(
+        &self,
+        predicate: impl Fn(&GelfMessage) -> bool,
+        timeout: Duration,
+    ) -> Option<GelfMessage> {
+        let deadline = Instant::now() + timeout;
+        let mut messages = self.lock();
+
+        loop {
+            if let Some(message) = messages.iter().find(|message| predicate(message)) {
+                return Some(message.clone());
+            }
+
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+
+            messages = self
+                .received
+                .arrived
+                .wait_timeout(messages, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<GelfMessage>> {
+        self.received
+            .messages
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for MockGelfServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl std::fmt::Debug for MockGelfServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockGelfServer")
+            .field("udp_address", &self.udp_address)
+            .field("tcp_address", &self.tcp_address)
+            .finish_non_exhaustive()
+    }
+}
+
+fn spawn(name: &str, f: impl FnOnce() + Send + 'static) -> JoinHandle<()> {
+    std::thread::Builder::new()
+        .name(String::from(name))
+        .spawn(f)
+        .expect("cannot start the thread of the mock GELF server")
+}
+
+fn receive_udp(socket: UdpSocket, received: &Received, stopped: &AtomicBool) {
+    let mut buffer = vec![0; 65536];
+    let mut chunked = Chunked::default();
+
+    while !stopped.load(Ordering::Acquire) {
+        let Ok(length) = socket.recv(&mut buffer) else {
+            continue;
+        };
+        let datagram = &buffer[..length];
+
+        if datagram.starts_with(&CHUNK_MAGIC) {
+            if let Some(payload) = chunked.add(datagram) {
+                received.push(&payload);
+            }
+        } else {
+            received.push(datagram);
+        }
+    }
+}
+
+fn accept_tcp(listener: TcpListener, received: &Arc<Received>, stopped: &Arc<AtomicBool>) {
+    let mut connections = Vec::new();
+
+    while !stopped.load(Ordering::Acquire) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let received = received.clone();
+                let stopped = stopped.clone();
+
+                connections.push(spawn("tracing-init-mock-gelf-connection", move || {
+                    receive_tcp(stream, &received, &stopped)
+                }));
+            }
+            Err(_) => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+
+    for connection in connections {
+        let _ = connection.join();
+    }
+}
+
+// The messages are terminated by a null byte
+fn receive_tcp(stream: TcpStream, received: &Received, stopped: &AtomicBool) {
+    if stream.set_nonblocking(false).is_err()
+        || stream.set_read_timeout(Some(POLL_INTERVAL)).is_err()
+    {
+        return;
+    }
+
+    let mut reader = BufReader::new(stream);
+    let mut message = Vec::new();
+
+    while !stopped.load(Ordering::Acquire) {
+        match reader.read_until(0, &mut message) {
+            // Closed by the client
+            Ok(0) => return,
+            Ok(_) if message.last() == Some(&0) => {
+                received.push(&message[..message.len() - 1]);
+                message.clear();
+            }
+            // A partial message read before the timeout, the rest is appended by the next read
+            _ => {}
+        }
+    }
+}
+
+// The chunks of the messages not received entirely yet, by message id
+#[derive(Default)]
+struct Chunked {
+    messages: HashMap<[u8; 8], Vec<Option<Vec<u8>>>>,
+}
+
+impl Chunked {
+    // The reassembled message once its last chunk was received
+    fn add(&mut self, chunk: &[u8]) -> Option<Vec<u8>> {
+        if chunk.len() < CHUNK_HEADER_LENGTH {
+            return None;
+        }
+
+        let id: [u8; 8] = chunk[2..10].try_into().ok()?;
+        let (sequence, count) = (usize::from(chunk[10]), usize::from(chunk[11]));
+
+        if sequence >= count {
+            return None;
+        }
+
+        let chunks = self.messages.entry(id).or_insert_with(|| vec![None; count]);
+
+        if chunks.len() != count {
+            return None;
+        }
+
+        chunks[sequence] = Some(chunk[CHUNK_HEADER_LENGTH..].to_vec());
+
+        if chunks.iter().all(Option::is_some) {
+            self.messages
+                .remove(&id)
+                .map(|chunks| chunks.into_iter().flatten().flatten().collect())
+        } else {
+            None
+        }
+    }
+}
+
+// The JSON of a plain, gzip or zlib compressed message
+fn decompress(payload: &[u8]) -> Option<Vec<u8>> {
+    let mut json = Vec::new();
+
+    match payload {
+        [0x1f, 0x8b, ..] => flate2::read::GzDecoder::new(payload)
+            .read_to_end(&mut json)
+            .ok()?,
+        [0x78, ..] => flate2::read::ZlibDecoder::new(payload)
+            .read_to_end(&mut json)
+            .ok()?,
+        _ => return Some(payload.to_vec()),
+    };
+
+    Some(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const MESSAGE: &str = r#"{"version":"1.1","host":"web-1","short_message":"disk full","level":3,"timestamp":1792108800.5,"_app":"App","_free_mb":12}"#;
+
+    #[test]
+    fn test_parse_message() {
+        let message = GelfMessage::parse(MESSAGE.as_bytes()).unwrap();
+
+        assert_eq!(message.level, 3);
+        assert_eq!(message.tracing_level(), Some(tracing::Level::ERROR));
+        assert_eq!(message.short_message, "disk full");
+        assert_eq!(message.host, "web-1");
+        assert_eq!(message.timestamp, Some(1_792_108_800.5));
+        assert_eq!(message.field("app"), Some("App"));
+        assert_eq!(message.field("free_mb"), Some("12"));
+        assert_eq!(message.field("version"), None);
+
+        assert_eq!(GelfMessage::parse(b"not json"), None);
+        assert_eq!(GelfMessage::parse(br#"{"host":"web-1"}"#), None);
+    }
+
+    // A datagram sent by the log server layer, as it is on the wire
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_parse_gelf_layer_message() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let options = crate::layers::ServerOptions {
+            address: socket.local_addr().unwrap().to_string(),
+            app_name: String::from("App"),
+            ..Default::default()
+        };
+        let (layer, _guard) = crate::layers::gelf(&options).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        // Wait for the connection before logging
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        tracing::subscriber::with_default(subscriber, || tracing::warn!(free_mb = 12, "disk full"));
+
+        let mut buffer = vec![0; 8192];
+        let length = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        let message = GelfMessage::parse(&buffer[..length]).expect("the message was not parsed");
+
+        assert_eq!(message.tracing_level(), Some(tracing::Level::WARN));
+        assert_eq!(message.short_message, "disk full");
+        assert_eq!(message.field("app"), Some("App"));
+        assert_eq!(message.field("free_mb"), Some("12"));
+    }
+
+    #[test]
+    fn test_compressed_and_chunked_messages() {
+        let server = MockGelfServer::start();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let send = |datagram: &[u8]| socket.send_to(datagram, server.address()).unwrap();
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(MESSAGE.replace("disk full", "gzip").as_bytes())
+            .unwrap();
+        send(&gzip.finish().unwrap());
+
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(MESSAGE.replace("disk full", "zlib").as_bytes())
+            .unwrap();
+        let zlib = zlib.finish().unwrap();
+
+        // Out of order chunks of the compressed message
+        let (first, second) = zlib.split_at(zlib.len() / 2);
+        let chunk = |sequence: u8, payload: &[u8]| {
+            [&CHUNK_MAGIC[..], &[7; 8], &[sequence, 2], payload].concat()
+        };
+        send(&chunk(1, second));
+        send(MESSAGE.as_bytes());
+        send(&chunk(0, first));
+
+        server
+            .wait_for(
+                |message| message.short_message == "zlib",
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        let messages = server
+            .messages()
+            .into_iter()
+            .map(|message| message.short_message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["gzip", "disk full", "zlib"]);
+    }
+
+    #[test]
+    fn test_tcp_messages() {
+        let server = MockGelfServer::start_with_tcp();
+        let mut stream = TcpStream::connect(server.tcp_address().unwrap()).unwrap();
+
+        stream
+            .write_all(
+                format!("{MESSAGE}\0{}\0", MESSAGE.replace("disk full", "second")).as_bytes(),
+            )
+            .unwrap();
+
+        assert!(server
+            .wait_for(
+                |message| message.short_message == "second",
+                Duration::from_secs(5)
+            )
+            .is_some());
+        assert_eq!(server.messages().len(), 2);
+        assert!(server
+            .wait_for(
+                |message| message.short_message == "third",
+                Duration::from_millis(100)
+            )
+            .is_none());
+    }
+}
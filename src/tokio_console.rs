@@ -0,0 +1,169 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use tracing::{Level, Metadata};
+use tracing_subscriber::layer::{Context, Filter};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::TracingInitError;
+
+// Directives enabling the runtime instrumentation needed by the console, added to the filter
+pub(crate) const DIRECTIVES: &[&str] = &["tokio=trace", "runtime=trace"];
+
+// The address the console server binds to when neither the builder nor TOKIO_CONSOLE_BIND set it
+pub(crate) fn default_address() -> String {
+    format!(
+        "{}:{}",
+        console_subscriber::Server::DEFAULT_IP,
+        console_subscriber::Server::DEFAULT_PORT
+    )
+}
+
+// The console layer cannot be built (it panics) unless tokio is instrumented
+pub(crate) fn check_tokio_unstable() -> Result<(), TracingInitError> {
+    if cfg!(any(tokio_unstable, console_without_tokio_unstable)) {
+        Ok(())
+    } else {
+        Err(TracingInitError::TokioUnstableRequired)
+    }
+}
+
+pub(crate) fn check_address(address: &str) -> Result<(), TracingInitError> {
+    match resolve_address(address) {
+        Some(_) => Ok(()),
+        None => Err(TracingInitError::InvalidTokioConsoleAddress {
+            address: address.to_string(),
+        }),
+    }
+}
+
+fn resolve_address(address: &str) -> Option<SocketAddr> {
+    address.to_socket_addrs().ok()?.next()
+}
+
+// The console layer, spawns the thread serving the console clients. The address must be valid (see check_address)
+pub(crate) fn layer<S>(address: &str) -> impl Layer<S> + Send + Sync + 'static
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    let address = resolve_address(address)
+        .expect("the tokio-console address is checked when the configuration is validated");
+
+    // The other TOKIO_CONSOLE_* variables (retention, publish interval...) are applied as well
+    console_subscriber::ConsoleLayer::builder()
+        .with_default_env()
+        .server_addr(address)
+        .spawn()
+}
+
+// Keeps the TRACE records of the tokio and runtime targets, enabled for the console, away from the log destinations
+pub(crate) struct ExcludeInstrumentation;
+
+impl<S> Filter<S> for ExcludeInstrumentation {
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: &Context<'_, S>) -> bool {
+        !is_instrumentation(metadata)
+    }
+}
+
+fn is_instrumentation(metadata: &Metadata<'_>) -> bool {
+    *metadata.level() == Level::TRACE
+        && (metadata.target().starts_with("tokio") || metadata.target().starts_with("runtime"))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "file")]
+    use crate::tests::test_log_dir;
+    use crate::tests::with_env;
+    use crate::{ResolvedConfig, TracingInit, TracingInitError};
+    #[cfg(feature = "file")]
+    use tracing::level_filters::LevelFilter;
+
+    #[cfg(feature = "file")]
+    #[test]
+    #[cfg_attr(not(tokio_unstable), ignore = "requires --cfg tokio_unstable")]
+    fn test_tokio_console() {
+        let log_dir = test_log_dir("tokio-console");
+        let (dispatch, guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .log_to_file(true)
+                .log_file_path(log_dir.to_str().unwrap())
+                .log_file_rotation(crate::Rotation::Never)
+                .with_tokio_console(true)
+                .tokio_console_addr("127.0.0.1:0".parse().unwrap())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        assert!(guard.to_string().contains("tokio-console on 127.0.0.1:0"));
+
+        let filter_handle = guard.filter_handle().unwrap();
+        assert!(filter_handle.current().contains("tokio=trace"));
+        assert!(filter_handle.current().contains("runtime=trace"));
+
+        // Replacing the filter keeps the console directives
+        filter_handle.set_filter("warn").unwrap();
+        filter_handle.set_level(LevelFilter::INFO).unwrap();
+        let mut directives = filter_handle
+            .current()
+            .split(',')
+            .map(String::from)
+            .collect::<Vec<_>>();
+        directives.sort();
+        assert_eq!(directives, ["info", "runtime=trace", "tokio=trace"]);
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::trace!(target: "runtime::resource::poll_op", "for the console only");
+            tracing::info!(target: "runtime", "written to the log file");
+        });
+
+        drop(guard);
+
+        let log = std::fs::read_to_string(log_dir.join("App.log")).unwrap();
+        assert!(!log.contains("for the console only"));
+        assert!(log.contains("written to the log file"));
+    }
+
+    #[test]
+    fn test_tokio_console_address() {
+        let config = with_env(&[("TOKIO_CONSOLE_BIND", "127.0.0.1:7000")], || {
+            let mut t = TracingInit::builder("App");
+
+            t.log_to_console(true)
+                .with_tokio_console(true)
+                .set_from_environment_variables();
+            ResolvedConfig::new(&t)
+        });
+
+        assert_eq!(config.tokio_console_address(), Some("127.0.0.1:7000"));
+        assert!(config.to_string().contains("tokio-console: 127.0.0.1:7000"));
+
+        let mut problems = with_env(&[("TOKIO_CONSOLE_BIND", "console")], || {
+            TracingInit::builder("App")
+                .log_to_console(true)
+                .with_tokio_console(true)
+                .validate()
+                .unwrap_err()
+        });
+
+        problems.retain(|problem| !matches!(problem, TracingInitError::TokioUnstableRequired));
+        assert!(matches!(
+            &problems[..],
+            [TracingInitError::InvalidTokioConsoleAddress { address }] if address == "console"
+        ));
+    }
+
+    #[test]
+    #[cfg(not(tokio_unstable))]
+    fn test_tokio_unstable_required() {
+        let result = TracingInit::builder("App")
+            .log_to_console(true)
+            .with_tokio_console(true)
+            .into_dispatch();
+
+        assert!(matches!(
+            result,
+            Err(TracingInitError::TokioUnstableRequired)
+        ));
+    }
+}
@@ -0,0 +1,443 @@
+use std::fmt::{Debug, Write as _};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+use tracing::dispatcher::WeakDispatch;
+use tracing::field::{Field, Visit};
+use tracing::{Dispatch, Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+use crate::clock::ClockSource;
+use crate::preinit::format_time;
+use crate::{json_string, Clock, TracingInitError};
+
+// The target of the delivery failure reports, the layer ignores its events so a failure cannot trigger a notification
+const TARGET: &str = "tracing_init::webhook";
+
+// How long a single notification may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The settings of alert_webhook
+#[derive(Debug, Clone)]
+pub(crate) struct WebhookConfig {
+    pub(crate) url: String,
+    pub(crate) min_level: Level,
+    pub(crate) rate_limit: Duration,
+}
+
+// A notification waiting for the delivery thread
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Alert {
+    level: Level,
+    target: String,
+    message: String,
+    timestamp: String,
+}
+
+// The first qualifying event since the last notification and the number of qualifying events after it
+#[derive(Default)]
+struct Pending {
+    alert: Option<Alert>,
+    suppressed: u64,
+    stopping: bool,
+    // Set by the delivery thread when it exits
+    stopped: bool,
+}
+
+struct Shared {
+    pending: Mutex<Pending>,
+    changed: Condvar,
+    // The dispatch the delivery failures are logged to, the global default one if not set
+    dispatch: OnceLock<WeakDispatch>,
+}
+
+impl Shared {
+    fn pending(&self) -> std::sync::MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Owns the delivery thread, dropping it sends the pending notification (bounded by the shutdown timeout) and stops
+// the thread
+pub(crate) struct Webhook {
+    shared: Arc<Shared>,
+    shutdown_timeout: Duration,
+}
+
+impl Webhook {
+    pub(crate) fn start(
+        config: &WebhookConfig,
+        app_name: &str,
+        shutdown_timeout: Duration,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> Result<(Webhook, WebhookLayer), TracingInitError> {
+        if !(config.url.starts_with("http://") || config.url.starts_with("https://")) {
+            return Err(TracingInitError::InvalidWebhookUrl {
+                url: config.url.clone(),
+            });
+        }
+
+        let shared = Arc::new(Shared {
+            pending: Mutex::default(),
+            changed: Condvar::new(),
+            dispatch: OnceLock::new(),
+        });
+        let sender = Sender {
+            shared: shared.clone(),
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            url: config.url.clone(),
+            app_name: app_name.to_string(),
+            hostname: hostname(),
+            min_level: config.min_level,
+            rate_limit: config.rate_limit,
+        };
+
+        std::thread::Builder::new()
+            .name(String::from("tracing-init-webhook"))
+            .spawn(move || {
+                sender.run();
+                sender.shared.pending().stopped = true;
+                sender.shared.changed.notify_all();
+            })
+            .map_err(TracingInitError::WebhookThread)?;
+
+        Ok((
+            Webhook {
+                shared: shared.clone(),
+                shutdown_timeout,
+            },
+            WebhookLayer {
+                shared,
+                min_level: config.min_level,
+                clock: ClockSource::new(clock),
+            },
+        ))
+    }
+}
+
+impl Webhook {
+    // Log the delivery failures to this dispatch rather than to the global default one
+    pub(crate) fn set_dispatch(&self, dispatch: &Dispatch) {
+        let _ = self.shared.dispatch.set(dispatch.downgrade());
+    }
+}
+
+impl Drop for Webhook {
+    fn drop(&mut self) {
+        self.shared.pending().stopping = true;
+        self.shared.changed.notify_all();
+
+        let _ = self.shared.changed.wait_timeout_while(
+            self.shared.pending(),
+            self.shutdown_timeout,
+            |pending| !pending.stopped,
+        );
+    }
+}
+
+// Queues the qualifying events for the delivery thread and reports its failures
+pub(crate) struct WebhookLayer {
+    shared: Arc<Shared>,
+    min_level: Level,
+    clock: ClockSource,
+}
+
+impl<S: Subscriber> Layer<S> for WebhookLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+
+        if metadata.target() == TARGET {
+            return;
+        }
+
+        if *metadata.level() > self.min_level {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let mut pending = self.shared.pending();
+
+        if pending.alert.is_none() {
+            pending.alert = Some(Alert {
+                level: *metadata.level(),
+                target: metadata.target().to_string(),
+                message: visitor.0,
+                timestamp: format_time(self.clock.now()),
+            });
+            self.shared.changed.notify_all();
+        } else {
+            pending.suppressed += 1;
+        }
+    }
+}
+
+// Sends the notifications, at most one per rate limit window
+struct Sender {
+    shared: Arc<Shared>,
+    agent: ureq::Agent,
+    url: String,
+    app_name: String,
+    hostname: String,
+    min_level: Level,
+    rate_limit: Duration,
+}
+
+impl Sender {
+    fn run(&self) {
+        loop {
+            let (alert, suppressed, stopping) = {
+                let mut pending = self
+                    .shared
+                    .changed
+                    .wait_while(self.shared.pending(), |pending| {
+                        pending.alert.is_none() && !pending.stopping
+                    })
+                    .unwrap_or_else(PoisonError::into_inner);
+
+                (
+                    pending.alert.take(),
+                    std::mem::take(&mut pending.suppressed),
+                    pending.stopping,
+                )
+            };
+
+            if let Some(alert) = alert {
+                if let Err(e) = self.send(&alert, suppressed) {
+                    self.report(&e);
+                }
+            }
+
+            if stopping {
+                return;
+            }
+
+            // The events of the window are counted, the first one is sent when it ends
+            let _ = self
+                .shared
+                .changed
+                .wait_timeout_while(self.shared.pending(), self.rate_limit, |pending| {
+                    !pending.stopping
+                })
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn report(&self, error: &ureq::Error) {
+        let warn =
+            || tracing::warn!(target: TARGET, %error, "failed to send the webhook notification");
+
+        match self.shared.dispatch.get().map(WeakDispatch::upgrade) {
+            Some(Some(dispatch)) => tracing::dispatcher::with_default(&dispatch, warn),
+            // The subscriber was dropped
+            Some(None) => {}
+            None => warn(),
+        }
+    }
+
+    fn send(&self, alert: &Alert, suppressed: u64) -> Result<(), Box<ureq::Error>> {
+        self.agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&self.payload(alert, suppressed))
+            .map(|_| ())
+            .map_err(Box::new)
+    }
+
+    // The text field is what Slack (and compatible chat webhooks) display
+    fn payload(&self, alert: &Alert, suppressed: u64) -> String {
+        let mut text = format!(
+            "[{}] {} {}: {}",
+            self.app_name, alert.level, alert.target, alert.message
+        );
+
+        if suppressed > 0 {
+            let kind = if self.min_level == Level::ERROR {
+                "errors"
+            } else {
+                "events"
+            };
+
+            let _ = write!(text, " (…and {suppressed} more {kind} suppressed)");
+        }
+
+        format!(
+            "{{\"text\":{},\"app\":{},\"level\":\"{}\",\"message\":{},\"target\":{},\"timestamp\":\"{}\",\"hostname\":{},\"suppressed\":{suppressed}}}",
+            json_string(&text),
+            json_string(&self.app_name),
+            alert.level,
+            json_string(&alert.message),
+            json_string(&alert.target),
+            alert.timestamp,
+            json_string(&self.hostname),
+        )
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_string();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+// The host name from the environment or the kernel, without adding a dependency for it
+fn hostname() -> String {
+    crate::env_var("HOSTNAME")
+        .or_else(|| crate::env_var("COMPUTERNAME"))
+        .or_else(|| {
+            std::fs::read_to_string("/proc/sys/kernel/hostname")
+                .ok()
+                .map(|name| name.trim().to_string())
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{with_env, CaptureLayer};
+    use crate::{ManualClock, TracingInit};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tracing::Level;
+
+    // Accepts the notifications and sends back their bodies
+    fn listener() -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut content_length = 0;
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+
+                    if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    } else if line == "\r\n" {
+                        break;
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                reader
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .unwrap();
+                sender.send(String::from_utf8(body).unwrap()).unwrap();
+            }
+        });
+
+        (url, receiver)
+    }
+
+    #[test]
+    fn test_alert_webhook() {
+        let (url, notifications) = listener();
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_792_108_800));
+        let (dispatch, guard) = with_env(&[("HOSTNAME", "web-1")], || {
+            TracingInit::builder("App")
+                .alert_webhook(&url, Level::ERROR, Duration::from_secs(3600))
+                .with_clock(clock.clone())
+                .allow_silent(true)
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            tracing::error!("disk \"data\" is full");
+            tracing::warn!("not an alert");
+        });
+
+        assert_eq!(
+            notifications.recv_timeout(Duration::from_secs(5)).unwrap(),
+            format!(
+                "{{\"text\":\"[App] ERROR {target}: disk \\\"data\\\" is full\",\"app\":\"App\",\"level\":\"ERROR\",\"message\":\"disk \\\"data\\\" is full\",\"target\":\"{target}\",\"timestamp\":\"2026-10-16T00:00:00.000Z\",\"hostname\":\"web-1\",\"suppressed\":0}}",
+                target = module_path!()
+            )
+        );
+
+        // Within the window the events are counted, the first one is sent with the count when the guard is dropped
+        tracing::dispatcher::with_default(&dispatch, || {
+            for n in 1..=15 {
+                tracing::error!("request {n} failed");
+            }
+        });
+        assert!(notifications
+            .recv_timeout(Duration::from_millis(200))
+            .is_err());
+
+        drop(guard);
+        let rollup = notifications.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(
+            rollup.contains("request 1 failed (…and 14 more errors suppressed)\""),
+            "{rollup}"
+        );
+        assert!(rollup.ends_with("\"suppressed\":14}"), "{rollup}");
+    }
+
+    #[test]
+    fn test_alert_webhook_failure() {
+        // Nothing listens on the port
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/hook", listener.local_addr().unwrap())
+        };
+        let capture = CaptureLayer::default();
+        let (dispatch, _guard) = with_env(&[], || {
+            TracingInit::builder("App")
+                .alert_webhook(&url, Level::WARN, Duration::from_millis(10))
+                .with_layer(capture.clone())
+                .into_dispatch()
+                .unwrap()
+        });
+
+        tracing::dispatcher::with_default(&dispatch, || tracing::error!("first"));
+        std::thread::sleep(Duration::from_millis(500));
+
+        // The failure is reported once, at the WARN level, without triggering another notification
+        let failures = capture
+            .events()
+            .into_iter()
+            .filter(|(_, target, _)| target == "tracing_init::webhook")
+            .collect::<Vec<_>>();
+        assert_eq!(failures.len(), 1, "{failures:?}");
+        assert_eq!(failures[0].0, Level::WARN);
+        assert_eq!(failures[0].2, "failed to send the webhook notification");
+    }
+
+    #[test]
+    fn test_alert_webhook_invalid_url() {
+        let error = with_env(&[], || {
+            TracingInit::builder("App")
+                .alert_webhook("hooks.slack.com/services/x", Level::ERROR, Duration::ZERO)
+                .into_dispatch()
+                .unwrap_err()
+        });
+
+        assert_eq!(
+            error.to_string(),
+            "invalid webhook URL 'hooks.slack.com/services/x' (expected http://... or https://...)"
+        );
+    }
+}
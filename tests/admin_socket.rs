@@ -0,0 +1,77 @@
+#![cfg(all(unix, feature = "admin-socket", feature = "file"))]
+
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use tracing::Level;
+use tracing_init::TracingInit;
+
+fn request(socket: &Path, method: &str, path: &str, body: &str) -> (String, String) {
+    let mut stream = UnixStream::connect(socket).unwrap();
+
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.lines().next().unwrap().to_string();
+
+    (status, body.trim().to_string())
+}
+
+#[test]
+fn test_admin_socket() {
+    let dir = std::env::temp_dir().join(format!("tracing-init-admin-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let socket = dir.join("admin.sock");
+    let guard = TracingInit::builder("App")
+        .log_to_file(true)
+        .log_file_path(dir.to_str().unwrap())
+        .log_file_rotation(tracing_init::Rotation::Never)
+        .level(Level::INFO)
+        .admin_socket(socket.to_str().unwrap())
+        .init()
+        .unwrap();
+
+    assert!(socket.exists());
+    assert_eq!(
+        request(&socket, "GET", "/level", ""),
+        (String::from("HTTP/1.1 200 OK"), String::from("info"))
+    );
+
+    tracing::debug!("before changing the level");
+
+    let (status, body) = request(&socket, "PUT", "/level", "app=loud");
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+    assert!(body.contains("invalid filter"));
+
+    let (status, body) = request(&socket, "PUT", "/level", "debug\n");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(body, "debug");
+
+    tracing::debug!("after changing the level");
+
+    let (status, body) = request(&socket, "GET", "/config", "");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(body.contains("file: "));
+    assert!(!body.contains("file: disabled"));
+
+    let (status, _) = request(&socket, "DELETE", "/level", "");
+    assert_eq!(status, "HTTP/1.1 405 Method Not Allowed");
+
+    drop(guard);
+    assert!(!socket.exists());
+
+    let log = std::fs::read_to_string(dir.join("App.log")).unwrap();
+    assert!(!log.contains("before changing the level"));
+    assert!(log.contains("after changing the level"));
+}
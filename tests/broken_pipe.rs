@@ -0,0 +1,66 @@
+#![cfg(all(unix, feature = "file"))]
+
+use tracing_init::{Rotation, TracingInit};
+
+#[test]
+fn test_console_broken_pipe() {
+    let dir = std::env::temp_dir().join(format!("tracing-init-broken-pipe-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let (dispatch, guard) = TracingInit::builder("App")
+        .log_to_console(true)
+        .log_to_file(true)
+        .log_file_path(dir.to_str().unwrap())
+        .log_file_rotation(Rotation::Never)
+        .into_dispatch()
+        .unwrap();
+
+    // Replace stdout by a pipe whose read end is closed, as when piped into head once it exited (SIGPIPE is ignored
+    // by the Rust runtime, the writes fail with EPIPE)
+    let stdout = unsafe { libc::dup(1) };
+    let mut fds = [0; 2];
+
+    unsafe {
+        assert_eq!(libc::pipe(fds.as_mut_ptr()), 0);
+        libc::close(fds[0]);
+        libc::dup2(fds[1], 1);
+        libc::close(fds[1]);
+    }
+
+    tracing::dispatcher::with_default(&dispatch, || {
+        tracing::info!("first record");
+        tracing::info!("second record");
+    });
+
+    unsafe {
+        libc::dup2(stdout, 1);
+        libc::close(stdout);
+    }
+
+    // The closing is logged by a thread
+    let read = || std::fs::read_to_string(dir.join("App.log")).unwrap_or_default();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+
+    while !read().contains("broken pipe") && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    tracing::dispatcher::with_default(&dispatch, || tracing::info!("third record"));
+    drop(guard);
+
+    let contents = read();
+
+    // The log file kept logging, and was told once
+    assert!(contents.contains("first record"), "{contents}");
+    assert!(contents.contains("second record"), "{contents}");
+    assert!(contents.contains("third record"), "{contents}");
+    assert_eq!(
+        contents
+            .lines()
+            .filter(|line| line
+                .contains(" WARN tracing_init: the console output was closed (broken pipe)"))
+            .count(),
+        1,
+        "{contents}"
+    );
+}
@@ -0,0 +1,55 @@
+#![cfg(all(unix, feature = "file"))]
+
+use tracing_init::{Rotation, TracingInit};
+
+#[test]
+fn test_reinit_after_fork() {
+    let dir = std::env::temp_dir().join(format!("tracing-init-fork-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // The records are written by a thread, which the child process does not have
+    let (dispatch, mut guard) = TracingInit::builder("App")
+        .log_to_file(true)
+        .log_file_path(dir.to_str().unwrap())
+        .log_file_rotation(Rotation::Never)
+        .log_file_non_blocking(true)
+        .runtime_destinations(true)
+        .into_dispatch()
+        .unwrap();
+
+    tracing::dispatcher::with_default(&dispatch, || tracing::info!("before the fork"));
+
+    let pid = unsafe { libc::fork() };
+
+    assert!(pid >= 0, "fork failed");
+
+    if pid == 0 {
+        let code = match guard.reinit_after_fork() {
+            Ok(()) => {
+                tracing::dispatcher::with_default(&dispatch, || {
+                    tracing::info!("in the child process")
+                });
+                drop(guard);
+                0
+            }
+            Err(_) => 1,
+        };
+
+        // Leave without running the rest of the test harness
+        unsafe { libc::_exit(code) };
+    }
+
+    let mut status = 0;
+
+    assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+    assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+
+    tracing::dispatcher::with_default(&dispatch, || tracing::info!("in the parent process"));
+    drop(guard);
+
+    let contents = std::fs::read_to_string(dir.join("App.log")).unwrap();
+
+    assert!(contents.contains("before the fork"), "{contents}");
+    assert!(contents.contains("in the child process"), "{contents}");
+    assert!(contents.contains("in the parent process"), "{contents}");
+}
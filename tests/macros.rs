@@ -0,0 +1,57 @@
+#![cfg(feature = "macros")]
+
+#[test]
+fn test_main_arguments() {
+    let t = trybuild::TestCases::new();
+
+    t.pass("tests/ui/main_*.rs");
+    t.compile_fail("tests/ui/*_argument.rs");
+    t.compile_fail("tests/ui/invalid_*.rs");
+    t.compile_fail("tests/ui/not_a_string.rs");
+    t.compile_fail("tests/ui/empty_app.rs");
+}
+
+// Fails after logging, as a main returning an error
+#[cfg(all(feature = "server", feature = "test-util"))]
+#[tracing_init::main(app = "App", destinations = "s")]
+#[tokio::main]
+async fn run() -> Result<(), String> {
+    tracing::info!("first record");
+    let value = "not a number"
+        .parse::<u32>()
+        .map_err(|error| error.to_string())?;
+
+    tracing::info!("not logged: {value}");
+    Ok(())
+}
+
+#[cfg(all(feature = "server", feature = "test-util"))]
+#[test]
+fn test_main_guard() {
+    use std::time::Duration;
+
+    use tracing_init::test::MockGelfServer;
+
+    let server = MockGelfServer::start();
+
+    // Not given by the attribute, taken from the environment variables
+    std::env::set_var("LOG_SERVER", server.address());
+
+    assert_eq!(run(), Err(String::from("invalid digit found in string")));
+
+    // The records are queued for the server until the guard is dropped: it was kept while the body ran, and dropped
+    // when it returned the error
+    server
+        .wait_for(
+            |message| message.short_message == "first record",
+            Duration::from_secs(5),
+        )
+        .expect("the records were not sent to the log server");
+
+    tracing::info!("logged once the guard was dropped");
+    std::thread::sleep(Duration::from_millis(200));
+
+    let messages = server.messages();
+
+    assert_eq!(messages.len(), 1, "{messages:?}");
+}
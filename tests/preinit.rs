@@ -0,0 +1,68 @@
+#![cfg(feature = "file")]
+
+use tracing::Level;
+use tracing_init::TracingInit;
+
+#[test]
+fn test_preinit_buffer() {
+    let dir = std::env::temp_dir().join(format!("tracing-init-preinit-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    tracing_init::preinit_buffer(3).unwrap();
+    assert!(tracing_init::preinit_buffer(3).is_err());
+
+    // The oldest event is dropped, the TRACE event is filtered out when replayed
+    tracing::info!("dropped");
+    tracing::trace!("parsing arguments");
+    tracing::warn!(path = "/etc/app.toml", "config file not found");
+    tracing::info!(port = 8080, "arguments parsed");
+
+    let guard = TracingInit::builder("App")
+        .log_to_file(true)
+        .log_file_path(dir.to_str().unwrap())
+        .log_file_rotation(tracing_init::Rotation::Never)
+        .level(Level::INFO)
+        .init()
+        .unwrap();
+
+    tracing::info!("started");
+
+    let span = tracing::info_span!("request", id = 1);
+    let _entered = span.enter();
+    assert_eq!(tracing::Span::current().id(), span.id());
+    tracing::info!("in request");
+
+    drop(_entered);
+    drop(guard);
+
+    let log = std::fs::read_to_string(dir.join("App.log")).unwrap();
+    // Without the warning about the guard being dropped right away
+    let lines = log
+        .lines()
+        .filter(|line| !line.contains("TracingGuard"))
+        .collect::<Vec<_>>();
+
+    assert_eq!(lines.len(), 5, "{log}");
+    assert!(
+        lines[0].contains(
+            "WARN tracing_init: dropped 1 events logged before init (the oldest ones) dropped=1"
+        ),
+        "{log}"
+    );
+    assert!(
+        lines[1].contains(
+            r#"WARN preinit: config file not found path="/etc/app.toml" replayed=true logged_at="#
+        ),
+        "{log}"
+    );
+    assert!(
+        lines[2].contains("INFO preinit: arguments parsed port=8080 replayed=true logged_at="),
+        "{log}"
+    );
+    assert!(lines[3].contains("INFO preinit: started"), "{log}");
+    assert!(!lines[3].contains("replayed"), "{log}");
+    assert!(
+        lines[4].contains("request{id=1}: preinit: in request"),
+        "{log}"
+    );
+}
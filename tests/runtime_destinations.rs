@@ -0,0 +1,198 @@
+#![cfg(feature = "file")]
+
+use std::path::{Path, PathBuf};
+
+use tracing::Level;
+use tracing_init::layers::FileOptions;
+use tracing_init::{LogFormat, Rotation, TracingGuard, TracingInit, TracingInitError};
+
+fn log_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "tracing-init-runtime-{name}-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn file_options(guard: &TracingGuard, dir: &Path) -> FileOptions {
+    FileOptions {
+        path: dir.to_path_buf(),
+        prefix: String::from("App"),
+        rotation: Rotation::Never,
+        ..guard.config().file_options()
+    }
+}
+
+#[test]
+fn test_enable_file_at_runtime() {
+    let dir = log_dir("enable");
+    let (dispatch, mut guard) = TracingInit::builder("App")
+        .allow_silent(true)
+        .level(Level::INFO)
+        .runtime_destinations(true)
+        .into_dispatch()
+        .unwrap();
+
+    assert!(guard.to_string().contains("file: disabled"), "{guard}");
+
+    tracing::dispatcher::with_default(&dispatch, || {
+        // Created before the log file is enabled, its fields are still written
+        let span = tracing::info_span!("request", id = 7);
+
+        tracing::info!("before enabling");
+
+        guard.enable_file(&file_options(&guard, &dir)).unwrap();
+        span.in_scope(|| tracing::info!("while enabled"));
+        tracing::debug!("filtered out");
+
+        assert!(guard.config().log_to_file());
+        assert!(guard.to_string().contains("App.log"), "{guard}");
+
+        guard.disable_file().unwrap();
+        tracing::info!("after disabling");
+    });
+
+    assert!(!guard.config().log_to_file());
+    assert!(guard.to_string().contains("file: disabled"), "{guard}");
+
+    let contents = std::fs::read_to_string(dir.join("App.log")).unwrap();
+
+    assert!(contents.contains("request{id=7}"), "{contents}");
+    assert!(contents.contains("while enabled"), "{contents}");
+    assert!(!contents.contains("before enabling"), "{contents}");
+    assert!(!contents.contains("filtered out"), "{contents}");
+    assert!(!contents.contains("after disabling"), "{contents}");
+}
+
+#[test]
+fn test_replace_file_at_runtime() {
+    let dir = log_dir("replace");
+    let (dispatch, mut guard) = TracingInit::builder("App")
+        .log_to_file(true)
+        .log_file_path(dir.to_str().unwrap())
+        .log_file_rotation(Rotation::Never)
+        .log_file_non_blocking(true)
+        .runtime_destinations(true)
+        .into_dispatch()
+        .unwrap();
+
+    tracing::dispatcher::with_default(&dispatch, || {
+        let span = tracing::info_span!("job", name = "export");
+
+        span.in_scope(|| tracing::info!("to the first file"));
+
+        let options = FileOptions {
+            prefix: String::from("Json"),
+            format: LogFormat::Json,
+            ..file_options(&guard, &dir)
+        };
+        guard.enable_file(&options).unwrap();
+        span.record("name", "import");
+        span.in_scope(|| tracing::info!("to the second file"));
+    });
+
+    // The first file was flushed when it was replaced, the second one when the guard is dropped
+    drop(guard);
+
+    let first = std::fs::read_to_string(dir.join("App.log")).unwrap();
+    let second = std::fs::read_to_string(dir.join("Json.log")).unwrap();
+
+    assert!(first.contains("to the first file"), "{first}");
+    assert!(!first.contains("to the second file"), "{first}");
+    assert!(
+        second.contains("\"message\":\"to the second file\""),
+        "{second}"
+    );
+    assert!(second.contains("\"name\":\"import\""), "{second}");
+    assert!(!second.contains("to the first file"), "{second}");
+}
+
+#[test]
+fn test_swap_while_logging() {
+    let dir = log_dir("swap");
+    let (dispatch, mut guard) = TracingInit::builder("App")
+        .allow_silent(true)
+        .runtime_destinations(true)
+        .into_dispatch()
+        .unwrap();
+    let options = file_options(&guard, &dir);
+    let done = std::sync::atomic::AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let dispatch = dispatch.clone();
+            let done = &done;
+
+            scope.spawn(move || {
+                tracing::dispatcher::with_default(&dispatch, || {
+                    let mut n = 0;
+
+                    while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                        tracing::info!(thread, "record {n} end");
+                        n += 1;
+                    }
+                })
+            });
+        }
+
+        for _ in 0..50 {
+            guard.enable_file(&options).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            guard.disable_file().unwrap();
+        }
+
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+    });
+
+    let contents = std::fs::read_to_string(dir.join("App.log")).unwrap();
+
+    // Every record is whole, none is cut by a swap
+    assert!(contents.lines().count() > 0);
+    assert!(
+        contents.lines().all(|line| line.contains(" INFO ")
+            && line.contains(": record ")
+            && line
+                .rsplit_once(" end thread=")
+                .is_some_and(|(_, thread)| thread.parse::<u32>().is_ok())),
+        "{contents}"
+    );
+}
+
+#[test]
+fn test_runtime_destinations_not_set() {
+    let dir = log_dir("not-set");
+    let (_dispatch, mut guard) = TracingInit::builder("App")
+        .allow_silent(true)
+        .into_dispatch()
+        .unwrap();
+    let options = file_options(&guard, &dir);
+
+    assert!(matches!(
+        guard.enable_file(&options),
+        Err(TracingInitError::RuntimeDestinationsDisabled)
+    ));
+    assert!(!guard.config().log_to_file());
+}
+
+#[test]
+fn test_enable_file_invalid_options() {
+    let dir = log_dir("invalid");
+    let (_dispatch, mut guard) = TracingInit::builder("App")
+        .allow_silent(true)
+        .runtime_destinations(true)
+        .into_dispatch()
+        .unwrap();
+    let options = FileOptions {
+        rotation: Rotation::Daily,
+        backups: 0,
+        ..file_options(&guard, &dir)
+    };
+
+    assert!(matches!(
+        guard.enable_file(&options),
+        Err(TracingInitError::InvalidLogFileBackups { .. })
+    ));
+    assert!(!guard.config().log_to_file());
+}
@@ -0,0 +1,86 @@
+#![cfg(all(feature = "server", feature = "test-util"))]
+
+use std::time::Duration;
+
+use tracing::Level;
+use tracing_init::test::MockGelfServer;
+use tracing_init::TracingInit;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_log_server() {
+    let server = MockGelfServer::start();
+    let guard = TracingInit::builder("App")
+        .log_to_server(true)
+        .log_server_address(&server.address())
+        .level(Level::INFO)
+        .correlation_ids(true)
+        .init()
+        .unwrap();
+
+    tracing::info_span!("request", trace_id = "4bf92f35").in_scope(|| {
+        tracing::warn!(user = "bob", attempts = 3, "login failed");
+    });
+    tracing::debug!("filtered out");
+    tracing::error!("last");
+
+    // The queued messages are sent when the guard is dropped
+    drop(guard);
+
+    let last = server
+        .wait_for(
+            |message| message.short_message == "last",
+            Duration::from_secs(5),
+        )
+        .expect("the records were not sent to the log server");
+    assert_eq!(last.tracing_level(), Some(Level::ERROR));
+
+    let messages = server.messages();
+    let login = messages
+        .iter()
+        .find(|message| message.short_message == "login failed")
+        .unwrap();
+
+    assert_eq!(login.tracing_level(), Some(Level::WARN));
+    assert_eq!(login.field("app"), Some("App"));
+    assert_eq!(login.field("user"), Some("bob"));
+    assert_eq!(login.field("attempts"), Some("3"));
+    assert_eq!(login.field("trace_id"), Some("4bf92f35"));
+    assert_eq!(login.field("span"), Some("request"));
+    assert!(messages
+        .iter()
+        .all(|message| message.short_message != "filtered out"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_enable_server_at_runtime() {
+    let server = MockGelfServer::start();
+    let (dispatch, mut guard) = TracingInit::builder("App")
+        .allow_silent(true)
+        .runtime_destinations(true)
+        .into_dispatch()
+        .unwrap();
+
+    tracing::dispatcher::with_default(&dispatch, || {
+        tracing::info!("before enabling");
+        guard.enable_server(&server.address()).unwrap();
+        tracing::info!("while enabled");
+        assert!(guard.config().log_to_server());
+
+        // The queued messages are sent when the server is disabled
+        guard.disable_server().unwrap();
+        tracing::info!("after disabling");
+    });
+
+    server
+        .wait_for(
+            |message| message.short_message == "while enabled",
+            Duration::from_secs(5),
+        )
+        .expect("the record was not sent to the log server");
+
+    assert!(!guard.config().log_to_server());
+    assert!(server
+        .messages()
+        .iter()
+        .all(|message| message.short_message == "while enabled"));
+}
@@ -0,0 +1,65 @@
+#![cfg(all(feature = "server", feature = "file"))]
+
+use std::time::{Duration, Instant};
+
+use tracing::Level;
+use tracing_init::{Rotation, TracingInit};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_unreachable_server() {
+    let dir = std::env::temp_dir().join(format!(
+        "tracing-init-server-connect-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // The address resolves, but sending to the broadcast address is not permitted (the socket is not SO_BROADCAST)
+    let guard = TracingInit::builder("App")
+        .log_to_file(true)
+        .log_file_path(dir.to_str().unwrap())
+        .log_file_rotation(Rotation::Never)
+        .log_to_server(true)
+        .log_server_address("255.255.255.255:12201")
+        .level(Level::INFO)
+        .init()
+        .unwrap();
+
+    tracing::info!("logged locally");
+
+    let log_file = dir.join("App.log");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let contents = loop {
+        let contents = std::fs::read_to_string(&log_file).unwrap_or_default();
+
+        if contents.contains("cannot be sent") || Instant::now() > deadline {
+            break contents;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+
+    assert!(contents.contains("logged locally"), "{contents}");
+    assert!(
+        contents.contains(
+            "the records cannot be sent to the log server, connecting again address=255.255.255.255:12201"
+        ),
+        "{contents}"
+    );
+
+    // Past the first retry the connection task is still running, it is not reported as ended
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    tracing::info!("logged after the retry");
+
+    let report = guard.diagnose();
+
+    assert!(
+        report.server.as_ref().is_some_and(|server| server.is_ok()),
+        "{report:?}"
+    );
+
+    // The connection task stops when the guard is dropped
+    let dropped = Instant::now();
+
+    drop(guard);
+    assert!(dropped.elapsed() < Duration::from_secs(1));
+}
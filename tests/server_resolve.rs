@@ -0,0 +1,55 @@
+#![cfg(all(feature = "server", feature = "file"))]
+
+use std::time::{Duration, Instant};
+
+use tracing::Level;
+use tracing_init::{Rotation, TracingInit};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_unresolved_server_address() {
+    let dir = std::env::temp_dir().join(format!(
+        "tracing-init-server-resolve-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // DNS is not ready (or the name does not exist), the address is resolved by the connection task
+    let guard = TracingInit::builder("App")
+        .log_to_file(true)
+        .log_file_path(dir.to_str().unwrap())
+        .log_file_rotation(Rotation::Never)
+        .log_to_server(true)
+        .log_server_address("logging-server.invalid:12201")
+        .level(Level::INFO)
+        .init()
+        .unwrap();
+
+    tracing::info!("logged locally");
+
+    let log_file = dir.join("App.log");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let contents = loop {
+        let contents = std::fs::read_to_string(&log_file).unwrap_or_default();
+
+        if contents.contains("cannot be resolved") || Instant::now() > deadline {
+            break contents;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+
+    assert!(contents.contains("logged locally"), "{contents}");
+    assert!(
+        contents.contains(
+            "the log server address cannot be resolved, retrying address=logging-server.invalid:12201"
+        ),
+        "{contents}"
+    );
+    assert!(guard.config().log_to_server());
+
+    // The connection task is still retrying, it stops when the guard is dropped
+    let dropped = Instant::now();
+
+    drop(guard);
+    assert!(dropped.elapsed() < Duration::from_secs(1));
+}
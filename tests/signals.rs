@@ -0,0 +1,118 @@
+#![cfg(all(unix, feature = "signals", feature = "file"))]
+
+use std::io::{BufRead, BufReader};
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use tracing_init::{TracingGuard, TracingInit};
+
+// Set in the child process, the directory of its log file
+const CHILD_LOG_DIR: &str = "TRACING_INIT_SIGNALS_CHILD";
+const SIGTERM: i32 = 15;
+
+// The logging of the child process, logging the signals
+fn init_child(dir: &str) -> TracingGuard {
+    TracingInit::builder("App")
+        .log_to_file(true)
+        .log_file_path(dir)
+        .log_file_rotation(tracing_init::Rotation::Never)
+        .log_file_non_blocking(true)
+        .log_signals(true)
+        .init()
+        .unwrap()
+}
+
+// Run the test in a child process, and send it SIGTERM once it is ready. Returns the child and its log directory
+fn terminate_child(test: &str) -> (Child, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("tracing-init-{test}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args([test, "--exact", "--nocapture"])
+        .env(CHILD_LOG_DIR, &dir)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    // After the name of the test printed by the harness
+    while !line.trim_end().ends_with("ready") {
+        line.clear();
+        assert!(stdout.read_line(&mut line).unwrap() > 0, "the child exited");
+    }
+    // Kept open for the rest of the output
+    child.stdout = Some(stdout.into_inner());
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    (child, dir)
+}
+
+// The process does not handle SIGTERM: it is logged, then the process is ended by the signal as without logging it
+#[test]
+fn test_log_signals() {
+    if let Ok(dir) = std::env::var(CHILD_LOG_DIR) {
+        let _guard = init_child(&dir);
+
+        tracing::info!("started");
+        println!("ready");
+        std::thread::sleep(Duration::from_secs(30));
+        panic!("not ended by SIGTERM");
+    }
+
+    let (mut child, dir) = terminate_child("test_log_signals");
+
+    let status = child.wait().unwrap();
+    assert_eq!(status.signal(), Some(SIGTERM), "{status}");
+
+    let log = std::fs::read_to_string(dir.join("App.log")).unwrap();
+    assert!(log.contains("INFO signals: started"), "{log}");
+    assert!(
+        log.contains(r#"WARN tracing_init: signal received signal="SIGTERM""#),
+        "{log}"
+    );
+}
+
+// The process handles SIGTERM with tokio::signal, listening after init: the signal is logged and the process is not
+// ended, it shuts down by itself
+#[test]
+fn test_log_signals_handled_after_init() {
+    if let Ok(dir) = std::env::var(CHILD_LOG_DIR) {
+        let guard = init_child(&dir);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async {
+            let mut sigterm =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).unwrap();
+
+            tracing::info!("started");
+            println!("ready");
+            tokio::time::timeout(Duration::from_secs(30), sigterm.recv())
+                .await
+                .expect("SIGTERM not received");
+        });
+
+        tracing::info!("shutting down");
+        drop(guard);
+        return;
+    }
+
+    let (mut child, dir) = terminate_child("test_log_signals_handled_after_init");
+
+    let status = child.wait().unwrap();
+    assert!(status.success(), "{status}");
+
+    let log = std::fs::read_to_string(dir.join("App.log")).unwrap();
+    assert!(
+        log.contains(r#"WARN tracing_init: signal received signal="SIGTERM""#),
+        "{log}"
+    );
+    assert!(log.contains("INFO signals: shutting down"), "{log}");
+}
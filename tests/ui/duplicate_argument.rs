@@ -0,0 +1,2 @@
+#[tracing_init::main(level = "debug", level = "info")]
+fn main() {}
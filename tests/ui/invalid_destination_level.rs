@@ -0,0 +1,2 @@
+#[tracing_init::main(destinations = "c:info,f:loud")]
+fn main() {}
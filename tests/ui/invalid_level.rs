@@ -0,0 +1,2 @@
+#[tracing_init::main(level = "verbose")]
+fn main() {}
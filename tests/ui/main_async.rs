@@ -0,0 +1,7 @@
+#[tracing_init::main]
+#[tokio::main]
+async fn main() {
+    tokio::spawn(async { tracing::info!("logged within the runtime") })
+        .await
+        .unwrap();
+}
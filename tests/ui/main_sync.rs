@@ -0,0 +1,5 @@
+#[tracing_init::main(app = "mytool", destinations = "c:debug", level = "info")]
+fn main() -> Result<(), String> {
+    tracing::info!("logged to the console");
+    Ok(())
+}
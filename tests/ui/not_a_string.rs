@@ -0,0 +1,2 @@
+#[tracing_init::main(level = 3)]
+fn main() {}
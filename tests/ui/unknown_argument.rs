@@ -0,0 +1,2 @@
+#[tracing_init::main(app = "mytool", name = "other")]
+fn main() {}